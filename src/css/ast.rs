@@ -1,25 +1,66 @@
+use crate::css::border::{
+    parse_border_color_value, parse_border_radius_value, parse_border_sides_value,
+    parse_border_style_value, parse_border_width_value, parse_corner_radius_value,
+};
+use crate::css::calc::{parse_length, CalcNode};
 use crate::css::color::Color;
+use crate::css::font::{
+    parse_font_family, parse_font_feature_settings_value, parse_font_stretch_value,
+    parse_font_style_value, parse_font_variant_ligatures_value, parse_font_weight_value, FontFace,
+};
+use crate::css::media::MediaQueryList;
+use crate::css::parse::try_parse_css;
 use crate::css::property::{property_type, Property};
+use crate::css::text::{
+    parse_text_decoration_value, parse_text_shadow_value, parse_text_underline_position_value,
+};
 use crate::html::{element_type, ElementType};
 use anyhow::Result;
 
-#[derive(Debug)]
+#[derive(Debug, Default, PartialEq)]
 pub struct StyleSheet {
     pub blocks: Vec<Block>,
+    pub font_faces: Vec<FontFace>,
 }
 impl StyleSheet {
     pub fn new() -> StyleSheet {
-        StyleSheet { blocks: vec![] }
+        StyleSheet {
+            blocks: vec![],
+            font_faces: vec![],
+        }
     }
-    pub fn append_block(self: &mut Self, block: Block) {
+    pub fn append_block(&mut self, block: Block) {
         self.blocks.push(block);
     }
+    pub fn append_font_face(&mut self, font_face: FontFace) {
+        self.font_faces.push(font_face);
+    }
+    /// CSSのruleをindexの位置に挿入する. ネストしたruleは展開されて複数のBlockになる.
+    /// 挿入したBlockの数を返す
+    pub fn insert_rule(&mut self, rule: &str, index: usize) -> Result<usize> {
+        if index > self.blocks.len() {
+            return Err(anyhow::anyhow!("index {} is out of range", index));
+        }
+        let blocks = try_parse_css(rule)?.blocks;
+        let count = blocks.len();
+        self.blocks.splice(index..index, blocks);
+        Ok(count)
+    }
+    pub fn delete_rule(&mut self, index: usize) -> Result<Block> {
+        if index >= self.blocks.len() {
+            return Err(anyhow::anyhow!("index {} is out of range", index));
+        }
+        Ok(self.blocks.remove(index))
+    }
 }
 
-#[derive(Debug)]
+// ネストしたruleはparse時に平坦なBlockに展開される
+#[derive(Debug, Default, PartialEq)]
 pub struct Block {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    // @mediaの条件. すべての条件に一致する場合のみ適用する
+    pub media: Vec<MediaQueryList>,
 }
 
 impl Block {
@@ -27,21 +68,81 @@ impl Block {
         Block {
             selectors: vec![],
             declarations: vec![],
+            media: vec![],
         }
     }
-    pub fn set_selector(self: &mut Self, input: Selector) {
+    pub fn set_selector(&mut self, input: Selector) {
         self.selectors.push(input);
     }
-    pub fn set_declaration(self: &mut Self, declaration: Declaration) {
+    pub fn set_declaration(&mut self, declaration: Declaration) {
         self.declarations.push(declaration);
     }
+    // 同じpropertyが複数ある場合は後に書かれた値が有効になる
+    pub fn get_property_value(&self, name: &str) -> Option<&Value> {
+        let property = property_type(name);
+        self.declarations
+            .iter()
+            .rev()
+            .find(|declaration| declaration.property == property)
+            .map(|declaration| &declaration.value)
+    }
+    /// 既に同じpropertyがある場合は最初の位置の値を置き換え、それ以外は末尾に追加する
+    pub fn set_property(&mut self, name: &str, value: &str) -> Result<()> {
+        let declaration = Declaration::new(name, value)?;
+        let property = declaration.property;
+        match self
+            .declarations
+            .iter()
+            .position(|d| d.property == property)
+        {
+            Some(index) => {
+                self.declarations[index] = declaration;
+                let mut current = 0;
+                self.declarations.retain(|d| {
+                    current += 1;
+                    current - 1 == index || d.property != property
+                });
+            }
+            None => self.declarations.push(declaration),
+        }
+        Ok(())
+    }
+    /// propertyをすべて取り除き、有効だった値を返す
+    pub fn remove_property(&mut self, name: &str) -> Option<Value> {
+        let property = property_type(name);
+        let value = self.get_property_value(name).cloned();
+        self.declarations.retain(|d| d.property != property);
+        value
+    }
 }
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Selector {
     pub element: Option<ElementType>,
+    // 書かれたままの要素名. 未対応の要素もElementType::Otherにまとめずに出力するため保持する
+    pub tag_name: Option<String>,
     pub class: Vec<String>,
     pub id: Option<String>,
     pub attribute: Option<String>,
+    pub pseudo_classes: Vec<PseudoClass>,
+    // 左側のselectorとそれを結ぶ結合子. `div > .a`の場合は.aが(Child, div)を持つ
+    pub combinator: Option<(Combinator, Box<Selector>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Combinator {
+    Descendant,
+    Child,
+    NextSibling,
+    SubsequentSibling,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PseudoClass {
+    Is(Vec<Selector>),
+    FirstChild,
+    LastChild,
+    // :hoverなど静的な描画では一致しないもの
+    Other(String),
 }
 
 pub type Specificity = (usize, usize, usize);
@@ -50,105 +151,159 @@ impl Selector {
     pub fn new() -> Selector {
         Selector {
             element: None,
+            tag_name: None,
             class: vec![],
             id: None,
             attribute: None,
+            pseudo_classes: vec![],
+            combinator: None,
         }
     }
     pub fn specificity(&self) -> Specificity {
-        let a = self.id.iter().count();
-        let b = self.class.len();
-        let c = self.element.iter().count();
+        let mut a = self.id.iter().count();
+        let mut b = self.class.len();
+        let mut c = self.element.iter().count();
+        for pseudo_class in &self.pseudo_classes {
+            match pseudo_class {
+                // :is()は引数の中で最も高い詳細度を持つ
+                PseudoClass::Is(selectors) => {
+                    let (is_a, is_b, is_c) = selectors
+                        .iter()
+                        .map(|s| s.specificity())
+                        .max()
+                        .unwrap_or((0, 0, 0));
+                    a += is_a;
+                    b += is_b;
+                    c += is_c;
+                }
+                _ => b += 1,
+            }
+        }
+        if let Some((_, left)) = &self.combinator {
+            let (left_a, left_b, left_c) = left.specificity();
+            a += left_a;
+            b += left_b;
+            c += left_c;
+        }
         (a, b, c)
     }
-    pub fn set_class(self: &mut Self, input: &str) {
+    pub fn set_class(&mut self, input: &str) {
         self.class.push(input.to_string());
     }
-    pub fn set_id(self: &mut Self, input: &str) {
+    pub fn set_id(&mut self, input: &str) {
         self.id = Some(input.to_string());
     }
-    pub fn set_element(self: &mut Self, input: &str) {
+    pub fn set_element(&mut self, input: &str) {
         self.element = Some(element_type(input));
+        self.tag_name = Some(input.to_string());
+    }
+    pub fn set_pseudo_class(&mut self, input: PseudoClass) {
+        self.pseudo_classes.push(input);
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Declaration {
     pub property: Property,
     pub value: Value,
 }
 
-fn get_px(input: &str) -> Result<f32> {
-    if !input.contains("px") {
-        return Err(anyhow::anyhow!("padding not px;"));
+// 長さとして解釈できない値はkeyword(autoなど)として扱う
+fn length_or_keyword(val: &str) -> Result<Value> {
+    match parse_length(val) {
+        Ok(value) => Ok(value),
+        // calc()などの型が合わない場合は宣言自体を無効にする
+        Err(err) if val.contains('(') => Err(err),
+        Err(_) => Ok(Value::Keyword(val.to_string())),
     }
-    let remove_px = input.replace("px", "");
-    let float: f32 = remove_px.parse().unwrap();
-    Ok(float)
 }
 
 impl Declaration {
     pub fn new(prop: &str, val: &str) -> Result<Declaration> {
         let property = property_type(prop);
         let value: Value = match property {
-            Property::Padding => {
-                let px = get_px(val);
-                match px {
-                    Ok(px) => Value::Length(px, Unit::Px),
-                    Err(err) => Value::Keyword(val.to_string()),
-                }
-            }
-            Property::Margin => {
-                let px = get_px(val);
-                match px {
-                    Ok(px) => Value::Length(px, Unit::Px),
-                    Err(err) => Value::Keyword(val.to_string()),
-                }
-            }
-            Property::MarginTop => {
-                let px = get_px(val);
-                match px {
-                    Ok(px) => Value::Length(px, Unit::Px),
-                    Err(err) => Value::Keyword(val.to_string()),
-                }
-            }
-            Property::MarginLeft => {
-                let px = get_px(val);
-                match px {
-                    Ok(px) => Value::Length(px, Unit::Px),
-                    Err(err) => Value::Keyword(val.to_string()),
-                }
-            }
-            Property::MarginRight => {
-                let px = get_px(val);
-                match px {
-                    Ok(px) => Value::Length(px, Unit::Px),
-                    Err(err) => Value::Keyword(val.to_string()),
-                }
-            }
-            Property::MarginBottom => {
-                let px = get_px(val);
-                match px {
-                    Ok(px) => Value::Length(px, Unit::Px),
-                    Err(err) => Value::Keyword(val.to_string()),
-                }
-            }
-            Property::Display => Value::Keyword(val.to_string()),
-            Property::Width => {
-                let px = get_px(val).unwrap();
-                Value::Length(px, Unit::Px)
-            }
-            Property::Height => {
-                let px = get_px(val).unwrap();
-                Value::Length(px, Unit::Px)
-            }
-            Property::FontSize => {
-                let px = get_px(val).unwrap();
-                Value::Length(px, Unit::Px)
-            }
-            Property::BackgroundColor => Value::Color(Color::new(val).unwrap()),
-            Property::Color => Value::Color(Color::new(val).unwrap()),
-            _ => Value::Undefined,
+            Property::Padding
+            | Property::PaddingTop
+            | Property::PaddingRight
+            | Property::PaddingBottom
+            | Property::PaddingLeft
+            | Property::Margin
+            | Property::MarginTop
+            | Property::MarginLeft
+            | Property::MarginRight
+            | Property::MarginBottom
+            | Property::Width
+            | Property::Height
+            | Property::MinWidth
+            | Property::MaxWidth
+            | Property::MinHeight
+            | Property::MaxHeight
+            | Property::FontSize
+            | Property::TextIndent
+            | Property::LetterSpacing
+            | Property::WordSpacing
+            | Property::VerticalAlign => length_or_keyword(val)?,
+            Property::Display
+            | Property::BoxSizing
+            | Property::WordBreak
+            | Property::LineBreak
+            | Property::OverflowWrap
+            | Property::Hyphens
+            | Property::WhiteSpace
+            | Property::TextAlign
+            | Property::TextTransform
+            | Property::Direction
+            | Property::UnicodeBidi
+            | Property::WritingMode
+            | Property::TextOrientation
+            | Property::TextCombineUpright
+            | Property::RubyPosition
+            | Property::RubyAlign
+            | Property::FontSmoothing => Value::Keyword(val.to_string()),
+            Property::BorderWidth => parse_border_sides_value(val, parse_border_width_value)?,
+            Property::BorderStyle => parse_border_sides_value(val, parse_border_style_value)?,
+            Property::BorderColor => parse_border_sides_value(val, parse_border_color_value)?,
+            Property::BorderTopWidth
+            | Property::BorderRightWidth
+            | Property::BorderBottomWidth
+            | Property::BorderLeftWidth => parse_border_width_value(val)?,
+            Property::BorderTopStyle
+            | Property::BorderRightStyle
+            | Property::BorderBottomStyle
+            | Property::BorderLeftStyle => parse_border_style_value(val)?,
+            Property::BorderTopColor
+            | Property::BorderRightColor
+            | Property::BorderBottomColor
+            | Property::BorderLeftColor => parse_border_color_value(val)?,
+            Property::BorderRadius => parse_border_radius_value(val)?,
+            Property::BorderTopLeftRadius
+            | Property::BorderTopRightRadius
+            | Property::BorderBottomRightRadius
+            | Property::BorderBottomLeftRadius => parse_corner_radius_value(val)?,
+            Property::TextDecoration => parse_text_decoration_value(val)?,
+            Property::TextShadow => parse_text_shadow_value(val)?,
+            Property::TextUnderlinePosition => parse_text_underline_position_value(val)?,
+            Property::FontFamily => parse_font_family(val)?,
+            Property::FontWeight => parse_font_weight_value(val)?,
+            Property::FontStyle => parse_font_style_value(val)?,
+            Property::FontStretch => parse_font_stretch_value(val)?,
+            Property::FontFeatureSettings => parse_font_feature_settings_value(val)?,
+            Property::FontVariantLigatures => parse_font_variant_ligatures_value(val)?,
+            // 単位のない数値はfont-sizeに対する倍率になる
+            Property::LineHeight => match val.parse::<f32>() {
+                Ok(number) if number >= 0.0 => Value::Number(number),
+                _ => length_or_keyword(val)?,
+            },
+            // 単位のない数値は空白の幅に対する倍率になる
+            Property::TabSize => match val.parse::<f32>() {
+                Ok(number) if number >= 0.0 => Value::Number(number),
+                _ => length_or_keyword(val)?,
+            },
+            Property::BackgroundColor | Property::Color => Value::Color(
+                Color::new(val).ok_or_else(|| anyhow::anyhow!("invalid color {}", val))?,
+            ),
+            // 未対応のpropertyは無視する
+            Property::Undefined => return Err(anyhow::anyhow!("unknown property {}", prop)),
         };
         Ok(Declaration { property, value })
     }
@@ -159,13 +314,24 @@ pub enum Value {
     Keyword(String),
     Color(Color),
     Length(f32, Unit),
+    Percentage(f32),
+    // 単位のない数値
+    Number(f32),
+    Math(CalcNode),
+    // 引用符で囲まれた文字列
+    String(String),
+    // カンマ区切りの値
+    List(Vec<Value>),
     Undefined,
 }
 
 impl Value {
-    pub fn to_px(&self) -> f32 {
-        match *self {
-            Value::Length(f, Unit::Px) => f,
+    /// %やem, calc()などを含めてpxに変換する. 長さでない値は0になる
+    pub fn resolve(&self, context: &LengthContext) -> f32 {
+        match self {
+            Value::Length(f, unit) => unit.to_px(*f, context),
+            Value::Percentage(p) => context.percentage_basis * p / 100.0,
+            Value::Math(node) => node.resolve(context),
             _ => 0.0,
         }
     }
@@ -174,4 +340,85 @@ impl Value {
 #[derive(Debug, PartialEq, Clone)]
 pub enum Unit {
     Px,
+    Em,
+    Rem,
+    Vw,
+    Vh,
+}
+
+impl Unit {
+    pub fn to_px(&self, value: f32, context: &LengthContext) -> f32 {
+        match self {
+            Unit::Px => value,
+            Unit::Em => value * context.font_size,
+            Unit::Rem => value * context.root_font_size,
+            Unit::Vw => value * context.viewport_width / 100.0,
+            Unit::Vh => value * context.viewport_height / 100.0,
+        }
+    }
+}
+
+/// %やem, vwなどをpxに変換するために必要な値
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LengthContext {
+    // %の基準となる長さ(widthなら親要素のwidth)
+    pub percentage_basis: f32,
+    pub font_size: f32,
+    pub root_font_size: f32,
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+}
+
+#[test]
+fn test_style_sheet_edit_rule() {
+    let mut style_sheet = try_parse_css(".a { color: red; } .b { color: blue; }").unwrap();
+    // ネストしたruleは複数のBlockとして挿入される
+    let count = style_sheet
+        .insert_rule(".c { color: green; & p { margin: 1px } }", 1)
+        .unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(style_sheet.blocks.len(), 4);
+    assert_eq!(style_sheet.blocks[1].selectors[0].class, vec!["c"]);
+    assert_eq!(style_sheet.blocks[3].selectors[0].class, vec!["b"]);
+
+    let deleted = style_sheet.delete_rule(0).unwrap();
+    assert_eq!(deleted.selectors[0].class, vec!["a"]);
+    assert_eq!(style_sheet.blocks.len(), 3);
+
+    // 範囲外の位置や不正なCSSはエラーになり、StyleSheetは変更されない
+    assert!(style_sheet.insert_rule(".d {}", 4).is_err());
+    assert!(style_sheet.insert_rule(".d { color: ", 0).is_err());
+    assert!(style_sheet.delete_rule(3).is_err());
+    assert_eq!(style_sheet.blocks.len(), 3);
+}
+
+#[test]
+fn test_block_edit_property() {
+    let mut style_sheet = try_parse_css(".a { margin: 1px; color: red; margin: 2px; }").unwrap();
+    let block = style_sheet.blocks.first_mut().unwrap();
+    assert_eq!(
+        block.get_property_value("margin"),
+        Some(&Value::Length(2.0, Unit::Px))
+    );
+
+    // 重複したpropertyは1つにまとめられる
+    block.set_property("margin", "3em").unwrap();
+    assert_eq!(block.declarations.len(), 2);
+    assert_eq!(block.declarations[0].property, Property::Margin);
+    assert_eq!(
+        block.get_property_value("margin"),
+        Some(&Value::Length(3.0, Unit::Em))
+    );
+
+    block.set_property("width", "calc(100% - 10px)").unwrap();
+    assert_eq!(block.declarations.len(), 3);
+    assert!(block.set_property("color", "not-a-color").is_err());
+    assert!(block.set_property("unknown", "1px").is_err());
+
+    assert_eq!(
+        block.remove_property("color"),
+        Some(Value::Color(Color::new("red").unwrap()))
+    );
+    assert_eq!(block.remove_property("color"), None);
+    assert_eq!(block.declarations.len(), 2);
 }
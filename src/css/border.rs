@@ -0,0 +1,356 @@
+use anyhow::Result;
+
+use crate::css::ast::{Unit, Value};
+use crate::css::calc::parse_length;
+use crate::css::color::Color;
+
+/// border-styleの線の種類. 初期値はnone
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BorderStyle {
+    #[default]
+    None,
+    Hidden,
+    Dotted,
+    Dashed,
+    Solid,
+    Double,
+    Groove,
+    Ridge,
+    Inset,
+    Outset,
+}
+
+impl BorderStyle {
+    pub fn from_keyword(keyword: &str) -> Option<BorderStyle> {
+        match keyword.to_lowercase().as_str() {
+            "none" => Some(BorderStyle::None),
+            "hidden" => Some(BorderStyle::Hidden),
+            "dotted" => Some(BorderStyle::Dotted),
+            "dashed" => Some(BorderStyle::Dashed),
+            "solid" => Some(BorderStyle::Solid),
+            "double" => Some(BorderStyle::Double),
+            "groove" => Some(BorderStyle::Groove),
+            "ridge" => Some(BorderStyle::Ridge),
+            "inset" => Some(BorderStyle::Inset),
+            "outset" => Some(BorderStyle::Outset),
+            _ => None,
+        }
+    }
+
+    /// noneとhiddenの辺はborderの幅が0になる
+    pub fn is_visible(self) -> bool {
+        !matches!(self, BorderStyle::None | BorderStyle::Hidden)
+    }
+}
+
+pub fn border_style(value: &Value) -> BorderStyle {
+    match value {
+        Value::Keyword(keyword) => BorderStyle::from_keyword(keyword).unwrap_or_default(),
+        _ => BorderStyle::default(),
+    }
+}
+
+// thin, medium, thickの幅(px)
+fn border_width_keyword(keyword: &str) -> Option<f32> {
+    match keyword.to_lowercase().as_str() {
+        "thin" => Some(1.0),
+        "medium" => Some(3.0),
+        "thick" => Some(5.0),
+        _ => None,
+    }
+}
+
+/// border-widthのkeywordはpxの長さにする. 負の値と%は無効
+pub fn parse_border_width_value(input: &str) -> Result<Value> {
+    if let Some(width) = border_width_keyword(input) {
+        return Ok(Value::Length(width, Unit::Px));
+    }
+    match parse_length(input)? {
+        Value::Length(width, _) if width < 0.0 => {
+            Err(anyhow::anyhow!("invalid border-width {}", input))
+        }
+        Value::Percentage(_) => Err(anyhow::anyhow!("invalid border-width {}", input)),
+        value => Ok(value),
+    }
+}
+
+pub fn parse_border_style_value(input: &str) -> Result<Value> {
+    match BorderStyle::from_keyword(input) {
+        Some(_) => Ok(Value::Keyword(input.to_lowercase())),
+        None => Err(anyhow::anyhow!("invalid border-style {}", input)),
+    }
+}
+
+/// currentcolorはkeywordのまま残し、描画するときに要素のcolorを使う
+pub fn parse_border_color_value(input: &str) -> Result<Value> {
+    if input.eq_ignore_ascii_case("currentcolor") {
+        return Ok(Value::Keyword(String::from("currentcolor")));
+    }
+    match Color::new(input) {
+        Some(color) => Ok(Value::Color(color)),
+        None => Err(anyhow::anyhow!("invalid border-color {}", input)),
+    }
+}
+
+// 括弧の外にある空白で区切る
+fn split_values(input: &str) -> Vec<&str> {
+    let mut values = vec![];
+    let mut depth = 0;
+    let mut start = None;
+    for (index, char) in input.char_indices() {
+        match char {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(start) = start.take() {
+                    values.push(&input[start..index]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(index);
+    }
+    if let Some(start) = start {
+        values.push(&input[start..]);
+    }
+    values
+}
+
+// 1から4個の値を、marginと同じ規則で省略した辺や角を補って4つにする
+fn expand_four(values: Vec<Value>) -> Option<[Value; 4]> {
+    let value = |index: usize| values[index].clone();
+    match values.len() {
+        1 => Some([value(0), value(0), value(0), value(0)]),
+        2 => Some([value(0), value(1), value(0), value(1)]),
+        3 => Some([value(0), value(1), value(2), value(1)]),
+        4 => Some([value(0), value(1), value(2), value(3)]),
+        _ => None,
+    }
+}
+
+// border-width, border-style, border-colorの上、右、下、左の値
+fn parse_border_sides(input: &str, parse: fn(&str) -> Result<Value>) -> Option<[Value; 4]> {
+    let values = split_values(input)
+        .into_iter()
+        .map(|value| parse(value).ok())
+        .collect::<Option<Vec<Value>>>()?;
+    expand_four(values)
+}
+
+/*
+border-width, border-style, border-colorのshorthand. 値が1つならその値にし、
+2つ以上なら空白をそろえた文字列のまま持って、辺ごとの値はborder_sideで取り出す
+*/
+pub fn parse_border_sides_value(input: &str, parse: fn(&str) -> Result<Value>) -> Result<Value> {
+    let values = split_values(input);
+    match parse_border_sides(input, parse) {
+        Some(_) if values.len() == 1 => parse(values[0]),
+        Some(_) => Ok(Value::Keyword(values.join(" "))),
+        None => Err(anyhow::anyhow!("invalid border value {}", input)),
+    }
+}
+
+// 宣言のときに検証しているので、値の種類はどの辺の値でも同じになる
+fn parse_border_side(input: &str) -> Result<Value> {
+    parse_border_width_value(input)
+        .or_else(|_| parse_border_style_value(input))
+        .or_else(|_| parse_border_color_value(input))
+}
+
+/// border-width, border-style, border-colorのshorthandの値から、辺(topなど)の値を取り出す
+pub fn border_side(value: &Value, side: &str) -> Value {
+    let index = match side {
+        "top" => 0,
+        "right" => 1,
+        "bottom" => 2,
+        _ => 3,
+    };
+    match value {
+        Value::Keyword(keyword) if keyword.contains(' ') => {
+            match parse_border_sides(keyword, parse_border_side) {
+                Some(values) => values[index].clone(),
+                None => Value::Undefined,
+            }
+        }
+        value => value.clone(),
+    }
+}
+
+// 1つの角の半径. 負の値は無効
+fn parse_radius(input: &str) -> Option<Value> {
+    match parse_length(input).ok()? {
+        Value::Length(length, _) if length < 0.0 => None,
+        Value::Percentage(percentage) if percentage < 0.0 => None,
+        value => Some(value),
+    }
+}
+
+// 左上、右上、右下、左下の順に1から4個の値を並べたもの
+fn parse_radius_list(input: &str) -> Option<[Value; 4]> {
+    let values = input
+        .split_whitespace()
+        .map(parse_radius)
+        .collect::<Option<Vec<Value>>>()?;
+    expand_four(values)
+}
+
+/*
+border-radiusの各角の横方向と縦方向の半径. 左上、右上、右下、左下の順に並べる.
+"/"の後に縦方向の半径を書かなければ横方向と同じ値になる
+*/
+fn parse_border_radius(input: &str) -> Option<[(Value, Value); 4]> {
+    let mut parts = input.split('/');
+    let horizontal = parse_radius_list(parts.next()?)?;
+    let vertical = match parts.next() {
+        Some(part) => parse_radius_list(part)?,
+        None => horizontal.clone(),
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    let [h0, h1, h2, h3] = horizontal;
+    let [v0, v1, v2, v3] = vertical;
+    Some([(h0, v0), (h1, v1), (h2, v2), (h3, v3)])
+}
+
+// border-top-left-radiusなどの横方向と縦方向の半径. 1つだけなら円になる
+fn parse_corner_radius(input: &str) -> Option<(Value, Value)> {
+    let values = input
+        .split_whitespace()
+        .map(parse_radius)
+        .collect::<Option<Vec<Value>>>()?;
+    match values.as_slice() {
+        [radius] => Some((radius.clone(), radius.clone())),
+        [horizontal, vertical] => Some((horizontal.clone(), vertical.clone())),
+        _ => None,
+    }
+}
+
+// 空白をそろえた文字列のまま持ち、描画するときに各角の半径にする
+fn normalize(input: &str) -> Value {
+    let parts = input
+        .split('/')
+        .map(|part| part.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .collect::<Vec<String>>();
+    Value::Keyword(parts.join(" / "))
+}
+
+pub fn parse_border_radius_value(input: &str) -> Result<Value> {
+    match parse_border_radius(input) {
+        Some(_) => Ok(normalize(input)),
+        None => Err(anyhow::anyhow!("invalid border-radius {}", input)),
+    }
+}
+
+pub fn parse_corner_radius_value(input: &str) -> Result<Value> {
+    match parse_corner_radius(input) {
+        Some(_) => Ok(normalize(input)),
+        None => Err(anyhow::anyhow!("invalid border radius {}", input)),
+    }
+}
+
+pub fn border_radius(value: &Value) -> Option<[(Value, Value); 4]> {
+    match value {
+        Value::Keyword(keyword) => parse_border_radius(keyword),
+        _ => None,
+    }
+}
+
+pub fn corner_radius(value: &Value) -> Option<(Value, Value)> {
+    match value {
+        Value::Keyword(keyword) => parse_corner_radius(keyword),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_border_values() {
+    assert_eq!(
+        parse_border_width_value("thin").unwrap(),
+        Value::Length(1.0, Unit::Px)
+    );
+    assert_eq!(
+        parse_border_width_value("thick").unwrap(),
+        Value::Length(5.0, Unit::Px)
+    );
+    assert_eq!(
+        parse_border_width_value("2px").unwrap(),
+        Value::Length(2.0, Unit::Px)
+    );
+    assert!(parse_border_width_value("-1px").is_err());
+    assert!(parse_border_width_value("10%").is_err());
+    assert!(parse_border_width_value("wide").is_err());
+    assert_eq!(
+        border_style(&parse_border_style_value("Dashed").unwrap()),
+        BorderStyle::Dashed
+    );
+    assert!(parse_border_style_value("wavy").is_err());
+    assert!(!BorderStyle::Hidden.is_visible());
+    assert_eq!(
+        parse_border_color_value("currentColor").unwrap(),
+        Value::Keyword(String::from("currentcolor"))
+    );
+    assert!(parse_border_color_value("nocolor").is_err());
+}
+
+#[test]
+fn test_parse_border_radius() {
+    let px = |length: f32| Value::Length(length, Unit::Px);
+    let radii = border_radius(&parse_border_radius_value("10px 20px/5px").unwrap()).unwrap();
+    assert_eq!(radii[0], (px(10.0), px(5.0)));
+    assert_eq!(radii[1], (px(20.0), px(5.0)));
+    assert_eq!(radii[2], (px(10.0), px(5.0)));
+    let radii = border_radius(&parse_border_radius_value("1px 2px 3px").unwrap()).unwrap();
+    assert_eq!(radii[3], (px(2.0), px(2.0)));
+    assert_eq!(
+        parse_border_radius_value("1px  2px /3px").unwrap(),
+        Value::Keyword(String::from("1px 2px / 3px"))
+    );
+    assert!(parse_border_radius_value("-1px").is_err());
+    assert!(parse_border_radius_value("1px / 2px / 3px").is_err());
+    assert!(parse_border_radius_value("1px 2px 3px 4px 5px").is_err());
+    assert_eq!(
+        corner_radius(&parse_corner_radius_value("50% 2em").unwrap()),
+        Some((Value::Percentage(50.0), Value::Length(2.0, Unit::Em)))
+    );
+    assert!(parse_corner_radius_value("1px 2px 3px").is_err());
+}
+
+#[test]
+fn test_parse_border_sides() {
+    let px = |length: f32| Value::Length(length, Unit::Px);
+    let sides =
+        |value: &Value| ["top", "right", "bottom", "left"].map(|side| border_side(value, side));
+    // 1つの値はそのまま持つ
+    let width = parse_border_sides_value("thin", parse_border_width_value).unwrap();
+    assert_eq!(width, px(1.0));
+    assert_eq!(sides(&width), [px(1.0), px(1.0), px(1.0), px(1.0)]);
+    // 2つの値は上下と左右、3つの値は上、左右、下になる
+    let width = parse_border_sides_value("6px  2px", parse_border_width_value).unwrap();
+    assert_eq!(width, Value::Keyword(String::from("6px 2px")));
+    assert_eq!(sides(&width), [px(6.0), px(2.0), px(6.0), px(2.0)]);
+    let width = parse_border_sides_value("1px thick calc(1px + 2px)", parse_border_width_value);
+    let [top, right, bottom, left] = sides(&width.unwrap());
+    assert_eq!((top, right.clone()), (px(1.0), px(5.0)));
+    assert_eq!(bottom, parse_border_width_value("calc(1px + 2px)").unwrap());
+    assert_eq!(left, right);
+    // 4つの値は上、右、下、左の順に並べる
+    let style = parse_border_sides_value("solid dashed dotted double", parse_border_style_value);
+    assert_eq!(
+        sides(&style.unwrap()).map(|value| border_style(&value)),
+        [
+            BorderStyle::Solid,
+            BorderStyle::Dashed,
+            BorderStyle::Dotted,
+            BorderStyle::Double
+        ]
+    );
+    let color = parse_border_sides_value("#ff0000 blue", parse_border_color_value).unwrap();
+    let red = Value::Color(Color::new("#ff0000").unwrap());
+    let blue = Value::Color(Color::new("blue").unwrap());
+    assert_eq!(sides(&color), [red.clone(), blue.clone(), red, blue]);
+    assert!(parse_border_sides_value("1px 2px 3px 4px 5px", parse_border_width_value).is_err());
+    assert!(parse_border_sides_value("solid 2px", parse_border_style_value).is_err());
+    assert!(parse_border_sides_value("red nocolor", parse_border_color_value).is_err());
+}
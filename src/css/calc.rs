@@ -0,0 +1,439 @@
+use anyhow::Result;
+use pest::iterators::Pair;
+use pest::Parser;
+
+use crate::css::ast::{LengthContext, Unit, Value};
+use crate::css::parse::{CSSParser, Rule};
+
+/*
+calc(), min(), max(), clamp()を表す式の木
+parse時に型チェックと単位ごとの畳み込みを行い、%やemなど
+layoutまで値が決まらない項だけを残す
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcNode {
+    Number(f32),
+    Length(f32, Unit),
+    Percentage(f32),
+    Sum(Vec<CalcNode>),
+    // 数値との掛け算. 割り算は逆数との掛け算として扱う
+    Product(Box<CalcNode>, f32),
+    Min(Vec<CalcNode>),
+    Max(Vec<CalcNode>),
+    Clamp(Box<CalcNode>, Box<CalcNode>, Box<CalcNode>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalcType {
+    Number,
+    Length,
+    Percentage,
+    LengthPercentage,
+}
+
+impl CalcType {
+    // 足し算, min(), max()などで2つの項を組み合わせたときの型
+    fn add(self, other: CalcType) -> Result<CalcType> {
+        match (self, other) {
+            (a, b) if a == b => Ok(a),
+            (CalcType::Number, _) | (_, CalcType::Number) => {
+                Err(anyhow::anyhow!("cannot add a number to a length"))
+            }
+            _ => Ok(CalcType::LengthPercentage),
+        }
+    }
+}
+
+/// px, %, em等の長さ、またはcalc()などの数式をparseする.
+/// 数値は0の場合のみ長さとして扱う
+pub fn parse_length(input: &str) -> Result<Value> {
+    let pair = CSSParser::parse(Rule::length, input.trim())?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("empty length"))?;
+    let (node, calc_type) = match pair.as_rule() {
+        Rule::math_function => parse_math_function(pair)?,
+        Rule::number if parse_number(pair.as_str())? == 0.0 => {
+            return Ok(Value::Length(0.0, Unit::Px))
+        }
+        _ => parse_calc_value(pair)?,
+    };
+    if calc_type == CalcType::Number {
+        return Err(anyhow::anyhow!("{} is not a length", input));
+    }
+    Ok(match node {
+        CalcNode::Length(value, unit) => Value::Length(value, unit),
+        CalcNode::Percentage(value) => Value::Percentage(value),
+        node => Value::Math(node),
+    })
+}
+
+fn parse_number(input: &str) -> Result<f32> {
+    Ok(input.parse::<f32>()?)
+}
+
+fn parse_math_function(rule: Pair<Rule>) -> Result<(CalcNode, CalcType)> {
+    let function = rule
+        .into_inner()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("empty math function"))?;
+    let function_rule = function.as_rule();
+    let mut args = vec![];
+    let mut calc_type: Option<CalcType> = None;
+    for arg in function.into_inner() {
+        let (node, arg_type) = parse_calc_sum(arg)?;
+        calc_type = Some(match calc_type {
+            Some(t) => t.add(arg_type)?,
+            None => arg_type,
+        });
+        args.push(node);
+    }
+    let calc_type = calc_type.ok_or_else(|| anyhow::anyhow!("math function without arguments"))?;
+    let node = match function_rule {
+        Rule::calc_function => args.remove(0),
+        Rule::min_function => min(args),
+        Rule::max_function => max(args),
+        Rule::clamp_function => {
+            let mut args = args.into_iter();
+            let (lower, value, upper) = match (args.next(), args.next(), args.next()) {
+                (Some(lower), Some(value), Some(upper)) => (lower, value, upper),
+                _ => return Err(anyhow::anyhow!("clamp() requires three arguments")),
+            };
+            clamp(lower, value, upper)
+        }
+        _ => return Err(anyhow::anyhow!("unknown math function")),
+    };
+    Ok((node, calc_type))
+}
+
+fn parse_calc_sum(rule: Pair<Rule>) -> Result<(CalcNode, CalcType)> {
+    let mut inner = rule.into_inner();
+    let first = inner
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("empty calc sum"))?;
+    let (node, mut calc_type) = parse_calc_product(first)?;
+    let mut terms = vec![node];
+    while let (Some(operator), Some(product)) = (inner.next(), inner.next()) {
+        let (node, product_type) = parse_calc_product(product)?;
+        calc_type = calc_type.add(product_type)?;
+        terms.push(match operator.as_str().trim() {
+            "-" => scale(node, -1.0),
+            _ => node,
+        });
+    }
+    Ok((sum(terms), calc_type))
+}
+
+fn parse_calc_product(rule: Pair<Rule>) -> Result<(CalcNode, CalcType)> {
+    let mut inner = rule.into_inner();
+    let first = inner
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("empty calc product"))?;
+    let (mut node, mut calc_type) = parse_calc_value(first)?;
+    while let (Some(operator), Some(value)) = (inner.next(), inner.next()) {
+        let (rhs, rhs_type) = parse_calc_value(value)?;
+        // 掛け算・割り算は少なくとも片方が数値である必要がある
+        match (operator.as_str(), calc_type, rhs_type) {
+            ("*", CalcType::Number, _) => {
+                node = scale(rhs, number_value(&node)?);
+                calc_type = rhs_type;
+            }
+            ("*", _, CalcType::Number) => node = scale(node, number_value(&rhs)?),
+            ("/", _, CalcType::Number) => {
+                let divisor = number_value(&rhs)?;
+                if divisor == 0.0 {
+                    return Err(anyhow::anyhow!("division by zero in calc()"));
+                }
+                node = scale(node, 1.0 / divisor);
+            }
+            _ => return Err(anyhow::anyhow!("invalid operand types for {}", operator)),
+        }
+    }
+    Ok((node, calc_type))
+}
+
+fn parse_calc_value(rule: Pair<Rule>) -> Result<(CalcNode, CalcType)> {
+    match rule.as_rule() {
+        Rule::math_function => parse_math_function(rule),
+        Rule::calc_sum => parse_calc_sum(rule),
+        Rule::number => Ok((
+            CalcNode::Number(parse_number(rule.as_str())?),
+            CalcType::Number,
+        )),
+        Rule::percentage => {
+            let number = rule.as_str().trim_end_matches('%');
+            Ok((
+                CalcNode::Percentage(parse_number(number)?),
+                CalcType::Percentage,
+            ))
+        }
+        Rule::dimension => {
+            let mut inner = rule.into_inner();
+            let (number, unit) = match (inner.next(), inner.next()) {
+                (Some(number), Some(unit)) => (number, unit),
+                _ => return Err(anyhow::anyhow!("invalid dimension")),
+            };
+            Ok((
+                CalcNode::Length(parse_number(number.as_str())?, unit_type(unit.as_str())?),
+                CalcType::Length,
+            ))
+        }
+        _ => Err(anyhow::anyhow!("unexpected token {}", rule.as_str())),
+    }
+}
+
+fn unit_type(input: &str) -> Result<Unit> {
+    match input.to_ascii_lowercase().as_str() {
+        "px" => Ok(Unit::Px),
+        "em" => Ok(Unit::Em),
+        "rem" => Ok(Unit::Rem),
+        "vw" => Ok(Unit::Vw),
+        "vh" => Ok(Unit::Vh),
+        _ => Err(anyhow::anyhow!("unknown unit {}", input)),
+    }
+}
+
+// 数値の項はparse時に必ず1つの値に畳み込まれる
+fn number_value(node: &CalcNode) -> Result<f32> {
+    match node {
+        CalcNode::Number(n) => Ok(*n),
+        _ => Err(anyhow::anyhow!("expected a number")),
+    }
+}
+
+// 同じ単位の項同士をまとめる. 1つの項にまとまった場合はその項を返す
+fn sum(terms: Vec<CalcNode>) -> CalcNode {
+    let mut result: Vec<CalcNode> = vec![];
+    for term in terms {
+        let flattened = match term {
+            CalcNode::Sum(inner) => inner,
+            term => vec![term],
+        };
+        for term in flattened {
+            let same_kind = result.iter_mut().find(|t| is_same_kind(t, &term));
+            match (same_kind, &term) {
+                (Some(CalcNode::Number(a)), CalcNode::Number(b)) => *a += b,
+                (Some(CalcNode::Percentage(a)), CalcNode::Percentage(b)) => *a += b,
+                (Some(CalcNode::Length(a, _)), CalcNode::Length(b, _)) => *a += b,
+                _ => result.push(term),
+            }
+        }
+    }
+    if result.len() == 1 {
+        return result.remove(0);
+    }
+    CalcNode::Sum(result)
+}
+
+fn scale(node: CalcNode, factor: f32) -> CalcNode {
+    if factor == 1.0 {
+        return node;
+    }
+    match node {
+        CalcNode::Number(n) => CalcNode::Number(n * factor),
+        CalcNode::Length(v, unit) => CalcNode::Length(v * factor, unit),
+        CalcNode::Percentage(p) => CalcNode::Percentage(p * factor),
+        CalcNode::Sum(terms) => {
+            CalcNode::Sum(terms.into_iter().map(|t| scale(t, factor)).collect())
+        }
+        CalcNode::Product(inner, f) => scale(*inner, f * factor),
+        node => CalcNode::Product(Box::new(node), factor),
+    }
+}
+
+// 単位が同じ値同士はparse時に比較できる
+fn is_same_kind(a: &CalcNode, b: &CalcNode) -> bool {
+    match (a, b) {
+        (CalcNode::Number(_), CalcNode::Number(_)) => true,
+        (CalcNode::Percentage(_), CalcNode::Percentage(_)) => true,
+        (CalcNode::Length(_, a), CalcNode::Length(_, b)) => a == b,
+        _ => false,
+    }
+}
+
+fn leaf_value(node: &CalcNode) -> Option<f32> {
+    match node {
+        CalcNode::Number(v) | CalcNode::Length(v, _) | CalcNode::Percentage(v) => Some(*v),
+        _ => None,
+    }
+}
+
+// 比較可能な項同士をpickで選んだ値にまとめる
+fn reduce(args: Vec<CalcNode>, pick: fn(f32, f32) -> f32) -> Vec<CalcNode> {
+    let mut result: Vec<CalcNode> = vec![];
+    for arg in args {
+        let existing = result.iter_mut().find(|a| is_same_kind(a, &arg));
+        match (existing, leaf_value(&arg)) {
+            (Some(existing), Some(v)) => {
+                let current = leaf_value(existing).unwrap_or(v);
+                if pick(current, v) != current {
+                    *existing = arg;
+                }
+            }
+            _ => result.push(arg),
+        }
+    }
+    result
+}
+
+fn min(args: Vec<CalcNode>) -> CalcNode {
+    let mut args = reduce(args, f32::min);
+    if args.len() == 1 {
+        return args.remove(0);
+    }
+    CalcNode::Min(args)
+}
+
+fn max(args: Vec<CalcNode>) -> CalcNode {
+    let mut args = reduce(args, f32::max);
+    if args.len() == 1 {
+        return args.remove(0);
+    }
+    CalcNode::Max(args)
+}
+
+fn clamp(lower: CalcNode, value: CalcNode, upper: CalcNode) -> CalcNode {
+    if is_same_kind(&lower, &value) && is_same_kind(&value, &upper) {
+        // clamp(MIN, VAL, MAX) = max(MIN, min(VAL, MAX))
+        return max(vec![lower, min(vec![value, upper])]);
+    }
+    CalcNode::Clamp(Box::new(lower), Box::new(value), Box::new(upper))
+}
+
+impl CalcNode {
+    /// layout時に%, em, vwなどの値が確定したタイミングでpxに変換する
+    pub fn resolve(&self, context: &LengthContext) -> f32 {
+        match self {
+            CalcNode::Number(n) => *n,
+            CalcNode::Length(v, unit) => unit.to_px(*v, context),
+            CalcNode::Percentage(p) => context.percentage_basis * p / 100.0,
+            CalcNode::Sum(terms) => terms.iter().map(|t| t.resolve(context)).sum(),
+            CalcNode::Product(node, factor) => node.resolve(context) * factor,
+            CalcNode::Min(args) => args
+                .iter()
+                .map(|a| a.resolve(context))
+                .fold(f32::INFINITY, f32::min),
+            CalcNode::Max(args) => args
+                .iter()
+                .map(|a| a.resolve(context))
+                .fold(f32::NEG_INFINITY, f32::max),
+            CalcNode::Clamp(lower, value, upper) => {
+                let lower = lower.resolve(context);
+                value
+                    .resolve(context)
+                    .min(upper.resolve(context))
+                    .max(lower)
+            }
+        }
+    }
+
+    pub fn has_percentage(&self) -> bool {
+        match self {
+            CalcNode::Number(_) | CalcNode::Length(_, _) => false,
+            CalcNode::Percentage(_) => true,
+            CalcNode::Product(node, _) => node.has_percentage(),
+            CalcNode::Sum(args) | CalcNode::Min(args) | CalcNode::Max(args) => {
+                args.iter().any(|a| a.has_percentage())
+            }
+            CalcNode::Clamp(lower, value, upper) => {
+                lower.has_percentage() || value.has_percentage() || upper.has_percentage()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> LengthContext {
+        LengthContext {
+            percentage_basis: 800.0,
+            font_size: 20.0,
+            root_font_size: 16.0,
+            viewport_width: 1000.0,
+            viewport_height: 600.0,
+        }
+    }
+
+    #[test]
+    fn test_parse_length() {
+        assert_eq!(parse_length("12px").unwrap(), Value::Length(12.0, Unit::Px));
+        assert_eq!(parse_length("1.5em").unwrap(), Value::Length(1.5, Unit::Em));
+        assert_eq!(parse_length("50%").unwrap(), Value::Percentage(50.0));
+        assert_eq!(parse_length("0").unwrap(), Value::Length(0.0, Unit::Px));
+        // 0以外の数値は長さではない
+        assert!(parse_length("12").is_err());
+        assert!(parse_length("auto").is_err());
+    }
+
+    #[test]
+    fn test_simplify() {
+        // 同じ単位の項はparse時にまとめられる
+        assert_eq!(
+            parse_length("calc(10px + 5px * 2)").unwrap(),
+            Value::Length(20.0, Unit::Px)
+        );
+        assert_eq!(
+            parse_length("calc((100% - 20%) / 2)").unwrap(),
+            Value::Percentage(40.0)
+        );
+        assert_eq!(
+            parse_length("min(10px, 20px)").unwrap(),
+            Value::Length(10.0, Unit::Px)
+        );
+        assert_eq!(
+            parse_length("clamp(14px, 30px, 20px)").unwrap(),
+            Value::Length(20.0, Unit::Px)
+        );
+        // %とpxは layout時まで解決できない
+        assert_eq!(
+            parse_length("calc(100% - 2rem)").unwrap(),
+            Value::Math(CalcNode::Sum(vec![
+                CalcNode::Percentage(100.0),
+                CalcNode::Length(-2.0, Unit::Rem),
+            ]))
+        );
+        assert_eq!(
+            parse_length("max(10px, 20px, 5%)").unwrap(),
+            Value::Math(CalcNode::Max(vec![
+                CalcNode::Length(20.0, Unit::Px),
+                CalcNode::Percentage(5.0),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_type_check() {
+        // 長さと数値は足せない
+        assert!(parse_length("calc(10px + 2)").is_err());
+        // 長さ同士は掛けられない
+        assert!(parse_length("calc(10px * 2px)").is_err());
+        // 数値で割る必要がある
+        assert!(parse_length("calc(2 / 10px)").is_err());
+        assert!(parse_length("calc(10px / 0)").is_err());
+        // 結果が数値になる式は長さではない
+        assert!(parse_length("calc(2 * 3)").is_err());
+        assert!(parse_length("min(10px, 2)").is_err());
+        assert!(parse_length("clamp(10px, 20px)").is_err());
+        // +と-の前後には空白が必要
+        assert!(parse_length("calc(100%-2rem)").is_err());
+        assert!(parse_length("calc(100% -2rem)").is_err());
+        assert!(parse_length("calc(100%- 2rem)").is_err());
+        assert!(parse_length("calc( 100%\t-\n2rem )").is_ok());
+        assert!(parse_length("calc(( 1px + 2px )*2)").is_ok());
+        assert!(parse_length("min( 10px , calc(1px + 2px) )").is_ok());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let context = context();
+        let width = parse_length("calc(100% - 2rem)").unwrap();
+        assert_eq!(width.resolve(&context), 768.0);
+
+        let font_size = parse_length("clamp(14px, 2vw, 30px)").unwrap();
+        assert_eq!(font_size.resolve(&context), 20.0);
+
+        let nested = parse_length("calc(min(50%, 300px) + 1em)").unwrap();
+        assert_eq!(nested.resolve(&context), 320.0);
+    }
+}
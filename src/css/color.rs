@@ -10,7 +10,7 @@ pub struct Color {
 
 impl Color {
     pub fn new(color_name: &str) -> Option<Self> {
-        if color_name.chars().next().unwrap() == '#' {
+        if color_name.starts_with('#') {
             return Color::hex_to_rgba(color_name);
         }
         Color::default_color_name(color_name)
@@ -25,7 +25,7 @@ impl Color {
         };
         if hex.chars().count() == 3 {
             let mut new_hex = String::from("");
-            for char in hex.chars().into_iter() {
+            for char in hex.chars() {
                 new_hex.push(char);
                 new_hex.push(char);
             }
@@ -56,6 +56,23 @@ impl Color {
         }
     }
 
+    /// RGBそれぞれのcoverageで背景色と混ぜる. sRGBの値のままではなく、
+    /// 光の強さに比例する線形の値で混ぜてからsRGBに戻す
+    pub fn blend_linear(&self, background: Color, coverage: [u8; 3]) -> Color {
+        let channel = |foreground: u8, background: u8, coverage: u8| {
+            let alpha = coverage as f32 / 255.0;
+            let linear =
+                srgb_to_linear(foreground) * alpha + srgb_to_linear(background) * (1.0 - alpha);
+            linear_to_srgb(linear)
+        };
+        Color {
+            r: channel(self.r, background.r, coverage[0]),
+            g: channel(self.g, background.g, coverage[1]),
+            b: channel(self.b, background.b, coverage[2]),
+            a: 255,
+        }
+    }
+
     fn default_color_name(name: &str) -> Option<Color> {
         match name {
             "black" => Some(Color {
@@ -80,7 +97,7 @@ impl Color {
                 r: 0,
                 g: 0,
                 b: 255,
-                a: 0,
+                a: 1,
             }),
 
             "green" => Some(Color {
@@ -110,6 +127,26 @@ fn hex_pair(input: &str) -> u8 {
     u8::from_str_radix(input, 16).unwrap()
 }
 
+// sRGBの値(0から255)を光の強さに比例する0.0から1.0の値にする
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let srgb = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0).round() as u8
+}
+
 fn remove_first_char(input: &str) -> &str {
     let mut chars = input.chars();
     chars.next();
@@ -129,6 +166,13 @@ mod tests {
         assert_eq!(test1.a, 1);
     }
     #[test]
+    fn test_named_color_alpha() {
+        // 名前付きの色は16進数表記と同じく不透明になる
+        for name in ["black", "gray", "white", "blue", "green", "yellow", "red"] {
+            assert_eq!(Color::new(name).unwrap().a, 1, "{}", name);
+        }
+    }
+    #[test]
     fn test_blend() {
         // 完全に透明な文字の場合、背景色がそのまま返される
         let char_color1 = Color::from_rgba(255, 0, 0, 255); // Red
@@ -149,4 +193,18 @@ mod tests {
         let expected_color4 = Color::from_rgba(191, 191, 64, 255); // Blended: Light Gray
         assert_eq!(expected_color4, blended_color4);
     }
+
+    #[test]
+    fn test_blend_linear() {
+        let black = Color::from_rgba(0, 0, 0, 255);
+        let white = Color::from_rgba(255, 255, 255, 255);
+        assert_eq!(black.blend_linear(white, [0, 0, 0]), white);
+        assert_eq!(black.blend_linear(white, [255, 255, 255]), black);
+        // 線形の値で半分混ぜると、sRGBの中間(128)より明るくなる
+        let half = black.blend_linear(white, [128, 128, 128]);
+        assert_eq!(half, Color::from_rgba(187, 187, 187, 255));
+        // subpixelごとに別のcoverageを使う
+        let fringe = black.blend_linear(white, [255, 128, 0]);
+        assert_eq!(fringe, Color::from_rgba(0, 187, 255, 255));
+    }
 }
@@ -0,0 +1,494 @@
+use std::convert::TryInto;
+
+use anyhow::Result;
+use pest::iterators::Pair;
+
+use crate::css::ast::Value;
+use crate::css::parse::Rule;
+
+/// @font-faceで定義されたfont
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFace {
+    pub family: String,
+    // 先頭から順に読み込みを試す
+    pub sources: Vec<FontFaceSource>,
+    // font-weight, font-stretchは可変fontのため範囲で持つ
+    pub weight: (f32, f32),
+    pub style: FontStyle,
+    pub stretch: (f32, f32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontFaceSource {
+    // 相対pathはstylesheetのあるdirectoryを基準にする
+    Url(String, Option<String>),
+    // システムにinstallされたfontの名前
+    Local(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+pub const NORMAL_WEIGHT: f32 = 400.0;
+pub const NORMAL_STRETCH: f32 = 100.0;
+
+impl FontFace {
+    pub fn new(family: &str) -> FontFace {
+        FontFace {
+            family: family.to_string(),
+            sources: vec![],
+            weight: (NORMAL_WEIGHT, NORMAL_WEIGHT),
+            style: FontStyle::Normal,
+            stretch: (NORMAL_STRETCH, NORMAL_STRETCH),
+        }
+    }
+}
+
+// font-familyとsrcがない@font-faceは無視する
+pub fn parse_font_face(rule: Pair<Rule>) -> Option<FontFace> {
+    let mut face = FontFace::new("");
+    for declaration in rule.into_inner() {
+        let mut items = declaration.into_inner();
+        let (property, value) = match (items.next(), items.next()) {
+            (Some(property), Some(value)) => (property.as_str(), value.as_str()),
+            _ => continue,
+        };
+        match property.to_ascii_lowercase().as_str() {
+            "font-family" => face.family = unquote(value).to_string(),
+            "src" => {
+                face.sources = split_list(value)
+                    .into_iter()
+                    .filter_map(parse_source)
+                    .collect()
+            }
+            // 不正なdescriptorは無視して初期値のままにする
+            "font-weight" => {
+                if let Some(weight) = parse_range(value, parse_font_weight) {
+                    face.weight = weight;
+                }
+            }
+            "font-style" => {
+                if let Some(style) = parse_font_style(value) {
+                    face.style = style;
+                }
+            }
+            "font-stretch" => {
+                if let Some(stretch) = parse_range(value, parse_font_stretch) {
+                    face.stretch = stretch;
+                }
+            }
+            _ => {}
+        }
+    }
+    if face.family.is_empty() || face.sources.is_empty() {
+        return None;
+    }
+    Some(face)
+}
+
+fn parse_source(input: &str) -> Option<FontFaceSource> {
+    let (name, argument, rest) = parse_function(input)?;
+    match name.as_str() {
+        "url" => {
+            let format = parse_function(rest.trim())
+                .filter(|(name, _, _)| name == "format")
+                .map(|(_, format, _)| format);
+            Some(FontFaceSource::Url(argument, format))
+        }
+        "local" => Some(FontFaceSource::Local(argument)),
+        _ => None,
+    }
+}
+
+// `url("a.ttf") format("truetype")`から関数名、引数、残りの文字列を取り出す
+fn parse_function(input: &str) -> Option<(String, String, &str)> {
+    let open = input.find('(')?;
+    let close = input.find(')')?;
+    if close < open {
+        return None;
+    }
+    let name = input[..open].trim().to_ascii_lowercase();
+    let argument = unquote(input[open + 1..close].trim()).to_string();
+    Some((name, argument, &input[close + 1..]))
+}
+
+fn parse_range(input: &str, parse: fn(&str) -> Option<f32>) -> Option<(f32, f32)> {
+    let values: Vec<f32> = input
+        .split_whitespace()
+        .map(parse)
+        .collect::<Option<Vec<f32>>>()?;
+    match values.as_slice() {
+        [value] => Some((*value, *value)),
+        [min, max] => Some((min.min(*max), min.max(*max))),
+        _ => None,
+    }
+}
+
+pub fn parse_font_weight(input: &str) -> Option<f32> {
+    match input {
+        "normal" => Some(NORMAL_WEIGHT),
+        "bold" => Some(700.0),
+        _ => input
+            .parse::<f32>()
+            .ok()
+            .filter(|weight| (1.0..=1000.0).contains(weight)),
+    }
+}
+
+// obliqueの角度は使わない
+pub fn parse_font_style(input: &str) -> Option<FontStyle> {
+    match input.split_whitespace().next()? {
+        "normal" => Some(FontStyle::Normal),
+        "italic" => Some(FontStyle::Italic),
+        "oblique" => Some(FontStyle::Oblique),
+        _ => None,
+    }
+}
+
+pub fn parse_font_stretch(input: &str) -> Option<f32> {
+    match input {
+        "ultra-condensed" => Some(50.0),
+        "extra-condensed" => Some(62.5),
+        "condensed" => Some(75.0),
+        "semi-condensed" => Some(87.5),
+        "normal" => Some(NORMAL_STRETCH),
+        "semi-expanded" => Some(112.5),
+        "expanded" => Some(125.0),
+        "extra-expanded" => Some(150.0),
+        "ultra-expanded" => Some(200.0),
+        _ => input
+            .strip_suffix('%')?
+            .parse::<f32>()
+            .ok()
+            .filter(|stretch| *stretch >= 0.0),
+    }
+}
+
+/// font-weightの値. 数値はNumber, それ以外はKeywordになる
+pub fn parse_font_weight_value(input: &str) -> Result<Value> {
+    match input {
+        "normal" | "bold" | "bolder" | "lighter" => Ok(Value::Keyword(input.to_string())),
+        _ => parse_font_weight(input)
+            .map(Value::Number)
+            .ok_or_else(|| anyhow::anyhow!("invalid font-weight {}", input)),
+    }
+}
+
+pub fn parse_font_style_value(input: &str) -> Result<Value> {
+    match parse_font_style(input) {
+        Some(_) => Ok(Value::Keyword(
+            input.split_whitespace().collect::<Vec<&str>>().join(" "),
+        )),
+        None => Err(anyhow::anyhow!("invalid font-style {}", input)),
+    }
+}
+
+pub fn parse_font_stretch_value(input: &str) -> Result<Value> {
+    match (parse_font_stretch(input), input.ends_with('%')) {
+        (Some(stretch), true) => Ok(Value::Percentage(stretch)),
+        (Some(_), false) => Ok(Value::Keyword(input.to_string())),
+        (None, _) => Err(anyhow::anyhow!("invalid font-stretch {}", input)),
+    }
+}
+
+/// font-weightを数値にする. bolder, lighterは親要素のweightを基準にする
+pub fn font_weight(value: &Value, parent: f32) -> f32 {
+    match value {
+        Value::Number(weight) => *weight,
+        Value::Keyword(keyword) => match keyword.as_str() {
+            "bold" => 700.0,
+            // https://www.w3.org/TR/css-fonts-4/#relative-weights
+            "bolder" => match parent {
+                w if w < 350.0 => 400.0,
+                w if w < 550.0 => 700.0,
+                w if w < 900.0 => 900.0,
+                w => w,
+            },
+            "lighter" => match parent {
+                w if w < 100.0 => w,
+                w if w < 550.0 => 100.0,
+                w if w < 750.0 => 400.0,
+                _ => 700.0,
+            },
+            _ => NORMAL_WEIGHT,
+        },
+        _ => NORMAL_WEIGHT,
+    }
+}
+
+pub fn font_style(value: &Value) -> FontStyle {
+    match value {
+        Value::Keyword(keyword) => parse_font_style(keyword).unwrap_or(FontStyle::Normal),
+        _ => FontStyle::Normal,
+    }
+}
+
+pub fn font_stretch(value: &Value) -> f32 {
+    match value {
+        Value::Percentage(stretch) => *stretch,
+        Value::Keyword(keyword) => parse_font_stretch(keyword).unwrap_or(NORMAL_STRETCH),
+        _ => NORMAL_STRETCH,
+    }
+}
+
+/// `"Noto Sans", Roboto, sans-serif`をfamily名のリストにする.
+/// 引用符で囲まれた名前はString, それ以外はKeywordになる
+pub fn parse_font_family(input: &str) -> Result<Value> {
+    let mut families = vec![];
+    for family in split_list(input) {
+        let unquoted = unquote(family);
+        if unquoted.len() != family.len() {
+            families.push(Value::String(unquoted.to_string()));
+            continue;
+        }
+        // 引用符のない名前の連続した空白は1つにまとめる
+        let words: Vec<&str> = family.split_whitespace().collect();
+        if words.is_empty() {
+            return Err(anyhow::anyhow!("invalid font-family {}", input));
+        }
+        families.push(Value::Keyword(words.join(" ")));
+    }
+    Ok(Value::List(families))
+}
+
+/// font-familyのValueからfamily名を取り出す
+pub fn font_families(value: &Value) -> Vec<String> {
+    match value {
+        Value::List(families) => families.iter().flat_map(font_families).collect(),
+        Value::Keyword(family) | Value::String(family) => vec![family.clone()],
+        _ => vec![],
+    }
+}
+
+/// font-feature-settings. `"liga" 0, "smcp"`をfeatureのtagと値のリストにする.
+/// 値を省略した場合とonは1、offは0になる
+pub fn parse_font_feature_settings(input: &str) -> Option<Vec<([u8; 4], u32)>> {
+    if input.trim() == "normal" {
+        return Some(vec![]);
+    }
+    split_list(input)
+        .into_iter()
+        .map(|setting| {
+            let quote = setting.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let end = setting[1..].find(quote)? + 1;
+            let tag: [u8; 4] = setting.as_bytes()[1..end].try_into().ok()?;
+            if !tag.iter().all(|byte| (0x20..=0x7E).contains(byte)) {
+                return None;
+            }
+            let value = match setting[end + 1..].trim() {
+                "" | "on" => 1,
+                "off" => 0,
+                number => number.parse::<u32>().ok()?,
+            };
+            Some((tag, value))
+        })
+        .collect()
+}
+
+/// font-variant-ligaturesをfeatureの指定にする. normalは既定のfeatureのままにする
+pub fn parse_font_variant_ligatures(input: &str) -> Option<Vec<([u8; 4], u32)>> {
+    let keywords: Vec<&str> = input.split_whitespace().collect();
+    match keywords.as_slice() {
+        [] => return None,
+        ["normal"] => return Some(vec![]),
+        ["none"] => {
+            let tags = [b"liga", b"clig", b"dlig", b"hlig", b"calt"];
+            return Some(tags.iter().map(|tag| (**tag, 0)).collect());
+        }
+        _ => {}
+    }
+    let mut features = vec![];
+    let mut names = vec![];
+    for keyword in keywords {
+        let (name, value) = match keyword.strip_prefix("no-") {
+            Some(name) => (name, 0),
+            None => (keyword, 1),
+        };
+        let tags: &[&[u8; 4]] = match name {
+            "common-ligatures" => &[b"liga", b"clig"],
+            "discretionary-ligatures" => &[b"dlig"],
+            "historical-ligatures" => &[b"hlig"],
+            "contextual" => &[b"calt"],
+            _ => return None,
+        };
+        // 同じ種類の指定は1つだけ
+        if names.contains(&name) {
+            return None;
+        }
+        names.push(name);
+        features.extend(tags.iter().map(|tag| (**tag, value)));
+    }
+    Some(features)
+}
+
+pub fn parse_font_feature_settings_value(input: &str) -> Result<Value> {
+    match parse_font_feature_settings(input) {
+        Some(_) => Ok(Value::Keyword(input.trim().to_string())),
+        None => Err(anyhow::anyhow!("invalid font-feature-settings {}", input)),
+    }
+}
+
+pub fn parse_font_variant_ligatures_value(input: &str) -> Result<Value> {
+    match parse_font_variant_ligatures(input) {
+        Some(_) => Ok(Value::Keyword(
+            input.split_whitespace().collect::<Vec<&str>>().join(" "),
+        )),
+        None => Err(anyhow::anyhow!("invalid font-variant-ligatures {}", input)),
+    }
+}
+
+pub fn font_feature_settings(value: &Value) -> Vec<([u8; 4], u32)> {
+    match value {
+        Value::Keyword(keyword) => parse_font_feature_settings(keyword).unwrap_or_default(),
+        _ => vec![],
+    }
+}
+
+pub fn font_variant_ligatures(value: &Value) -> Vec<([u8; 4], u32)> {
+    match value {
+        Value::Keyword(keyword) => parse_font_variant_ligatures(keyword).unwrap_or_default(),
+        _ => vec![],
+    }
+}
+
+// 引用符と括弧の外にあるカンマで区切る
+pub(crate) fn split_list(input: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let mut quote = None;
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, char) in input.char_indices() {
+        match (quote, char) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(char),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                items.push(input[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(input[start..].trim());
+    items
+}
+
+fn unquote(input: &str) -> &str {
+    let quoted = input.len() >= 2
+        && ((input.starts_with('"') && input.ends_with('"'))
+            || (input.starts_with('\'') && input.ends_with('\'')));
+    if quoted {
+        &input[1..input.len() - 1]
+    } else {
+        input
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_font_family() {
+        let value = parse_font_family("\"Noto Sans JP\", Times  New Roman,sans-serif").unwrap();
+        assert_eq!(
+            value,
+            Value::List(vec![
+                Value::String(String::from("Noto Sans JP")),
+                Value::Keyword(String::from("Times New Roman")),
+                Value::Keyword(String::from("sans-serif")),
+            ])
+        );
+        assert_eq!(
+            font_families(&value),
+            vec!["Noto Sans JP", "Times New Roman", "sans-serif"]
+        );
+        assert!(parse_font_family("Roboto,").is_err());
+    }
+
+    #[test]
+    fn test_font_weight() {
+        let weight = |input: &str, parent: f32| {
+            font_weight(&parse_font_weight_value(input).unwrap(), parent)
+        };
+        assert_eq!(weight("bold", 100.0), 700.0);
+        assert_eq!(weight("350", 100.0), 350.0);
+        assert_eq!(weight("bolder", 300.0), 400.0);
+        assert_eq!(weight("bolder", 400.0), 700.0);
+        assert_eq!(weight("bolder", 950.0), 950.0);
+        assert_eq!(weight("lighter", 700.0), 400.0);
+        assert_eq!(weight("lighter", 900.0), 700.0);
+        assert!(parse_font_weight_value("heavy").is_err());
+        assert_eq!(
+            font_stretch(&parse_font_stretch_value("semi-expanded").unwrap()),
+            112.5
+        );
+        assert_eq!(
+            font_style(&parse_font_style_value("oblique  20deg").unwrap()),
+            FontStyle::Oblique
+        );
+    }
+
+    #[test]
+    fn test_parse_source() {
+        assert_eq!(
+            parse_source("url(\"fonts/a b.ttf\") format('truetype')"),
+            Some(FontFaceSource::Url(
+                String::from("fonts/a b.ttf"),
+                Some(String::from("truetype"))
+            ))
+        );
+        assert_eq!(
+            parse_source("local(Roboto Bold)"),
+            Some(FontFaceSource::Local(String::from("Roboto Bold")))
+        );
+        assert_eq!(parse_source("foo"), None);
+    }
+
+    #[test]
+    fn test_parse_descriptor() {
+        assert_eq!(parse_range("bold", parse_font_weight), Some((700.0, 700.0)));
+        assert_eq!(
+            parse_range("900 100", parse_font_weight),
+            Some((100.0, 900.0))
+        );
+        assert_eq!(parse_range("1001", parse_font_weight), None);
+        assert_eq!(parse_font_stretch("condensed"), Some(75.0));
+        assert_eq!(parse_font_stretch("80%"), Some(80.0));
+        assert_eq!(parse_font_style("oblique 10deg"), Some(FontStyle::Oblique));
+    }
+
+    #[test]
+    fn test_font_feature_settings() {
+        assert_eq!(
+            parse_font_feature_settings("\"liga\" 0, 'smcp', \"salt\" 3, \"kern\" off"),
+            Some(vec![
+                (*b"liga", 0),
+                (*b"smcp", 1),
+                (*b"salt", 3),
+                (*b"kern", 0)
+            ])
+        );
+        assert_eq!(parse_font_feature_settings("normal"), Some(vec![]));
+        // tagは引用符で囲んだ4文字
+        assert_eq!(parse_font_feature_settings("liga"), None);
+        assert_eq!(parse_font_feature_settings("\"lig\""), None);
+        assert_eq!(parse_font_feature_settings("\"liga\" -1"), None);
+
+        assert_eq!(
+            parse_font_variant_ligatures("no-common-ligatures discretionary-ligatures"),
+            Some(vec![(*b"liga", 0), (*b"clig", 0), (*b"dlig", 1)])
+        );
+        assert_eq!(parse_font_variant_ligatures("none").unwrap().len(), 5);
+        assert_eq!(
+            parse_font_variant_ligatures("contextual no-contextual"),
+            None
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use pest::iterators::Pair;
+
+use crate::css::ast::{LengthContext, Value};
+use crate::css::calc::parse_length;
+use crate::css::parse::Rule;
+
+/// @mediaの評価に使う描画先の情報
+#[derive(Debug, Clone, Copy)]
+pub struct MediaContext {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl MediaContext {
+    // media featureのemは初期値のfont-sizeを基準にする
+    fn length_context(&self) -> LengthContext {
+        LengthContext {
+            percentage_basis: 0.0,
+            font_size: 16.0,
+            root_font_size: 16.0,
+            viewport_width: self.width,
+            viewport_height: self.height,
+        }
+    }
+}
+
+/// `screen and (min-width: 600px), print`のようにカンマで区切られたquery. いずれかに一致すればよい
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQueryList(pub Vec<MediaQuery>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    pub negated: bool,
+    pub media_type: Option<String>,
+    pub features: Vec<MediaFeature>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaFeature {
+    Width(Value),
+    MinWidth(Value),
+    MaxWidth(Value),
+    Height(Value),
+    MinHeight(Value),
+    MaxHeight(Value),
+    Orientation(String),
+    // 対応していないfeatureは一致しない
+    Unknown(String),
+}
+
+impl MediaQueryList {
+    pub fn matches(&self, context: &MediaContext) -> bool {
+        self.0.iter().any(|query| query.matches(context))
+    }
+}
+
+impl MediaQuery {
+    pub fn matches(&self, context: &MediaContext) -> bool {
+        // 画像として出力するためscreenとして扱う
+        let type_matches = match &self.media_type {
+            Some(media_type) => matches!(media_type.as_str(), "all" | "screen"),
+            None => true,
+        };
+        let matched = type_matches && self.features.iter().all(|f| f.matches(context));
+        matched != self.negated
+    }
+}
+
+impl MediaFeature {
+    pub fn matches(&self, context: &MediaContext) -> bool {
+        let length = context.length_context();
+        match self {
+            MediaFeature::Width(v) => context.width == v.resolve(&length),
+            MediaFeature::MinWidth(v) => context.width >= v.resolve(&length),
+            MediaFeature::MaxWidth(v) => context.width <= v.resolve(&length),
+            MediaFeature::Height(v) => context.height == v.resolve(&length),
+            MediaFeature::MinHeight(v) => context.height >= v.resolve(&length),
+            MediaFeature::MaxHeight(v) => context.height <= v.resolve(&length),
+            MediaFeature::Orientation(orientation) => match orientation.as_str() {
+                "portrait" => context.height >= context.width,
+                "landscape" => context.width > context.height,
+                _ => false,
+            },
+            MediaFeature::Unknown(_) => false,
+        }
+    }
+}
+
+pub fn parse_media_query_list(rule: Pair<Rule>) -> MediaQueryList {
+    MediaQueryList(rule.into_inner().map(parse_media_query).collect())
+}
+
+fn parse_media_query(rule: Pair<Rule>) -> MediaQuery {
+    let mut query = MediaQuery {
+        negated: false,
+        media_type: None,
+        features: vec![],
+    };
+    for item in rule.into_inner() {
+        match item.as_rule() {
+            Rule::media_modifier => query.negated = item.as_str().eq_ignore_ascii_case("not"),
+            Rule::media_type => query.media_type = Some(item.as_str().to_ascii_lowercase()),
+            Rule::media_feature => query.features.push(parse_media_feature(item)),
+            _ => {}
+        }
+    }
+    query
+}
+
+fn parse_media_feature(rule: Pair<Rule>) -> MediaFeature {
+    let mut name = String::from("");
+    let mut value = String::from("");
+    for item in rule.into_inner() {
+        match item.as_rule() {
+            Rule::media_feature_name => name = item.as_str().to_ascii_lowercase(),
+            Rule::media_feature_value => value = item.as_str().trim().to_string(),
+            _ => {}
+        }
+    }
+    let length = parse_length(&value);
+    match (name.as_str(), length) {
+        ("width", Ok(v)) => MediaFeature::Width(v),
+        ("min-width", Ok(v)) => MediaFeature::MinWidth(v),
+        ("max-width", Ok(v)) => MediaFeature::MaxWidth(v),
+        ("height", Ok(v)) => MediaFeature::Height(v),
+        ("min-height", Ok(v)) => MediaFeature::MinHeight(v),
+        ("max-height", Ok(v)) => MediaFeature::MaxHeight(v),
+        ("orientation", _) => MediaFeature::Orientation(value.to_ascii_lowercase()),
+        _ => MediaFeature::Unknown(name),
+    }
+}
@@ -1,9 +1,32 @@
 mod ast;
+mod border;
+mod calc;
 mod color;
+mod font;
+mod media;
 mod parse;
 mod property;
+mod serialize;
+mod text;
 
-pub use crate::css::ast::{Block, Declaration, Selector, Specificity, StyleSheet, Unit, Value};
+pub use crate::css::ast::{
+    Block, Combinator, Declaration, LengthContext, PseudoClass, Selector, Specificity, StyleSheet,
+    Unit, Value,
+};
+pub use crate::css::border::{
+    border_radius, border_side, border_style, corner_radius, BorderStyle,
+};
+pub use crate::css::calc::CalcNode;
 pub use crate::css::color::Color;
-pub use crate::css::parse::parse_css;
+pub use crate::css::font::{
+    font_families, font_feature_settings, font_stretch, font_style, font_variant_ligatures,
+    font_weight, FontFace, FontFaceSource, FontStyle, NORMAL_WEIGHT,
+};
+pub use crate::css::media::{MediaContext, MediaFeature, MediaQuery, MediaQueryList};
+pub use crate::css::parse::{parse_css, try_parse_css};
 pub use crate::css::property::{property_type, Property};
+pub use crate::css::serialize::{CssFormat, ToCss};
+pub use crate::css::text::{
+    text_decoration, text_shadows, text_underline_position, DecorationStyle, TextDecoration,
+    TextShadow, UnderlinePosition, UnderlineSide,
+};
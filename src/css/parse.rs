@@ -1,13 +1,16 @@
-use std::result;
-
+use anyhow::Result;
 use pest::iterators::Pair;
 use pest::Parser;
 
+use crate::css::ast::{Block, Combinator, Declaration, PseudoClass, Selector, StyleSheet};
+use crate::css::font::parse_font_face;
+use crate::css::media::{parse_media_query_list, MediaQueryList};
+#[cfg(test)]
 use crate::{
-    css::ast::{Block, Declaration, Selector, StyleSheet, Unit, Value},
+    css::ast::{Unit, Value},
     css::color::Color,
     css::property::Property,
-    html::{element_type, Element, ElementType},
+    html::ElementType,
 };
 
 #[derive(Parser)]
@@ -15,74 +18,298 @@ use crate::{
 pub struct CSSParser;
 
 pub fn parse_css(input: &str) -> StyleSheet {
-    let parser = CSSParser::parse(Rule::css, input).unwrap();
+    try_parse_css(input).unwrap()
+}
+
+pub fn try_parse_css(input: &str) -> Result<StyleSheet> {
+    let parser = CSSParser::parse(Rule::css, input)?;
     let mut css = StyleSheet::new();
     for line in parser.into_iter() {
-        match line.as_rule() {
-            Rule::block => {
-                let block = parse_block(line.as_str());
-                css.append_block(block);
+        if line.as_rule() == Rule::font_face {
+            if let Some(font_face) = parse_font_face(line) {
+                css.append_font_face(font_face);
             }
-            _ => {}
+            continue;
+        }
+        let blocks = match line.as_rule() {
+            Rule::block => parse_style_block(line, None, &[]),
+            Rule::media => parse_media_block(line, None, &[]),
+            _ => vec![],
+        };
+        for block in blocks {
+            css.append_block(block);
         }
     }
-    css
+    Ok(css)
 }
 
+// parseとserializeのテストで共有するCSS
+#[cfg(test)]
+pub(crate) const CSS_HEADING: &str = "
+h1 {
+    font-size: 50px;
+}
+";
+#[cfg(test)]
+pub(crate) const CSS_MULTILINE_DECLARATIONS: &str = "
+span {
+    display: inline-block;
+    background-color: black;
+}
+";
+#[cfg(test)]
+pub(crate) const CSS_TOY_ENGINE: &str = "
+h1, h2, h3 { margin: auto; color: #cc0000; }
+div.note { margin-bottom: 20px; padding: 10px; }
+#answer { display: none; }
+";
+#[cfg(test)]
+pub(crate) const CSS_MATH_FUNCTION: &str = "
+.container {
+    width: calc(100% - 2rem);
+    font-size: clamp(14px, 2vw, 20px);
+    padding: calc(2px * 3);
+    height: calc(10px + 2);
+}
+";
+#[cfg(test)]
+pub(crate) const CSS_NESTING: &str = "
+.card {
+    color: red;
+    & > h2 { color: blue; }
+    &:hover { color: green; }
+    p { margin: 2px; }
+    @media (min-width: 600px) {
+        padding: 4px;
+        .title & { padding: 8px; }
+    }
+    background-color: black;
+}
+";
+#[cfg(test)]
+pub(crate) const CSS_MEDIA: &str = "
+@media screen and (max-width: 600px), print {
+    .a { color: red; }
+    @media (orientation: landscape) {
+        .b { color: blue; }
+    }
+}
+";
+
+#[cfg(test)]
 fn parse_block(input: &str) -> Block {
     let parser = CSSParser::parse(Rule::block, input).unwrap();
     let mut block = Block::new();
     for line in parser.into_iter() {
-        match line.as_rule() {
-            Rule::block => {
-                block = parse_style_block(line);
-            }
-            _ => {}
+        if line.as_rule() == Rule::block {
+            block = parse_style_block(line, None, &[]).remove(0);
         }
     }
     block
 }
 
-fn parse_style_block(rule: Pair<Rule>) -> Block {
+/*
+ネストしたstyle ruleは平坦なBlockに展開する.
+- 子のselectorの`&`は`:is(親のselector)`に置き換える
+- `&`を含まない子のselectorは`& h2`のように親の子孫として扱う
+- ネストしたruleより後に書かれたdeclarationは別のBlockにして出現順を保つ
+*/
+fn parse_style_block(
+    rule: Pair<Rule>,
+    parent: Option<&[Selector]>,
+    media: &[MediaQueryList],
+) -> Vec<Block> {
+    let items: Vec<Pair<Rule>> = rule.into_inner().collect();
+    let selectors: Vec<Selector> = items
+        .iter()
+        .filter(|item| item.as_rule() == Rule::selector)
+        .map(|item| parse_selector(item.clone(), parent))
+        .collect();
     let mut block = Block::new();
-    for line in rule.into_inner().into_iter() {
-        match line.as_rule() {
-            Rule::selector => {
-                let mut selector = Selector::new();
-                for selector_line in line.into_inner().into_iter() {
-                    match selector_line.as_rule() {
-                        // id, classは先頭の文字(. #)を取り除く
-                        Rule::id => selector.set_id(&selector_line.as_str()[1..]),
-                        Rule::class => selector.set_class(&selector_line.as_str()[1..]),
-                        Rule::element => selector.set_element(selector_line.as_str().trim()),
-                        _ => {
-                            println!("other {:?}", selector_line);
-                        }
-                    }
-                }
-                block.set_selector(selector);
-            }
+    for selector in &selectors {
+        block.set_selector(selector.clone());
+    }
+    block.media = media.to_vec();
+    let mut blocks = vec![block];
+    parse_rule_body(items, Some(&selectors), media, &mut blocks, Some(0));
+    blocks
+}
+
+// @mediaの中のdeclarationは親のstyle ruleのselectorに対して適用する
+fn parse_media_block(
+    rule: Pair<Rule>,
+    parent: Option<&[Selector]>,
+    media: &[MediaQueryList],
+) -> Vec<Block> {
+    let mut media = media.to_vec();
+    let mut items = vec![];
+    for item in rule.into_inner() {
+        match item.as_rule() {
+            Rule::media_query_list => media.push(parse_media_query_list(item)),
+            _ => items.push(item),
+        }
+    }
+    let mut blocks = vec![];
+    parse_rule_body(items, parent, &media, &mut blocks, None);
+    blocks
+}
+
+// current: declarationを追加するBlockのindex
+fn parse_rule_body(
+    items: Vec<Pair<Rule>>,
+    selectors: Option<&[Selector]>,
+    media: &[MediaQueryList],
+    blocks: &mut Vec<Block>,
+    mut current: Option<usize>,
+) {
+    for item in items {
+        match item.as_rule() {
             Rule::declaration => {
-                let mut property: &str = "";
-                let mut value: &str = "";
-                for line_declaration in line.into_inner().into_iter() {
-                    match line_declaration.as_rule() {
-                        Rule::property => {
-                            property = line_declaration.as_str();
-                        }
-                        Rule::value => {
-                            value = line_declaration.as_str();
-                        }
-                        _ => {}
+                let (selectors, declaration) = match (selectors, parse_declaration(item)) {
+                    (Some(selectors), Some(declaration)) => (selectors, declaration),
+                    _ => continue,
+                };
+                let index = match current {
+                    Some(index) => index,
+                    None => {
+                        let mut block = Block::new();
+                        block.selectors = selectors.to_vec();
+                        block.media = media.to_vec();
+                        blocks.push(block);
+                        blocks.len() - 1
                     }
+                };
+                blocks[index].set_declaration(declaration);
+                current = Some(index);
+            }
+            Rule::block => {
+                blocks.extend(parse_style_block(item, selectors, media));
+                current = None;
+            }
+            Rule::media => {
+                blocks.extend(parse_media_block(item, selectors, media));
+                current = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_declaration(rule: Pair<Rule>) -> Option<Declaration> {
+    let mut property: &str = "";
+    let mut value: &str = "";
+    for line_declaration in rule.into_inner() {
+        match line_declaration.as_rule() {
+            Rule::property => {
+                property = line_declaration.as_str();
+            }
+            Rule::value => {
+                value = line_declaration.as_str();
+            }
+            _ => {}
+        }
+    }
+    // 型が合わないなど無効な宣言は無視する
+    Declaration::new(property, value).ok()
+}
+
+// ネストしたruleで`&`を含まないselectorは親のselectorからの相対指定になる
+fn parse_selector(rule: Pair<Rule>, parent: Option<&[Selector]>) -> Selector {
+    let (mut selector, has_nesting, leading) = build_selector(rule, parent);
+    if let (Some(parent), false) = (parent, has_nesting) {
+        let mut nesting = Selector::new();
+        nesting.set_pseudo_class(PseudoClass::Is(parent.to_vec()));
+        leftmost(&mut selector).combinator =
+            Some((leading.unwrap_or(Combinator::Descendant), Box::new(nesting)));
+    }
+    selector
+}
+
+fn leftmost(selector: &mut Selector) -> &mut Selector {
+    match selector.combinator {
+        Some((_, ref mut left)) => leftmost(left),
+        None => selector,
+    }
+}
+
+// selectorと、`&`を含むかどうか、先頭の結合子を返す
+fn build_selector(
+    rule: Pair<Rule>,
+    parent: Option<&[Selector]>,
+) -> (Selector, bool, Option<Combinator>) {
+    let mut selector = Selector::new();
+    let mut has_nesting = false;
+    let mut leading = None;
+    for (index, selector_line) in rule.into_inner().enumerate() {
+        match selector_line.as_rule() {
+            // id, classは先頭の文字(. #)を取り除く
+            Rule::id => selector.set_id(&selector_line.as_str()[1..]),
+            Rule::class => selector.set_class(&selector_line.as_str()[1..]),
+            Rule::element => selector.set_element(selector_line.as_str().trim()),
+            Rule::nesting => {
+                has_nesting = true;
+                selector.set_pseudo_class(match parent {
+                    Some(parent) => PseudoClass::Is(parent.to_vec()),
+                    // ネストしていない場合の&は:scope(root要素)を表す
+                    None => PseudoClass::Other(String::from("scope")),
+                });
+            }
+            Rule::pseudo_class => {
+                let (pseudo_class, nested) = parse_pseudo_class(selector_line, parent);
+                has_nesting |= nested;
+                selector.set_pseudo_class(pseudo_class);
+            }
+            Rule::combinator => {
+                let combinator = combinator_type(selector_line.as_str());
+                if index == 0 {
+                    leading = Some(combinator);
+                } else {
+                    let left = std::mem::replace(&mut selector, Selector::new());
+                    selector.combinator = Some((combinator, Box::new(left)));
                 }
-                let declaration = Declaration::new(property, value).unwrap();
-                block.set_declaration(declaration);
             }
             _ => {}
         }
     }
-    block
+    (selector, has_nesting, leading)
+}
+
+fn parse_pseudo_class(rule: Pair<Rule>, parent: Option<&[Selector]>) -> (PseudoClass, bool) {
+    let item = match rule.into_inner().next() {
+        Some(item) => item,
+        None => return (PseudoClass::Other(String::from("")), false),
+    };
+    match item.as_rule() {
+        Rule::is_function => {
+            let mut has_nesting = false;
+            let selectors = item
+                .into_inner()
+                .map(|selector| {
+                    let (selector, nested, _) = build_selector(selector, parent);
+                    has_nesting |= nested;
+                    selector
+                })
+                .collect();
+            (PseudoClass::Is(selectors), has_nesting)
+        }
+        _ => {
+            let pseudo_class = match item.as_str().to_ascii_lowercase().as_str() {
+                "first-child" => PseudoClass::FirstChild,
+                "last-child" => PseudoClass::LastChild,
+                name => PseudoClass::Other(name.to_string()),
+            };
+            (pseudo_class, false)
+        }
+    }
+}
+
+fn combinator_type(input: &str) -> Combinator {
+    match input.trim() {
+        ">" => Combinator::Child,
+        "+" => Combinator::NextSibling,
+        "~" => Combinator::SubsequentSibling,
+        _ => Combinator::Descendant,
+    }
 }
 
 #[test]
@@ -138,13 +365,7 @@ fn test_css_parse() {
 #[test]
 fn test_css_parse_2() {
     // 改行がある
-    let result1 = parse_css(
-        "
-h1 {
-    font-size: 50px;
-}
-",
-    );
+    let result1 = parse_css(CSS_HEADING);
     let block1 = result1.blocks.get(0).unwrap();
     let selector1 = block1.selectors.get(0).unwrap();
     assert_eq!(selector1.element, Some(ElementType::H1));
@@ -153,14 +374,7 @@ h1 {
     assert_eq!(dec1.value, Value::Length(50.0, Unit::Px));
 
     // 複数行の宣言がある
-    let result2 = parse_css(
-        "
-span {
-    display: inline-block;
-    background-color: black;
-}
-",
-    );
+    let result2 = parse_css(CSS_MULTILINE_DECLARATIONS);
     let block2 = result2.blocks.get(0).unwrap();
     let selector2 = block2.selectors.get(0).unwrap();
     assert_eq!(selector2.element, Some(ElementType::Span));
@@ -184,13 +398,7 @@ span {
 fn test_css_parse_3() {
     // https://limpet.net/mbrubeck/2014/08/13/toy-layout-engine-3-css.html
     // のパターンに対応する
-    let result1 = parse_css(
-        "
-h1, h2, h3 { margin: auto; color: #cc0000; }
-div.note { margin-bottom: 20px; padding: 10px; }
-#answer { display: none; }
-",
-    );
+    let result1 = parse_css(CSS_TOY_ENGINE);
     let block1 = result1.blocks.get(0).unwrap();
     let sel1_1 = block1.selectors.get(0).unwrap();
     assert_eq!(sel1_1.element, Some(ElementType::H1));
@@ -314,3 +522,98 @@ fn test_pest_parser_selector() {
         assert_eq!(elem2.as_str(), "div");
     }
 }
+
+#[test]
+fn test_css_parse_math_function() {
+    let result1 = parse_css(CSS_MATH_FUNCTION);
+    let block1 = result1.blocks.first().unwrap();
+    let dec1_1 = block1.declarations.first().unwrap();
+    assert_eq!(dec1_1.property, Property::Width);
+    assert!(matches!(dec1_1.value, Value::Math(_)));
+    let dec1_2 = block1.declarations.get(1).unwrap();
+    assert_eq!(dec1_2.property, Property::FontSize);
+    assert!(matches!(dec1_2.value, Value::Math(_)));
+    // 単位が揃っている式はparse時に計算される
+    let dec1_3 = block1.declarations.get(2).unwrap();
+    assert_eq!(dec1_3.property, Property::Padding);
+    assert_eq!(dec1_3.value, Value::Length(6.0, Unit::Px));
+    // 型が合わない宣言は無視される
+    assert_eq!(block1.declarations.len(), 3);
+}
+
+#[test]
+fn test_css_parse_nesting() {
+    let result1 = parse_css(CSS_NESTING);
+    // ネストしたruleは出現順に平坦なBlockへ展開される
+    assert_eq!(result1.blocks.len(), 7);
+    let parent = result1.blocks.first().unwrap();
+    assert_eq!(*parent.selectors[0].class.first().unwrap(), "card");
+    assert_eq!(parent.declarations.len(), 1);
+    let card = parent.selectors.clone();
+
+    // & > h2 は :is(.card) > h2 になる
+    let child = &result1.blocks[1].selectors[0];
+    assert_eq!(child.element, Some(ElementType::H2));
+    let (combinator, left) = child.combinator.as_ref().unwrap();
+    assert_eq!(*combinator, Combinator::Child);
+    assert_eq!(left.pseudo_classes, vec![PseudoClass::Is(card.clone())]);
+    assert_eq!(child.specificity(), (0, 1, 1));
+
+    // &:hover は :is(.card):hover になる
+    let hover = &result1.blocks[2].selectors[0];
+    assert_eq!(
+        hover.pseudo_classes,
+        vec![
+            PseudoClass::Is(card.clone()),
+            PseudoClass::Other(String::from("hover"))
+        ]
+    );
+    assert_eq!(hover.specificity(), (0, 2, 0));
+
+    // &を含まない場合は親の子孫になる
+    let descendant = &result1.blocks[3].selectors[0];
+    assert_eq!(descendant.element, Some(ElementType::P));
+    let (combinator, left) = descendant.combinator.as_ref().unwrap();
+    assert_eq!(*combinator, Combinator::Descendant);
+    assert_eq!(left.pseudo_classes, vec![PseudoClass::Is(card.clone())]);
+
+    // @mediaの中のdeclarationは親のselectorに適用される
+    let media = &result1.blocks[4];
+    assert_eq!(media.selectors, card);
+    assert_eq!(media.media.len(), 1);
+    assert_eq!(
+        media.declarations.first().unwrap().value,
+        Value::Length(4.0, Unit::Px)
+    );
+    let media_nested = &result1.blocks[5];
+    assert_eq!(media_nested.media.len(), 1);
+    assert_eq!(
+        media_nested.selectors[0].pseudo_classes,
+        vec![PseudoClass::Is(card.clone())]
+    );
+    let (combinator, left) = media_nested.selectors[0].combinator.as_ref().unwrap();
+    assert_eq!(*combinator, Combinator::Descendant);
+    assert_eq!(*left.class.first().unwrap(), "title");
+
+    // ネストしたruleより後のdeclarationも順序を保つ
+    let trailing = &result1.blocks[6];
+    assert_eq!(trailing.selectors, card);
+    assert!(trailing.media.is_empty());
+    assert_eq!(
+        trailing.declarations.first().unwrap().property,
+        Property::BackgroundColor
+    );
+}
+
+#[test]
+fn test_css_parse_media() {
+    let result1 = parse_css(CSS_MEDIA);
+    assert_eq!(result1.blocks.len(), 2);
+    let block1 = result1.blocks.first().unwrap();
+    assert_eq!(*block1.selectors[0].class.first().unwrap(), "a");
+    assert_eq!(block1.media.len(), 1);
+    assert_eq!(block1.media[0].0.len(), 2);
+    // ネストした@mediaは条件が追加される
+    let block2 = &result1.blocks[1];
+    assert_eq!(block2.media.len(), 2);
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Property {
     Color,
@@ -8,22 +10,138 @@ pub enum Property {
     MarginRight,
     MarginBottom,
     Padding,
+    PaddingTop,
+    PaddingRight,
+    PaddingBottom,
+    PaddingLeft,
+    BorderWidth,
+    BorderTopWidth,
+    BorderRightWidth,
+    BorderBottomWidth,
+    BorderLeftWidth,
+    BorderStyle,
+    BorderTopStyle,
+    BorderRightStyle,
+    BorderBottomStyle,
+    BorderLeftStyle,
+    BorderColor,
+    BorderTopColor,
+    BorderRightColor,
+    BorderBottomColor,
+    BorderLeftColor,
+    BorderRadius,
+    BorderTopLeftRadius,
+    BorderTopRightRadius,
+    BorderBottomRightRadius,
+    BorderBottomLeftRadius,
     Width,
     Height,
+    MinWidth,
+    MaxWidth,
+    MinHeight,
+    MaxHeight,
+    BoxSizing,
     Display,
     FontSize,
+    FontFamily,
+    FontWeight,
+    FontStyle,
+    FontStretch,
+    FontFeatureSettings,
+    FontVariantLigatures,
+    FontSmoothing,
+    LineHeight,
+    WordBreak,
+    LineBreak,
+    OverflowWrap,
+    Hyphens,
+    WhiteSpace,
+    TabSize,
+    TextAlign,
+    TextIndent,
+    LetterSpacing,
+    WordSpacing,
+    TextTransform,
+    TextDecoration,
+    TextShadow,
+    TextUnderlinePosition,
+    VerticalAlign,
+    Direction,
+    UnicodeBidi,
+    WritingMode,
+    TextOrientation,
+    TextCombineUpright,
+    RubyPosition,
+    RubyAlign,
     Undefined,
 }
 
 impl Property {
-    pub fn to_string(self: &Self) -> String {
-        property_to_string(*self)
+    /// 指定がない場合に親要素の値を引き継ぐproperty.
+    /// font-size, line-heightなど長さを持つものは相対値を計算するためlayoutで引き継ぐ
+    pub fn inherited(self) -> bool {
+        matches!(
+            self,
+            Property::Color
+                | Property::FontFamily
+                | Property::FontWeight
+                | Property::FontStyle
+                | Property::FontStretch
+                | Property::FontFeatureSettings
+                | Property::FontVariantLigatures
+                | Property::FontSmoothing
+                | Property::WordBreak
+                | Property::LineBreak
+                | Property::OverflowWrap
+                | Property::Hyphens
+                | Property::WhiteSpace
+                | Property::TabSize
+                | Property::TextTransform
+                | Property::TextShadow
+                | Property::TextUnderlinePosition
+                | Property::Direction
+                | Property::WritingMode
+                | Property::TextOrientation
+                | Property::TextCombineUpright
+                | Property::RubyPosition
+                | Property::RubyAlign
+        )
+    }
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", property_to_string(*self))
     }
 }
 
 pub fn property_type(input: &str) -> Property {
     match input {
         "padding" => Property::Padding,
+        "padding-top" => Property::PaddingTop,
+        "padding-right" => Property::PaddingRight,
+        "padding-bottom" => Property::PaddingBottom,
+        "padding-left" => Property::PaddingLeft,
+        "border-width" => Property::BorderWidth,
+        "border-top-width" => Property::BorderTopWidth,
+        "border-right-width" => Property::BorderRightWidth,
+        "border-bottom-width" => Property::BorderBottomWidth,
+        "border-left-width" => Property::BorderLeftWidth,
+        "border-style" => Property::BorderStyle,
+        "border-top-style" => Property::BorderTopStyle,
+        "border-right-style" => Property::BorderRightStyle,
+        "border-bottom-style" => Property::BorderBottomStyle,
+        "border-left-style" => Property::BorderLeftStyle,
+        "border-color" => Property::BorderColor,
+        "border-top-color" => Property::BorderTopColor,
+        "border-right-color" => Property::BorderRightColor,
+        "border-bottom-color" => Property::BorderBottomColor,
+        "border-left-color" => Property::BorderLeftColor,
+        "border-radius" => Property::BorderRadius,
+        "border-top-left-radius" => Property::BorderTopLeftRadius,
+        "border-top-right-radius" => Property::BorderTopRightRadius,
+        "border-bottom-right-radius" => Property::BorderBottomRightRadius,
+        "border-bottom-left-radius" => Property::BorderBottomLeftRadius,
         "margin" => Property::Margin,
         "margin-top" => Property::MarginTop,
         "margin-left" => Property::MarginLeft,
@@ -33,7 +151,43 @@ pub fn property_type(input: &str) -> Property {
         "background-color" => Property::BackgroundColor,
         "width" => Property::Width,
         "height" => Property::Height,
+        "min-width" => Property::MinWidth,
+        "max-width" => Property::MaxWidth,
+        "min-height" => Property::MinHeight,
+        "max-height" => Property::MaxHeight,
+        "box-sizing" => Property::BoxSizing,
         "font-size" => Property::FontSize,
+        "font-family" => Property::FontFamily,
+        "font-weight" => Property::FontWeight,
+        "font-style" => Property::FontStyle,
+        "font-stretch" => Property::FontStretch,
+        "font-feature-settings" => Property::FontFeatureSettings,
+        "font-variant-ligatures" => Property::FontVariantLigatures,
+        "-webkit-font-smoothing" => Property::FontSmoothing,
+        "line-height" => Property::LineHeight,
+        "word-break" => Property::WordBreak,
+        "line-break" => Property::LineBreak,
+        // word-wrapはoverflow-wrapの古い名前
+        "overflow-wrap" | "word-wrap" => Property::OverflowWrap,
+        "hyphens" => Property::Hyphens,
+        "white-space" => Property::WhiteSpace,
+        "tab-size" => Property::TabSize,
+        "text-align" => Property::TextAlign,
+        "text-indent" => Property::TextIndent,
+        "letter-spacing" => Property::LetterSpacing,
+        "word-spacing" => Property::WordSpacing,
+        "text-transform" => Property::TextTransform,
+        "text-decoration" => Property::TextDecoration,
+        "text-shadow" => Property::TextShadow,
+        "text-underline-position" => Property::TextUnderlinePosition,
+        "vertical-align" => Property::VerticalAlign,
+        "direction" => Property::Direction,
+        "unicode-bidi" => Property::UnicodeBidi,
+        "writing-mode" => Property::WritingMode,
+        "text-orientation" => Property::TextOrientation,
+        "text-combine-upright" => Property::TextCombineUpright,
+        "ruby-position" => Property::RubyPosition,
+        "ruby-align" => Property::RubyAlign,
         "display" => Property::Display,
         _ => Property::Undefined,
     }
@@ -42,6 +196,30 @@ pub fn property_type(input: &str) -> Property {
 pub fn property_to_string(input: Property) -> String {
     let result = match input {
         Property::Padding => "padding",
+        Property::PaddingTop => "padding-top",
+        Property::PaddingRight => "padding-right",
+        Property::PaddingBottom => "padding-bottom",
+        Property::PaddingLeft => "padding-left",
+        Property::BorderWidth => "border-width",
+        Property::BorderTopWidth => "border-top-width",
+        Property::BorderRightWidth => "border-right-width",
+        Property::BorderBottomWidth => "border-bottom-width",
+        Property::BorderLeftWidth => "border-left-width",
+        Property::BorderStyle => "border-style",
+        Property::BorderTopStyle => "border-top-style",
+        Property::BorderRightStyle => "border-right-style",
+        Property::BorderBottomStyle => "border-bottom-style",
+        Property::BorderLeftStyle => "border-left-style",
+        Property::BorderColor => "border-color",
+        Property::BorderTopColor => "border-top-color",
+        Property::BorderRightColor => "border-right-color",
+        Property::BorderBottomColor => "border-bottom-color",
+        Property::BorderLeftColor => "border-left-color",
+        Property::BorderRadius => "border-radius",
+        Property::BorderTopLeftRadius => "border-top-left-radius",
+        Property::BorderTopRightRadius => "border-top-right-radius",
+        Property::BorderBottomRightRadius => "border-bottom-right-radius",
+        Property::BorderBottomLeftRadius => "border-bottom-left-radius",
         Property::Margin => "margin",
         Property::MarginTop => "margin-top",
         Property::MarginLeft => "margin-left",
@@ -51,7 +229,42 @@ pub fn property_to_string(input: Property) -> String {
         Property::BackgroundColor => "background-color",
         Property::Width => "width",
         Property::Height => "height",
+        Property::MinWidth => "min-width",
+        Property::MaxWidth => "max-width",
+        Property::MinHeight => "min-height",
+        Property::MaxHeight => "max-height",
+        Property::BoxSizing => "box-sizing",
         Property::FontSize => "font-size",
+        Property::FontFamily => "font-family",
+        Property::FontWeight => "font-weight",
+        Property::FontStyle => "font-style",
+        Property::FontStretch => "font-stretch",
+        Property::FontFeatureSettings => "font-feature-settings",
+        Property::FontVariantLigatures => "font-variant-ligatures",
+        Property::FontSmoothing => "-webkit-font-smoothing",
+        Property::LineHeight => "line-height",
+        Property::WordBreak => "word-break",
+        Property::LineBreak => "line-break",
+        Property::OverflowWrap => "overflow-wrap",
+        Property::Hyphens => "hyphens",
+        Property::WhiteSpace => "white-space",
+        Property::TabSize => "tab-size",
+        Property::TextAlign => "text-align",
+        Property::TextIndent => "text-indent",
+        Property::LetterSpacing => "letter-spacing",
+        Property::WordSpacing => "word-spacing",
+        Property::TextTransform => "text-transform",
+        Property::TextDecoration => "text-decoration",
+        Property::TextShadow => "text-shadow",
+        Property::TextUnderlinePosition => "text-underline-position",
+        Property::VerticalAlign => "vertical-align",
+        Property::Direction => "direction",
+        Property::UnicodeBidi => "unicode-bidi",
+        Property::WritingMode => "writing-mode",
+        Property::TextOrientation => "text-orientation",
+        Property::TextCombineUpright => "text-combine-upright",
+        Property::RubyPosition => "ruby-position",
+        Property::RubyAlign => "ruby-align",
         Property::Display => "display",
         Property::Undefined => "undefined",
     };
@@ -0,0 +1,422 @@
+use crate::css::ast::{
+    Block, Combinator, Declaration, PseudoClass, Selector, StyleSheet, Unit, Value,
+};
+use crate::css::calc::CalcNode;
+use crate::css::color::Color;
+use crate::css::font::{FontFace, FontFaceSource, FontStyle, NORMAL_STRETCH, NORMAL_WEIGHT};
+use crate::css::media::{MediaFeature, MediaQuery, MediaQueryList};
+
+/// CSSの出力形式. Minifiedは不要な空白と改行を取り除く
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CssFormat {
+    Pretty,
+    Minified,
+}
+
+/// CSSのテキストに変換する. 出力したテキストをparseすると元と同じ値になる
+pub trait ToCss {
+    fn to_css(&self, format: CssFormat) -> String;
+}
+
+const INDENT: &str = "  ";
+
+impl ToCss for StyleSheet {
+    fn to_css(&self, format: CssFormat) -> String {
+        let blocks: Vec<String> = self
+            .font_faces
+            .iter()
+            .map(|f| f.to_css(format))
+            .chain(self.blocks.iter().map(|b| b.to_css(format)))
+            .collect();
+        match format {
+            CssFormat::Pretty => blocks.join("\n\n") + "\n",
+            CssFormat::Minified => blocks.concat(),
+        }
+    }
+}
+
+impl ToCss for Block {
+    fn to_css(&self, format: CssFormat) -> String {
+        let selectors: Vec<String> = self.selectors.iter().map(|s| s.to_css(format)).collect();
+        let declarations: Vec<String> =
+            self.declarations.iter().map(|d| d.to_css(format)).collect();
+        let mut css = match (format, declarations.is_empty()) {
+            (CssFormat::Pretty, true) => format!("{} {{}}", selectors.join(", ")),
+            (CssFormat::Pretty, false) => format!(
+                "{} {{\n{}{}\n}}",
+                selectors.join(", "),
+                INDENT,
+                declarations.join(&format!("\n{}", INDENT))
+            ),
+            (CssFormat::Minified, _) => {
+                format!("{}{{{}}}", selectors.join(","), declarations.join(""))
+            }
+        };
+        // @mediaの条件は内側から順に囲む
+        for media in self.media.iter().rev() {
+            css = match format {
+                CssFormat::Pretty => format!(
+                    "@media {} {{\n{}{}\n}}",
+                    media.to_css(format),
+                    INDENT,
+                    css.replace('\n', &format!("\n{}", INDENT))
+                ),
+                CssFormat::Minified => format!("@media {}{{{}}}", media.to_css(format), css),
+            };
+        }
+        css
+    }
+}
+
+impl ToCss for Declaration {
+    fn to_css(&self, format: CssFormat) -> String {
+        match format {
+            CssFormat::Pretty => format!("{}: {};", self.property, self.value.to_css(format)),
+            CssFormat::Minified => format!("{}:{};", self.property, self.value.to_css(format)),
+        }
+    }
+}
+
+impl ToCss for Selector {
+    fn to_css(&self, format: CssFormat) -> String {
+        let mut css = match &self.combinator {
+            Some((combinator, left)) => {
+                let combinator = match (combinator, format) {
+                    (Combinator::Descendant, _) => " ",
+                    (Combinator::Child, CssFormat::Pretty) => " > ",
+                    (Combinator::Child, CssFormat::Minified) => ">",
+                    (Combinator::NextSibling, CssFormat::Pretty) => " + ",
+                    (Combinator::NextSibling, CssFormat::Minified) => "+",
+                    (Combinator::SubsequentSibling, CssFormat::Pretty) => " ~ ",
+                    (Combinator::SubsequentSibling, CssFormat::Minified) => "~",
+                };
+                left.to_css(format) + combinator
+            }
+            None => String::from(""),
+        };
+        if let Some(tag_name) = &self.tag_name {
+            css.push_str(tag_name);
+        }
+        if let Some(id) = &self.id {
+            css.push_str(&format!("#{}", id));
+        }
+        for class in &self.class {
+            css.push_str(&format!(".{}", class));
+        }
+        if let Some(attribute) = &self.attribute {
+            css.push_str(&format!("[{}]", attribute));
+        }
+        for pseudo_class in &self.pseudo_classes {
+            css.push_str(&pseudo_class.to_css(format));
+        }
+        css
+    }
+}
+
+impl ToCss for PseudoClass {
+    fn to_css(&self, format: CssFormat) -> String {
+        match self {
+            PseudoClass::Is(selectors) => {
+                let selectors: Vec<String> = selectors.iter().map(|s| s.to_css(format)).collect();
+                format!(":is({})", selectors.join(separator(format)))
+            }
+            PseudoClass::FirstChild => String::from(":first-child"),
+            PseudoClass::LastChild => String::from(":last-child"),
+            PseudoClass::Other(name) => format!(":{}", name),
+        }
+    }
+}
+
+impl ToCss for Value {
+    fn to_css(&self, format: CssFormat) -> String {
+        match self {
+            Value::Keyword(keyword) => keyword.clone(),
+            Value::Color(color) => color.to_css(format),
+            Value::Length(value, unit) => format!("{}{}", value, unit.to_css(format)),
+            Value::Percentage(value) => format!("{}%", value),
+            Value::Number(value) => value.to_string(),
+            Value::Math(node) => match node {
+                CalcNode::Min(_) | CalcNode::Max(_) | CalcNode::Clamp(_, _, _) => {
+                    node.to_css(format)
+                }
+                _ => format!("calc({})", node.to_css(format)),
+            },
+            Value::String(string) => quote(string),
+            Value::List(values) => {
+                let values: Vec<String> = values.iter().map(|v| v.to_css(format)).collect();
+                values.join(separator(format))
+            }
+            Value::Undefined => String::from(""),
+        }
+    }
+}
+
+// 初期値のdescriptorは出力しない
+impl ToCss for FontFace {
+    fn to_css(&self, format: CssFormat) -> String {
+        let sources: Vec<String> = self
+            .sources
+            .iter()
+            .map(|source| match source {
+                FontFaceSource::Url(url, Some(hint)) => {
+                    format!("url({}) format({})", quote(url), quote(hint))
+                }
+                FontFaceSource::Url(url, None) => format!("url({})", quote(url)),
+                FontFaceSource::Local(name) => format!("local({})", quote(name)),
+            })
+            .collect();
+        let mut descriptors = vec![
+            ("font-family", quote(&self.family)),
+            ("src", sources.join(separator(format))),
+        ];
+        let range = |(min, max): (f32, f32)| match min == max {
+            true => min.to_string(),
+            false => format!("{} {}", min, max),
+        };
+        if self.weight != (NORMAL_WEIGHT, NORMAL_WEIGHT) {
+            descriptors.push(("font-weight", range(self.weight)));
+        }
+        match self.style {
+            FontStyle::Normal => {}
+            FontStyle::Italic => descriptors.push(("font-style", String::from("italic"))),
+            FontStyle::Oblique => descriptors.push(("font-style", String::from("oblique"))),
+        }
+        if self.stretch != (NORMAL_STRETCH, NORMAL_STRETCH) {
+            let (min, max) = self.stretch;
+            let stretch = match min == max {
+                true => format!("{}%", min),
+                false => format!("{}% {}%", min, max),
+            };
+            descriptors.push(("font-stretch", stretch));
+        }
+        match format {
+            CssFormat::Pretty => {
+                let descriptors: Vec<String> = descriptors
+                    .iter()
+                    .map(|(name, value)| format!("{}{}: {};", INDENT, name, value))
+                    .collect();
+                format!("@font-face {{\n{}\n}}", descriptors.join("\n"))
+            }
+            CssFormat::Minified => {
+                let descriptors: Vec<String> = descriptors
+                    .iter()
+                    .map(|(name, value)| format!("{}:{};", name, value))
+                    .collect();
+                format!("@font-face{{{}}}", descriptors.concat())
+            }
+        }
+    }
+}
+
+impl ToCss for Unit {
+    fn to_css(&self, _format: CssFormat) -> String {
+        let unit = match self {
+            Unit::Px => "px",
+            Unit::Em => "em",
+            Unit::Rem => "rem",
+            Unit::Vw => "vw",
+            Unit::Vh => "vh",
+        };
+        unit.to_string()
+    }
+}
+
+impl ToCss for Color {
+    fn to_css(&self, _format: CssFormat) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+// calc()の中身. +と-の前後の空白は省略できない
+impl ToCss for CalcNode {
+    fn to_css(&self, format: CssFormat) -> String {
+        let join = |args: &Vec<CalcNode>| {
+            let args: Vec<String> = args.iter().map(|a| a.to_css(format)).collect();
+            args.join(separator(format))
+        };
+        match self {
+            CalcNode::Number(n) => n.to_string(),
+            CalcNode::Length(value, unit) => format!("{}{}", value, unit.to_css(format)),
+            CalcNode::Percentage(value) => format!("{}%", value),
+            CalcNode::Sum(terms) => {
+                let mut css = String::from("");
+                for (index, term) in terms.iter().enumerate() {
+                    let (negative, term) = match term {
+                        CalcNode::Number(n) if *n < 0.0 => (true, CalcNode::Number(-n)),
+                        CalcNode::Length(v, unit) if *v < 0.0 => {
+                            (true, CalcNode::Length(-v, unit.clone()))
+                        }
+                        CalcNode::Percentage(p) if *p < 0.0 => (true, CalcNode::Percentage(-p)),
+                        CalcNode::Product(node, f) if *f < 0.0 => {
+                            (true, CalcNode::Product(node.clone(), -f))
+                        }
+                        term => (false, term.clone()),
+                    };
+                    css.push_str(&match (index, negative) {
+                        (0, true) => format!("-{}", term.to_css(format)),
+                        (0, false) => term.to_css(format),
+                        (_, true) => format!(" - {}", term.to_css(format)),
+                        (_, false) => format!(" + {}", term.to_css(format)),
+                    });
+                }
+                css
+            }
+            CalcNode::Product(node, factor) => {
+                let node = match **node {
+                    CalcNode::Sum(_) => format!("({})", node.to_css(format)),
+                    _ => node.to_css(format),
+                };
+                match format {
+                    CssFormat::Pretty => format!("{} * {}", node, factor),
+                    CssFormat::Minified => format!("{}*{}", node, factor),
+                }
+            }
+            CalcNode::Min(args) => format!("min({})", join(args)),
+            CalcNode::Max(args) => format!("max({})", join(args)),
+            CalcNode::Clamp(lower, value, upper) => format!(
+                "clamp({})",
+                join(&vec![*lower.clone(), *value.clone(), *upper.clone()])
+            ),
+        }
+    }
+}
+
+impl ToCss for MediaQueryList {
+    fn to_css(&self, format: CssFormat) -> String {
+        let queries: Vec<String> = self.0.iter().map(|q| q.to_css(format)).collect();
+        queries.join(separator(format))
+    }
+}
+
+impl ToCss for MediaQuery {
+    fn to_css(&self, format: CssFormat) -> String {
+        let mut parts = vec![];
+        if self.negated {
+            parts.push(String::from("not"));
+        }
+        if let Some(media_type) = &self.media_type {
+            parts.push(media_type.clone());
+        }
+        let features: Vec<String> = self.features.iter().map(|f| f.to_css(format)).collect();
+        // media typeがない場合は先頭のfeatureの前にandを付けない
+        let and_features = features.join(" and ");
+        if !and_features.is_empty() {
+            match self.media_type {
+                Some(_) => parts.push(format!("and {}", and_features)),
+                None => parts.push(and_features),
+            }
+        }
+        parts.join(" ")
+    }
+}
+
+impl ToCss for MediaFeature {
+    fn to_css(&self, format: CssFormat) -> String {
+        let (name, value) = match self {
+            MediaFeature::Width(v) => ("width", v.to_css(format)),
+            MediaFeature::MinWidth(v) => ("min-width", v.to_css(format)),
+            MediaFeature::MaxWidth(v) => ("max-width", v.to_css(format)),
+            MediaFeature::Height(v) => ("height", v.to_css(format)),
+            MediaFeature::MinHeight(v) => ("min-height", v.to_css(format)),
+            MediaFeature::MaxHeight(v) => ("max-height", v.to_css(format)),
+            MediaFeature::Orientation(o) => ("orientation", o.clone()),
+            MediaFeature::Unknown(name) => return format!("({})", name),
+        };
+        match format {
+            CssFormat::Pretty => format!("({}: {})", name, value),
+            CssFormat::Minified => format!("({}:{})", name, value),
+        }
+    }
+}
+
+// 文字列に"が含まれる場合は'で囲む
+fn quote(string: &str) -> String {
+    match string.contains('"') {
+        true => format!("'{}'", string),
+        false => format!("\"{}\"", string),
+    }
+}
+
+fn separator(format: CssFormat) -> &'static str {
+    match format {
+        CssFormat::Pretty => ", ",
+        CssFormat::Minified => ",",
+    }
+}
+
+#[cfg(test)]
+use crate::css::parse::{
+    parse_css, CSS_HEADING, CSS_MATH_FUNCTION, CSS_MEDIA, CSS_MULTILINE_DECLARATIONS, CSS_NESTING,
+    CSS_TOY_ENGINE,
+};
+
+// parse.rsのテストで使っているCSS
+#[cfg(test)]
+const ROUND_TRIP_INPUTS: [&str; 9] = [
+    CSS_HEADING,
+    CSS_MULTILINE_DECLARATIONS,
+    CSS_TOY_ENGINE,
+    CSS_MATH_FUNCTION,
+    CSS_NESTING,
+    CSS_MEDIA,
+    // 上記に含まれない結合子や式
+    "
+div + .a ~ span:first-child, #c > p:last-child { margin-top: -1.5em; }
+.d { width: calc((10px + 5%) * 2); margin: min(10px, 1vh - 2px, 3rem); }
+@media not print and (min-height: 30em) { .e {} }
+",
+    // 未対応の要素名もそのまま出力される
+    "
+ul > li.item, section ruby rt { color: red; }
+article {}
+",
+    "
+@font-face {
+    font-family: Body Font;
+    src: url(fonts/body.otf) format('opentype'), local(\"Roboto\");
+    font-weight: 100 900;
+    font-style: italic;
+    font-stretch: condensed;
+}
+@font-face { font-family: 'A'; src: local(A); }
+p { font-family: \"Noto Sans JP\", Times New Roman, serif; font-weight: 350 }
+em { font-style: oblique 10deg; font-weight: bolder; font-stretch: 87.5% }
+",
+];
+
+#[test]
+fn test_to_css() {
+    let style_sheet = parse_css("a,.b>p{color:red;padding:calc(1px + 2em)} .c { }");
+    assert_eq!(
+        style_sheet.to_css(CssFormat::Pretty),
+        "a, .b > p {\n  color: #ff0000;\n  padding: calc(1px + 2em);\n}\n\n.c {}\n"
+    );
+    assert_eq!(
+        style_sheet.to_css(CssFormat::Minified),
+        "a,.b>p{color:#ff0000;padding:calc(1px + 2em);}.c{}"
+    );
+
+    let style_sheet = parse_css("@media (min-width: 600px) { .a { width: 50% } }");
+    assert_eq!(
+        style_sheet.to_css(CssFormat::Pretty),
+        "@media (min-width: 600px) {\n  .a {\n    width: 50%;\n  }\n}\n"
+    );
+
+    // 未対応の要素名は書かれたまま出力される
+    let style_sheet = parse_css("ul > li { }");
+    assert_eq!(style_sheet.to_css(CssFormat::Minified), "ul>li{}");
+}
+
+#[test]
+fn test_to_css_round_trip() {
+    for input in ROUND_TRIP_INPUTS {
+        let style_sheet = parse_css(input);
+        for format in [CssFormat::Pretty, CssFormat::Minified] {
+            // 出力したCSSを再度parseすると同じ値になり、再出力しても変わらない
+            let css = style_sheet.to_css(format);
+            let reparsed = parse_css(&css);
+            assert_eq!(reparsed, style_sheet, "{}", css);
+            assert_eq!(reparsed.to_css(format), css);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::Result;
+
+use crate::css::ast::{Unit, Value};
+use crate::css::calc::parse_length;
+use crate::css::color::Color;
+use crate::css::font::split_list;
+
+/// text-decorationの線の種類
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DecorationStyle {
+    #[default]
+    Solid,
+    Double,
+    Dotted,
+    Dashed,
+    Wavy,
+}
+
+/// text-decorationの値. colorがない場合は要素のcolorを使う
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TextDecoration {
+    pub underline: bool,
+    pub overline: bool,
+    pub line_through: bool,
+    pub style: DecorationStyle,
+    pub color: Option<Color>,
+    // autoの場合はfontの値を使う
+    pub thickness: Option<Value>,
+}
+
+/// text-shadowの1つの影
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextShadow {
+    pub offset_x: Value,
+    pub offset_y: Value,
+    pub blur: Value,
+    pub color: Option<Color>,
+}
+
+/// 縦書きで下線を引く側
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UnderlineSide {
+    Left,
+    // autoでは日本語の縦書きと同じく右側に引く
+    #[default]
+    Right,
+}
+
+/// text-underline-positionの値. underは横書きの下線をfontの下端より下に引く
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UnderlinePosition {
+    pub under: bool,
+    pub side: UnderlineSide,
+}
+
+impl TextDecoration {
+    pub fn is_none(&self) -> bool {
+        !self.underline && !self.overline && !self.line_through
+    }
+}
+
+/// `underline wavy red 2px`のような空白区切りの値. 順番は問わない
+pub fn parse_text_decoration(input: &str) -> Option<TextDecoration> {
+    let mut decoration = TextDecoration::default();
+    let mut none = false;
+    for token in input.split_whitespace() {
+        match token {
+            "none" => none = true,
+            "underline" => decoration.underline = true,
+            "overline" => decoration.overline = true,
+            "line-through" => decoration.line_through = true,
+            // 点滅は表示しない
+            "blink" => {}
+            "solid" => decoration.style = DecorationStyle::Solid,
+            "double" => decoration.style = DecorationStyle::Double,
+            "dotted" => decoration.style = DecorationStyle::Dotted,
+            "dashed" => decoration.style = DecorationStyle::Dashed,
+            "wavy" => decoration.style = DecorationStyle::Wavy,
+            "auto" | "from-font" => decoration.thickness = None,
+            _ => match (Color::new(token), parse_length(token)) {
+                (Some(color), _) => decoration.color = Some(color),
+                (None, Ok(length)) => decoration.thickness = Some(length),
+                (None, Err(_)) => return None,
+            },
+        }
+    }
+    // noneと線の種類は同時に指定できない
+    if none && !decoration.is_none() {
+        return None;
+    }
+    Some(decoration)
+}
+
+/// `auto`, `under`, `left`, `under right`のような値. left, rightは1つだけ指定できる
+pub fn parse_text_underline_position(input: &str) -> Option<UnderlinePosition> {
+    let mut position = UnderlinePosition::default();
+    let tokens: Vec<&str> = input.split_whitespace().collect();
+    if tokens == ["auto"] || tokens == ["from-font"] {
+        return Some(position);
+    }
+    let mut side = None;
+    for token in &tokens {
+        match *token {
+            "under" if !position.under => position.under = true,
+            "left" if side.is_none() => side = Some(UnderlineSide::Left),
+            "right" if side.is_none() => side = Some(UnderlineSide::Right),
+            _ => return None,
+        }
+    }
+    if tokens.is_empty() {
+        return None;
+    }
+    position.side = side.unwrap_or_default();
+    Some(position)
+}
+
+/// `1px 1px 2px black, 0 0 1em red`のようなカンマ区切りの影. noneは空になる
+pub fn parse_text_shadows(input: &str) -> Option<Vec<TextShadow>> {
+    if input.trim() == "none" {
+        return Some(vec![]);
+    }
+    let mut shadows = vec![];
+    for item in split_list(input) {
+        let mut lengths = vec![];
+        let mut color = None;
+        for token in item.split_whitespace() {
+            match (Color::new(token), parse_length(token)) {
+                (Some(c), _) if color.is_none() => color = Some(c),
+                (None, Ok(length)) => lengths.push(length),
+                _ => return None,
+            }
+        }
+        let zero = Value::Length(0.0, Unit::Px);
+        let mut lengths = lengths.into_iter();
+        let shadow = TextShadow {
+            offset_x: lengths.next()?,
+            offset_y: lengths.next()?,
+            blur: lengths.next().unwrap_or(zero),
+            color,
+        };
+        if lengths.next().is_some() {
+            return None;
+        }
+        shadows.push(shadow);
+    }
+    Some(shadows)
+}
+
+pub fn parse_text_decoration_value(input: &str) -> Result<Value> {
+    match parse_text_decoration(input) {
+        Some(_) => Ok(Value::Keyword(
+            input.split_whitespace().collect::<Vec<&str>>().join(" "),
+        )),
+        None => Err(anyhow::anyhow!("invalid text-decoration {}", input)),
+    }
+}
+
+pub fn parse_text_shadow_value(input: &str) -> Result<Value> {
+    match parse_text_shadows(input) {
+        Some(_) => Ok(Value::Keyword(
+            input.split_whitespace().collect::<Vec<&str>>().join(" "),
+        )),
+        None => Err(anyhow::anyhow!("invalid text-shadow {}", input)),
+    }
+}
+
+pub fn parse_text_underline_position_value(input: &str) -> Result<Value> {
+    match parse_text_underline_position(input) {
+        Some(_) => Ok(Value::Keyword(
+            input.split_whitespace().collect::<Vec<&str>>().join(" "),
+        )),
+        None => Err(anyhow::anyhow!("invalid text-underline-position {}", input)),
+    }
+}
+
+pub fn text_decoration(value: &Value) -> TextDecoration {
+    match value {
+        Value::Keyword(keyword) => parse_text_decoration(keyword).unwrap_or_default(),
+        _ => TextDecoration::default(),
+    }
+}
+
+pub fn text_underline_position(value: &Value) -> UnderlinePosition {
+    match value {
+        Value::Keyword(keyword) => parse_text_underline_position(keyword).unwrap_or_default(),
+        _ => UnderlinePosition::default(),
+    }
+}
+
+pub fn text_shadows(value: &Value) -> Vec<TextShadow> {
+    match value {
+        Value::Keyword(keyword) => parse_text_shadows(keyword).unwrap_or_default(),
+        _ => vec![],
+    }
+}
+
+#[test]
+fn test_parse_text_decoration() {
+    let decoration = parse_text_decoration("underline line-through wavy red 2px").unwrap();
+    assert!(decoration.underline && decoration.line_through && !decoration.overline);
+    assert_eq!(decoration.style, DecorationStyle::Wavy);
+    assert_eq!(decoration.color, Color::new("red"));
+    assert_eq!(decoration.thickness, Some(Value::Length(2.0, Unit::Px)));
+    assert!(parse_text_decoration("none").unwrap().is_none());
+    assert!(parse_text_decoration("none underline").is_none());
+    assert!(parse_text_decoration("underline sparkly").is_none());
+
+    let shadows = parse_text_shadows("1px 2px #000, red 0 0 4px").unwrap();
+    assert_eq!(shadows.len(), 2);
+    assert_eq!(shadows[0].offset_y, Value::Length(2.0, Unit::Px));
+    assert_eq!(shadows[0].blur, Value::Length(0.0, Unit::Px));
+    assert_eq!(shadows[1].color, Color::new("red"));
+    assert_eq!(shadows[1].blur, Value::Length(4.0, Unit::Px));
+    assert!(parse_text_shadows("none").unwrap().is_empty());
+    assert!(parse_text_shadows("1px").is_none());
+}
+
+#[test]
+fn test_parse_text_underline_position() {
+    let position = |input: &str| parse_text_underline_position(input);
+    assert_eq!(position("auto"), Some(UnderlinePosition::default()));
+    assert_eq!(
+        position("left under"),
+        Some(UnderlinePosition {
+            under: true,
+            side: UnderlineSide::Left,
+        })
+    );
+    assert_eq!(position("right").unwrap().side, UnderlineSide::Right);
+    assert!(position("left right").is_none());
+    assert!(position("auto under").is_none());
+    assert!(position("").is_none());
+}
@@ -0,0 +1,174 @@
+use crate::css::Color;
+use crate::font::rasterize::{apply_antialiasing, generate_font, shift_subpixel, Antialiasing};
+use crate::font::{ColorBitmap, FontBitmap, FontDatabase, SelectedFont};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+/// 描画の間、fontとrasterizeしたglyphを持つ. faceはFontDatabaseが一度だけ読み込み、
+/// glyphはface、glyph index、size、subpixelのずれ、antialiasの方法ごとに一度だけrasterizeし、bitmapは共有する
+pub struct FontContext<'a> {
+    pub fonts: &'a FontDatabase,
+    // -webkit-font-smoothingがautoの場合のantialiasの方法
+    pub antialiasing: Antialiasing,
+    glyphs: RefCell<HashMap<GlyphKey, (fontdue::Metrics, FontBitmap)>>,
+    // 色を持つglyphのRGBAの画像. COLRの文字の色で塗るlayerがあるため、文字の色ごとに持つ
+    colors: RefCell<HashMap<ColorKey, Option<(fontdue::Metrics, ColorBitmap)>>>,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
+}
+
+// sizeはf32のbit列で比べる. 合成した太字と斜体は別のbitmapになる
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    face: usize,
+    glyph: u16,
+    size: u32,
+    offset: usize,
+    antialiasing: Antialiasing,
+    bold: bool,
+    oblique: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct ColorKey {
+    face: usize,
+    glyph: u16,
+    size: u32,
+    foreground: (u8, u8, u8),
+}
+
+/// glyphのcacheの統計. profileに使う
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GlyphCacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    // cacheにあるglyphの数
+    pub glyphs: usize,
+}
+
+impl<'a> FontContext<'a> {
+    pub fn new(fonts: &'a FontDatabase) -> FontContext<'a> {
+        FontContext {
+            fonts,
+            antialiasing: Antialiasing::default(),
+            glyphs: RefCell::new(HashMap::new()),
+            colors: RefCell::new(HashMap::new()),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        }
+    }
+
+    /// glyphをRGBそれぞれのcoverageのbitmapにする. offsetはsubpixel単位(0から2)の右へのずれ
+    pub fn rasterize(
+        &self,
+        font: &SelectedFont,
+        glyph: u16,
+        size: f32,
+        offset: usize,
+        antialiasing: Antialiasing,
+    ) -> (fontdue::Metrics, FontBitmap) {
+        let key = GlyphKey {
+            face: font.face,
+            glyph,
+            size: size.to_bits(),
+            offset,
+            antialiasing,
+            bold: font.synthesis.bold,
+            oblique: font.synthesis.oblique,
+        };
+        if let Some(cached) = self.glyphs.borrow().get(&key) {
+            self.hits.set(self.hits.get() + 1);
+            return cached.clone();
+        }
+        self.misses.set(self.misses.get() + 1);
+        let (mut metrics, bitmap) = generate_font(font.font, glyph, size, font.synthesis);
+        let mut bitmap = shift_subpixel(&mut metrics, &bitmap, offset);
+        apply_antialiasing(&mut bitmap, antialiasing);
+        let bitmap = FontBitmap::from(bitmap);
+        self.glyphs
+            .borrow_mut()
+            .insert(key, (metrics, bitmap.clone()));
+        (metrics, bitmap)
+    }
+
+    /// 色を持つglyphをRGBAの画像にする. 色を持たないglyphはNone
+    pub fn rasterize_color(
+        &self,
+        font: &SelectedFont,
+        glyph: u16,
+        size: f32,
+        foreground: Color,
+    ) -> Option<(fontdue::Metrics, ColorBitmap)> {
+        let key = ColorKey {
+            face: font.face,
+            glyph,
+            size: size.to_bits(),
+            foreground: (foreground.r, foreground.g, foreground.b),
+        };
+        if let Some(cached) = self.colors.borrow().get(&key) {
+            self.hits.set(self.hits.get() + 1);
+            return cached.clone();
+        }
+        self.misses.set(self.misses.get() + 1);
+        let image = font.color.rasterize(font.font, glyph, size, foreground);
+        self.colors.borrow_mut().insert(key, image.clone());
+        image
+    }
+
+    pub fn stats(&self) -> GlyphCacheStats {
+        GlyphCacheStats {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+            glyphs: self.glyphs.borrow().len() + self.colors.borrow().len(),
+        }
+    }
+}
+
+/// 描画する位置を、pxの位置と1/3px単位のずれに分ける
+pub fn subpixel_position(x: f32) -> (f32, usize) {
+    let thirds = (x * 3.0).round();
+    let position = (thirds / 3.0).floor();
+    (position, (thirds - position * 3.0) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::FontQuery;
+
+    #[test]
+    fn test_font_context() {
+        let fonts = FontDatabase::new();
+        let context = FontContext::new(&fonts);
+        let font = fonts.primary_font(&[], &FontQuery::default());
+        let glyph = font.font.lookup_glyph_index('a');
+        let (metrics, bitmap) = context.rasterize(&font, glyph, 16.0, 0, Antialiasing::default());
+        assert_eq!(bitmap.len(), metrics.width * metrics.height * 3);
+        // 2回目はcacheのbitmapをコピーせずに共有する
+        let (_, cached) = context.rasterize(&font, glyph, 16.0, 0, Antialiasing::default());
+        assert!(std::rc::Rc::ptr_eq(&cached, &bitmap));
+        // sizeやずれが違う場合は別にrasterizeする
+        let (shifted, _) = context.rasterize(&font, glyph, 16.0, 1, Antialiasing::default());
+        assert_eq!(shifted.width, metrics.width + 1);
+        context.rasterize(&font, glyph, 20.0, 0, Antialiasing::None);
+        let stats = context.stats();
+        assert_eq!(
+            stats,
+            GlyphCacheStats {
+                hits: 1,
+                misses: 3,
+                glyphs: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn test_subpixel_position() {
+        assert_eq!(subpixel_position(10.0), (10.0, 0));
+        assert_eq!(subpixel_position(10.4), (10.0, 1));
+        assert_eq!(subpixel_position(10.7), (10.0, 2));
+        // 1/3pxの倍数に丸めて次のpxになる場合
+        assert_eq!(subpixel_position(10.9), (11.0, 0));
+        assert_eq!(subpixel_position(-0.4), (-1.0, 2));
+    }
+}
@@ -0,0 +1,314 @@
+use crate::css::Color;
+use crate::font::entity::ColorBitmap;
+use crate::font::kerning::Reader;
+use std::collections::HashMap;
+
+/// 色を持つglyph. COLR/CPALで色を付けて重ねるglyphと、CBDT/CBLCの画像のglyph
+// COLRのbase glyphごとのlayerのglyphと色. 色がNoneのlayerは文字の色で塗る
+type Layers = HashMap<u16, Vec<(u16, Option<Color>)>>;
+
+#[derive(Debug, Default)]
+pub struct ColorGlyphs {
+    layers: Layers,
+    // CBDTとCBLCを持つfontのdataとcollectionの中のindex. 画像は描画する時に読む
+    bitmaps: Option<(Vec<u8>, u32)>,
+}
+
+impl ColorGlyphs {
+    pub fn new(data: &[u8], index: u32) -> ColorGlyphs {
+        let face = match ttf_parser::Face::from_slice(data, index) {
+            Ok(face) => face,
+            Err(_) => return ColorGlyphs::default(),
+        };
+        let table = |tag: &[u8; 4]| {
+            face.table_data(ttf_parser::Tag::from_bytes(tag))
+                .map(Reader)
+        };
+        let layers = match (table(b"COLR"), table(b"CPAL")) {
+            (Some(colr), Some(cpal)) => {
+                parse_colr(colr, &parse_cpal(cpal).unwrap_or_default()).unwrap_or_default()
+            }
+            _ => HashMap::new(),
+        };
+        let bitmaps = match (table(b"CBLC"), table(b"CBDT")) {
+            (Some(_), Some(_)) => Some((data.to_vec(), index)),
+            _ => None,
+        };
+        ColorGlyphs { layers, bitmaps }
+    }
+
+    /// 色を持つglyphがないfont
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty() && self.bitmaps.is_none()
+    }
+
+    pub fn contains(&self, glyph: u16) -> bool {
+        if self.layers.contains_key(&glyph) {
+            return true;
+        }
+        match &self.bitmaps {
+            Some((data, index)) => ttf_parser::Face::from_slice(data, *index)
+                .ok()
+                .map(|face| {
+                    face.glyph_raster_image(ttf_parser::GlyphId(glyph), u16::MAX)
+                        .is_some()
+                })
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// glyphをRGBAの画像にする. 色を持たないglyphはNone
+    pub fn rasterize(
+        &self,
+        font: &fontdue::Font,
+        glyph: u16,
+        size: f32,
+        foreground: Color,
+    ) -> Option<(fontdue::Metrics, ColorBitmap)> {
+        match self.layers.get(&glyph) {
+            Some(layers) => Some(rasterize_layers(font, glyph, layers, size, foreground)),
+            None => self.rasterize_bitmap(font, glyph, size),
+        }
+    }
+
+    /*
+    CBDTのPNG画像を読み、font-sizeに合わせて拡大縮小する. 画像は決まった大きさ(ppem)ごとにあるため、
+    font-size以上で最も小さいもの(なければ最も大きいもの)を使う
+    */
+    fn rasterize_bitmap(
+        &self,
+        font: &fontdue::Font,
+        glyph: u16,
+        size: f32,
+    ) -> Option<(fontdue::Metrics, ColorBitmap)> {
+        let (data, index) = self.bitmaps.as_ref()?;
+        let face = ttf_parser::Face::from_slice(data, *index).ok()?;
+        let ppem = size.round().clamp(1.0, u16::MAX as f32) as u16;
+        let raster = face.glyph_raster_image(ttf_parser::GlyphId(glyph), ppem)?;
+        if raster.format != ttf_parser::RasterImageFormat::PNG || raster.pixels_per_em == 0 {
+            return None;
+        }
+        let image = image::load_from_memory_with_format(raster.data, image::ImageFormat::Png)
+            .ok()?
+            .to_rgba8();
+        let scale = size / raster.pixels_per_em as f32;
+        let width = (image.width() as f32 * scale).round().max(1.0) as u32;
+        let height = (image.height() as f32 * scale).round().max(1.0) as u32;
+        let image =
+            image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle);
+        let metrics = fontdue::Metrics {
+            xmin: (raster.x as f32 * scale).round() as i32,
+            ymin: (raster.y as f32 * scale).round() as i32,
+            width: width as usize,
+            height: height as usize,
+            advance_width: font.metrics_indexed(glyph, size).advance_width,
+            ..Default::default()
+        };
+        Some((metrics, image.into_raw().into()))
+    }
+}
+
+/*
+COLRのlayerのglyphを下から順に色を付けて重ねる. 各layerの輪郭はfontdueでcoverageにし、
+重ねた全体を囲む大きさの画像にする
+*/
+fn rasterize_layers(
+    font: &fontdue::Font,
+    glyph: u16,
+    layers: &[(u16, Option<Color>)],
+    size: f32,
+    foreground: Color,
+) -> (fontdue::Metrics, ColorBitmap) {
+    let rasterized: Vec<(fontdue::Metrics, Vec<u8>, Color)> = layers
+        .iter()
+        .map(|(layer, color)| {
+            let (metrics, coverage) = font.rasterize_indexed(*layer, size);
+            // 文字の色のalphaは使わない(名前で指定した色は1になっている)
+            let foreground = Color {
+                a: 255,
+                ..foreground
+            };
+            (metrics, coverage, color.unwrap_or(foreground))
+        })
+        .filter(|(metrics, _, _)| metrics.width > 0 && metrics.height > 0)
+        .collect();
+    // 上向きのy座標で、全layerを囲む範囲
+    let left = rasterized.iter().map(|(m, _, _)| m.xmin).min().unwrap_or(0);
+    let right = rasterized
+        .iter()
+        .map(|(m, _, _)| m.xmin + m.width as i32)
+        .max()
+        .unwrap_or(0);
+    let bottom = rasterized.iter().map(|(m, _, _)| m.ymin).min().unwrap_or(0);
+    let top = rasterized
+        .iter()
+        .map(|(m, _, _)| m.ymin + m.height as i32)
+        .max()
+        .unwrap_or(0);
+    let (width, height) = ((right - left) as usize, (top - bottom) as usize);
+    let mut image = vec![0u8; width * height * 4];
+    for (metrics, coverage, color) in &rasterized {
+        let x0 = (metrics.xmin - left) as usize;
+        let y0 = (top - metrics.ymin - metrics.height as i32) as usize;
+        for y in 0..metrics.height {
+            for x in 0..metrics.width {
+                let alpha = coverage[y * metrics.width + x] as f32 / 255.0 * color.a as f32 / 255.0;
+                let index = ((y0 + y) * width + x0 + x) * 4;
+                composite(&mut image[index..index + 4], color, alpha);
+            }
+        }
+    }
+    let metrics = fontdue::Metrics {
+        xmin: left,
+        ymin: bottom,
+        width,
+        height,
+        advance_width: font.metrics_indexed(glyph, size).advance_width,
+        ..Default::default()
+    };
+    (metrics, image.into())
+}
+
+// alphaを乗算していないRGBAの画素の上に、alphaの色を重ねる
+fn composite(pixel: &mut [u8], color: &Color, alpha: f32) {
+    if alpha <= 0.0 {
+        return;
+    }
+    let below = pixel[3] as f32 / 255.0 * (1.0 - alpha);
+    let out = alpha + below;
+    let channel = |source: u8, destination: u8| {
+        ((source as f32 * alpha + destination as f32 * below) / out).round() as u8
+    };
+    pixel[0] = channel(color.r, pixel[0]);
+    pixel[1] = channel(color.g, pixel[1]);
+    pixel[2] = channel(color.b, pixel[2]);
+    pixel[3] = (out * 255.0).round() as u8;
+}
+
+// CPALの最初のpaletteの色. color recordはBGRAの順に並ぶ
+fn parse_cpal(cpal: Reader) -> Option<Vec<Color>> {
+    let entries = cpal.u16(2)? as usize;
+    let records = cpal.u32(8)? as usize;
+    let first = cpal.u16(12)? as usize;
+    (0..entries)
+        .map(|entry| {
+            let offset = records + (first + entry) * 4;
+            let bytes = cpal.0.get(offset..offset + 4)?;
+            Some(Color {
+                r: bytes[2],
+                g: bytes[1],
+                b: bytes[0],
+                a: bytes[3],
+            })
+        })
+        .collect()
+}
+
+// COLR version 0のbase glyphとlayer. paletteの0xFFFFは文字の色を表す
+fn parse_colr(colr: Reader, palette: &[Color]) -> Option<Layers> {
+    let base_count = colr.u16(2)? as usize;
+    let base_offset = colr.u32(4)? as usize;
+    let layer_offset = colr.u32(8)? as usize;
+    let mut glyphs = HashMap::new();
+    for base in 0..base_count {
+        let record = base_offset + base * 6;
+        let glyph = colr.u16(record)?;
+        let first = colr.u16(record + 2)? as usize;
+        let count = colr.u16(record + 4)? as usize;
+        let layers = (first..first + count)
+            .map(|layer| {
+                let record = layer_offset + layer * 4;
+                let index = colr.u16(record + 2)?;
+                let color = match index {
+                    0xFFFF => None,
+                    _ => Some(*palette.get(index as usize)?),
+                };
+                Some((colr.u16(record)?, color))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        glyphs.insert(glyph, layers);
+    }
+    Some(glyphs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::rasterize::{init_fontdue, DEFAULT_FONT};
+
+    // fontのtable directoryを作り直し、tableを追加したfont
+    fn with_tables(font: &[u8], tables: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let reader = Reader(font);
+        let count = reader.u16(4).unwrap() as usize;
+        let mut records: Vec<([u8; 4], Vec<u8>)> = (0..count)
+            .map(|table| {
+                let record = 12 + table * 16;
+                let tag = [
+                    font[record],
+                    font[record + 1],
+                    font[record + 2],
+                    font[record + 3],
+                ];
+                let offset = reader.u32(record + 8).unwrap() as usize;
+                let length = reader.u32(record + 12).unwrap() as usize;
+                (tag, font[offset..offset + length].to_vec())
+            })
+            .collect();
+        records.extend(tables.iter().map(|(tag, data)| (**tag, data.clone())));
+        records.sort_by_key(|(tag, _)| *tag);
+        let mut data = font[0..4].to_vec();
+        data.extend((records.len() as u16).to_be_bytes());
+        data.extend([0; 6]);
+        let mut offset = 12 + records.len() * 16;
+        for (tag, table) in &records {
+            data.extend(tag);
+            data.extend([0; 4]);
+            data.extend((offset as u32).to_be_bytes());
+            data.extend((table.len() as u32).to_be_bytes());
+            offset += table.len().div_ceil(4) * 4;
+        }
+        for (_, table) in &records {
+            data.extend(table);
+            data.resize(data.len().div_ceil(4) * 4, 0);
+        }
+        data
+    }
+
+    #[test]
+    fn test_color_layers() {
+        let font = init_fontdue();
+        let glyph = |c: char| font.lookup_glyph_index(c);
+        // Aを赤いOと文字の色のAを重ねたglyphにする
+        // version, base glyphの数, base glyphとlayerのrecordの位置, layerの数
+        let mut colr = vec![0, 0, 0, 1, 0, 0, 0, 14, 0, 0, 0, 20, 0, 2];
+        for value in [glyph('A'), 0, 2, glyph('O'), 0, glyph('A'), 0xFFFF] {
+            colr.extend(value.to_be_bytes());
+        }
+        let mut cpal = vec![];
+        for value in [0u16, 1, 1, 1, 0, 14, 0] {
+            cpal.extend(value.to_be_bytes());
+        }
+        cpal.extend([0, 0, 255, 255]);
+        let data = with_tables(DEFAULT_FONT, &[(b"COLR", colr), (b"CPAL", cpal)]);
+        let colors = ColorGlyphs::new(&data, 0);
+        assert!(!colors.is_empty());
+        assert!(colors.contains(glyph('A')));
+        assert!(!colors.contains(glyph('B')));
+        assert!(ColorGlyphs::new(DEFAULT_FONT, 0).is_empty());
+
+        let blue = Color::from_rgba(0, 0, 255, 1);
+        let (metrics, image) = colors.rasterize(&font, glyph('A'), 32.0, blue).unwrap();
+        assert_eq!(image.len(), metrics.width * metrics.height * 4);
+        // 画像はOとAの両方を囲む大きさになる
+        let (o, a) = (font.metrics('O', 32.0), font.metrics('A', 32.0));
+        let right = (o.xmin + o.width as i32).max(a.xmin + a.width as i32);
+        assert_eq!(metrics.xmin, o.xmin.min(a.xmin));
+        assert_eq!(metrics.xmin + metrics.width as i32, right);
+        assert_eq!(metrics.advance_width, a.advance_width);
+        let pixels: Vec<&[u8]> = image.chunks(4).collect();
+        assert!(pixels.contains(&&[255, 0, 0, 255][..]));
+        assert!(pixels.contains(&&[0, 0, 255, 255][..]));
+        assert!(colors.rasterize(&font, glyph('B'), 32.0, blue).is_none());
+    }
+}
@@ -0,0 +1,537 @@
+use std::cell::{OnceCell, RefCell};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::css::{FontFace, FontFaceSource, FontStyle};
+use crate::font::color::ColorGlyphs;
+use crate::font::entity::DecorationMetrics;
+use crate::font::grapheme::is_emoji_cluster;
+use crate::font::kerning::Kerning;
+use crate::font::matching::{select_face, FontDescriptor, FontQuery, Synthesis};
+use crate::font::missing::is_default_ignorable;
+use crate::font::rasterize::{init_fontdue, DEFAULT_FONT};
+use crate::font::shaping::Shaper;
+
+// font-familyの総称名に対応するfamily
+const SERIF_FAMILIES: [&str; 5] = [
+    "DejaVu Serif",
+    "Liberation Serif",
+    "Noto Serif",
+    "Times New Roman",
+    "Times",
+];
+const MONOSPACE_FAMILIES: [&str; 5] = [
+    "DejaVu Sans Mono",
+    "Liberation Mono",
+    "Noto Sans Mono",
+    "Courier New",
+    "Menlo",
+];
+
+// font-familyと既定のfontのどちらにもglyphがない文字に使うfamily
+const FALLBACK_FAMILIES: [&str; 11] = [
+    "DejaVu Sans",
+    "Noto Sans",
+    "Noto Sans CJK JP",
+    "Noto Sans JP",
+    "Noto Sans Symbols",
+    "Noto Sans Symbols2",
+    "Symbola",
+    "Noto Color Emoji",
+    "Twemoji Mozilla",
+    "Apple Color Emoji",
+    "Segoe UI Emoji",
+];
+
+// 対応していない形式のfont
+const UNSUPPORTED_FORMATS: [&str; 4] = ["woff", "woff2", "embedded-opentype", "svg"];
+
+// 埋め込みのfont(Roboto Regular)の情報
+const DEFAULT_DESCRIPTOR: FontDescriptor = FontDescriptor {
+    weight: (400.0, 400.0),
+    style: FontStyle::Normal,
+    stretch: (100.0, 100.0),
+};
+
+/// 文字の描画に使うfont
+#[derive(Clone, Copy)]
+pub struct SelectedFont<'a> {
+    /// glyphのcacheでfaceを区別する番号
+    pub face: usize,
+    pub font: &'a fontdue::Font,
+    pub kerning: &'a Kerning,
+    pub shaper: &'a Shaper,
+    pub color: &'a ColorGlyphs,
+    pub decoration: DecorationMetrics,
+    pub synthesis: Synthesis,
+}
+
+// 読み込んだfontと、fontdueでは読めないkerningやshaping、色を持つglyph、下線の情報
+struct LoadedFont {
+    id: usize,
+    font: fontdue::Font,
+    kerning: Kerning,
+    shaper: Shaper,
+    color: ColorGlyphs,
+    decoration: DecorationMetrics,
+}
+
+/// @font-faceとシステムにinstallされたfontから、font-familyに対応するfontを探す
+pub struct FontDatabase {
+    faces: Vec<FaceEntry>,
+    // どのfamilyにも一致しない場合に使う埋め込みのfont
+    default_font: LoadedFont,
+    // 既定のfontにもglyphがない文字を探すfamily
+    fallback_families: Vec<String>,
+    // どのfontにもglyphがなかった文字
+    missing_chars: RefCell<BTreeSet<char>>,
+}
+
+struct FaceEntry {
+    // 小文字にしたfamily名
+    family: String,
+    // local()の照合に使うfull nameとpostscript name
+    names: Vec<String>,
+    descriptor: FontDescriptor,
+    path: PathBuf,
+    // font collection(.ttc)の中のindex
+    index: u32,
+    // @font-faceで定義されたfamilyは同名のシステムのfontより優先する
+    web_font: bool,
+    // 既定のfontは0、登録したfaceは1からの番号
+    id: usize,
+    // fontは使われるまで読み込まない
+    font: OnceCell<Option<LoadedFont>>,
+}
+
+impl FaceEntry {
+    fn font(&self) -> Option<&LoadedFont> {
+        self.font
+            .get_or_init(|| {
+                let data = fs::read(&self.path).ok()?;
+                let kerning = Kerning::new(&data, self.index);
+                let shaper = Shaper::new(&data, self.index);
+                let color = ColorGlyphs::new(&data, self.index);
+                let decoration = DecorationMetrics::new(&data, self.index);
+                let settings = fontdue::FontSettings {
+                    collection_index: self.index,
+                    ..fontdue::FontSettings::default()
+                };
+                let font = fontdue::Font::from_bytes(data, settings).ok()?;
+                Some(LoadedFont {
+                    id: self.id,
+                    font,
+                    kerning,
+                    shaper,
+                    color,
+                    decoration,
+                })
+            })
+            .as_ref()
+    }
+}
+
+impl Default for FontDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FontDatabase {
+    pub fn new() -> FontDatabase {
+        FontDatabase {
+            faces: vec![],
+            default_font: LoadedFont {
+                id: 0,
+                font: init_fontdue(),
+                kerning: Kerning::new(DEFAULT_FONT, 0),
+                shaper: Shaper::new(DEFAULT_FONT, 0),
+                color: ColorGlyphs::default(),
+                decoration: DecorationMetrics::new(DEFAULT_FONT, 0),
+            },
+            fallback_families: FALLBACK_FAMILIES
+                .iter()
+                .map(|family| family.to_string())
+                .collect(),
+            missing_chars: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    /// 既定のfontにもglyphがない文字を探すfamilyを置き換える
+    pub fn set_fallback_families(&mut self, families: Vec<String>) {
+        self.fallback_families = families;
+    }
+
+    /// どのfontにもglyphがなかった文字. code pointの順に並べる
+    pub fn missing_chars(&self) -> Vec<char> {
+        self.missing_chars.borrow().iter().copied().collect()
+    }
+
+    /// OSごとのfontのdirectoryを探す
+    pub fn scan_system_fonts(&mut self) {
+        let mut dirs = vec![
+            PathBuf::from("/usr/share/fonts"),
+            PathBuf::from("/usr/local/share/fonts"),
+            PathBuf::from("/System/Library/Fonts"),
+            PathBuf::from("/Library/Fonts"),
+            PathBuf::from("C:\\Windows\\Fonts"),
+        ];
+        if let Some(home) = std::env::var_os("HOME") {
+            let home = PathBuf::from(home);
+            dirs.push(home.join(".fonts"));
+            dirs.push(home.join(".local/share/fonts"));
+            dirs.push(home.join("Library/Fonts"));
+        }
+        for dir in dirs {
+            self.scan_dir(&dir);
+        }
+    }
+
+    /// directory以下のTTF/OTFを再帰的に探して登録する. 読み込めないfileは無視する
+    pub fn scan_dir(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+        // 同じdirectoryでも実行ごとに登録順が変わらないようにする
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                self.scan_dir(&path);
+            } else if is_font_file(&path) {
+                self.add_system_font(&path);
+            }
+        }
+    }
+
+    fn add_system_font(&mut self, path: &Path) {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+        let count = ttf_parser::fonts_in_collection(&data).unwrap_or(1);
+        for index in 0..count {
+            let face = match ttf_parser::Face::from_slice(&data, index) {
+                Ok(face) => face,
+                Err(_) => continue,
+            };
+            let name = |id: u16| {
+                face.names()
+                    .filter(|name| name.name_id() == id)
+                    .find_map(|name| name.to_string())
+            };
+            // typographic familyがある場合はBoldなどを含まない名前になっている
+            let family = match name(ttf_parser::name_id::TYPOGRAPHIC_FAMILY)
+                .or_else(|| name(ttf_parser::name_id::FAMILY))
+            {
+                Some(family) => family,
+                None => continue,
+            };
+            let names = [
+                ttf_parser::name_id::FULL_NAME,
+                ttf_parser::name_id::POST_SCRIPT_NAME,
+            ]
+            .iter()
+            .filter_map(|id| name(*id))
+            .map(|name| name.to_lowercase())
+            .collect();
+            let style = if face.is_italic() {
+                FontStyle::Italic
+            } else if face.is_oblique() {
+                FontStyle::Oblique
+            } else {
+                FontStyle::Normal
+            };
+            let weight = face.weight().to_number() as f32;
+            let stretch = width_to_stretch(face.width().to_number());
+            self.faces.push(FaceEntry {
+                family: family.to_lowercase(),
+                names,
+                descriptor: FontDescriptor {
+                    weight: (weight, weight),
+                    style,
+                    stretch: (stretch, stretch),
+                },
+                path: path.to_path_buf(),
+                index,
+                web_font: false,
+                id: self.faces.len() + 1,
+                font: OnceCell::new(),
+            });
+        }
+    }
+
+    /// @font-faceを登録する. urlはbase_dir(stylesheetのあるdirectory)からの相対pathとして扱う.
+    /// srcは先頭から順に試し、最初に読み込めたfontを使う
+    pub fn add_font_faces(&mut self, font_faces: &[FontFace], base_dir: &Path) {
+        for font_face in font_faces {
+            let descriptor = FontDescriptor {
+                weight: font_face.weight,
+                style: font_face.style,
+                stretch: font_face.stretch,
+            };
+            let source = font_face
+                .sources
+                .iter()
+                .find_map(|source| self.find_source(source, base_dir));
+            if let Some((path, index)) = source {
+                self.faces.push(FaceEntry {
+                    family: font_face.family.to_lowercase(),
+                    names: vec![],
+                    descriptor,
+                    path,
+                    index,
+                    web_font: true,
+                    id: self.faces.len() + 1,
+                    font: OnceCell::new(),
+                });
+            }
+        }
+    }
+
+    fn find_source(&self, source: &FontFaceSource, base_dir: &Path) -> Option<(PathBuf, u32)> {
+        match source {
+            FontFaceSource::Url(url, format) => {
+                if let Some(format) = format {
+                    if UNSUPPORTED_FORMATS.contains(&format.to_lowercase().as_str()) {
+                        return None;
+                    }
+                }
+                // 手元のfileのみ読み込む
+                if url.contains("://") {
+                    return None;
+                }
+                let path = base_dir.join(url);
+                let data = fs::read(&path).ok()?;
+                ttf_parser::Face::from_slice(&data, 0).ok()?;
+                Some((path, 0))
+            }
+            FontFaceSource::Local(name) => {
+                let name = name.to_lowercase();
+                self.faces
+                    .iter()
+                    .find(|face| !face.web_font && face.names.contains(&name))
+                    .map(|face| (face.path.clone(), face.index))
+            }
+        }
+    }
+
+    // familyの中からqueryに最も近いfaceを選ぶ
+    fn select(&self, family: &str, query: &FontQuery) -> Option<&FaceEntry> {
+        let family = family.to_lowercase();
+        let faces: Vec<&FaceEntry> = self
+            .faces
+            .iter()
+            .filter(|face| face.family == family)
+            .collect();
+        let web_fonts: Vec<&FaceEntry> =
+            faces.iter().copied().filter(|face| face.web_font).collect();
+        let faces = match web_fonts.is_empty() {
+            true => faces,
+            false => web_fonts,
+        };
+        select_face(faces, |face| face.descriptor, query)
+    }
+
+    // 総称名は対応するfamilyのリストに置き換える. sans-serifは既定のfontを使う
+    fn candidate_fonts(
+        &self,
+        family: &str,
+        query: &FontQuery,
+    ) -> Vec<(&LoadedFont, FontDescriptor)> {
+        let families: Vec<&str> = match family.to_lowercase().as_str() {
+            "sans-serif" => return vec![(&self.default_font, DEFAULT_DESCRIPTOR)],
+            "serif" => SERIF_FAMILIES.to_vec(),
+            "monospace" => MONOSPACE_FAMILIES.to_vec(),
+            _ => vec![family],
+        };
+        families
+            .into_iter()
+            .filter_map(|family| self.select(family, query))
+            .filter_map(|face| face.font().map(|font| (font, face.descriptor)))
+            .collect()
+    }
+
+    // fontを選んだfaceの情報と、求めるstyleとの差から合成するstyleをまとめる
+    fn selected<'a>(
+        &'a self,
+        font: &'a LoadedFont,
+        descriptor: FontDescriptor,
+        query: &FontQuery,
+    ) -> SelectedFont<'a> {
+        SelectedFont {
+            face: font.id,
+            font: &font.font,
+            kerning: &font.kerning,
+            shaper: &font.shaper,
+            color: &font.color,
+            decoration: font.decoration,
+            synthesis: Synthesis::new(&descriptor, query),
+        }
+    }
+
+    // 文字を探すfontの順. font-familyのリスト、既定のfont、fallbackのfamily
+    fn fallback_fonts<'a: 'b, 'b>(
+        &'a self,
+        families: &'b [String],
+        query: &'b FontQuery,
+    ) -> impl Iterator<Item = (&'a LoadedFont, FontDescriptor)> + 'b {
+        families
+            .iter()
+            .flat_map(move |family| self.candidate_fonts(family, query))
+            .chain(vec![(&self.default_font, DEFAULT_DESCRIPTOR)])
+            .chain(
+                self.fallback_families
+                    .iter()
+                    .flat_map(move |family| self.candidate_fonts(family, query)),
+            )
+    }
+
+    /// font-familyのリストの中で最初に使えるfont. 行の高さなどの計算に使う
+    pub fn primary_font(&self, families: &[String], query: &FontQuery) -> SelectedFont<'_> {
+        let (font, descriptor) = families
+            .iter()
+            .flat_map(|family| self.candidate_fonts(family, query))
+            .next()
+            .unwrap_or((&self.default_font, DEFAULT_DESCRIPTOR));
+        self.selected(font, descriptor, query)
+    }
+
+    /// font-familyのリスト、既定のfont、fallbackのfamilyの順に、文字のglyphを持つ最初のfontを返す.
+    /// どのfontにもない場合は既定のfontを使い、欠けた文字として記録する
+    pub fn font_for_char(
+        &self,
+        families: &[String],
+        query: &FontQuery,
+        c: char,
+    ) -> SelectedFont<'_> {
+        let found = self
+            .fallback_fonts(families, query)
+            .find(|(font, _)| font.font.lookup_glyph_index(c) != 0);
+        if found.is_none() && !is_default_ignorable(c) {
+            self.missing_chars.borrow_mut().insert(c);
+        }
+        let (font, descriptor) = found.unwrap_or((&self.default_font, DEFAULT_DESCRIPTOR));
+        self.selected(font, descriptor, query)
+    }
+
+    /// 書記素クラスタの文字をすべて持つ最初のfontを返す. 絵文字として表示するクラスタは
+    /// 色を持つglyphがあるfontを優先する. どのfontにもない場合は先頭の文字でfontを選ぶ
+    pub fn font_for_cluster(
+        &self,
+        families: &[String],
+        query: &FontQuery,
+        cluster: &str,
+    ) -> SelectedFont<'_> {
+        let base = match cluster.chars().next() {
+            Some(base) => base,
+            None => return self.primary_font(families, query),
+        };
+        let chars: Vec<char> = cluster
+            .chars()
+            .filter(|c| !is_default_ignorable(*c))
+            .collect();
+        if chars.len() <= 1 && !is_emoji_cluster(cluster) {
+            return self.font_for_char(families, query, base);
+        }
+        let emoji = match is_emoji_cluster(cluster) {
+            true => self.fallback_fonts(families, query).find(|(font, _)| {
+                let glyph = font.font.lookup_glyph_index(base);
+                glyph != 0 && !font.color.is_empty() && font.color.contains(glyph)
+            }),
+            false => None,
+        };
+        let found = emoji.or_else(|| {
+            self.fallback_fonts(families, query)
+                .find(|(font, _)| chars.iter().all(|c| font.font.lookup_glyph_index(*c) != 0))
+        });
+        match found {
+            Some((font, descriptor)) => self.selected(font, descriptor, query),
+            None => self.font_for_char(families, query, base),
+        }
+    }
+}
+
+fn is_font_file(path: &Path) -> bool {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => matches!(
+            extension.to_lowercase().as_str(),
+            "ttf" | "otf" | "ttc" | "otc"
+        ),
+        None => false,
+    }
+}
+
+// OS/2のusWidthClass(1~9)を%に変換する
+fn width_to_stretch(width: u16) -> f32 {
+    match width {
+        1 => 50.0,
+        2 => 62.5,
+        3 => 75.0,
+        4 => 87.5,
+        6 => 112.5,
+        7 => 125.0,
+        8 => 150.0,
+        9 => 200.0,
+        _ => 100.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::parse_css;
+
+    #[test]
+    fn test_scan_dir() {
+        let mut database = FontDatabase::new();
+        database.scan_dir(Path::new("resources"));
+        let face = database.select("roboto", &FontQuery::default()).unwrap();
+        assert_eq!(face.descriptor.weight, (400.0, 400.0));
+        assert_eq!(face.descriptor.style, FontStyle::Normal);
+        assert!(face.names.contains(&String::from("roboto")));
+        assert!(face.font().is_some());
+    }
+
+    #[test]
+    fn test_font_face() {
+        let style_sheet = parse_css(
+            "
+@font-face { font-family: Body; src: url(missing.ttf), url(Roboto-Regular.ttf); }
+@font-face { font-family: Local; src: local(Roboto); }
+@font-face { font-family: Broken; src: url(missing.ttf); }
+",
+        );
+        let mut database = FontDatabase::new();
+        database.scan_dir(Path::new("resources"));
+        database.add_font_faces(&style_sheet.font_faces, Path::new("resources"));
+        let query = FontQuery::default();
+        // 読み込めないsrcは飛ばされる
+        let body = database.select("Body", &query).unwrap();
+        assert_eq!(body.path, Path::new("resources/Roboto-Regular.ttf"));
+        assert!(body.web_font);
+        assert!(database.select("local", &query).is_some());
+        assert!(database.select("broken", &query).is_none());
+
+        // glyphがないfamilyは飛ばし、どこにもない文字は既定のfontを使う
+        let families = vec![String::from("Broken"), String::from("Body")];
+        let body_font = &body.font().unwrap().font;
+        assert!(std::ptr::eq(
+            database.font_for_char(&families, &query, 'a').font,
+            body_font
+        ));
+        assert!(std::ptr::eq(
+            database.font_for_char(&families, &query, 'あ').font,
+            &database.default_font.font
+        ));
+
+        // 太字のfaceがないため合成する
+        let bold = FontQuery {
+            weight: 700.0,
+            ..Default::default()
+        };
+        assert!(database.font_for_char(&families, &bold, 'a').synthesis.bold);
+    }
+}
@@ -1,5 +1,10 @@
-// Font用のBitmap
-pub type FontBitmap = Vec<u8>;
+use std::rc::Rc;
+
+// Font用のBitmap. glyphのcacheと描画命令で同じbitmapを共有する
+pub type FontBitmap = Rc<[u8]>;
+
+// 色を持つglyphのBitmap. 1pxがRGBAの4byteで、alphaは乗算していない
+pub type ColorBitmap = Rc<[u8]>;
 
 #[derive(Debug)]
 pub struct FontMetrics {
@@ -11,14 +16,6 @@ pub struct FontMetrics {
     pub height: usize,
 }
 
-#[derive(Debug)]
-pub struct FontBounds {
-    pub xmin: f32,
-    pub ymin: f32,
-    pub width: f32,
-    pub height: f32,
-}
-
 impl FontMetrics {
     pub fn from_fontdue_metrics(x: f32, y: f32, metrics: fontdue::Metrics) -> Self {
         Self {
@@ -31,3 +28,54 @@ impl FontMetrics {
         }
     }
 }
+
+/// 下線と取り消し線の位置と太さ. 位置はbaselineから上向きの距離で、単位はem
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecorationMetrics {
+    pub underline_position: f32,
+    pub underline_thickness: f32,
+    pub strikeout_position: f32,
+    pub strikeout_thickness: f32,
+}
+
+impl Default for DecorationMetrics {
+    fn default() -> Self {
+        DecorationMetrics {
+            underline_position: -0.1,
+            underline_thickness: 0.05,
+            strikeout_position: 0.3,
+            strikeout_thickness: 0.05,
+        }
+    }
+}
+
+impl DecorationMetrics {
+    /// postとOS/2 tableから読む. tableがない場合は一般的な比率を使う
+    pub fn new(data: &[u8], index: u32) -> DecorationMetrics {
+        let mut metrics = DecorationMetrics::default();
+        let face = match ttf_parser::Face::from_slice(data, index) {
+            Ok(face) => face,
+            Err(_) => return metrics,
+        };
+        let units_per_em = face.units_per_em().unwrap_or(1000) as f32;
+        if let Some(underline) = face.underline_metrics() {
+            metrics.underline_position = underline.position as f32 / units_per_em;
+            metrics.underline_thickness = underline.thickness as f32 / units_per_em;
+        }
+        if let Some(strikeout) = face.strikeout_metrics() {
+            metrics.strikeout_position = strikeout.position as f32 / units_per_em;
+            metrics.strikeout_thickness = strikeout.thickness as f32 / units_per_em;
+        }
+        metrics
+    }
+
+    /// font-sizeを掛けてpxにする
+    pub fn scale(&self, font_size: f32) -> DecorationMetrics {
+        DecorationMetrics {
+            underline_position: self.underline_position * font_size,
+            underline_thickness: self.underline_thickness * font_size,
+            strikeout_position: self.strikeout_position * font_size,
+            strikeout_thickness: self.strikeout_thickness * font_size,
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use crate::font::script::is_mark;
+
+// UAX #29で書記素クラスタの境界を決める文字の分類. PrependとSpacingMarkは区別しない
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GraphemeCategory {
+    Cr,
+    Lf,
+    Control,
+    Extend,
+    Zwj,
+    RegionalIndicator,
+    // ハングルの字母と音節
+    L,
+    V,
+    T,
+    Lv,
+    Lvt,
+    ExtendedPictographic,
+    Other,
+}
+
+fn grapheme_category(c: char) -> GraphemeCategory {
+    let code = c as u32;
+    match c {
+        '\r' => GraphemeCategory::Cr,
+        '\n' => GraphemeCategory::Lf,
+        '\u{200D}' => GraphemeCategory::Zwj,
+        // ZWNJ、異体字セレクタ、タグ、肌の色の修飾子は前の文字に続ける
+        '\u{200C}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{E0020}'..='\u{E007F}'
+        | '\u{E0100}'..='\u{E01EF}'
+        | '\u{1F3FB}'..='\u{1F3FF}' => GraphemeCategory::Extend,
+        '\u{1F1E6}'..='\u{1F1FF}' => GraphemeCategory::RegionalIndicator,
+        '\u{1100}'..='\u{115F}' | '\u{A960}'..='\u{A97C}' => GraphemeCategory::L,
+        '\u{1160}'..='\u{11A7}' | '\u{D7B0}'..='\u{D7C6}' => GraphemeCategory::V,
+        '\u{11A8}'..='\u{11FF}' | '\u{D7CB}'..='\u{D7FB}' => GraphemeCategory::T,
+        '\u{AC00}'..='\u{D7A3}' => match (code - 0xAC00) % 28 {
+            0 => GraphemeCategory::Lv,
+            _ => GraphemeCategory::Lvt,
+        },
+        // インド系の用字の幅を持つ母音記号も結合文字として扱う
+        c if is_mark(c) => GraphemeCategory::Extend,
+        c if c.is_control() || matches!(c, '\u{2028}' | '\u{2029}') => GraphemeCategory::Control,
+        c if is_extended_pictographic(c) => GraphemeCategory::ExtendedPictographic,
+        _ => GraphemeCategory::Other,
+    }
+}
+
+/// 絵文字として表示する記号. Extended_Pictographicのおおよその範囲
+pub fn is_extended_pictographic(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00A9}'
+            | '\u{00AE}'
+            | '\u{203C}'
+            | '\u{2049}'
+            | '\u{2122}'
+            | '\u{2139}'
+            | '\u{2194}'..='\u{2199}'
+            | '\u{21A9}'..='\u{21AA}'
+            | '\u{231A}'..='\u{231B}'
+            | '\u{2328}'
+            | '\u{23CF}'
+            | '\u{23E9}'..='\u{23F3}'
+            | '\u{23F8}'..='\u{23FA}'
+            | '\u{24C2}'
+            | '\u{25AA}'..='\u{25AB}'
+            | '\u{25B6}'
+            | '\u{25C0}'
+            | '\u{25FB}'..='\u{25FE}'
+            | '\u{2600}'..='\u{27BF}'
+            | '\u{2934}'..='\u{2935}'
+            | '\u{2B05}'..='\u{2B07}'
+            | '\u{2B1B}'..='\u{2B1C}'
+            | '\u{2B50}'
+            | '\u{2B55}'
+            | '\u{3030}'
+            | '\u{303D}'
+            | '\u{3297}'
+            | '\u{3299}'
+            | '\u{1F000}'..='\u{1F1E5}'
+            | '\u{1F200}'..='\u{1F3FA}'
+            | '\u{1F400}'..='\u{1FAFF}'
+            | '\u{1FC00}'..='\u{1FFFD}'
+    )
+}
+
+// 異体字セレクタがなくても絵文字として表示する文字. Emoji_Presentationのおおよその範囲
+fn is_emoji_presentation(c: char) -> bool {
+    matches!(
+        c,
+        '\u{231A}'..='\u{231B}'
+            | '\u{23E9}'..='\u{23EC}'
+            | '\u{23F0}'
+            | '\u{23F3}'
+            | '\u{25FD}'..='\u{25FE}'
+            | '\u{2614}'..='\u{2615}'
+            | '\u{2648}'..='\u{2653}'
+            | '\u{267F}'
+            | '\u{2693}'
+            | '\u{26A1}'
+            | '\u{26AA}'..='\u{26AB}'
+            | '\u{26BD}'..='\u{26BE}'
+            | '\u{26C4}'..='\u{26C5}'
+            | '\u{26CE}'
+            | '\u{26D4}'
+            | '\u{26EA}'
+            | '\u{26F2}'..='\u{26F3}'
+            | '\u{26F5}'
+            | '\u{26FA}'
+            | '\u{26FD}'
+            | '\u{2705}'
+            | '\u{270A}'..='\u{270B}'
+            | '\u{2728}'
+            | '\u{274C}'
+            | '\u{274E}'
+            | '\u{2753}'..='\u{2755}'
+            | '\u{2757}'
+            | '\u{2795}'..='\u{2797}'
+            | '\u{27B0}'
+            | '\u{27BF}'
+            | '\u{2B1B}'..='\u{2B1C}'
+            | '\u{2B50}'
+            | '\u{2B55}'
+            | '\u{1F1E6}'..='\u{1F1FF}'
+    ) || (c >= '\u{1F000}' && is_extended_pictographic(c))
+}
+
+/// 書記素クラスタを絵文字として表示するか. U+FE0Eを付けた場合は文字として表示する
+pub fn is_emoji_cluster(cluster: &str) -> bool {
+    let base = match cluster.chars().next() {
+        Some(base) => base,
+        None => return false,
+    };
+    if cluster.contains('\u{FE0E}') {
+        return false;
+    }
+    // 数字などに囲みのキーキャップを付けたもの
+    let keycap = cluster.contains('\u{20E3}') && cluster.contains('\u{FE0F}');
+    let pictographic = is_extended_pictographic(base) || is_emoji_presentation(base);
+    keycap
+        || (pictographic
+            && (is_emoji_presentation(base)
+                || cluster.contains('\u{FE0F}')
+                || cluster.contains('\u{200D}')
+                || cluster
+                    .chars()
+                    .any(|c| ('\u{1F3FB}'..='\u{1F3FF}').contains(&c))))
+}
+
+/*
+文字列をUAX #29の拡張書記素クラスタに分ける. 結合文字、ZWJでつないだ絵文字、
+肌の色の修飾子、国旗の2文字の組は分けない. 各クラスタの先頭の位置と文字列を返す
+*/
+pub fn grapheme_clusters(text: &str) -> Vec<(usize, &str)> {
+    let mut clusters = vec![];
+    let mut start = 0;
+    let mut previous: Option<GraphemeCategory> = None;
+    // クラスタの中の連続した国旗の文字の数
+    let mut regional_indicators = 0;
+    // 絵文字の後に結合文字とZWJだけが続いている. ZWJの後の絵文字はつなぐ
+    let mut pictographic = false;
+    for (index, c) in text.char_indices() {
+        let category = grapheme_category(c);
+        let joins = match previous {
+            None => true,
+            Some(previous) => joins_grapheme(previous, category, regional_indicators, pictographic),
+        };
+        if !joins {
+            clusters.push((start, &text[start..index]));
+            start = index;
+            regional_indicators = 0;
+        }
+        match category {
+            GraphemeCategory::RegionalIndicator => regional_indicators += 1,
+            _ => regional_indicators = 0,
+        }
+        pictographic = match category {
+            GraphemeCategory::ExtendedPictographic => true,
+            GraphemeCategory::Extend | GraphemeCategory::Zwj => pictographic,
+            _ => false,
+        };
+        previous = Some(category);
+    }
+    if start < text.len() {
+        clusters.push((start, &text[start..]));
+    }
+    clusters
+}
+
+// previousの文字の後にcategoryの文字が同じクラスタとして続くか
+fn joins_grapheme(
+    previous: GraphemeCategory,
+    category: GraphemeCategory,
+    regional_indicators: usize,
+    pictographic: bool,
+) -> bool {
+    use GraphemeCategory::*;
+    match (previous, category) {
+        (Cr, Lf) => true,
+        (Cr | Lf | Control, _) | (_, Cr | Lf | Control) => false,
+        (L, L | V | Lv | Lvt) | (Lv | V, V | T) | (Lvt | T, T) => true,
+        (_, Extend | Zwj) => true,
+        (Zwj, ExtendedPictographic) => pictographic,
+        (RegionalIndicator, RegionalIndicator) => regional_indicators % 2 == 1,
+        _ => false,
+    }
+}
+
+#[test]
+fn test_grapheme_clusters() {
+    fn clusters(text: &str) -> Vec<&str> {
+        grapheme_clusters(text)
+            .into_iter()
+            .map(|(_, cluster)| cluster)
+            .collect()
+    }
+    assert_eq!(clusters("ab"), vec!["a", "b"]);
+    assert_eq!(clusters("e\u{0301}x"), vec!["e\u{0301}", "x"]);
+    assert_eq!(clusters("\r\n\n"), vec!["\r\n", "\n"]);
+    // 肌の色の修飾子とZWJでつないだ家族の絵文字
+    assert_eq!(
+        clusters("\u{1F44D}\u{1F3FD}!"),
+        vec!["\u{1F44D}\u{1F3FD}", "!"]
+    );
+    let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+    assert_eq!(clusters(family), vec![family]);
+    // 絵文字の前にないZWJは次の文字とつながない
+    assert_eq!(
+        clusters("a\u{200D}\u{1F469}"),
+        vec!["a\u{200D}", "\u{1F469}"]
+    );
+    // 国旗は2文字ずつ
+    let flags = "\u{1F1EF}\u{1F1F5}\u{1F1FA}\u{1F1F8}\u{1F1EB}";
+    assert_eq!(
+        clusters(flags),
+        vec!["\u{1F1EF}\u{1F1F5}", "\u{1F1FA}\u{1F1F8}", "\u{1F1EB}"]
+    );
+    // キーキャップとハングルの字母
+    assert_eq!(clusters("1\u{FE0F}\u{20E3}"), vec!["1\u{FE0F}\u{20E3}"]);
+    assert_eq!(
+        clusters("\u{1100}\u{1161}\u{11A8}"),
+        vec!["\u{1100}\u{1161}\u{11A8}"]
+    );
+    assert_eq!(grapheme_clusters("a\u{1F600}")[1].0, 1);
+}
+
+#[test]
+fn test_is_emoji_cluster() {
+    assert!(is_emoji_cluster("\u{1F600}"));
+    assert!(is_emoji_cluster("\u{2764}\u{FE0F}"));
+    assert!(!is_emoji_cluster("\u{2764}"));
+    assert!(!is_emoji_cluster("\u{1F600}\u{FE0E}"));
+    assert!(is_emoji_cluster("1\u{FE0F}\u{20E3}"));
+    assert!(!is_emoji_cluster("1"));
+    assert!(!is_emoji_cluster("\u{00A9}"));
+}
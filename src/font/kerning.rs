@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+
+/*
+fontのkerning情報.
+GPOSのkern featureに含まれるpair adjustmentを読み、GPOSがない場合はkern tableを使う.
+script, languageごとの切り替えはせず、すべてのkern featureのlookupを使う
+*/
+#[derive(Debug)]
+pub struct Kerning {
+    units_per_em: f32,
+    // lookupごとのsubtable. lookupの値は足し合わせる
+    lookups: Vec<Vec<PairSubtable>>,
+}
+
+#[derive(Debug)]
+enum PairSubtable {
+    // glyphの組ごとの値
+    Glyphs(HashMap<(u16, u16), i16>),
+    // glyphのclassの組ごとの値
+    Classes {
+        coverage: HashMap<u16, u16>,
+        first_classes: HashMap<u16, u16>,
+        second_classes: HashMap<u16, u16>,
+        second_class_count: usize,
+        values: Vec<i16>,
+    },
+}
+
+impl PairSubtable {
+    // 最初のglyphが対象外の場合はNoneになり、次のsubtableを探す
+    fn get(&self, left: u16, right: u16) -> Option<i16> {
+        match self {
+            PairSubtable::Glyphs(pairs) => pairs.get(&(left, right)).copied(),
+            PairSubtable::Classes {
+                coverage,
+                first_classes,
+                second_classes,
+                second_class_count,
+                values,
+            } => {
+                coverage.get(&left)?;
+                // classDefにないglyphはclass 0になる
+                let first = *first_classes.get(&left).unwrap_or(&0) as usize;
+                let second = *second_classes.get(&right).unwrap_or(&0) as usize;
+                values.get(first * second_class_count + second).copied()
+            }
+        }
+    }
+}
+
+impl Default for Kerning {
+    fn default() -> Self {
+        Kerning {
+            units_per_em: 1000.0,
+            lookups: vec![],
+        }
+    }
+}
+
+impl Kerning {
+    /// fontのdataからkerning情報を読む. 読めない場合は空になる
+    pub fn new(data: &[u8], index: u32) -> Kerning {
+        let face = match ttf_parser::Face::from_slice(data, index) {
+            Ok(face) => face,
+            Err(_) => return Kerning::default(),
+        };
+        let units_per_em = face.units_per_em().unwrap_or(1000) as f32;
+        let gpos = face
+            .table_data(ttf_parser::Tag::from_bytes(b"GPOS"))
+            .and_then(|data| parse_gpos(Reader(data)))
+            .filter(|lookups| !lookups.is_empty());
+        let lookups = match gpos {
+            Some(lookups) => lookups,
+            None => face
+                .table_data(ttf_parser::Tag::from_bytes(b"kern"))
+                .and_then(|data| parse_kern(Reader(data)))
+                .map(|subtables| vec![subtables])
+                .unwrap_or_default(),
+        };
+        Kerning {
+            units_per_em,
+            lookups,
+        }
+    }
+
+    /// 隣り合うglyphの間隔の調整量(px). 負の値の場合は近づける
+    pub fn kern(&self, left: u16, right: u16, size: f32) -> f32 {
+        let units: i32 = self
+            .lookups
+            .iter()
+            .filter_map(|subtables| {
+                subtables
+                    .iter()
+                    .find_map(|subtable| subtable.get(left, right))
+            })
+            .map(i32::from)
+            .sum();
+        units as f32 * size / self.units_per_em
+    }
+}
+
+// big endianの値を読む
+#[derive(Clone, Copy)]
+pub(super) struct Reader<'a>(pub(super) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(super) fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.0.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(super) fn i16(&self, offset: usize) -> Option<i16> {
+        self.u16(offset).map(|value| value as i16)
+    }
+
+    pub(super) fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.0.get(offset..offset + 4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub(super) fn at(&self, offset: usize) -> Option<Reader<'a>> {
+        self.0.get(offset..).map(Reader)
+    }
+}
+
+const PAIR_ADJUSTMENT: u16 = 2;
+const EXTENSION: u16 = 9;
+
+fn parse_gpos(gpos: Reader) -> Option<Vec<Vec<PairSubtable>>> {
+    let feature_list = gpos.at(gpos.u16(6)? as usize)?;
+    let lookup_list = gpos.at(gpos.u16(8)? as usize)?;
+
+    // kern featureが使うlookupのindex
+    let mut indices = vec![];
+    for i in 0..feature_list.u16(0)? as usize {
+        let record = 2 + i * 6;
+        if feature_list.0.get(record..record + 4)? != b"kern" {
+            continue;
+        }
+        let feature = feature_list.at(feature_list.u16(record + 4)? as usize)?;
+        for j in 0..feature.u16(2)? as usize {
+            indices.push(feature.u16(4 + j * 2)?);
+        }
+    }
+    indices.sort_unstable();
+    indices.dedup();
+
+    let mut lookups = vec![];
+    for index in indices {
+        let lookup = lookup_list.at(lookup_list.u16(2 + index as usize * 2)? as usize)?;
+        let lookup_type = lookup.u16(0)?;
+        let mut subtables = vec![];
+        for i in 0..lookup.u16(4)? as usize {
+            let mut subtable = lookup.at(lookup.u16(6 + i * 2)? as usize)?;
+            let mut subtable_type = lookup_type;
+            // extensionは32bitのoffsetで実際のsubtableを指す
+            if lookup_type == EXTENSION {
+                subtable_type = subtable.u16(2)?;
+                subtable = subtable.at(subtable.u32(4)? as usize)?;
+            }
+            if subtable_type != PAIR_ADJUSTMENT {
+                continue;
+            }
+            if let Some(subtable) = parse_pair_subtable(subtable) {
+                subtables.push(subtable);
+            }
+        }
+        lookups.push(subtables);
+    }
+    Some(lookups)
+}
+
+// value recordの大きさと、その中のx advanceの位置
+fn value_record(format: u16) -> (usize, Option<usize>) {
+    let size = format.count_ones() as usize * 2;
+    let x_advance = match format & 0x0004 {
+        0 => None,
+        _ => Some((format & 0x0003).count_ones() as usize * 2),
+    };
+    (size, x_advance)
+}
+
+fn parse_pair_subtable(subtable: Reader) -> Option<PairSubtable> {
+    let coverage = parse_coverage(subtable.at(subtable.u16(2)? as usize)?)?;
+    let (size1, x_advance) = value_record(subtable.u16(4)?);
+    let (size2, _) = value_record(subtable.u16(6)?);
+    // 最初のglyphのx advanceだけを使う
+    let read_value = |reader: Reader, offset: usize| match x_advance {
+        Some(x_advance) => reader.i16(offset + x_advance),
+        None => Some(0),
+    };
+    match subtable.u16(0)? {
+        1 => {
+            let mut pairs = HashMap::new();
+            for (&glyph, &coverage_index) in &coverage {
+                let offset = subtable.u16(10 + coverage_index as usize * 2)?;
+                let pair_set = subtable.at(offset as usize)?;
+                let record_size = 2 + size1 + size2;
+                for i in 0..pair_set.u16(0)? as usize {
+                    let record = 2 + i * record_size;
+                    let second = pair_set.u16(record)?;
+                    pairs.insert((glyph, second), read_value(pair_set, record + 2)?);
+                }
+            }
+            Some(PairSubtable::Glyphs(pairs))
+        }
+        2 => {
+            let first_classes = parse_class_def(subtable.at(subtable.u16(8)? as usize)?)?;
+            let second_classes = parse_class_def(subtable.at(subtable.u16(10)? as usize)?)?;
+            let first_class_count = subtable.u16(12)? as usize;
+            let second_class_count = subtable.u16(14)? as usize;
+            let record_size = size1 + size2;
+            let values = (0..first_class_count * second_class_count)
+                .map(|i| read_value(subtable, 16 + i * record_size))
+                .collect::<Option<Vec<i16>>>()?;
+            Some(PairSubtable::Classes {
+                coverage,
+                first_classes,
+                second_classes,
+                second_class_count,
+                values,
+            })
+        }
+        _ => None,
+    }
+}
+
+// glyph id -> coverage index
+pub(super) fn parse_coverage(coverage: Reader) -> Option<HashMap<u16, u16>> {
+    let mut map = HashMap::new();
+    match coverage.u16(0)? {
+        1 => {
+            for i in 0..coverage.u16(2)? {
+                map.insert(coverage.u16(4 + i as usize * 2)?, i);
+            }
+        }
+        2 => {
+            for i in 0..coverage.u16(2)? as usize {
+                let record = 4 + i * 6;
+                let (start, end) = (coverage.u16(record)?, coverage.u16(record + 2)?);
+                let start_index = coverage.u16(record + 4)?;
+                for glyph in start..=end {
+                    map.insert(glyph, start_index + (glyph - start));
+                }
+            }
+        }
+        _ => return None,
+    }
+    Some(map)
+}
+
+// glyph id -> class
+pub(super) fn parse_class_def(class_def: Reader) -> Option<HashMap<u16, u16>> {
+    let mut map = HashMap::new();
+    match class_def.u16(0)? {
+        1 => {
+            let start = class_def.u16(2)?;
+            for i in 0..class_def.u16(4)? {
+                map.insert(start + i, class_def.u16(6 + i as usize * 2)?);
+            }
+        }
+        2 => {
+            for i in 0..class_def.u16(2)? as usize {
+                let record = 4 + i * 6;
+                let (start, end) = (class_def.u16(record)?, class_def.u16(record + 2)?);
+                let class = class_def.u16(record + 4)?;
+                for glyph in start..=end {
+                    map.insert(glyph, class);
+                }
+            }
+        }
+        _ => return None,
+    }
+    Some(map)
+}
+
+// OpenTypeのkern table(version 0)のformat 0の横書き用subtableを読む
+fn parse_kern(kern: Reader) -> Option<Vec<PairSubtable>> {
+    if kern.u16(0)? != 0 {
+        return None;
+    }
+    let mut subtables = vec![];
+    let mut offset = 4;
+    for _ in 0..kern.u16(2)? {
+        let length = kern.u16(offset + 2)? as usize;
+        let coverage = kern.u16(offset + 4)?;
+        // 横書き、format 0で、最小値やcross-streamではないもの
+        if coverage & 0x0007 == 0x0001 && coverage >> 8 == 0 {
+            let subtable = kern.at(offset + 6)?;
+            let mut pairs = HashMap::new();
+            for i in 0..subtable.u16(0)? as usize {
+                let record = 8 + i * 6;
+                let left = subtable.u16(record)?;
+                let right = subtable.u16(record + 2)?;
+                pairs.insert((left, right), subtable.i16(record + 4)?);
+            }
+            subtables.push(PairSubtable::Glyphs(pairs));
+        }
+        offset += length;
+    }
+    Some(subtables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::rasterize::DEFAULT_FONT;
+
+    #[test]
+    fn test_kerning() {
+        let kerning = Kerning::new(DEFAULT_FONT, 0);
+        let face = ttf_parser::Face::from_slice(DEFAULT_FONT, 0).unwrap();
+        let glyph = |c| face.glyph_index(c).unwrap().0;
+        // "AV"は近づける
+        assert!(kerning.kern(glyph('A'), glyph('V'), 16.0) < 0.0);
+        assert_eq!(
+            kerning.kern(glyph('A'), glyph('V'), 32.0),
+            kerning.kern(glyph('A'), glyph('V'), 16.0) * 2.0
+        );
+        assert_eq!(kerning.kern(glyph('l'), glyph('l'), 16.0), 0.0);
+        assert_eq!(Kerning::new(&[], 0).kern(1, 2, 16.0), 0.0);
+    }
+
+    #[test]
+    fn test_kern_table() {
+        // format 0のsubtableが1つ、pairが1つのkern table
+        let table: [&[u16]; 4] = [
+            // version, nTables
+            &[0, 1],
+            // version, length, coverage
+            &[0, 20, 0x0001],
+            // nPairs, searchRange, entrySelector, rangeShift
+            &[1, 6, 0, 0],
+            // left, right, value
+            &[3, 4, (-50i16) as u16],
+        ];
+        let data: Vec<u8> = table
+            .concat()
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        let subtables = parse_kern(Reader(&data)).unwrap();
+        let kerning = Kerning {
+            units_per_em: 1000.0,
+            lookups: vec![subtables],
+        };
+        assert_eq!(kerning.kern(3, 4, 20.0), -1.0);
+        assert_eq!(kerning.kern(4, 3, 20.0), 0.0);
+    }
+}
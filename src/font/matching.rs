@@ -0,0 +1,221 @@
+use crate::css::FontStyle;
+
+/// fontが対応するweight, style, stretch. 可変fontの場合は範囲を持つ
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontDescriptor {
+    pub weight: (f32, f32),
+    pub style: FontStyle,
+    pub stretch: (f32, f32),
+}
+
+/// 要素が求めるfontのweight, style, stretch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontQuery {
+    pub weight: f32,
+    pub style: FontStyle,
+    pub stretch: f32,
+}
+
+impl Default for FontQuery {
+    fn default() -> Self {
+        FontQuery {
+            weight: 400.0,
+            style: FontStyle::Normal,
+            stretch: 100.0,
+        }
+    }
+}
+
+/// 一致するfaceがない場合に、glyphのbitmapを加工して作るstyle
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Synthesis {
+    pub bold: bool,
+    pub oblique: bool,
+}
+
+impl Synthesis {
+    /// 太字を求めているのに細いfaceしかない場合や、斜体を求めているのに立体しかない場合に合成する
+    pub fn new(descriptor: &FontDescriptor, query: &FontQuery) -> Synthesis {
+        Synthesis {
+            bold: query.weight >= 600.0 && descriptor.weight.1 <= 500.0,
+            oblique: query.style != FontStyle::Normal && descriptor.style == FontStyle::Normal,
+        }
+    }
+}
+
+// 値が小さいほど優先する. 同じ順位の中では距離の近いものを選ぶ
+type Rank = (u8, f32);
+
+/*
+CSS Fonts Level 4のfont matching algorithmに従い、
+stretch, style, weightの順に候補を絞り込む.
+https://www.w3.org/TR/css-fonts-4/#font-style-matching
+*/
+pub fn select_face<T>(
+    faces: Vec<T>,
+    descriptor: impl Fn(&T) -> FontDescriptor,
+    query: &FontQuery,
+) -> Option<T> {
+    let faces = closest(faces, |face| stretch_rank(&descriptor(face), query.stretch));
+    let faces = closest(faces, |face| style_rank(&descriptor(face), query.style));
+    let faces = closest(faces, |face| weight_rank(&descriptor(face), query.weight));
+    faces.into_iter().next()
+}
+
+fn closest<T>(faces: Vec<T>, rank: impl Fn(&T) -> Rank) -> Vec<T> {
+    let best = faces
+        .iter()
+        .map(&rank)
+        .min_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    faces
+        .into_iter()
+        .filter(|face| Some(rank(face)) == best)
+        .collect()
+}
+
+// 100%以下を求める場合は狭いfontを、それ以外は広いfontを先に探す
+fn stretch_rank(descriptor: &FontDescriptor, desired: f32) -> Rank {
+    let (min, max) = descriptor.stretch;
+    if (min..=max).contains(&desired) {
+        (0, 0.0)
+    } else if desired <= 100.0 {
+        match max < desired {
+            true => (1, desired - max),
+            false => (2, min - desired),
+        }
+    } else {
+        match min > desired {
+            true => (1, min - desired),
+            false => (2, desired - max),
+        }
+    }
+}
+
+fn style_rank(descriptor: &FontDescriptor, desired: FontStyle) -> Rank {
+    let order = match desired {
+        FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+        FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal],
+        FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic],
+    };
+    let rank = order
+        .iter()
+        .position(|style| *style == descriptor.style)
+        .unwrap_or(order.len());
+    (rank as u8, 0.0)
+}
+
+/*
+- 400以上500以下: 500までの重いfont, 軽いfont, 500より重いfontの順
+- 400未満: 軽いfont, 重いfontの順
+- 500より大きい: 重いfont, 軽いfontの順
+*/
+fn weight_rank(descriptor: &FontDescriptor, desired: f32) -> Rank {
+    let (min, max) = descriptor.weight;
+    if (min..=max).contains(&desired) {
+        (0, 0.0)
+    } else if (400.0..=500.0).contains(&desired) {
+        if min > desired && min <= 500.0 {
+            (1, min - desired)
+        } else if max < desired {
+            (2, desired - max)
+        } else {
+            (3, min - desired)
+        }
+    } else if desired < 400.0 {
+        match max < desired {
+            true => (1, desired - max),
+            false => (2, min - desired),
+        }
+    } else {
+        match min > desired {
+            true => (1, min - desired),
+            false => (2, desired - max),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(weight: f32, style: FontStyle, stretch: f32) -> FontDescriptor {
+        FontDescriptor {
+            weight: (weight, weight),
+            style,
+            stretch: (stretch, stretch),
+        }
+    }
+
+    fn select(faces: &[FontDescriptor], weight: f32, style: FontStyle) -> FontDescriptor {
+        let query = FontQuery {
+            weight,
+            style,
+            ..Default::default()
+        };
+        *select_face(faces.iter().collect(), |face| **face, &query).unwrap()
+    }
+
+    #[test]
+    fn test_select_weight() {
+        let faces = [
+            descriptor(300.0, FontStyle::Normal, 100.0),
+            descriptor(600.0, FontStyle::Normal, 100.0),
+            descriptor(900.0, FontStyle::Normal, 100.0),
+        ];
+        assert_eq!(select(&faces, 300.0, FontStyle::Normal), faces[0]);
+        // 400は500までに重いfontがないため軽いfontを選ぶ
+        assert_eq!(select(&faces, 400.0, FontStyle::Normal), faces[0]);
+        // 700より重いfontを先に探す
+        assert_eq!(select(&faces, 700.0, FontStyle::Normal), faces[2]);
+        assert_eq!(select(&faces, 100.0, FontStyle::Normal), faces[0]);
+
+        let variable = [FontDescriptor {
+            weight: (100.0, 900.0),
+            style: FontStyle::Normal,
+            stretch: (100.0, 100.0),
+        }];
+        assert_eq!(select(&variable, 700.0, FontStyle::Normal), variable[0]);
+    }
+
+    #[test]
+    fn test_synthesis() {
+        let regular = descriptor(400.0, FontStyle::Normal, 100.0);
+        let query = FontQuery {
+            weight: 700.0,
+            style: FontStyle::Italic,
+            ..Default::default()
+        };
+        assert_eq!(
+            Synthesis::new(&regular, &query),
+            Synthesis {
+                bold: true,
+                oblique: true
+            }
+        );
+        let bold_italic = descriptor(700.0, FontStyle::Italic, 100.0);
+        assert_eq!(Synthesis::new(&bold_italic, &query), Synthesis::default());
+        assert_eq!(
+            Synthesis::new(&regular, &FontQuery::default()),
+            Synthesis::default()
+        );
+    }
+
+    #[test]
+    fn test_select_style_and_stretch() {
+        let faces = [
+            descriptor(400.0, FontStyle::Normal, 100.0),
+            descriptor(700.0, FontStyle::Oblique, 100.0),
+            descriptor(400.0, FontStyle::Normal, 75.0),
+        ];
+        // styleはweightより優先される
+        assert_eq!(select(&faces, 400.0, FontStyle::Italic), faces[1]);
+        assert_eq!(select(&faces, 700.0, FontStyle::Normal), faces[0]);
+
+        let query = FontQuery {
+            stretch: 87.5,
+            ..Default::default()
+        };
+        let selected = select_face(faces.iter().collect(), |face| **face, &query).unwrap();
+        assert_eq!(*selected, faces[2]);
+    }
+}
@@ -0,0 +1,494 @@
+use crate::font::grapheme::grapheme_clusters;
+use crate::font::missing::{hex_box_advance, is_default_ignorable};
+use crate::font::rasterize::bold_strength;
+use crate::font::script::{Script, Tag};
+use crate::font::shaping::ShapedGlyph;
+use crate::font::vertical::TextOrientation;
+use crate::font::{DecorationMetrics, FontDatabase, FontQuery, SelectedFont};
+
+/// line-heightの計算値
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum LineHeight {
+    // fontのascent, descent, line gapの合計
+    #[default]
+    Normal,
+    // font-sizeに対する倍率
+    Number(f32),
+    Length(f32),
+}
+
+/// 文字列の描画に使うfontの指定. layoutとpaintで同じ値を使う
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextStyle {
+    pub families: Vec<String>,
+    pub query: FontQuery,
+    pub font_size: f32,
+    pub line_height: LineHeight,
+    // 文字の後ろ、単語の区切りの後ろに足す間隔(px)
+    pub letter_spacing: f32,
+    pub word_spacing: f32,
+    // 縦書きの文字の向き. 横書きの場合はNone
+    pub orientation: Option<TextOrientation>,
+    // text-combine-upright: all. 縦書きで文字列全体を横に並べ、1文字分の大きさにする
+    pub combine_upright: bool,
+    // font-variant-ligaturesとfont-feature-settingsで指定したfeatureの値. 後の指定を優先する
+    pub features: Vec<(Tag, u32)>,
+}
+
+impl TextStyle {
+    /// 指定したfeatureの値. 指定がない場合はNone
+    pub fn feature(&self, tag: &Tag) -> Option<u32> {
+        self.features
+            .iter()
+            .rev()
+            .find(|(feature, _)| feature == tag)
+            .map(|(_, value)| *value)
+    }
+
+    /// 縦書きで縦中横にする文字列
+    pub fn combined(&self) -> bool {
+        self.combine_upright && self.orientation.is_some()
+    }
+}
+
+/// 計測した文字列の大きさ
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TextMetrics {
+    pub width: f32,
+    // baselineから上端、下端までの距離
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_height: f32,
+    // 小文字のxの高さ. vertical-align: middleで使う
+    pub x_height: f32,
+}
+
+impl TextMetrics {
+    /// 行の上端からbaselineまでの距離. line-heightとfontの高さの差は上下に半分ずつ配分する
+    pub fn baseline(&self) -> f32 {
+        (self.line_height - (self.ascent + self.descent)) / 2.0 + self.ascent
+    }
+}
+
+/// 行の中に並べたglyph. xは文字列の先頭からの距離で、縦書きでは上からの距離になる
+pub struct PositionedGlyph<'a> {
+    // glyphの元の文字. 合字では最初の文字になる
+    pub c: char,
+    pub glyph: u16,
+    pub x: f32,
+    // baselineから上向きのずれ(px). 結合文字を重ねる位置に使う
+    pub y: f32,
+    pub font: SelectedFont<'a>,
+    // 縦書きで時計回りに90度回転して描画する文字
+    pub rotated: bool,
+    // 描画するfontの大きさ(px). 縦中横では縮めた大きさになる
+    pub size: f32,
+    // どのfontにもglyphがない文字. 16進数の箱を描く
+    pub missing: bool,
+}
+
+// 同じfont, 用字, 向きで続けてshapingする文字の列
+struct ShapingRun<'a> {
+    font: SelectedFont<'a>,
+    script: Script,
+    upright: bool,
+    // 文字列の中の位置、文字、glyph
+    chars: Vec<(usize, char, u16)>,
+}
+
+// shapingしたglyphの送り幅、または重ねるglyphのindexとその原点からの位置(px)
+enum Placement {
+    Advance(f32),
+    Attached(usize, f32, f32),
+}
+
+impl FontDatabase {
+    /*
+    文字列を書記素クラスタごとに同じfontと用字の列に分ける. クラスタの先頭にある結合文字は
+    前の文字のfontにglyphがあれば同じ列にし、数字や記号は前の列に含める. 右から左に書く用字の列には他の用字の文字を含めない
+    */
+    fn shaping_runs(
+        &self,
+        text: &str,
+        style: &TextStyle,
+        upright: impl Fn(char) -> bool,
+    ) -> Vec<ShapingRun<'_>> {
+        let mut runs: Vec<ShapingRun> = vec![];
+        for (start, cluster) in grapheme_clusters(text) {
+            // クラスタの文字は同じfontのglyphにし、用字と向きは先頭の文字で決める
+            let c = cluster.chars().next().unwrap();
+            let script = Script::of(c);
+            let font = self.font_for_cluster(&style.families, &style.query, cluster);
+            let chars = |font: &SelectedFont| -> Vec<(usize, char, u16)> {
+                cluster
+                    .char_indices()
+                    .map(|(index, c)| (start + index, c, font.font.lookup_glyph_index(c)))
+                    .collect()
+            };
+            if let Some(run) = runs.last_mut() {
+                let same_font = std::ptr::eq(run.font.font, font.font);
+                let joins = match script {
+                    Script::Inherited => run.font.font.lookup_glyph_index(c) != 0 || !same_font,
+                    Script::Common => {
+                        same_font && !run.script.is_rtl() && upright(c) == run.upright
+                    }
+                    _ => {
+                        same_font
+                            && upright(c) == run.upright
+                            && (run.script == script
+                                || (run.script == Script::Common && !script.is_rtl()))
+                    }
+                };
+                if joins {
+                    if matches!(run.script, Script::Common | Script::Inherited) {
+                        run.script = script;
+                    }
+                    let chars = chars(&run.font);
+                    run.chars.extend(chars);
+                    continue;
+                }
+            }
+            runs.push(ShapingRun {
+                chars: chars(&font),
+                font,
+                script,
+                upright: upright(c),
+            });
+        }
+        runs
+    }
+
+    /*
+    文字列をshapingしてglyphをadvance widthずつ並べ、同じfontのglyphが続く場合はkerningで間隔を調整する.
+    右から左に書く用字の文字は表示順に並んでいるものとして、論理順に戻してからshapingする.
+    縦書きで正立させる文字は1emずつ並べる. 並べたglyphと文字列全体の長さを返す.
+    縦中横では横に並べた文字を1em以内に縮め、縮めた後の幅を返す
+    */
+    pub fn position_glyphs(
+        &self,
+        text: &str,
+        style: &TextStyle,
+    ) -> (Vec<PositionedGlyph<'_>>, f32) {
+        let orientation = match style.combined() {
+            true => None,
+            false => style.orientation,
+        };
+        let upright = |c: char| orientation.is_some_and(|orientation| orientation.is_upright(c));
+        let kerning = style.feature(b"kern") != Some(0);
+        let mut glyphs: Vec<PositionedGlyph> = vec![];
+        let mut x = 0.0;
+        // kerningを調整する直前のglyph
+        let mut previous: Option<(*const fontdue::Font, u16)> = None;
+        for mut run in self.shaping_runs(text, style, upright) {
+            if run.script.is_rtl() {
+                run.chars.reverse();
+            }
+            let shaped = run
+                .font
+                .shaper
+                .shape(&run.chars, run.script, &style.features);
+            let placements = place_glyphs(text, &run, &shaped, style.font_size);
+            // 表示順に並べる
+            let mut order: Vec<usize> = (0..shaped.len()).collect();
+            if run.script.is_rtl() {
+                order.reverse();
+                previous = None;
+            }
+            let mut positions = vec![0; shaped.len()];
+            for index in order {
+                let shaped_glyph = &shaped[index];
+                let c = text[shaped_glyph.cluster..].chars().next().unwrap_or(' ');
+                let font = run.font;
+                let mut advance = match placements[index] {
+                    Placement::Advance(advance) => advance,
+                    Placement::Attached(..) => 0.0,
+                };
+                if let Placement::Advance(_) = placements[index] {
+                    let key = (font.font as *const fontdue::Font, shaped_glyph.glyph);
+                    if let Some((previous_font, previous_glyph)) = previous {
+                        if kerning && std::ptr::eq(previous_font, key.0) && !run.upright {
+                            x += font.kerning.kern(previous_glyph, key.1, style.font_size);
+                        }
+                    }
+                    previous = match run.upright {
+                        true => None,
+                        false => Some(key),
+                    };
+                    // 合成した太字は太くした分だけ広がる
+                    if font.synthesis.bold {
+                        advance += bold_strength(style.font_size) as f32;
+                    }
+                    advance += style.letter_spacing;
+                    if is_word_separator(c) {
+                        advance += style.word_spacing;
+                    }
+                }
+                positions[index] = glyphs.len();
+                glyphs.push(PositionedGlyph {
+                    c,
+                    glyph: shaped_glyph.glyph,
+                    x,
+                    y: 0.0,
+                    font,
+                    rotated: orientation.is_some() && !run.upright,
+                    size: style.font_size,
+                    missing: shaped_glyph.glyph == 0 && !is_default_ignorable(c),
+                });
+                x += advance;
+            }
+            // 結合文字を重ねるglyphの位置に合わせる. 重ねるglyphは論理順で前にある
+            for (index, placement) in placements.iter().enumerate() {
+                if let Placement::Attached(base, dx, dy) = placement {
+                    let (base_x, base_y) = {
+                        let base = &glyphs[positions[*base]];
+                        (base.x, base.y)
+                    };
+                    let glyph = &mut glyphs[positions[index]];
+                    glyph.x = base_x + dx;
+                    glyph.y = base_y + dy;
+                }
+            }
+        }
+        if style.combined() && x > style.font_size {
+            let scale = style.font_size / x;
+            for glyph in glyphs.iter_mut() {
+                glyph.x *= scale;
+                glyph.y *= scale;
+                glyph.size *= scale;
+            }
+            x = style.font_size;
+        }
+        (glyphs, x)
+    }
+
+    /// 最初に使えるfamilyのfontの下線と取り消し線の位置と太さ(px)
+    pub fn decoration_metrics(&self, style: &TextStyle) -> DecorationMetrics {
+        let primary = self.primary_font(&style.families, &style.query);
+        primary.decoration.scale(style.font_size)
+    }
+
+    /// 文字列の幅と行の高さを計算する. 縦方向の大きさは最初に使えるfamilyのfontで決まる
+    pub fn measure(&self, text: &str, style: &TextStyle) -> TextMetrics {
+        let (_, mut width) = self.position_glyphs(text, style);
+        // 縦中横は文字列全体で1文字分の長さになる
+        if style.combined() && !text.is_empty() {
+            width = style.font_size;
+        }
+        let primary = self.primary_font(&style.families, &style.query);
+        // hheaがないfontは一般的な比率を使う
+        let (mut ascent, mut descent, line_gap) =
+            match primary.font.horizontal_line_metrics(style.font_size) {
+                Some(line) => (line.ascent, -line.descent, line.line_gap),
+                None => (style.font_size * 0.8, style.font_size * 0.2, 0.0),
+            };
+        let line_height = match style.line_height {
+            LineHeight::Normal => ascent + descent + line_gap,
+            LineHeight::Number(number) => number * style.font_size,
+            LineHeight::Length(length) => length,
+        };
+        // 縦書きでは行の中央をbaselineにするため、fontの高さを半分ずつ分ける
+        if style.orientation.is_some() {
+            ascent = (ascent + descent) / 2.0;
+            descent = ascent;
+        }
+        // OS/2のsxHeightはfontdueでは読めないため、xのglyphの高さを使う
+        let x_height = match primary.font.lookup_glyph_index('x') {
+            0 => style.font_size * 0.5,
+            _ => primary.font.metrics('x', style.font_size).height as f32,
+        };
+        TextMetrics {
+            width,
+            ascent,
+            descent,
+            line_height,
+            x_height,
+        }
+    }
+}
+
+/*
+shapingしたglyphの送り幅を決める. GPOSで位置を決めた結合文字は重ねるglyphの位置に置き、
+位置が決まらなかった結合文字は前のglyphの中央の上(または下)に重ねる.
+インド系の用字の母音記号などは幅を持つため、fontの送り幅のまま並べる.
+glyphがない文字は16進数の箱の幅にし、表示しない文字は幅0にする
+*/
+fn place_glyphs(text: &str, run: &ShapingRun, shaped: &[ShapedGlyph], size: f32) -> Vec<Placement> {
+    let font = run.font.font;
+    let scale = size / font.units_per_em();
+    let mut placements = vec![];
+    let mut base: Option<usize> = None;
+    for (index, glyph) in shaped.iter().enumerate() {
+        let metrics = font.metrics_indexed(glyph.glyph, size);
+        if let Some((attached, dx, dy)) = glyph.attachment {
+            placements.push(Placement::Attached(
+                attached,
+                dx as f32 * scale,
+                dy as f32 * scale,
+            ));
+            continue;
+        }
+        if glyph.glyph == 0 {
+            let c = text[glyph.cluster..].chars().next().unwrap_or(' ');
+            placements.push(Placement::Advance(match c {
+                c if is_default_ignorable(c) => 0.0,
+                _ if run.upright => size,
+                c => hex_box_advance(c, size),
+            }));
+            continue;
+        }
+        let mark = run.font.shaper.is_mark(glyph);
+        match base {
+            Some(base) if mark && !run.script.is_indic() => {
+                let bounds = metrics.bounds;
+                let base_metrics = font.metrics_indexed(shaped[base].glyph, size);
+                let base_bounds = base_metrics.bounds;
+                let dx = base_metrics.advance_width / 2.0 - (bounds.xmin + bounds.width / 2.0);
+                // 大文字などに重ならないように、上の記号は上へ、下の記号は下へずらす
+                let gap = size * 0.05;
+                let base_top = base_bounds.ymin + base_bounds.height;
+                let dy = if bounds.ymin >= 0.0 {
+                    (base_top + gap - bounds.ymin).max(0.0)
+                } else if bounds.ymin + bounds.height <= 0.0 {
+                    (base_bounds.ymin - gap - (bounds.ymin + bounds.height)).min(0.0)
+                } else {
+                    0.0
+                };
+                placements.push(Placement::Attached(base, dx, dy));
+            }
+            _ => {
+                if !mark {
+                    base = Some(index);
+                }
+                placements.push(Placement::Advance(match run.upright {
+                    true => size,
+                    false => metrics.advance_width,
+                }));
+            }
+        }
+    }
+    placements
+}
+
+// word-spacingを足す単語の区切りの文字
+fn is_word_separator(c: char) -> bool {
+    matches!(
+        c,
+        ' ' | '\u{00A0}' | '\u{1361}' | '\u{10100}' | '\u{10101}' | '\u{1039F}' | '\u{1091F}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(font_size: f32, line_height: LineHeight) -> TextStyle {
+        TextStyle {
+            families: vec![],
+            query: FontQuery::default(),
+            font_size,
+            line_height,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+            orientation: None,
+            combine_upright: false,
+            features: vec![],
+        }
+    }
+
+    #[test]
+    fn test_measure() {
+        let fonts = FontDatabase::new();
+        let small = fonts.measure("Hello", &style(16.0, LineHeight::Normal));
+        let large = fonts.measure("Hello", &style(32.0, LineHeight::Normal));
+        assert!(small.width > 0.0);
+        // font-sizeに比例して大きくなる
+        assert!((large.width - small.width * 2.0).abs() < 0.01);
+        assert!((large.line_height - small.line_height * 2.0).abs() < 0.01);
+        // normalはfontのascentとdescentを含む
+        assert!(small.line_height >= small.ascent + small.descent);
+        // "AV"はkerningで近づける
+        let pair = fonts.measure("AV", &style(16.0, LineHeight::Normal));
+        let a = fonts.measure("A", &style(16.0, LineHeight::Normal));
+        let v = fonts.measure("V", &style(16.0, LineHeight::Normal));
+        assert!(pair.width < a.width + v.width);
+        // 空白も幅を持つ
+        assert!(fonts.measure(" ", &style(16.0, LineHeight::Normal)).width > 0.0);
+
+        let number = fonts.measure("Hello", &style(16.0, LineHeight::Number(2.0)));
+        assert_eq!(number.line_height, 32.0);
+        assert_eq!(number.width, small.width);
+        let length = fonts.measure("", &style(16.0, LineHeight::Length(10.0)));
+        assert_eq!(length.width, 0.0);
+        // leadingは上下に半分ずつ配分され、負の場合はfontが行からはみ出す
+        assert!(number.baseline() > number.ascent);
+        assert!(length.baseline() < length.ascent);
+        assert!(length.x_height > 0.0 && length.x_height < length.ascent);
+    }
+
+    #[test]
+    fn test_measure_vertical() {
+        let fonts = FontDatabase::new();
+        let horizontal = style(16.0, LineHeight::Normal);
+        let vertical = TextStyle {
+            orientation: Some(TextOrientation::Mixed),
+            ..horizontal.clone()
+        };
+        // 正立させる仮名は1em、横に倒す欧文は横書きと同じ幅だけ進む
+        let kana = fonts.measure("\u{3042}\u{3044}", &vertical);
+        assert_eq!(kana.width, 32.0);
+        let latin = fonts.measure("ab", &vertical);
+        assert_eq!(latin.width, fonts.measure("ab", &horizontal).width);
+        // 行の中央がbaselineになる
+        assert_eq!(kana.ascent, kana.descent);
+        let (glyphs, _) = fonts.position_glyphs("a\u{3042}", &vertical);
+        assert!(glyphs[0].rotated && !glyphs[1].rotated);
+
+        // 縦中横は横に並べた文字列を1em以内に縮める
+        let combined = TextStyle {
+            combine_upright: true,
+            ..vertical.clone()
+        };
+        assert_eq!(fonts.measure("123", &combined).width, 16.0);
+        let (glyphs, width) = fonts.position_glyphs("123", &combined);
+        assert!(width <= 16.0);
+        assert!(glyphs
+            .iter()
+            .all(|glyph| !glyph.rotated && glyph.size < 16.0));
+    }
+
+    #[test]
+    fn test_spacing() {
+        let fonts = FontDatabase::new();
+        let normal = style(16.0, LineHeight::Normal);
+        let spaced = TextStyle {
+            letter_spacing: 2.0,
+            word_spacing: 5.0,
+            ..normal.clone()
+        };
+        let width = |text, style| fonts.measure(text, style).width;
+        // 文字ごとにletter-spacing, 空白ごとにword-spacingを足す
+        assert!((width("ab c", &spaced) - width("ab c", &normal) - 13.0).abs() < 0.01);
+        let decoration = fonts.decoration_metrics(&normal);
+        assert!(decoration.underline_position < 0.0);
+        assert!(decoration.strikeout_position > 0.0);
+        assert!(decoration.underline_thickness > 0.0);
+    }
+
+    #[test]
+    fn test_missing_glyph() {
+        let fonts = FontDatabase::new();
+        let style = style(16.0, LineHeight::Normal);
+        // 既定のfontにない文字は16進数の箱の幅になり、欠けた文字として記録する
+        let (glyphs, width) = fonts.position_glyphs("a\u{3042}", &style);
+        let a = fonts.measure("a", &style).width;
+        assert!(!glyphs[0].missing);
+        assert!(glyphs[1].missing);
+        assert_eq!(width, a + hex_box_advance('\u{3042}', 16.0));
+        assert_eq!(fonts.missing_chars(), vec!['\u{3042}']);
+        // 表示しない文字は幅0で、欠けた文字にしない
+        let (glyphs, width) = fonts.position_glyphs("a\u{2060}", &style);
+        assert!(!glyphs[1].missing);
+        assert_eq!(width, a);
+        assert_eq!(fonts.missing_chars(), vec!['\u{3042}']);
+    }
+}
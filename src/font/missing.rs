@@ -0,0 +1,144 @@
+// 0からFまでの数字を3x5の点で表す. 各行の下位3bitが左から右の点になる
+const HEX_DIGITS: [[u8; 5]; 16] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+    [0b111, 0b101, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b110, 0b101, 0b110],
+    [0b111, 0b100, 0b100, 0b100, 0b111],
+    [0b110, 0b101, 0b101, 0b101, 0b110],
+    [0b111, 0b100, 0b111, 0b100, 0b111],
+    [0b111, 0b100, 0b111, 0b100, 0b100],
+];
+
+// 箱の高さ(点). 枠と余白、2行の数字とその間隔
+const BOX_HEIGHT: usize = 15;
+
+/// 表示しない文字. glyphがなくても幅0にし、欠けた文字として扱わない
+pub fn is_default_ignorable(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{00AD}'
+                | '\u{034F}'
+                | '\u{061C}'
+                | '\u{115F}'..='\u{1160}'
+                | '\u{17B4}'..='\u{17B5}'
+                | '\u{180B}'..='\u{180F}'
+                | '\u{200B}'..='\u{200F}'
+                | '\u{202A}'..='\u{202E}'
+                | '\u{2060}'..='\u{206F}'
+                | '\u{3164}'
+                | '\u{FE00}'..='\u{FE0F}'
+                | '\u{FEFF}'
+                | '\u{FFA0}'
+                | '\u{FFF0}'..='\u{FFF8}'
+                | '\u{1BCA0}'..='\u{1BCA3}'
+                | '\u{1D173}'..='\u{1D17A}'
+                | '\u{E0000}'..='\u{E0FFF}'
+        )
+}
+
+// BMPの文字は4桁を2x2に、それ以外は6桁を3x2に並べる
+fn hex_digits(c: char) -> Vec<usize> {
+    let code = c as u32;
+    let count = if code > 0xFFFF { 6 } else { 4 };
+    (0..count)
+        .rev()
+        .map(|digit| ((code >> (digit * 4)) & 0xF) as usize)
+        .collect()
+}
+
+// 1つの点の大きさ(px). 箱の高さがfont-sizeの8割ほどになるようにする
+fn dot_size(size: f32) -> usize {
+    (size * 0.8 / BOX_HEIGHT as f32).round().max(1.0) as usize
+}
+
+// 箱の幅(点). 枠と余白、数字とその間隔
+fn box_width(c: char) -> usize {
+    hex_digits(c).len() / 2 * 4 + 3
+}
+
+/// どのfontにもglyphがない文字の代わりに描く箱の送り幅(px). 左右に1点ずつ空ける
+pub fn hex_box_advance(c: char, size: f32) -> f32 {
+    ((box_width(c) + 2) * dot_size(size)) as f32
+}
+
+/*
+code pointの16進数を入れた箱をsubpixelのbitmapにする. 文字の代わりであることがわかるように、
+枠と数字はantialiasせずに塗る. 箱の下端はbaselineより2点下になる
+*/
+pub fn rasterize_hex_box(c: char, size: f32) -> (fontdue::Metrics, Vec<u8>) {
+    let dot = dot_size(size);
+    let digits = hex_digits(c);
+    let columns = digits.len() / 2;
+    let width = box_width(c);
+    let mut dots = vec![false; width * BOX_HEIGHT];
+    for x in 0..width {
+        dots[x] = true;
+        dots[(BOX_HEIGHT - 1) * width + x] = true;
+    }
+    for y in 0..BOX_HEIGHT {
+        dots[y * width] = true;
+        dots[y * width + width - 1] = true;
+    }
+    for (index, digit) in digits.iter().enumerate() {
+        let left = 2 + (index % columns) * 4;
+        let top = 2 + (index / columns) * 6;
+        for (row, bits) in HEX_DIGITS[*digit].iter().enumerate() {
+            for column in 0..3 {
+                if (bits >> (2 - column)) & 1 == 1 {
+                    dots[(top + row) * width + left + column] = true;
+                }
+            }
+        }
+    }
+    // 1つの点をdot px四方に広げる
+    let (pixel_width, pixel_height) = (width * dot, BOX_HEIGHT * dot);
+    let mut bitmap = vec![0; pixel_width * pixel_height * 3];
+    for y in 0..pixel_height {
+        for x in 0..pixel_width {
+            if dots[(y / dot) * width + x / dot] {
+                let index = (y * pixel_width + x) * 3;
+                bitmap[index..index + 3].fill(255);
+            }
+        }
+    }
+    let metrics = fontdue::Metrics {
+        xmin: dot as i32,
+        ymin: -2 * dot as i32,
+        width: pixel_width,
+        height: pixel_height,
+        advance_width: hex_box_advance(c, size),
+        ..Default::default()
+    };
+    (metrics, bitmap)
+}
+
+#[test]
+fn test_hex_box() {
+    assert_eq!(hex_digits('\u{3042}'), vec![3, 0, 4, 2]);
+    assert_eq!(hex_digits('\u{1F600}'), vec![0, 1, 0xF, 6, 0, 0]);
+    // 16pxでは1点が1pxになる
+    assert_eq!(hex_box_advance('\u{3042}', 16.0), 13.0);
+    assert_eq!(hex_box_advance('\u{1F600}', 16.0), 17.0);
+    let (metrics, bitmap) = rasterize_hex_box('\u{3042}', 32.0);
+    assert_eq!((metrics.width, metrics.height), (22, 30));
+    assert_eq!(metrics.advance_width, 26.0);
+    assert_eq!(bitmap.len(), 22 * 30 * 3);
+    // 左上の角は枠、内側の余白は空ける
+    let pixel = |x: usize, y: usize| bitmap[(y * 22 + x) * 3];
+    assert_eq!(pixel(0, 0), 255);
+    assert_eq!(pixel(2, 2), 0);
+    // 最初の数字3の左上の点
+    assert_eq!(pixel(4, 4), 255);
+    assert!(is_default_ignorable('\u{200D}'));
+    assert!(!is_default_ignorable('a'));
+}
@@ -1,4 +1,25 @@
+pub mod cache;
+pub mod color;
+pub mod database;
 pub mod entity;
+pub mod grapheme;
+pub mod kerning;
+pub mod matching;
+pub mod measure;
+pub mod missing;
 pub mod rasterize;
+pub mod script;
+pub mod shaping;
+pub mod vertical;
 
-pub use crate::font::entity::{FontBitmap, FontMetrics};
+pub use crate::font::cache::{FontContext, GlyphCacheStats};
+pub use crate::font::color::ColorGlyphs;
+pub use crate::font::database::{FontDatabase, SelectedFont};
+pub use crate::font::entity::{ColorBitmap, DecorationMetrics, FontBitmap, FontMetrics};
+pub use crate::font::kerning::Kerning;
+pub use crate::font::matching::{FontDescriptor, FontQuery, Synthesis};
+pub use crate::font::measure::{LineHeight, PositionedGlyph, TextMetrics, TextStyle};
+pub use crate::font::rasterize::Antialiasing;
+pub use crate::font::script::{Script, Tag};
+pub use crate::font::shaping::{ShapedGlyph, Shaper};
+pub use crate::font::vertical::TextOrientation;
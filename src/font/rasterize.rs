@@ -1,14 +1,283 @@
+use crate::font::Synthesis;
+
+// 合成するobliqueの傾き(14度)
+const OBLIQUE_SKEW: f32 = 0.249_328;
+
+// 埋め込みのfont
+pub const DEFAULT_FONT: &[u8] = include_bytes!("../../resources/Roboto-Regular.ttf");
+
 // fontdue::Fontの初期化をする
 pub fn init_fontdue() -> fontdue::Font {
-    let font_file = include_bytes!("../../resources/Roboto-Regular.ttf") as &[u8];
-    fontdue::Font::from_bytes(font_file, fontdue::FontSettings::default()).unwrap()
+    fontdue::Font::from_bytes(DEFAULT_FONT, fontdue::FontSettings::default()).unwrap()
+}
+
+/// 文字のantialiasの方法. subpixelは画面のRGBの並びの向きで分ける
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Antialiasing {
+    None,
+    Grayscale,
+    #[default]
+    SubpixelRgb,
+    SubpixelBgr,
+}
+
+impl Antialiasing {
+    /// command lineで指定する名前
+    pub fn from_name(name: &str) -> Option<Antialiasing> {
+        match name {
+            "none" => Some(Antialiasing::None),
+            "grayscale" => Some(Antialiasing::Grayscale),
+            "rgb" => Some(Antialiasing::SubpixelRgb),
+            "bgr" => Some(Antialiasing::SubpixelBgr),
+            _ => None,
+        }
+    }
+
+    /// -webkit-font-smoothingの値を既定の方法に適用する. autoは既定の方法のまま、
+    /// subpixel-antialiasedは既定のRGBの並びを使う
+    pub fn with_font_smoothing(self, keyword: &str) -> Antialiasing {
+        match keyword {
+            "none" => Antialiasing::None,
+            "antialiased" => Antialiasing::Grayscale,
+            "subpixel-antialiased" => match self {
+                Antialiasing::SubpixelBgr => Antialiasing::SubpixelBgr,
+                _ => Antialiasing::SubpixelRgb,
+            },
+            _ => self,
+        }
+    }
+}
+
+/// 太字を合成する時に太くする幅(px). glyphの送り幅もこの分だけ広がる
+pub fn bold_strength(size: f32) -> usize {
+    (size / 24.0).round().max(1.0) as usize
 }
 
+/// glyph indexのglyphをbitmapにする. shapingで文字と対応しないglyphになる場合がある
 pub fn generate_font(
     font_cli: &fontdue::Font,
-    charactor: char,
+    glyph: u16,
     size: f32,
+    synthesis: Synthesis,
 ) -> (fontdue::Metrics, Vec<u8>) {
-    let (metrics, bitmap) = font_cli.rasterize_subpixel(charactor, size);
+    let (mut metrics, mut bitmap) = font_cli.rasterize_indexed_subpixel(glyph, size);
+    if synthesis.bold {
+        bitmap = embolden(&mut metrics, &bitmap, bold_strength(size));
+    }
+    if synthesis.oblique {
+        bitmap = shear(&mut metrics, &bitmap, OBLIQUE_SKEW);
+    }
     (metrics, bitmap)
 }
+
+/*
+subpixelのbitmapは1pxがRGBの3byteになっている.
+各subpixelを右にstrength px分まで引き伸ばして太くする
+*/
+fn embolden(metrics: &mut fontdue::Metrics, bitmap: &[u8], strength: usize) -> Vec<u8> {
+    let row = metrics.width * 3;
+    let new_width = metrics.width + strength;
+    let new_row = new_width * 3;
+    let mut emboldened = vec![0; new_row * metrics.height];
+    for y in 0..metrics.height {
+        for x in 0..new_row {
+            // 左側strength px以内で最も濃い値を使う
+            let from = x.saturating_sub(strength * 3);
+            let to = x.min(row.saturating_sub(1));
+            if from > to || row == 0 {
+                continue;
+            }
+            emboldened[y * new_row + x] = bitmap[y * row + from..=y * row + to]
+                .iter()
+                .copied()
+                .max()
+                .unwrap_or(0);
+        }
+    }
+    metrics.width = new_width;
+    metrics.advance_width += strength as f32;
+    metrics.bounds.width += strength as f32;
+    emboldened
+}
+
+/*
+baselineからの高さに比例して各行を右にずらし、斜体にする.
+baselineより下の行は左にずれるため、bitmapの左端(xmin)も移動する
+*/
+fn shear(metrics: &mut fontdue::Metrics, bitmap: &[u8], skew: f32) -> Vec<u8> {
+    if metrics.height == 0 {
+        return bitmap.to_vec();
+    }
+    let row = metrics.width * 3;
+    // 行ごとのずれをsubpixel単位で求める. y = 0が一番上の行
+    let shifts: Vec<i32> = (0..metrics.height)
+        .map(|y| {
+            let height = metrics.ymin + (metrics.height - 1 - y) as i32;
+            (height as f32 * skew * 3.0).round() as i32
+        })
+        .collect();
+    let min_shift = shifts.iter().min().copied().unwrap_or(0).div_euclid(3);
+    let max_shift = shifts.iter().max().copied().unwrap_or(0) - min_shift * 3;
+    let new_width = metrics.width + (max_shift as usize).div_ceil(3);
+    let new_row = new_width * 3;
+    let mut sheared = vec![0; new_row * metrics.height];
+    for (y, shift) in shifts.iter().enumerate() {
+        let offset = (shift - min_shift * 3) as usize;
+        sheared[y * new_row + offset..y * new_row + offset + row]
+            .copy_from_slice(&bitmap[y * row..(y + 1) * row]);
+    }
+    metrics.xmin += min_shift;
+    metrics.width = new_width;
+    sheared
+}
+
+/*
+glyphの位置の1px未満のずれを、subpixel単位(1/3px)でbitmapを右にずらして表す.
+ずらした分だけbitmapの幅が1px広がる
+*/
+pub fn shift_subpixel(metrics: &mut fontdue::Metrics, bitmap: &[u8], offset: usize) -> Vec<u8> {
+    if offset == 0 || metrics.width == 0 {
+        return bitmap.to_vec();
+    }
+    let row = metrics.width * 3;
+    let new_row = row + 3;
+    let mut shifted = vec![0; new_row * metrics.height];
+    for y in 0..metrics.height {
+        shifted[y * new_row + offset..y * new_row + offset + row]
+            .copy_from_slice(&bitmap[y * row..(y + 1) * row]);
+    }
+    metrics.width += 1;
+    shifted
+}
+
+/*
+subpixelのbitmapを、antialiasの方法に合わせたRGBそれぞれのcoverageにする.
+grayscaleは3つの値の平均を使い、noneは半分以上覆われたpxだけを塗る
+*/
+pub fn apply_antialiasing(bitmap: &mut [u8], antialiasing: Antialiasing) {
+    for pixel in bitmap.chunks_mut(3) {
+        let average = ((pixel[0] as u16 + pixel[1] as u16 + pixel[2] as u16) / 3) as u8;
+        match antialiasing {
+            Antialiasing::None => pixel.fill(if average >= 128 { 255 } else { 0 }),
+            Antialiasing::Grayscale => pixel.fill(average),
+            Antialiasing::SubpixelRgb => {}
+            // BGRでは右端のsubpixelが赤になるため、左右を入れ替える
+            Antialiasing::SubpixelBgr => pixel.swap(0, 2),
+        }
+    }
+}
+
+/*
+縦書きで横に倒す文字のbitmapを時計回りに90度回転する. 回転後はglyphの上が右を向き、
+原点から下へ進む. subpixelの並びは縦になるため、3つの値の平均をRGBのすべてに使う
+*/
+pub fn rotate_clockwise(metrics: &mut fontdue::Metrics, bitmap: &[u8]) -> Vec<u8> {
+    let (width, height) = (metrics.width, metrics.height);
+    let mut rotated = vec![0; width * height * 3];
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y * width + x) * 3;
+            let value =
+                (bitmap[index] as u16 + bitmap[index + 1] as u16 + bitmap[index + 2] as u16) / 3;
+            // 元の(x, y)は、回転後のx行目の右からy番目になる
+            let new_index = (x * height + (height - 1 - y)) * 3;
+            rotated[new_index..new_index + 3].fill(value as u8);
+        }
+    }
+    let xmin = metrics.xmin;
+    metrics.xmin = metrics.ymin;
+    metrics.ymin = -(xmin + width as i32);
+    metrics.width = height;
+    metrics.height = width;
+    rotated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(width: usize, height: usize, ymin: i32) -> fontdue::Metrics {
+        fontdue::Metrics {
+            width,
+            height,
+            ymin,
+            advance_width: width as f32,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_embolden() {
+        // 1px(3subpixel) x 1行のbitmapを2px太くする
+        let mut metrics = metrics(1, 1, 0);
+        let bitmap = embolden(&mut metrics, &[10, 20, 30], 2);
+        assert_eq!(metrics.width, 3);
+        assert_eq!(metrics.advance_width, 3.0);
+        assert_eq!(bitmap, vec![10, 20, 30, 30, 30, 30, 30, 30, 30]);
+    }
+
+    #[test]
+    fn test_rotate_clockwise() {
+        // 2px x 1行のbitmapは、1px x 2行になる
+        let mut metrics = metrics(2, 1, 3);
+        metrics.xmin = 1;
+        let rotated = rotate_clockwise(&mut metrics, &[30, 30, 30, 90, 90, 90]);
+        assert_eq!((metrics.width, metrics.height), (1, 2));
+        assert_eq!((metrics.xmin, metrics.ymin), (3, -3));
+        // 左端の画素が上に来る
+        assert_eq!(rotated, vec![30, 30, 30, 90, 90, 90]);
+    }
+
+    #[test]
+    fn test_shear() {
+        // baselineの上下に2行ずつある1px幅の縦線
+        let mut metrics = metrics(1, 4, -2);
+        let bitmap = vec![255; 12];
+        // 1px上がるごとに1subpixel右にずれる
+        let sheared = shear(&mut metrics, &bitmap, 1.0 / 3.0);
+        assert_eq!(metrics.xmin, -1);
+        assert_eq!(metrics.width, 3);
+        let rows: Vec<&[u8]> = sheared.chunks(9).collect();
+        assert_eq!(rows[0], &[0, 0, 0, 0, 255, 255, 255, 0, 0]);
+        assert_eq!(rows[3], &[0, 255, 255, 255, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_shift_subpixel() {
+        // 1px x 1行のbitmapを2subpixel右にずらすと2pxになる
+        let mut metrics = metrics(1, 1, 0);
+        let shifted = shift_subpixel(&mut metrics, &[10, 20, 30], 2);
+        assert_eq!(metrics.width, 2);
+        assert_eq!(metrics.advance_width, 1.0);
+        assert_eq!(shifted, vec![0, 0, 10, 20, 30, 0]);
+    }
+
+    #[test]
+    fn test_apply_antialiasing() {
+        let bitmap = [30, 60, 90, 200, 200, 170];
+        let apply = |antialiasing| {
+            let mut bitmap = bitmap;
+            apply_antialiasing(&mut bitmap, antialiasing);
+            bitmap
+        };
+        assert_eq!(apply(Antialiasing::SubpixelRgb), bitmap);
+        assert_eq!(
+            apply(Antialiasing::SubpixelBgr),
+            [90, 60, 30, 170, 200, 200]
+        );
+        assert_eq!(apply(Antialiasing::Grayscale), [60, 60, 60, 190, 190, 190]);
+        assert_eq!(apply(Antialiasing::None), [0, 0, 0, 255, 255, 255]);
+        // autoは既定の方法、subpixel-antialiasedは既定の並びを使う
+        let bgr = Antialiasing::SubpixelBgr;
+        assert_eq!(bgr.with_font_smoothing("auto"), bgr);
+        assert_eq!(bgr.with_font_smoothing("subpixel-antialiased"), bgr);
+        assert_eq!(
+            Antialiasing::None.with_font_smoothing("subpixel-antialiased"),
+            Antialiasing::SubpixelRgb
+        );
+        assert_eq!(
+            bgr.with_font_smoothing("antialiased"),
+            Antialiasing::Grayscale
+        );
+    }
+}
@@ -0,0 +1,418 @@
+/*
+shapingに使う文字の性質. scriptの判定、結合文字、アラビア文字の連結の形、
+インド系文字の音節の並べ替えを行う. 表は主要な文字だけを持つ
+*/
+
+/// OpenTypeのscript, feature, lookupの名前
+pub type Tag = [u8; 4];
+
+/// 文字の用字(Unicode Script property)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Script {
+    // 数字や記号などどの用字でも使う文字と、前の文字の用字を引き継ぐ結合文字
+    Common,
+    Inherited,
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Hebrew,
+    Arabic,
+    Syriac,
+    Thaana,
+    Devanagari,
+    Bengali,
+    Gurmukhi,
+    Gujarati,
+    Oriya,
+    Tamil,
+    Telugu,
+    Kannada,
+    Malayalam,
+    Thai,
+    Lao,
+    Tibetan,
+    Myanmar,
+    Georgian,
+    Hangul,
+    Khmer,
+    Han,
+    Hiragana,
+    Katakana,
+}
+
+impl Script {
+    pub fn of(c: char) -> Script {
+        if is_mark(c) || matches!(c, '\u{200C}' | '\u{200D}') {
+            return Script::Inherited;
+        }
+        let code = c as u32;
+        match code {
+            0x0041..=0x005A | 0x0061..=0x007A | 0x00AA | 0x00BA => Script::Latin,
+            0x00C0..=0x00D6 | 0x00D8..=0x00F6 | 0x00F8..=0x024F => Script::Latin,
+            0x1E00..=0x1EFF | 0x2C60..=0x2C7F | 0xA720..=0xA7FF | 0xFB00..=0xFB06 => Script::Latin,
+            0x0370..=0x03FF | 0x1F00..=0x1FFF => Script::Greek,
+            0x0400..=0x052F | 0x2DE0..=0x2DFF | 0xA640..=0xA69F => Script::Cyrillic,
+            0x0531..=0x058F | 0xFB13..=0xFB17 => Script::Armenian,
+            0x0590..=0x05FF | 0xFB1D..=0xFB4F => Script::Hebrew,
+            // 連結に使うtatweel(U+0640)や句読点もアラビア文字の中で形を変えるため含める
+            0x0600..=0x06FF | 0x0750..=0x077F | 0x08A0..=0x08FF => Script::Arabic,
+            0xFB50..=0xFDFF | 0xFE70..=0xFEFF => Script::Arabic,
+            0x0700..=0x074F => Script::Syriac,
+            0x0780..=0x07BF => Script::Thaana,
+            0x0900..=0x097F | 0xA8E0..=0xA8FF => Script::Devanagari,
+            0x0980..=0x09FF => Script::Bengali,
+            0x0A00..=0x0A7F => Script::Gurmukhi,
+            0x0A80..=0x0AFF => Script::Gujarati,
+            0x0B00..=0x0B7F => Script::Oriya,
+            0x0B80..=0x0BFF => Script::Tamil,
+            0x0C00..=0x0C7F => Script::Telugu,
+            0x0C80..=0x0CFF => Script::Kannada,
+            0x0D00..=0x0D7F => Script::Malayalam,
+            0x0E00..=0x0E7F => Script::Thai,
+            0x0E80..=0x0EFF => Script::Lao,
+            0x0F00..=0x0FFF => Script::Tibetan,
+            0x1000..=0x109F => Script::Myanmar,
+            0x10A0..=0x10FF | 0x2D00..=0x2D2F => Script::Georgian,
+            0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Script::Hangul,
+            0x1780..=0x17FF => Script::Khmer,
+            0x3041..=0x309F => Script::Hiragana,
+            0x30A0..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F => Script::Katakana,
+            0x2E80..=0x2FDF | 0x3005 | 0x3007 | 0x3021..=0x3029 | 0x3400..=0x4DBF => Script::Han,
+            0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x3FFFD => Script::Han,
+            _ => Script::Common,
+        }
+    }
+
+    /// 右から左に書く用字
+    pub fn is_rtl(self) -> bool {
+        matches!(
+            self,
+            Script::Hebrew | Script::Arabic | Script::Syriac | Script::Thaana
+        )
+    }
+
+    /// 音節の中で文字を並べ替えるインド系の用字
+    pub fn is_indic(self) -> bool {
+        matches!(
+            self,
+            Script::Devanagari
+                | Script::Bengali
+                | Script::Gurmukhi
+                | Script::Gujarati
+                | Script::Oriya
+                | Script::Tamil
+                | Script::Telugu
+                | Script::Kannada
+                | Script::Malayalam
+        )
+    }
+
+    /// 文字の連結で形が変わる用字
+    pub fn is_joining(self) -> bool {
+        matches!(self, Script::Arabic | Script::Syriac)
+    }
+
+    /// OpenTypeのscript tag. インド系の用字は新しい仕様(dev2など)を優先する
+    pub fn tags(self) -> &'static [&'static Tag] {
+        match self {
+            Script::Common | Script::Inherited => &[],
+            Script::Latin => &[b"latn"],
+            Script::Greek => &[b"grek"],
+            Script::Cyrillic => &[b"cyrl"],
+            Script::Armenian => &[b"armn"],
+            Script::Hebrew => &[b"hebr"],
+            Script::Arabic => &[b"arab"],
+            Script::Syriac => &[b"syrc"],
+            Script::Thaana => &[b"thaa"],
+            Script::Devanagari => &[b"dev2", b"deva"],
+            Script::Bengali => &[b"bng2", b"beng"],
+            Script::Gurmukhi => &[b"gur2", b"guru"],
+            Script::Gujarati => &[b"gjr2", b"gujr"],
+            Script::Oriya => &[b"ory2", b"orya"],
+            Script::Tamil => &[b"tml2", b"taml"],
+            Script::Telugu => &[b"tel2", b"telu"],
+            Script::Kannada => &[b"knd2", b"knda"],
+            Script::Malayalam => &[b"mlm2", b"mlym"],
+            Script::Thai => &[b"thai"],
+            Script::Lao => &[b"lao "],
+            Script::Tibetan => &[b"tibt"],
+            Script::Myanmar => &[b"mym2", b"mymr"],
+            Script::Georgian => &[b"geor"],
+            Script::Hangul => &[b"hang"],
+            Script::Khmer => &[b"khmr"],
+            Script::Han => &[b"hani"],
+            Script::Hiragana | Script::Katakana => &[b"kana"],
+        }
+    }
+}
+
+/// 前の文字に重ねて描画する結合文字(General CategoryがMn, Me, Mc)
+pub fn is_mark(c: char) -> bool {
+    let code = c as u32;
+    match code {
+        0x0300..=0x036F | 0x0483..=0x0489 | 0x0591..=0x05BD | 0x05BF | 0x05C1..=0x05C2 => true,
+        0x05C4..=0x05C5 | 0x05C7 | 0x0610..=0x061A | 0x064B..=0x065F | 0x0670 => true,
+        0x06D6..=0x06DC | 0x06DF..=0x06E4 | 0x06E7..=0x06E8 | 0x06EA..=0x06ED => true,
+        0x0711 | 0x0730..=0x074A | 0x07A6..=0x07B0 | 0x08D3..=0x08E1 | 0x08E3..=0x08FF => true,
+        // タイ文字、ラオ文字の母音記号と声調記号
+        0x0E31 | 0x0E34..=0x0E3A | 0x0E47..=0x0E4E => true,
+        0x0EB1 | 0x0EB4..=0x0EBC | 0x0EC8..=0x0ECD => true,
+        0x0F18..=0x0F19 | 0x0F35 | 0x0F37 | 0x0F39 | 0x0F71..=0x0F84 | 0x0F86..=0x0F87 => true,
+        0x0F8D..=0x0FBC | 0x102B..=0x103E | 0x17B4..=0x17D3 => true,
+        0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF | 0x302A..=0x302F => true,
+        0x3099..=0x309A | 0xFE00..=0xFE0F | 0xFE20..=0xFE2F | 0xE0100..=0xE01EF => true,
+        // インド系の用字の母音記号、virama、nukta
+        0x0900..=0x0D7F => is_indic_mark(code & 0x7F),
+        _ => false,
+    }
+}
+
+// インド系の用字の各blockの中で、共通の位置にある記号
+fn is_indic_mark(offset: u32) -> bool {
+    matches!(offset, 0x00..=0x03 | 0x3A..=0x3C | 0x3E..=0x4F | 0x51..=0x57 | 0x62..=0x63)
+}
+
+// アラビア文字の連結の種類(ArabicShaping.txtのJoining_Type)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JoiningType {
+    // 連結しない
+    NonJoining,
+    // 前の文字(右側)とだけ連結する
+    Right,
+    // 前後の文字と連結する
+    Dual,
+    // tatweel, ZWJ. 前後の文字を連結させる
+    Causing,
+    // 結合文字. 連結の判定では読み飛ばす
+    Transparent,
+}
+
+fn joining_type(c: char) -> JoiningType {
+    if is_mark(c) || c == '\u{200B}' {
+        return JoiningType::Transparent;
+    }
+    let code = c as u32;
+    match code {
+        0x0640 | 0x07FA | 0x200D => JoiningType::Causing,
+        0x0622..=0x0625 | 0x0627 | 0x0629 | 0x062F..=0x0632 | 0x0648 => JoiningType::Right,
+        0x0671..=0x0673 | 0x0675..=0x0677 | 0x0688..=0x0699 | 0x06C0 => JoiningType::Right,
+        0x06C3..=0x06CB | 0x06CD | 0x06CF | 0x06D2..=0x06D3 | 0x06D5 | 0x06EE..=0x06EF => {
+            JoiningType::Right
+        }
+        0x0710 | 0x0715..=0x0719 | 0x071E | 0x0728 | 0x072A | 0x072C | 0x072F => JoiningType::Right,
+        0x0620 | 0x0626 | 0x0628 | 0x062A..=0x062E | 0x0633..=0x063F | 0x0641..=0x0647 => {
+            JoiningType::Dual
+        }
+        0x0649..=0x064A | 0x066E..=0x066F | 0x0678..=0x0687 | 0x069A..=0x06BF => JoiningType::Dual,
+        0x06C1..=0x06C2 | 0x06CC | 0x06CE | 0x06D0..=0x06D1 | 0x06FA..=0x06FC | 0x06FF => {
+            JoiningType::Dual
+        }
+        0x0712..=0x0714 | 0x071A..=0x071D | 0x071F..=0x0727 | 0x0729 | 0x072B | 0x072D..=0x072E => {
+            JoiningType::Dual
+        }
+        0x0750..=0x077F | 0x08A0..=0x08B4 => JoiningType::Dual,
+        _ => JoiningType::NonJoining,
+    }
+}
+
+/*
+論理順の文字列の各文字の連結の形(isol, init, medi, fina). 連結しない文字と結合文字はNone.
+前の文字が後ろと連結でき、次の文字が前と連結できる場合に連結する
+*/
+pub fn joining_forms(chars: &[char]) -> Vec<Option<&'static Tag>> {
+    let types: Vec<JoiningType> = chars.iter().map(|c| joining_type(*c)).collect();
+    let neighbor = |indices: &mut dyn Iterator<Item = usize>| {
+        indices
+            .map(|index| types[index])
+            .find(|joining| *joining != JoiningType::Transparent)
+    };
+    (0..chars.len())
+        .map(|index| {
+            let joining = types[index];
+            if !matches!(joining, JoiningType::Right | JoiningType::Dual) {
+                return None;
+            }
+            let previous = neighbor(&mut (0..index).rev());
+            let next = neighbor(&mut (index + 1..chars.len()));
+            let joins_previous = matches!(previous, Some(JoiningType::Dual | JoiningType::Causing));
+            let joins_next = joining == JoiningType::Dual
+                && matches!(
+                    next,
+                    Some(JoiningType::Right | JoiningType::Dual | JoiningType::Causing)
+                );
+            Some(match (joins_previous, joins_next) {
+                (true, true) => b"medi",
+                (true, false) => b"fina",
+                (false, true) => b"init",
+                (false, false) => b"isol",
+            })
+        })
+        .collect()
+}
+
+// インド系の用字の文字の種類. 各blockの中の位置で決まる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IndicCategory {
+    Consonant,
+    Nukta,
+    Virama,
+    // 子音の前に書く母音記号
+    PreBaseMatra,
+    Matra,
+    // anusvaraなど音節の最後に付く記号
+    Modifier,
+    Joiner,
+    Other,
+}
+
+fn indic_category(script: Script, c: char) -> IndicCategory {
+    if matches!(c, '\u{200C}' | '\u{200D}') {
+        return IndicCategory::Joiner;
+    }
+    let offset = c as u32 & 0x7F;
+    match offset {
+        0x15..=0x39 | 0x58..=0x5F => IndicCategory::Consonant,
+        0x3C => IndicCategory::Nukta,
+        0x4D => IndicCategory::Virama,
+        0x3F if matches!(
+            script,
+            Script::Devanagari | Script::Bengali | Script::Gurmukhi | Script::Gujarati
+        ) =>
+        {
+            IndicCategory::PreBaseMatra
+        }
+        0x47 | 0x48 if matches!(script, Script::Bengali | Script::Oriya) => {
+            IndicCategory::PreBaseMatra
+        }
+        0x46..=0x48 if matches!(script, Script::Tamil | Script::Malayalam) => {
+            IndicCategory::PreBaseMatra
+        }
+        0x3A..=0x3B | 0x3E..=0x4C | 0x4E..=0x4F | 0x55..=0x57 | 0x62..=0x63 => IndicCategory::Matra,
+        0x00..=0x03 => IndicCategory::Modifier,
+        _ => IndicCategory::Other,
+    }
+}
+
+/// 音節の先頭でrephになる子音(ra)
+fn is_ra(c: char) -> bool {
+    matches!(c as u32 & 0x7F, 0x30) && !matches!(Script::of(c), Script::Tamil | Script::Malayalam)
+}
+
+/*
+インド系の用字の音節(子音+virama の連続と母音記号)ごとに、子音の前に書く母音記号を音節の先頭へ移す.
+音節の先頭のra+viramaはrephとしてそのまま残し、母音記号はその後ろに置く.
+並べ替えた後の元の文字のindexと音節の番号(1から)、rephかどうかを返す
+*/
+pub fn reorder_syllables(script: Script, chars: &[char]) -> Vec<(usize, usize, bool)> {
+    let categories: Vec<IndicCategory> = chars.iter().map(|c| indic_category(script, *c)).collect();
+    let mut result = vec![];
+    let mut start = 0;
+    let mut syllable = 0;
+    while start < chars.len() {
+        syllable += 1;
+        // 子音(+nukta)をviramaでつないだ列
+        let mut end = start;
+        while end < chars.len() && categories[end] == IndicCategory::Consonant {
+            end += 1;
+            while end < chars.len() && categories[end] == IndicCategory::Nukta {
+                end += 1;
+            }
+            // viramaの後ろに子音がない場合は音節の最後になる
+            if end < chars.len() && categories[end] == IndicCategory::Virama {
+                end += 1;
+                while end < chars.len() && categories[end] == IndicCategory::Joiner {
+                    end += 1;
+                }
+                continue;
+            }
+            break;
+        }
+        if end == start {
+            result.push((start, 0, false));
+            start += 1;
+            continue;
+        }
+        // 母音記号と末尾の記号
+        while end < chars.len()
+            && matches!(
+                categories[end],
+                IndicCategory::Matra
+                    | IndicCategory::PreBaseMatra
+                    | IndicCategory::Modifier
+                    | IndicCategory::Nukta
+            )
+        {
+            end += 1;
+        }
+        let reph = end - start > 2
+            && is_ra(chars[start])
+            && categories[start + 1] == IndicCategory::Virama
+            && categories[start + 2] == IndicCategory::Consonant;
+        let consonants = if reph { start + 2 } else { start };
+        let mut order: Vec<usize> = (start..end).collect();
+        for (index, category) in categories.iter().enumerate().take(end).skip(start) {
+            if *category == IndicCategory::PreBaseMatra {
+                order.retain(|i| *i != index);
+                order.insert(consonants - start, index);
+            }
+        }
+        result.extend(
+            order
+                .into_iter()
+                .map(|index| (index, syllable, reph && index < start + 2)),
+        );
+        start = end;
+    }
+    result
+}
+
+#[test]
+fn test_script() {
+    assert_eq!(Script::of('a'), Script::Latin);
+    assert_eq!(Script::of('\u{0628}'), Script::Arabic);
+    assert_eq!(Script::of('\u{0301}'), Script::Inherited);
+    assert_eq!(Script::of('1'), Script::Common);
+    assert!(Script::Arabic.is_rtl() && !Script::Latin.is_rtl());
+    assert!(is_mark('\u{093F}') && is_mark('\u{0E48}') && !is_mark('\u{0915}'));
+}
+
+#[test]
+fn test_joining_forms() {
+    // باب: 右から順にinit, medi(右側だけ連結するalefはfina), isol
+    let forms = joining_forms(&['\u{0628}', '\u{0627}', '\u{0628}']);
+    assert_eq!(forms, vec![Some(b"init"), Some(b"fina"), Some(b"isol")]);
+    // 結合文字は読み飛ばして連結する
+    let forms = joining_forms(&['\u{0628}', '\u{064E}', '\u{0628}', '\u{0628}']);
+    assert_eq!(
+        forms,
+        vec![Some(b"init"), None, Some(b"medi"), Some(b"fina")]
+    );
+    assert_eq!(joining_forms(&['a', '\u{0628}']), vec![None, Some(b"isol")]);
+}
+
+#[test]
+fn test_reorder_syllables() {
+    // कि: i matraは子音の前に移る
+    let order = reorder_syllables(Script::Devanagari, &['\u{0915}', '\u{093F}']);
+    assert_eq!(order, vec![(1, 1, false), (0, 1, false)]);
+    // क्षि: viramaでつないだ子音の列の前に移る
+    let order = reorder_syllables(
+        Script::Devanagari,
+        &['\u{0915}', '\u{094D}', '\u{0937}', '\u{093F}'],
+    );
+    let indices: Vec<usize> = order.iter().map(|(index, _, _)| *index).collect();
+    assert_eq!(indices, vec![3, 0, 1, 2]);
+    // र्कि: rephのra+viramaは先頭に残す
+    let order = reorder_syllables(
+        Script::Devanagari,
+        &['\u{0930}', '\u{094D}', '\u{0915}', '\u{093F}'],
+    );
+    assert_eq!(
+        order,
+        vec![(0, 1, true), (1, 1, true), (3, 1, false), (2, 1, false)]
+    );
+    // 2つの音節
+    let order = reorder_syllables(Script::Devanagari, &['\u{0915}', '\u{0916}']);
+    assert_eq!(order, vec![(0, 1, false), (1, 2, false)]);
+}
@@ -0,0 +1,1017 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use crate::font::kerning::{parse_class_def, parse_coverage, Reader};
+use crate::font::script::{joining_forms, reorder_syllables, Script, Tag};
+
+/*
+OpenTypeのGSUBとGPOSによるshaping.
+GSUBで文字のglyphを合字や連結の形、音節の中の形に置き換え、GPOSで結合文字の位置を決める.
+kerningはKerningで計算するため、GPOSはmark, mkmk featureだけを使う
+*/
+#[derive(Debug, Default)]
+pub struct Shaper {
+    gsub: Option<LayoutTable>,
+    gpos: Option<LayoutTable>,
+    // GDEFのglyph classとmark attachment class
+    glyph_classes: HashMap<u16, u16>,
+    mark_classes: HashMap<u16, u16>,
+    // lookupのmark filtering setで使うglyphの集合
+    mark_sets: Vec<HashMap<u16, u16>>,
+}
+
+/// shapingの途中と結果のglyph
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapedGlyph {
+    pub glyph: u16,
+    // 元の文字列の中での文字の位置(byte). 合字は最初の文字の位置になる
+    pub cluster: usize,
+    // 結合文字(GDEFがないfontで使う)
+    pub mark: bool,
+    // init, finaなど、一部の文字にだけ使うfeature
+    pub form: Option<&'static Tag>,
+    // インド系の用字の音節の番号. 0は音節の外
+    pub syllable: usize,
+    // GPOSで重ねたglyphのindexと、その原点からの位置(font units)
+    pub attachment: Option<(usize, i16, i16)>,
+}
+
+// GSUBまたはGPOSのscript, feature, lookupの一覧
+#[derive(Debug)]
+struct LayoutTable {
+    // scriptのtagと、既定の言語で使うfeatureのindex
+    scripts: HashMap<Tag, Vec<u16>>,
+    features: Vec<(Tag, Vec<u16>)>,
+    lookups: Vec<Lookup>,
+}
+
+#[derive(Debug)]
+struct Lookup {
+    flag: u16,
+    mark_set: Option<u16>,
+    subtables: Vec<Subtable>,
+}
+
+#[derive(Debug)]
+enum Subtable {
+    Single(HashMap<u16, u16>),
+    // 1つのglyphを複数のglyphにする. alternateは候補のリストとして同じ形で持つ
+    Multiple(HashMap<u16, Vec<u16>>),
+    Alternate(HashMap<u16, Vec<u16>>),
+    // 最初のglyphと、続くglyphの列と合字のglyph
+    Ligature(HashMap<u16, Vec<(Vec<u16>, u16)>>),
+    Context(Context),
+    MarkToBase(MarkAttachment),
+    MarkToLigature(MarkAttachment),
+    MarkToMark(MarkAttachment),
+}
+
+/*
+(chain) context. 前後のglyphの列が一致した場合に、入力の列の中の位置にlookupを適用する.
+format 1はglyph, format 2はclass, format 3はcoverageで列を表す
+*/
+#[derive(Debug)]
+struct Context {
+    format: u16,
+    coverage: HashMap<u16, u16>,
+    // backtrack, input, lookaheadのclass定義
+    class_defs: [HashMap<u16, u16>; 3],
+    // format 1はcoverage index, format 2は最初のglyphのclassで選ぶ. format 3は1つだけ
+    rule_sets: Vec<Vec<ContextRule>>,
+}
+
+#[derive(Debug)]
+struct ContextRule {
+    // backtrackは近い順に並ぶ. inputは最初のglyphを含まない
+    backtrack: Vec<GlyphSet>,
+    input: Vec<GlyphSet>,
+    lookahead: Vec<GlyphSet>,
+    // inputの中の位置と適用するlookup
+    lookups: Vec<(u16, u16)>,
+}
+
+#[derive(Debug)]
+enum GlyphSet {
+    Glyph(u16),
+    Class(u16),
+    Coverage(HashMap<u16, u16>),
+}
+
+// markのclassと位置、baseのclassごとの位置
+#[derive(Debug)]
+struct MarkAttachment {
+    marks: HashMap<u16, (u16, (i16, i16))>,
+    bases: HashMap<u16, Vec<Option<(i16, i16)>>>,
+}
+
+// GDEFのglyph class
+const BASE_GLYPH: u16 = 1;
+const LIGATURE_GLYPH: u16 = 2;
+const MARK_GLYPH: u16 = 3;
+
+// lookup flag
+const IGNORE_BASE_GLYPHS: u16 = 0x0002;
+const IGNORE_LIGATURES: u16 = 0x0004;
+const IGNORE_MARKS: u16 = 0x0008;
+const USE_MARK_FILTERING_SET: u16 = 0x0010;
+
+// contextから呼ぶlookupの深さの上限
+const MAX_NESTING: usize = 8;
+
+// 特に指定がなくても使うfeature
+const DEFAULT_FEATURES: [&Tag; 10] = [
+    b"ccmp", b"locl", b"rlig", b"rclt", b"calt", b"liga", b"clig", b"kern", b"mark", b"mkmk",
+];
+const JOINING_FEATURES: [&Tag; 5] = [b"isol", b"fina", b"medi", b"init", b"mset"];
+const INDIC_FEATURES: [&Tag; 13] = [
+    b"nukt", b"akhn", b"rphf", b"blwf", b"half", b"pstf", b"vatu", b"cjct", b"pres", b"abvs",
+    b"blws", b"psts", b"haln",
+];
+// 形(ShapedGlyph::form)が一致するglyphにだけ使うfeature
+const RESTRICTED_FEATURES: [&Tag; 5] = [b"isol", b"fina", b"medi", b"init", b"rphf"];
+
+impl Shaper {
+    /// fontのdataからGSUB, GPOS, GDEFを読む. 読めないtableは使わない
+    pub fn new(data: &[u8], index: u32) -> Shaper {
+        let face = match ttf_parser::Face::from_slice(data, index) {
+            Ok(face) => face,
+            Err(_) => return Shaper::default(),
+        };
+        let table = |tag: &Tag| {
+            face.table_data(ttf_parser::Tag::from_bytes(tag))
+                .map(Reader)
+        };
+        let mut shaper = Shaper {
+            gsub: table(b"GSUB").and_then(|gsub| parse_layout_table(gsub, false)),
+            gpos: table(b"GPOS").and_then(|gpos| parse_layout_table(gpos, true)),
+            ..Default::default()
+        };
+        if let Some(gdef) = table(b"GDEF") {
+            let class_def = |offset: usize| {
+                let offset = gdef.u16(offset)? as usize;
+                match offset {
+                    0 => None,
+                    _ => parse_class_def(gdef.at(offset)?),
+                }
+            };
+            shaper.glyph_classes = class_def(4).unwrap_or_default();
+            shaper.mark_classes = class_def(10).unwrap_or_default();
+            if gdef.u16(2) >= Some(2) {
+                shaper.mark_sets = parse_mark_sets(gdef).unwrap_or_default();
+            }
+        }
+        shaper
+    }
+
+    /*
+    論理順の文字(文字列の中の位置、文字、glyph)をshapingする.
+    featuresは使うfeatureの指定で、0の場合は既定で使うfeatureも使わない
+    */
+    pub fn shape(
+        &self,
+        chars: &[(usize, char, u16)],
+        script: Script,
+        features: &[(Tag, u32)],
+    ) -> Vec<ShapedGlyph> {
+        let mut glyphs: Vec<ShapedGlyph> = chars
+            .iter()
+            .map(|(cluster, c, glyph)| ShapedGlyph {
+                glyph: *glyph,
+                cluster: *cluster,
+                mark: crate::font::script::is_mark(*c),
+                form: None,
+                syllable: 0,
+                attachment: None,
+            })
+            .collect();
+        let text: Vec<char> = chars.iter().map(|(_, c, _)| *c).collect();
+        if script.is_joining() {
+            for (glyph, form) in glyphs.iter_mut().zip(joining_forms(&text)) {
+                glyph.form = form;
+            }
+        }
+        if script.is_indic() {
+            let original = glyphs.clone();
+            glyphs = reorder_syllables(script, &text)
+                .into_iter()
+                .map(|(index, syllable, reph)| ShapedGlyph {
+                    syllable,
+                    form: if reph { Some(b"rphf") } else { None },
+                    ..original[index].clone()
+                })
+                .collect();
+        }
+
+        let mut enabled: Vec<&Tag> = DEFAULT_FEATURES.to_vec();
+        if script.is_joining() {
+            enabled.extend(JOINING_FEATURES);
+        }
+        if script.is_indic() {
+            enabled.extend(INDIC_FEATURES);
+        }
+        // 後に指定した値を優先する
+        let mut values: HashMap<Tag, u32> = enabled.into_iter().map(|tag| (*tag, 1)).collect();
+        values.extend(features.iter().copied());
+
+        if let Some(gsub) = &self.gsub {
+            for (lookup, forms, value) in gsub.lookups_for(script, &values) {
+                self.apply_lookup(gsub, lookup, &mut glyphs, &forms, value, 0);
+            }
+        }
+        if script.is_indic() {
+            move_reph(&mut glyphs);
+        }
+        if let Some(gpos) = &self.gpos {
+            for (lookup, forms, value) in gpos.lookups_for(script, &values) {
+                self.apply_lookup(gpos, lookup, &mut glyphs, &forms, value, 0);
+            }
+        }
+        glyphs
+    }
+
+    fn glyph_class(&self, glyph: &ShapedGlyph) -> u16 {
+        match self.glyph_classes.get(&glyph.glyph) {
+            Some(class) => *class,
+            None if glyph.mark => MARK_GLYPH,
+            None => BASE_GLYPH,
+        }
+    }
+
+    /// GDEFまたはUnicodeで結合文字とされるglyph
+    pub fn is_mark(&self, glyph: &ShapedGlyph) -> bool {
+        self.glyph_class(glyph) == MARK_GLYPH
+    }
+
+    // lookup flagで読み飛ばすglyph
+    fn skip(&self, lookup: &Lookup, glyph: &ShapedGlyph) -> bool {
+        let class = self.glyph_class(glyph);
+        match class {
+            BASE_GLYPH => lookup.flag & IGNORE_BASE_GLYPHS != 0,
+            LIGATURE_GLYPH => lookup.flag & IGNORE_LIGATURES != 0,
+            MARK_GLYPH => {
+                if lookup.flag & IGNORE_MARKS != 0 {
+                    return true;
+                }
+                if let Some(set) = lookup.mark_set {
+                    return self
+                        .mark_sets
+                        .get(set as usize)
+                        .is_some_and(|set| !set.contains_key(&glyph.glyph));
+                }
+                let attachment_type = lookup.flag >> 8;
+                attachment_type != 0
+                    && self.mark_classes.get(&glyph.glyph).copied().unwrap_or(0) != attachment_type
+            }
+            _ => false,
+        }
+    }
+
+    // indexの後ろ(forwardがfalseの場合は前)で、読み飛ばさない最初のglyph
+    fn neighbor(
+        &self,
+        lookup: &Lookup,
+        glyphs: &[ShapedGlyph],
+        index: usize,
+        forward: bool,
+    ) -> Option<usize> {
+        match forward {
+            true => (index + 1..glyphs.len()).find(|i| !self.skip(lookup, &glyphs[*i])),
+            false => (0..index).rev().find(|i| !self.skip(lookup, &glyphs[*i])),
+        }
+    }
+
+    // 列の先頭から順にlookupを適用する. formsがある場合は形が一致するglyphにだけ使う
+    fn apply_lookup(
+        &self,
+        table: &LayoutTable,
+        index: u16,
+        glyphs: &mut Vec<ShapedGlyph>,
+        forms: &Option<Vec<Tag>>,
+        value: u32,
+        depth: usize,
+    ) {
+        let lookup = match table.lookups.get(index as usize) {
+            Some(lookup) => lookup,
+            None => return,
+        };
+        let mut position = 0;
+        while position < glyphs.len() {
+            let allowed = match (forms, glyphs[position].form) {
+                (None, _) => true,
+                (Some(forms), Some(form)) => forms.contains(form),
+                (Some(_), None) => false,
+            };
+            if allowed && !self.skip(lookup, &glyphs[position]) {
+                if let Some(next) = self.apply_at(table, lookup, glyphs, position, value, depth) {
+                    position = next.max(position + 1);
+                    continue;
+                }
+            }
+            position += 1;
+        }
+    }
+
+    // positionのglyphにlookupを適用する. 適用した場合は次に見る位置を返す
+    fn apply_at(
+        &self,
+        table: &LayoutTable,
+        lookup: &Lookup,
+        glyphs: &mut Vec<ShapedGlyph>,
+        position: usize,
+        value: u32,
+        depth: usize,
+    ) -> Option<usize> {
+        let glyph = glyphs[position].glyph;
+        for subtable in &lookup.subtables {
+            match subtable {
+                Subtable::Single(map) => {
+                    if let Some(substitute) = map.get(&glyph) {
+                        glyphs[position].glyph = *substitute;
+                        return Some(position + 1);
+                    }
+                }
+                Subtable::Multiple(map) => {
+                    if let Some(sequence) = map.get(&glyph) {
+                        let original = glyphs[position].clone();
+                        let replaced = sequence.iter().map(|glyph| ShapedGlyph {
+                            glyph: *glyph,
+                            ..original.clone()
+                        });
+                        glyphs.splice(position..position + 1, replaced);
+                        return Some(position + sequence.len());
+                    }
+                }
+                // 値は1から始まる候補の番号
+                Subtable::Alternate(map) => {
+                    if let Some(alternates) = map.get(&glyph) {
+                        let index = (value.max(1) - 1) as usize;
+                        if let Some(alternate) = alternates.get(index).or(alternates.first()) {
+                            glyphs[position].glyph = *alternate;
+                        }
+                        return Some(position + 1);
+                    }
+                }
+                Subtable::Ligature(map) => {
+                    for (components, ligature) in map.get(&glyph).into_iter().flatten() {
+                        let mut matched = vec![];
+                        let mut current = position;
+                        for component in components {
+                            match self.neighbor(lookup, glyphs, current, true) {
+                                Some(next) if glyphs[next].glyph == *component => {
+                                    matched.push(next);
+                                    current = next;
+                                }
+                                _ => break,
+                            }
+                        }
+                        if matched.len() != components.len() {
+                            continue;
+                        }
+                        glyphs[position].glyph = *ligature;
+                        glyphs[position].mark = false;
+                        // 間で読み飛ばしたmarkは合字の後ろに残る
+                        for index in matched.into_iter().rev() {
+                            glyphs.remove(index);
+                        }
+                        return Some(position + 1);
+                    }
+                }
+                Subtable::Context(context) => {
+                    if let Some(next) =
+                        self.apply_context(table, lookup, context, glyphs, position, depth)
+                    {
+                        return Some(next);
+                    }
+                }
+                Subtable::MarkToBase(attachment)
+                | Subtable::MarkToLigature(attachment)
+                | Subtable::MarkToMark(attachment) => {
+                    let to_mark = matches!(subtable, Subtable::MarkToMark(_));
+                    if let Some(attached) =
+                        self.attach(lookup, attachment, glyphs, position, to_mark)
+                    {
+                        glyphs[position].attachment = Some(attached);
+                        return Some(position + 1);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /*
+    markを重ねるglyphと、その原点からの位置. mark-to-markは直前のmarkに、
+    それ以外は前の結合文字でないglyphに重ねる
+    */
+    fn attach(
+        &self,
+        lookup: &Lookup,
+        attachment: &MarkAttachment,
+        glyphs: &[ShapedGlyph],
+        position: usize,
+        to_mark: bool,
+    ) -> Option<(usize, i16, i16)> {
+        let (class, (mark_x, mark_y)) = *attachment.marks.get(&glyphs[position].glyph)?;
+        let base = match to_mark {
+            true => self
+                .neighbor(lookup, glyphs, position, false)
+                .filter(|base| self.is_mark(&glyphs[*base]))?,
+            false => (0..position)
+                .rev()
+                .find(|index| !self.is_mark(&glyphs[*index]))?,
+        };
+        let anchors = attachment.bases.get(&glyphs[base].glyph)?;
+        let (x, y) = anchors.get(class as usize).copied().flatten()?;
+        Some((base, x - mark_x, y - mark_y))
+    }
+
+    fn apply_context(
+        &self,
+        table: &LayoutTable,
+        lookup: &Lookup,
+        context: &Context,
+        glyphs: &mut Vec<ShapedGlyph>,
+        position: usize,
+        depth: usize,
+    ) -> Option<usize> {
+        let glyph = glyphs[position].glyph;
+        let coverage_index = *context.coverage.get(&glyph)?;
+        let rule_set = match context.format {
+            1 => context.rule_sets.get(coverage_index as usize)?,
+            2 => {
+                let class = context.class_defs[1].get(&glyph).copied().unwrap_or(0);
+                context.rule_sets.get(class as usize)?
+            }
+            _ => context.rule_sets.first()?,
+        };
+        let matches = |set: &GlyphSet, kind: usize, glyph: u16| match set {
+            GlyphSet::Glyph(expected) => *expected == glyph,
+            GlyphSet::Class(class) => {
+                context.class_defs[kind].get(&glyph).copied().unwrap_or(0) == *class
+            }
+            GlyphSet::Coverage(coverage) => coverage.contains_key(&glyph),
+        };
+        // 読み飛ばさないglyphをたどり、列のすべてが一致した場合はその位置を返す
+        let follow = |sets: &[GlyphSet], kind: usize, from: usize, forward: bool| {
+            let mut positions = vec![];
+            let mut current = from;
+            for set in sets {
+                current = self.neighbor(lookup, glyphs, current, forward)?;
+                if !matches(set, kind, glyphs[current].glyph) {
+                    return None;
+                }
+                positions.push(current);
+            }
+            Some(positions)
+        };
+        for rule in rule_set {
+            let input = match follow(&rule.input, 1, position, true) {
+                Some(input) => input,
+                None => continue,
+            };
+            let last = input.last().copied().unwrap_or(position);
+            if follow(&rule.backtrack, 0, position, false).is_none()
+                || follow(&rule.lookahead, 2, last, true).is_none()
+            {
+                continue;
+            }
+            let mut positions = vec![position];
+            positions.extend(input);
+            let mut end = last + 1;
+            if depth < MAX_NESTING {
+                for (sequence_index, lookup_index) in &rule.lookups {
+                    let target = match positions.get(*sequence_index as usize) {
+                        Some(target) => *target,
+                        None => continue,
+                    };
+                    let nested = match table.lookups.get(*lookup_index as usize) {
+                        Some(nested) => nested,
+                        None => continue,
+                    };
+                    let length = glyphs.len();
+                    self.apply_at(table, nested, glyphs, target, 1, depth + 1);
+                    // 合字などで列の長さが変わった分だけ後ろの位置をずらす
+                    let delta = glyphs.len() as isize - length as isize;
+                    for position in positions.iter_mut().filter(|p| **p > target) {
+                        *position = (*position as isize + delta).max(target as isize) as usize;
+                    }
+                    end = (end as isize + delta) as usize;
+                }
+            }
+            return Some(end);
+        }
+        None
+    }
+}
+
+/*
+GSUBの後、rephを音節の最後へ移す. rephは最後の子音の上に描画する.
+FIXME: 後置形の子音や母音記号との前後関係は考慮しない
+*/
+fn move_reph(glyphs: &mut Vec<ShapedGlyph>) {
+    let mut index = 0;
+    while index < glyphs.len() {
+        let glyph = &glyphs[index];
+        let starts_syllable = index == 0 || glyphs[index - 1].syllable != glyph.syllable;
+        if glyph.syllable == 0 || glyph.form != Some(b"rphf") || !starts_syllable {
+            index += 1;
+            continue;
+        }
+        // rphfで合字にならなかった場合はrephではない
+        let count = glyphs[index..]
+            .iter()
+            .take_while(|next| next.syllable == glyph.syllable && next.form == Some(b"rphf"))
+            .count();
+        let end = index
+            + glyphs[index..]
+                .iter()
+                .take_while(|next| next.syllable == glyph.syllable)
+                .count();
+        if count == 1 && end > index + 1 {
+            let reph = glyphs.remove(index);
+            glyphs.insert(end - 1, reph);
+        }
+        index = end;
+    }
+}
+
+impl LayoutTable {
+    /*
+    scriptで使うfeatureのうち、値が0でないもののlookupを、LookupListの順に並べる.
+    形を限定するfeatureだけから使うlookupは、その形のglyphにだけ使う
+    */
+    fn lookups_for(
+        &self,
+        script: Script,
+        values: &HashMap<Tag, u32>,
+    ) -> Vec<(u16, Option<Vec<Tag>>, u32)> {
+        // scriptのtagがない場合は既定のscriptを使う
+        let features = script
+            .tags()
+            .iter()
+            .copied()
+            .chain([b"DFLT", b"dflt", b"latn"])
+            .find_map(|tag| self.scripts.get(tag));
+        let features = match features {
+            Some(features) => features,
+            None => return vec![],
+        };
+        let mut lookups: HashMap<u16, (Option<Vec<Tag>>, u32)> = HashMap::new();
+        for feature_index in features {
+            let (tag, indices) = match self.features.get(*feature_index as usize) {
+                Some(feature) => feature,
+                None => continue,
+            };
+            let value = match values.get(tag) {
+                Some(value) if *value > 0 => *value,
+                _ => continue,
+            };
+            let restricted = RESTRICTED_FEATURES.contains(&tag);
+            for index in indices {
+                let entry = lookups.entry(*index).or_insert((Some(vec![]), value));
+                match (&mut entry.0, restricted) {
+                    (Some(forms), true) => forms.push(*tag),
+                    (forms, _) => *forms = None,
+                }
+            }
+        }
+        let mut lookups: Vec<(u16, Option<Vec<Tag>>, u32)> = lookups
+            .into_iter()
+            .map(|(index, (forms, value))| (index, forms, value))
+            .collect();
+        lookups.sort_by_key(|(index, _, _)| *index);
+        lookups
+    }
+}
+
+const EXTENSION_GSUB: u16 = 7;
+const EXTENSION_GPOS: u16 = 9;
+
+fn tag_at(reader: Reader, offset: usize) -> Option<Tag> {
+    reader.0.get(offset..offset + 4)?.try_into().ok()
+}
+
+fn parse_layout_table(table: Reader, gpos: bool) -> Option<LayoutTable> {
+    let script_list = table.at(table.u16(4)? as usize)?;
+    let feature_list = table.at(table.u16(6)? as usize)?;
+    let lookup_list = table.at(table.u16(8)? as usize)?;
+
+    let mut scripts = HashMap::new();
+    for i in 0..script_list.u16(0)? as usize {
+        let record = 2 + i * 6;
+        let tag = tag_at(script_list, record)?;
+        let script = script_list.at(script_list.u16(record + 4)? as usize)?;
+        // 言語ごとの指定は使わず、既定の言語の指定だけを読む
+        let offset = script.u16(0)? as usize;
+        if offset == 0 {
+            continue;
+        }
+        let lang_sys = script.at(offset)?;
+        let mut features = vec![];
+        let required = lang_sys.u16(2)?;
+        if required != 0xFFFF {
+            features.push(required);
+        }
+        for j in 0..lang_sys.u16(4)? as usize {
+            features.push(lang_sys.u16(6 + j * 2)?);
+        }
+        scripts.insert(tag, features);
+    }
+
+    let mut features = vec![];
+    for i in 0..feature_list.u16(0)? as usize {
+        let record = 2 + i * 6;
+        let tag = tag_at(feature_list, record)?;
+        let feature = feature_list.at(feature_list.u16(record + 4)? as usize)?;
+        let indices = (0..feature.u16(2)? as usize)
+            .map(|j| feature.u16(4 + j * 2))
+            .collect::<Option<Vec<u16>>>()?;
+        features.push((tag, indices));
+    }
+
+    let mut lookups = vec![];
+    for i in 0..lookup_list.u16(0)? as usize {
+        let lookup = lookup_list.at(lookup_list.u16(2 + i * 2)? as usize)?;
+        let lookup_type = lookup.u16(0)?;
+        let flag = lookup.u16(2)?;
+        let count = lookup.u16(4)? as usize;
+        let mark_set = match flag & USE_MARK_FILTERING_SET {
+            0 => None,
+            _ => Some(lookup.u16(6 + count * 2)?),
+        };
+        let mut subtables = vec![];
+        for j in 0..count {
+            let mut subtable = lookup.at(lookup.u16(6 + j * 2)? as usize)?;
+            let mut subtable_type = lookup_type;
+            // extensionは32bitのoffsetで実際のsubtableを指す
+            if lookup_type == if gpos { EXTENSION_GPOS } else { EXTENSION_GSUB } {
+                subtable_type = subtable.u16(2)?;
+                subtable = subtable.at(subtable.u32(4)? as usize)?;
+            }
+            let parsed = match gpos {
+                true => parse_gpos_subtable(subtable, subtable_type),
+                false => parse_gsub_subtable(subtable, subtable_type),
+            };
+            // 読めないsubtableは飛ばす
+            if let Some(parsed) = parsed {
+                subtables.push(parsed);
+            }
+        }
+        lookups.push(Lookup {
+            flag,
+            mark_set,
+            subtables,
+        });
+    }
+    Some(LayoutTable {
+        scripts,
+        features,
+        lookups,
+    })
+}
+
+// u16の数と、その後に続くu16の列
+fn u16_array(reader: Reader, offset: usize) -> Option<Vec<u16>> {
+    (0..reader.u16(offset)? as usize)
+        .map(|i| reader.u16(offset + 2 + i * 2))
+        .collect()
+}
+
+// coverageの順に並んだoffsetの先のglyphの列
+fn sequences(subtable: Reader, coverage: &HashMap<u16, u16>) -> Option<HashMap<u16, Vec<u16>>> {
+    let mut map = HashMap::new();
+    for (&glyph, &index) in coverage {
+        let offset = subtable.u16(6 + index as usize * 2)? as usize;
+        map.insert(glyph, u16_array(subtable.at(offset)?, 0)?);
+    }
+    Some(map)
+}
+
+fn parse_gsub_subtable(subtable: Reader, subtable_type: u16) -> Option<Subtable> {
+    let format = subtable.u16(0)?;
+    let coverage = || parse_coverage(subtable.at(subtable.u16(2)? as usize)?);
+    match (subtable_type, format) {
+        (1, 1) => {
+            let delta = subtable.i16(4)?;
+            let map = coverage()?
+                .into_keys()
+                .map(|glyph| (glyph, glyph.wrapping_add(delta as u16)))
+                .collect();
+            Some(Subtable::Single(map))
+        }
+        (1, 2) => {
+            let mut map = HashMap::new();
+            for (glyph, index) in coverage()? {
+                map.insert(glyph, subtable.u16(6 + index as usize * 2)?);
+            }
+            Some(Subtable::Single(map))
+        }
+        (2, 1) => Some(Subtable::Multiple(sequences(subtable, &coverage()?)?)),
+        (3, 1) => Some(Subtable::Alternate(sequences(subtable, &coverage()?)?)),
+        (4, 1) => {
+            let mut map = HashMap::new();
+            for (glyph, index) in coverage()? {
+                let set = subtable.at(subtable.u16(6 + index as usize * 2)? as usize)?;
+                let mut ligatures = vec![];
+                for offset in u16_array(set, 0)? {
+                    let ligature = set.at(offset as usize)?;
+                    let count = ligature.u16(2)? as usize;
+                    let components = (0..count.saturating_sub(1))
+                        .map(|i| ligature.u16(4 + i * 2))
+                        .collect::<Option<Vec<u16>>>()?;
+                    ligatures.push((components, ligature.u16(0)?));
+                }
+                map.insert(glyph, ligatures);
+            }
+            Some(Subtable::Ligature(map))
+        }
+        (5, _) => parse_context(subtable, false).map(Subtable::Context),
+        (6, _) => parse_context(subtable, true).map(Subtable::Context),
+        // reverse chaining(8)は使わない
+        _ => None,
+    }
+}
+
+fn parse_gpos_subtable(subtable: Reader, subtable_type: u16) -> Option<Subtable> {
+    match subtable_type {
+        4 => parse_mark_attachment(subtable, false).map(Subtable::MarkToBase),
+        5 => parse_mark_attachment(subtable, true).map(Subtable::MarkToLigature),
+        6 => parse_mark_attachment(subtable, false).map(Subtable::MarkToMark),
+        // pair adjustmentはKerningで使う. その他の位置の調整は使わない
+        _ => None,
+    }
+}
+
+fn parse_anchor(anchor: Reader) -> Option<(i16, i16)> {
+    Some((anchor.i16(2)?, anchor.i16(4)?))
+}
+
+// classごとのanchorのoffsetの表. offsetが0の場合はanchorがない
+fn parse_anchor_row(
+    reader: Reader,
+    base: Reader,
+    offset: usize,
+    class_count: usize,
+) -> Option<Vec<Option<(i16, i16)>>> {
+    (0..class_count)
+        .map(|class| match reader.u16(offset + class * 2)? {
+            0 => Some(None),
+            anchor => parse_anchor(base.at(anchor as usize)?).map(Some),
+        })
+        .collect()
+}
+
+/*
+mark-to-base, mark-to-ligature, mark-to-markのsubtable. 構造は同じで、
+合字の場合は最後の構成要素のanchorを使う
+*/
+fn parse_mark_attachment(subtable: Reader, ligature: bool) -> Option<MarkAttachment> {
+    let mark_coverage = parse_coverage(subtable.at(subtable.u16(2)? as usize)?)?;
+    let base_coverage = parse_coverage(subtable.at(subtable.u16(4)? as usize)?)?;
+    let class_count = subtable.u16(6)? as usize;
+    let mark_array = subtable.at(subtable.u16(8)? as usize)?;
+    let base_array = subtable.at(subtable.u16(10)? as usize)?;
+
+    let mut marks = HashMap::new();
+    for (glyph, index) in mark_coverage {
+        let record = 2 + index as usize * 4;
+        let class = mark_array.u16(record)?;
+        let anchor = parse_anchor(mark_array.at(mark_array.u16(record + 2)? as usize)?)?;
+        marks.insert(glyph, (class, anchor));
+    }
+    let mut bases = HashMap::new();
+    for (glyph, index) in base_coverage {
+        let anchors = match ligature {
+            true => {
+                let attach = base_array.at(base_array.u16(2 + index as usize * 2)? as usize)?;
+                let components = attach.u16(0)? as usize;
+                if components == 0 {
+                    continue;
+                }
+                let offset = 2 + (components - 1) * class_count * 2;
+                parse_anchor_row(attach, attach, offset, class_count)?
+            }
+            false => {
+                let offset = 2 + index as usize * class_count * 2;
+                parse_anchor_row(base_array, base_array, offset, class_count)?
+            }
+        };
+        bases.insert(glyph, anchors);
+    }
+    Some(MarkAttachment { marks, bases })
+}
+
+// lookup recordの列(入力の中の位置、lookupのindex)
+fn lookup_records(reader: Reader, offset: usize, count: usize) -> Option<Vec<(u16, u16)>> {
+    (0..count)
+        .map(|i| Some((reader.u16(offset + i * 4)?, reader.u16(offset + i * 4 + 2)?)))
+        .collect()
+}
+
+fn parse_context(subtable: Reader, chain: bool) -> Option<Context> {
+    let format = subtable.u16(0)?;
+    let mut context = Context {
+        format,
+        coverage: HashMap::new(),
+        class_defs: Default::default(),
+        rule_sets: vec![],
+    };
+    if format == 3 {
+        let coverages = |offset: usize| -> Option<(Vec<GlyphSet>, usize)> {
+            let count = subtable.u16(offset)? as usize;
+            let sets = (0..count)
+                .map(|i| {
+                    let coverage = subtable.at(subtable.u16(offset + 2 + i * 2)? as usize)?;
+                    parse_coverage(coverage).map(GlyphSet::Coverage)
+                })
+                .collect::<Option<Vec<GlyphSet>>>()?;
+            Some((sets, offset + 2 + count * 2))
+        };
+        let rule = match chain {
+            true => {
+                let (backtrack, offset) = coverages(2)?;
+                let (mut input, offset) = coverages(offset)?;
+                let (lookahead, offset) = coverages(offset)?;
+                let count = subtable.u16(offset)? as usize;
+                let lookups = lookup_records(subtable, offset + 2, count)?;
+                let first = match input.first() {
+                    Some(_) => input.remove(0),
+                    None => return None,
+                };
+                (first, backtrack, input, lookahead, lookups)
+            }
+            false => {
+                let glyph_count = subtable.u16(2)? as usize;
+                let lookup_count = subtable.u16(4)? as usize;
+                let mut input = (0..glyph_count)
+                    .map(|i| {
+                        let coverage = subtable.at(subtable.u16(6 + i * 2)? as usize)?;
+                        parse_coverage(coverage).map(GlyphSet::Coverage)
+                    })
+                    .collect::<Option<Vec<GlyphSet>>>()?;
+                let lookups = lookup_records(subtable, 6 + glyph_count * 2, lookup_count)?;
+                if input.is_empty() {
+                    return None;
+                }
+                let first = input.remove(0);
+                (first, vec![], input, vec![], lookups)
+            }
+        };
+        let (first, backtrack, input, lookahead, lookups) = rule;
+        context.coverage = match first {
+            GlyphSet::Coverage(coverage) => coverage,
+            _ => return None,
+        };
+        context.rule_sets.push(vec![ContextRule {
+            backtrack,
+            input,
+            lookahead,
+            lookups,
+        }]);
+        return Some(context);
+    }
+
+    context.coverage = parse_coverage(subtable.at(subtable.u16(2)? as usize)?)?;
+    // format 2はclass定義の後にrule setのoffsetが並ぶ
+    let sets_offset = match (format, chain) {
+        (1, _) => 4,
+        (2, false) => {
+            context.class_defs[1] = parse_class_def(subtable.at(subtable.u16(4)? as usize)?)?;
+            6
+        }
+        (2, true) => {
+            for (kind, offset) in [4, 6, 8].iter().enumerate() {
+                context.class_defs[kind] = match subtable.u16(*offset)? {
+                    0 => HashMap::new(),
+                    offset => parse_class_def(subtable.at(offset as usize)?)?,
+                };
+            }
+            10
+        }
+        _ => return None,
+    };
+    let set = |value: u16| match format {
+        1 => GlyphSet::Glyph(value),
+        _ => GlyphSet::Class(value),
+    };
+    for i in 0..subtable.u16(sets_offset)? as usize {
+        let offset = subtable.u16(sets_offset + 2 + i * 2)? as usize;
+        if offset == 0 {
+            context.rule_sets.push(vec![]);
+            continue;
+        }
+        let rule_set = subtable.at(offset)?;
+        let mut rules = vec![];
+        for rule_offset in u16_array(rule_set, 0)? {
+            let rule = rule_set.at(rule_offset as usize)?;
+            let parsed = match chain {
+                true => {
+                    let backtrack = u16_array(rule, 0)?;
+                    let mut offset = 2 + backtrack.len() * 2;
+                    let input_count = rule.u16(offset)? as usize;
+                    let input = (0..input_count.saturating_sub(1))
+                        .map(|j| rule.u16(offset + 2 + j * 2))
+                        .collect::<Option<Vec<u16>>>()?;
+                    offset += 2 + input.len() * 2;
+                    let lookahead = u16_array(rule, offset)?;
+                    offset += 2 + lookahead.len() * 2;
+                    let count = rule.u16(offset)? as usize;
+                    ContextRule {
+                        backtrack: backtrack.into_iter().map(set).collect(),
+                        input: input.into_iter().map(set).collect(),
+                        lookahead: lookahead.into_iter().map(set).collect(),
+                        lookups: lookup_records(rule, offset + 2, count)?,
+                    }
+                }
+                false => {
+                    let glyph_count = rule.u16(0)? as usize;
+                    let lookup_count = rule.u16(2)? as usize;
+                    let input = (0..glyph_count.saturating_sub(1))
+                        .map(|j| rule.u16(4 + j * 2))
+                        .collect::<Option<Vec<u16>>>()?;
+                    let offset = 4 + input.len() * 2;
+                    ContextRule {
+                        backtrack: vec![],
+                        input: input.into_iter().map(set).collect(),
+                        lookahead: vec![],
+                        lookups: lookup_records(rule, offset, lookup_count)?,
+                    }
+                }
+            };
+            rules.push(parsed);
+        }
+        context.rule_sets.push(rules);
+    }
+    Some(context)
+}
+
+// GDEFのmark glyph set. coverageのoffsetは32bit
+fn parse_mark_sets(gdef: Reader) -> Option<Vec<HashMap<u16, u16>>> {
+    let offset = gdef.u16(12)? as usize;
+    if offset == 0 {
+        return None;
+    }
+    let sets = gdef.at(offset)?;
+    (0..sets.u16(2)? as usize)
+        .map(|i| parse_coverage(sets.at(sets.u32(4 + i * 4)? as usize)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::rasterize::DEFAULT_FONT;
+
+    fn shape(text: &str, features: &[(Tag, u32)]) -> Vec<u16> {
+        let face = ttf_parser::Face::from_slice(DEFAULT_FONT, 0).unwrap();
+        let chars: Vec<(usize, char, u16)> = text
+            .char_indices()
+            .map(|(index, c)| (index, c, face.glyph_index(c).unwrap().0))
+            .collect();
+        Shaper::new(DEFAULT_FONT, 0)
+            .shape(&chars, Script::Latin, features)
+            .into_iter()
+            .map(|glyph| glyph.glyph)
+            .collect()
+    }
+
+    #[test]
+    fn test_ligature() {
+        // fiは合字の1つのglyphになる
+        let ligature = shape("fi", &[]);
+        assert_eq!(ligature.len(), 1);
+        assert_ne!(ligature, shape("f", &[]));
+        // ligaを無効にすると合字にしない
+        assert_eq!(shape("fi", &[(*b"liga", 0)]).len(), 2);
+    }
+
+    #[test]
+    fn test_context() {
+        // 上に付く結合文字の前のiは、ccmpのchain contextで点のないglyphになる
+        let accented = shape("i\u{0301}", &[]);
+        assert_eq!(accented.len(), 2);
+        assert_ne!(accented[0], shape("i", &[])[0]);
+        assert_eq!(shape("i\u{0301}", &[(*b"ccmp", 0)])[0], shape("i", &[])[0]);
+    }
+
+    #[test]
+    fn test_move_reph() {
+        let glyph = |glyph: u16, syllable: usize, reph: bool| ShapedGlyph {
+            glyph,
+            cluster: 0,
+            mark: false,
+            form: if reph { Some(b"rphf") } else { None },
+            syllable,
+            attachment: None,
+        };
+        // rphfで1つのglyphになったrephは音節の最後に移る
+        let mut glyphs = vec![glyph(1, 1, true), glyph(2, 1, false), glyph(3, 2, false)];
+        move_reph(&mut glyphs);
+        let order: Vec<u16> = glyphs.iter().map(|glyph| glyph.glyph).collect();
+        assert_eq!(order, vec![2, 1, 3]);
+        // 合字にならなかったra+viramaは動かさない
+        let mut glyphs = vec![glyph(1, 1, true), glyph(2, 1, true), glyph(3, 1, false)];
+        move_reph(&mut glyphs);
+        assert_eq!(glyphs[0].glyph, 1);
+    }
+}
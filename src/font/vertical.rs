@@ -0,0 +1,74 @@
+/// text-orientation. 縦書きで文字を正立させるか、横に倒すか
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TextOrientation {
+    // 漢字や仮名は正立させ、欧文は時計回りに90度回転する
+    #[default]
+    Mixed,
+    Upright,
+    Sideways,
+}
+
+impl TextOrientation {
+    pub fn from_keyword(keyword: &str) -> Option<TextOrientation> {
+        match keyword.to_lowercase().as_str() {
+            "mixed" => Some(TextOrientation::Mixed),
+            "upright" => Some(TextOrientation::Upright),
+            // sideways-rightはsidewaysの古い名前
+            "sideways" | "sideways-right" => Some(TextOrientation::Sideways),
+            _ => None,
+        }
+    }
+
+    /// 縦書きでこの文字を正立させるかどうか
+    pub fn is_upright(self, c: char) -> bool {
+        match self {
+            TextOrientation::Mixed => is_vertical_upright(c),
+            TextOrientation::Upright => true,
+            TextOrientation::Sideways => false,
+        }
+    }
+}
+
+/*
+UAX #50のVertical_OrientationがU, Tuの文字(の主なもの).
+括弧や長音記号などTrの文字は、縦書き用のglyphがないため回転して描画する
+*/
+fn is_vertical_upright(c: char) -> bool {
+    let code = c as u32;
+    match code {
+        0x00A7 | 0x00A9 | 0x00AE | 0x00B1 | 0x00BC..=0x00BE | 0x00D7 | 0x00F7 => true,
+        // ハングル字母、カナダ先住民音節文字
+        0x1100..=0x11FF | 0x1401..=0x167F | 0x18B0..=0x18FF => true,
+        // 記号、丸数字、図形
+        0x2100..=0x214F | 0x2160..=0x2189 | 0x2460..=0x24FF | 0x25A0..=0x27BF => true,
+        // CJKの記号と句読点、仮名、漢字など. 括弧と長音記号は回転する
+        0x3008..=0x3011 | 0x3014..=0x301F | 0x3030 | 0x30A0 | 0x30FC => false,
+        0x2E80..=0xA4CF | 0xA960..=0xA97F | 0xAC00..=0xD7FF => true,
+        // 私用領域、互換漢字、縦書き用の形、小字形
+        0xE000..=0xFAFF | 0xFE10..=0xFE1F | 0xFE30..=0xFE6F => true,
+        // 全角形. 括弧、コロン、波ダッシュなどは回転する
+        0xFF08 | 0xFF09 | 0xFF1A..=0xFF1E | 0xFF3B | 0xFF3D | 0xFF3F | 0xFF5B..=0xFF60 => false,
+        0xFF01..=0xFF60 | 0xFFE0..=0xFFE6 => true,
+        // 絵文字、CJK統合漢字拡張B以降
+        0x1F000..=0x1FAFF | 0x20000..=0x3FFFD => true,
+        _ => false,
+    }
+}
+
+#[test]
+fn test_text_orientation() {
+    let mixed = TextOrientation::Mixed;
+    assert!(mixed.is_upright('あ'));
+    assert!(mixed.is_upright('漢'));
+    assert!(mixed.is_upright('１'));
+    assert!(!mixed.is_upright('a'));
+    assert!(!mixed.is_upright('1'));
+    assert!(!mixed.is_upright('「'));
+    assert!(!mixed.is_upright('ー'));
+    assert!(TextOrientation::Upright.is_upright('a'));
+    assert!(!TextOrientation::Sideways.is_upright('あ'));
+    assert_eq!(
+        TextOrientation::from_keyword("sideways-right"),
+        Some(TextOrientation::Sideways)
+    );
+}
@@ -6,21 +6,23 @@ pub struct Element {
 
 impl Element {
     pub fn new(name: String) -> Element {
-        let elm_name = element_type(&*name);
+        let elm_name = element_type(&name);
         Element {
             element_data: ElementData {
                 name: elm_name,
                 text: String::from(""),
                 id: String::from(""),
                 class: String::from(""),
+                dir: String::from(""),
             },
             children: vec![],
         }
     }
-    pub fn set_attr(self: &mut Self, key: &str, value: &str) {
+    pub fn set_attr(&mut self, key: &str, value: &str) {
         match key {
             "id" => self.element_data.id = value.to_string(),
             "class" => self.element_data.class = value.to_string(),
+            "dir" => self.element_data.dir = value.to_lowercase(),
             _ => {}
         };
     }
@@ -32,6 +34,8 @@ pub struct ElementData {
     pub text: String,
     pub id: String,
     pub class: String,
+    // 文字の方向(ltr, rtl, auto)
+    pub dir: String,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -49,6 +53,12 @@ pub enum ElementType {
     Em,
     Label,
     Input,
+    Pre,
+    Textarea,
+    Ruby,
+    Rb,
+    Rt,
+    Rp,
     Text,
     Error,
     Other,
@@ -69,7 +79,13 @@ pub fn element_type(name: &str) -> ElementType {
         "a" => ElementType::A,
         "label" => ElementType::Label,
         "input" => ElementType::Input,
+        "pre" => ElementType::Pre,
+        "textarea" => ElementType::Textarea,
         "em" => ElementType::Em,
+        "ruby" => ElementType::Ruby,
+        "rb" => ElementType::Rb,
+        "rt" => ElementType::Rt,
+        "rp" => ElementType::Rp,
         "text" => ElementType::Text,
         "error" => ElementType::Error,
         "" => ElementType::Undefined,
@@ -11,11 +11,8 @@ pub fn parse_nodes(input: &str) -> Element {
     let parser = HTMLParser::parse(Rule::html, input).unwrap();
     let mut element: Element = Element::new(String::from(""));
     for line in parser.into_iter() {
-        match line.as_rule() {
-            Rule::element => {
-                element = parse_element(line);
-            }
-            _ => {}
+        if line.as_rule() == Rule::element {
+            element = parse_element(line);
         }
     }
     element
@@ -23,20 +20,31 @@ pub fn parse_nodes(input: &str) -> Element {
 
 fn parse_element(rule: Pair<Rule>) -> Element {
     let mut element = Element::new(String::from(""));
-    for item in rule.into_inner().into_iter() {
+    for item in rule.into_inner() {
         match item.as_rule() {
-            Rule::elementName => {
-                if element.element_data.name == ElementType::Undefined {
-                    element.element_data.name = element_type(item.as_str());
-                }
+            Rule::elementName if element.element_data.name == ElementType::Undefined => {
+                element.element_data.name = element_type(item.as_str());
             }
             Rule::element => {
                 let child_element = parse_element(item);
                 element.children.push(child_element);
             }
             Rule::text => {
+                let mut text = item.as_str();
+                // pre, textareaの開始タグ直後の改行は無視する
+                if element.children.is_empty()
+                    && matches!(
+                        element.element_data.name,
+                        ElementType::Pre | ElementType::Textarea
+                    )
+                {
+                    text = text
+                        .strip_prefix("\r\n")
+                        .or_else(|| text.strip_prefix('\n'))
+                        .unwrap_or(text);
+                }
                 let mut text_element = Element::new(String::from("text"));
-                text_element.element_data.text = item.as_str().to_string();
+                text_element.element_data.text = text.to_string();
                 element.children.push(text_element);
             }
             Rule::elementAttr => {
@@ -96,13 +104,14 @@ fn test_parse() {
 ",
     );
     assert_eq!(result4.element_data.name, ElementType::Div);
-    // // 直下にspanがあるかどうか
-    assert_eq!(result4.children.len(), 1);
-    assert_eq!(result4.children[0].element_data.name, ElementType::H1);
-    // // spanのさらに下にtextがあるか
-    assert_eq!(result4.children[0].children.len(), 1);
+    // 要素の間の空白はtextとして残る
+    assert_eq!(result4.children.len(), 3);
+    assert_eq!(result4.children[0].element_data.name, ElementType::Text);
+    assert_eq!(result4.children[0].element_data.text, "\n    ");
+    assert_eq!(result4.children[1].element_data.name, ElementType::H1);
+    assert_eq!(result4.children[1].children.len(), 1);
     assert_eq!(
-        result4.children[0].children[0].element_data.name,
+        result4.children[1].children[0].element_data.name,
         ElementType::Text
     );
 
@@ -119,20 +128,21 @@ fn test_parse() {
     </html>
     ",
     );
+    // 空白のtextを除いた子要素
+    fn elements(element: &Element) -> Vec<&Element> {
+        element
+            .children
+            .iter()
+            .filter(|child| child.element_data.name != ElementType::Text)
+            .collect()
+    }
     assert_eq!(result5.element_data.name, ElementType::Html);
-    assert_eq!(result5.children[0].element_data.name, ElementType::Body);
-    assert_eq!(
-        result5.children[0].children[0].element_data.name,
-        ElementType::H1
-    );
+    let body = elements(&result5)[0];
+    assert_eq!(body.element_data.name, ElementType::Body);
+    assert_eq!(elements(body)[0].element_data.name, ElementType::H1);
+    assert_eq!(elements(body)[1].element_data.name, ElementType::Div);
     assert_eq!(
-        result5.children[0].children[1].element_data.name,
-        ElementType::Div
-    );
-    assert_eq!(
-        result5.children[0].children[1].children[0]
-            .element_data
-            .name,
+        elements(elements(body)[1])[0].element_data.name,
         ElementType::P
     );
 
@@ -152,6 +162,21 @@ fn test_parse() {
     assert_eq!(result7.element_data.class, "hi");
     assert_eq!(result7.children[0].element_data.name, ElementType::Text);
     assert_eq!(result7.children[0].element_data.text, "text");
+
+    // 日本語や記号を含むtext
+    let result8 = parse_nodes("<p>今日は「晴れ」です。(really)</p>");
+    assert_eq!(
+        result8.children[0].element_data.text,
+        "今日は「晴れ」です。(really)"
+    );
+
+    // preの開始タグ直後の改行は無視し、それ以外の空白は残す
+    let result9 = parse_nodes("<pre>\nfn main() {\n\tok();\n}\n</pre>");
+    assert_eq!(result9.element_data.name, ElementType::Pre);
+    assert_eq!(
+        result9.children[0].element_data.text,
+        "fn main() {\n\tok();\n}\n"
+    );
 }
 
 #[test]
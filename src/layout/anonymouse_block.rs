@@ -1,4 +1,5 @@
-use crate::layout::entity::Dimensions;
+use crate::font::FontDatabase;
+use crate::layout::entity::{BoxType, CollapsedMargins, Dimensions};
 use crate::layout::layout_box::LayoutBox;
 
 /*
@@ -6,14 +7,42 @@ AnonymousBlockとは、Block要素の直下にinline要素が来た場合に、
 複数のInline要素をまとめて1つのBlockにするための概念的なBlockのこと
 */
 impl<'a> LayoutBox<'a> {
-    pub fn layout_anonymous_block(&mut self, containing_block: Dimensions) {
+    pub fn layout_anonymous_block(&mut self, containing_block: Dimensions, fonts: &FontDatabase) {
         self.set_anonymouse_block_position(containing_block);
-        self.layout_block_children();
+        match self
+            .children
+            .iter()
+            .all(|child| matches!(child.box_type, BoxType::InlineNode(_)))
+        {
+            // inline要素を行に並べ、行の高さの合計をblockの高さにする
+            true => self.layout_inline_children(fonts),
+            false => self.layout_split_root(fonts),
+        }
+        // 行がない場合は前後のmarginを隔てない
+        self.collapsed = CollapsedMargins {
+            through: self.dimensions.content.height == 0.0,
+            ..Default::default()
+        };
+    }
+
+    /*
+    block要素で分けたroot要素の断片を包むAnonymouseBlockと、block要素を縦に並べる.
+    FIXME: 子要素のmarginは相殺しない
+    */
+    fn layout_split_root(&mut self, fonts: &FontDatabase) {
+        let context = self.context;
+        for child in &mut self.children {
+            let child_context = child.inherit_context(context);
+            child.layout(self.dimensions, child_context, fonts);
+            self.dimensions.content.height += child.dimensions.margin_box().height;
+        }
     }
 
     fn set_anonymouse_block_position(&mut self, containing_block: Dimensions) {
         let d = &mut self.dimensions;
         d.content.x = containing_block.content.x;
-        d.content.y = containing_block.content.y;
+        // 先に配置した兄弟要素の下に置く
+        d.content.y = containing_block.content.y + containing_block.content.height;
+        d.content.width = containing_block.content.width;
     }
 }
@@ -0,0 +1,840 @@
+use crate::css::Value;
+
+/*
+UAX #9(Unicode Bidirectional Algorithm)で文字の埋め込みレベルを求め、行を表示順に並べ替える.
+https://www.unicode.org/reports/tr9/
+classの表は主要な文字だけを持ち、表にない文字はLとして扱う
+*/
+
+/// UAX #9のbidi class
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BidiClass {
+    // 強い方向を持つ文字. ALはArabic Letter
+    L,
+    R,
+    AL,
+    // 数字と、数字の間や前後に来る記号
+    EN,
+    ES,
+    ET,
+    AN,
+    CS,
+    NSM,
+    BN,
+    // 段落の区切り、tab、空白、その他の中立の文字
+    B,
+    S,
+    WS,
+    ON,
+    // 明示的な埋め込み、上書き、分離の制御文字
+    LRE,
+    LRO,
+    RLE,
+    RLO,
+    PDF,
+    LRI,
+    RLI,
+    FSI,
+    PDI,
+}
+
+/// direction
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+/// unicode-bidi. inline要素の前後に制御文字を置いたものとして扱う
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UnicodeBidi {
+    #[default]
+    Normal,
+    Embed,
+    Isolate,
+    BidiOverride,
+    IsolateOverride,
+    // 内容の最初の強い文字で方向を決める
+    Plaintext,
+}
+
+// keywordでない値や未対応のkeywordは初期値にする
+fn keyword(value: Option<Value>) -> String {
+    match value {
+        Some(Value::Keyword(keyword)) => keyword.to_lowercase(),
+        _ => String::new(),
+    }
+}
+
+impl Direction {
+    pub fn from_value(value: Option<Value>) -> Option<Direction> {
+        match keyword(value).as_str() {
+            "ltr" => Some(Direction::Ltr),
+            "rtl" => Some(Direction::Rtl),
+            _ => None,
+        }
+    }
+
+    /// 段落の埋め込みレベル
+    pub fn level(self) -> u8 {
+        match self {
+            Direction::Ltr => 0,
+            Direction::Rtl => 1,
+        }
+    }
+}
+
+impl UnicodeBidi {
+    pub fn from_value(value: Option<Value>) -> UnicodeBidi {
+        match keyword(value).as_str() {
+            "embed" => UnicodeBidi::Embed,
+            "isolate" => UnicodeBidi::Isolate,
+            "bidi-override" => UnicodeBidi::BidiOverride,
+            "isolate-override" => UnicodeBidi::IsolateOverride,
+            "plaintext" => UnicodeBidi::Plaintext,
+            _ => UnicodeBidi::Normal,
+        }
+    }
+
+    /// inline要素の開始と終了に置く制御文字
+    pub fn controls(self, direction: Direction) -> (&'static str, &'static str) {
+        let rtl = direction == Direction::Rtl;
+        match self {
+            UnicodeBidi::Normal => ("", ""),
+            UnicodeBidi::Embed if rtl => ("\u{202B}", "\u{202C}"),
+            UnicodeBidi::Embed => ("\u{202A}", "\u{202C}"),
+            UnicodeBidi::Isolate if rtl => ("\u{2067}", "\u{2069}"),
+            UnicodeBidi::Isolate => ("\u{2066}", "\u{2069}"),
+            UnicodeBidi::BidiOverride if rtl => ("\u{202E}", "\u{202C}"),
+            UnicodeBidi::BidiOverride => ("\u{202D}", "\u{202C}"),
+            UnicodeBidi::IsolateOverride if rtl => ("\u{2067}\u{202E}", "\u{202C}\u{2069}"),
+            UnicodeBidi::IsolateOverride => ("\u{2066}\u{202D}", "\u{202C}\u{2069}"),
+            UnicodeBidi::Plaintext => ("\u{2068}", "\u{2069}"),
+        }
+    }
+}
+
+pub fn bidi_class(c: char) -> BidiClass {
+    use BidiClass::*;
+    match c {
+        '\n' | '\r' | '\u{1C}'..='\u{1E}' | '\u{85}' | '\u{2029}' => B,
+        '\t' | '\u{0B}' | '\u{1F}' => S,
+        ' '
+        | '\u{0C}'
+        | '\u{1680}'
+        | '\u{2000}'..='\u{200A}'
+        | '\u{2028}'
+        | '\u{205F}'
+        | '\u{3000}' => WS,
+        '\u{00}'..='\u{08}' | '\u{0E}'..='\u{1B}' | '\u{7F}'..='\u{84}' | '\u{86}'..='\u{9F}' => BN,
+        '\u{00AD}'
+        | '\u{200B}'
+        | '\u{200C}'
+        | '\u{200D}'
+        | '\u{2060}'..='\u{2064}'
+        | '\u{FEFF}' => BN,
+        '\u{202A}' => LRE,
+        '\u{202B}' => RLE,
+        '\u{202C}' => PDF,
+        '\u{202D}' => LRO,
+        '\u{202E}' => RLO,
+        '\u{2066}' => LRI,
+        '\u{2067}' => RLI,
+        '\u{2068}' => FSI,
+        '\u{2069}' => PDI,
+        '\u{200E}' => L,
+        '\u{200F}' => R,
+        '\u{061C}' => AL,
+
+        // ASCII, Latin-1
+        '0'..='9' | '\u{B2}' | '\u{B3}' | '\u{B9}' => EN,
+        '+' | '-' => ES,
+        '#'..='%' | '\u{A2}'..='\u{A5}' | '\u{B0}' | '\u{B1}' => ET,
+        ',' | '.' | '/' | ':' | '\u{A0}' => CS,
+        '!' | '"' | '&'..='*' | ';'..='@' | '['..='`' | '{'..='~' => ON,
+        '\u{A1}'
+        | '\u{A6}'..='\u{A9}'
+        | '\u{AB}'
+        | '\u{AC}'
+        | '\u{AE}'
+        | '\u{AF}'
+        | '\u{B4}'
+        | '\u{B6}'..='\u{B8}'
+        | '\u{BB}'..='\u{BF}'
+        | '\u{D7}'
+        | '\u{F7}' => ON,
+        // 結合文字
+        '\u{0300}'..='\u{036F}' | '\u{20D0}'..='\u{20FF}' | '\u{FE00}'..='\u{FE0F}' => NSM,
+
+        // ヘブライ文字
+        '\u{0591}'..='\u{05BD}'
+        | '\u{05BF}'
+        | '\u{05C1}'
+        | '\u{05C2}'
+        | '\u{05C4}'
+        | '\u{05C5}'
+        | '\u{05C7}' => NSM,
+        '\u{0590}'..='\u{05FF}' | '\u{07C0}'..='\u{085F}' | '\u{FB1D}'..='\u{FB4F}' => R,
+        // アラビア文字
+        '\u{0600}'..='\u{0605}'
+        | '\u{0660}'..='\u{0669}'
+        | '\u{066B}'
+        | '\u{066C}'
+        | '\u{06DD}' => AN,
+        '\u{0609}' | '\u{060A}' | '\u{066A}' => ET,
+        '\u{060C}' => CS,
+        '\u{0610}'..='\u{061A}'
+        | '\u{064B}'..='\u{065F}'
+        | '\u{0670}'
+        | '\u{06D6}'..='\u{06DC}'
+        | '\u{06DF}'..='\u{06E4}'
+        | '\u{06E7}'
+        | '\u{06E8}'
+        | '\u{06EA}'..='\u{06ED}' => NSM,
+        '\u{06F0}'..='\u{06F9}' => EN,
+        '\u{0600}'..='\u{07BF}'
+        | '\u{0860}'..='\u{08FF}'
+        | '\u{FB50}'..='\u{FDFF}'
+        | '\u{FE70}'..='\u{FEFE}' => AL,
+
+        // 一般句読点, 記号
+        '\u{2010}'..='\u{2027}' | '\u{2030}'..='\u{205E}' => match c {
+            '\u{2030}'..='\u{2034}' => ET,
+            '\u{2044}' => CS,
+            _ => ON,
+        },
+        '\u{2070}' | '\u{2074}'..='\u{2079}' | '\u{2080}'..='\u{2089}' => EN,
+        '\u{207A}' | '\u{207B}' | '\u{208A}' | '\u{208B}' => ES,
+        '\u{20A0}'..='\u{20CF}' => ET,
+        '\u{2190}'..='\u{23FF}' | '\u{2500}'..='\u{27FF}' => ON,
+        '\u{3001}'..='\u{3004}' | '\u{3008}'..='\u{3020}' | '\u{FF01}'..='\u{FF0F}' => ON,
+        '\u{10800}'..='\u{10FFF}' | '\u{1E800}'..='\u{1EDFF}' => R,
+        '\u{1EE00}'..='\u{1EEFF}' => AL,
+        _ => L,
+    }
+}
+
+/// L4. 右から左に表示する場合に左右を反転した形の文字
+pub fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '<' => '>',
+        '>' => '<',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '\u{AB}' => '\u{BB}',
+        '\u{BB}' => '\u{AB}',
+        '\u{2039}' => '\u{203A}',
+        '\u{203A}' => '\u{2039}',
+        '\u{2264}' => '\u{2265}',
+        '\u{2265}' => '\u{2264}',
+        '\u{3008}' => '\u{3009}',
+        '\u{3009}' => '\u{3008}',
+        '\u{300A}' => '\u{300B}',
+        '\u{300B}' => '\u{300A}',
+        '\u{300C}' => '\u{300D}',
+        '\u{300D}' => '\u{300C}',
+        '\u{300E}' => '\u{300F}',
+        '\u{300F}' => '\u{300E}',
+        '\u{3010}' => '\u{3011}',
+        '\u{3011}' => '\u{3010}',
+        '\u{FF08}' => '\u{FF09}',
+        '\u{FF09}' => '\u{FF08}',
+        _ => c,
+    }
+}
+
+// BD14, BD15. 対になる括弧. 開き括弧の場合は閉じ括弧を返す
+fn opening_bracket(c: char) -> Option<char> {
+    match c {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '{' => Some('}'),
+        '\u{2045}' => Some('\u{2046}'),
+        '\u{3008}' => Some('\u{3009}'),
+        '\u{300A}' => Some('\u{300B}'),
+        '\u{300C}' => Some('\u{300D}'),
+        '\u{300E}' => Some('\u{300F}'),
+        '\u{3010}' => Some('\u{3011}'),
+        '\u{FF08}' => Some('\u{FF09}'),
+        _ => None,
+    }
+}
+
+fn is_isolate_initiator(class: BidiClass) -> bool {
+    matches!(class, BidiClass::LRI | BidiClass::RLI | BidiClass::FSI)
+}
+
+// X9で取り除く文字
+fn is_removed(class: BidiClass) -> bool {
+    use BidiClass::*;
+    matches!(class, LRE | RLE | LRO | RLO | PDF | BN)
+}
+
+// N1, N2の中立の文字
+fn is_neutral(class: BidiClass) -> bool {
+    use BidiClass::*;
+    matches!(class, B | S | WS | ON | LRI | RLI | FSI | PDI)
+}
+
+// 埋め込みレベルの方向
+fn direction_of(level: u8) -> BidiClass {
+    match level % 2 {
+        0 => BidiClass::L,
+        _ => BidiClass::R,
+    }
+}
+
+/// P2, P3. 分離の外側にある最初の強い文字で段落の埋め込みレベルを決める
+pub fn paragraph_level(text: &str) -> Option<u8> {
+    first_strong(&text.chars().map(bidi_class).collect::<Vec<_>>())
+}
+
+// 分離の内側は数えず、対応するPDIか段落の区切りまでに見つかった強い文字のレベル
+fn first_strong(classes: &[BidiClass]) -> Option<u8> {
+    let mut isolates = 0;
+    for class in classes {
+        match class {
+            BidiClass::L if isolates == 0 => return Some(0),
+            BidiClass::R | BidiClass::AL if isolates == 0 => return Some(1),
+            BidiClass::LRI | BidiClass::RLI | BidiClass::FSI => isolates += 1,
+            BidiClass::PDI if isolates > 0 => isolates -= 1,
+            BidiClass::PDI | BidiClass::B => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+// BD9. 分離の開始に対応するPDIの位置
+fn matching_pdis(classes: &[BidiClass]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; classes.len()];
+    let mut stack: Vec<usize> = vec![];
+    for (index, class) in classes.iter().enumerate() {
+        match class {
+            BidiClass::LRI | BidiClass::RLI | BidiClass::FSI => stack.push(index),
+            BidiClass::PDI => {
+                if let Some(start) = stack.pop() {
+                    matches[start] = Some(index);
+                }
+            }
+            BidiClass::B => stack.clear(),
+            _ => {}
+        }
+    }
+    matches
+}
+
+const MAX_DEPTH: u8 = 125;
+
+// 方向の埋め込みの状態(directional status stack)
+#[derive(Clone, Copy)]
+struct Status {
+    level: u8,
+    // 上書きする方向
+    override_class: Option<BidiClass>,
+    isolate: bool,
+}
+
+/// 文字ごとの埋め込みレベル. 段落の区切りごとにbase_levelから始める
+pub fn resolve_levels(text: &str, base_level: u8) -> Vec<u8> {
+    let chars: Vec<char> = text.chars().collect();
+    let original: Vec<BidiClass> = chars.iter().map(|c| bidi_class(*c)).collect();
+    let mut classes = original.clone();
+    let matches = matching_pdis(&original);
+    let mut levels = explicit_levels(&mut classes, &matches, base_level);
+
+    // X9で取り除く文字を除いた位置
+    let kept: Vec<usize> = (0..chars.len())
+        .filter(|index| !is_removed(original[*index]))
+        .collect();
+    for sequence in isolating_run_sequences(&kept, &levels, &original, &matches) {
+        resolve_sequence(
+            &sequence,
+            &chars,
+            &mut classes,
+            &mut levels,
+            &kept,
+            base_level,
+        );
+    }
+
+    // L1. 段落の区切りとtab、その前と段落の最後の空白は段落のレベルに戻す
+    let mut trailing = true;
+    for index in (0..chars.len()).rev() {
+        match original[index] {
+            BidiClass::B | BidiClass::S => {
+                levels[index] = base_level;
+                trailing = true;
+            }
+            class if trailing && (class == BidiClass::WS || is_removed(class)) => {
+                levels[index] = base_level
+            }
+            class if trailing && (is_isolate_initiator(class) || class == BidiClass::PDI) => {
+                levels[index] = base_level
+            }
+            _ => trailing = false,
+        }
+    }
+    // 取り除いた文字は直前の文字のレベルにする
+    for index in 0..chars.len() {
+        if is_removed(original[index]) {
+            levels[index] = match index {
+                0 => base_level,
+                _ => levels[index - 1],
+            };
+        }
+    }
+    levels
+}
+
+// X1-X8. 明示的な埋め込みと分離のレベル. 上書きされた文字はclassを置き換える
+fn explicit_levels(
+    classes: &mut [BidiClass],
+    matches: &[Option<usize>],
+    base_level: u8,
+) -> Vec<u8> {
+    use BidiClass::*;
+    let base = Status {
+        level: base_level,
+        override_class: None,
+        isolate: false,
+    };
+    let mut stack = vec![base];
+    let mut overflow_isolates = 0;
+    let mut overflow_embeddings = 0;
+    let mut valid_isolates = 0;
+    let mut levels = vec![base_level; classes.len()];
+    for index in 0..classes.len() {
+        let last = *stack.last().unwrap();
+        let next_level = |rtl: bool| match rtl {
+            true => (last.level + 1) | 1,
+            false => (last.level + 2) & !1,
+        };
+        match classes[index] {
+            class @ (RLE | LRE | RLO | LRO) => {
+                levels[index] = last.level;
+                let level = next_level(matches!(class, RLE | RLO));
+                if level <= MAX_DEPTH && overflow_isolates == 0 && overflow_embeddings == 0 {
+                    stack.push(Status {
+                        level,
+                        override_class: match class {
+                            RLO => Some(R),
+                            LRO => Some(L),
+                            _ => None,
+                        },
+                        isolate: false,
+                    });
+                } else if overflow_isolates == 0 {
+                    overflow_embeddings += 1;
+                }
+            }
+            class @ (RLI | LRI | FSI) => {
+                levels[index] = last.level;
+                if let Some(override_class) = last.override_class {
+                    classes[index] = override_class;
+                }
+                let rtl = match class {
+                    RLI => true,
+                    LRI => false,
+                    // 対応するPDIまでの内容の最初の強い文字で方向を決める
+                    _ => {
+                        let end = matches[index].unwrap_or(classes.len());
+                        first_strong(&classes[index + 1..end]) == Some(1)
+                    }
+                };
+                let level = next_level(rtl);
+                if level <= MAX_DEPTH && overflow_isolates == 0 && overflow_embeddings == 0 {
+                    valid_isolates += 1;
+                    stack.push(Status {
+                        level,
+                        override_class: None,
+                        isolate: true,
+                    });
+                } else {
+                    overflow_isolates += 1;
+                }
+            }
+            PDI => {
+                if overflow_isolates > 0 {
+                    overflow_isolates -= 1;
+                } else if valid_isolates > 0 {
+                    overflow_embeddings = 0;
+                    while !stack.last().unwrap().isolate {
+                        stack.pop();
+                    }
+                    stack.pop();
+                    valid_isolates -= 1;
+                }
+                let last = *stack.last().unwrap();
+                levels[index] = last.level;
+                if let Some(override_class) = last.override_class {
+                    classes[index] = override_class;
+                }
+            }
+            PDF => {
+                if overflow_isolates > 0 {
+                } else if overflow_embeddings > 0 {
+                    overflow_embeddings -= 1;
+                } else if !last.isolate && stack.len() >= 2 {
+                    stack.pop();
+                }
+                levels[index] = stack.last().unwrap().level;
+            }
+            // X8. 段落の区切りですべての埋め込みを終える
+            B => {
+                levels[index] = base_level;
+                stack.truncate(1);
+                overflow_isolates = 0;
+                overflow_embeddings = 0;
+                valid_isolates = 0;
+            }
+            BN => levels[index] = last.level,
+            _ => {
+                levels[index] = last.level;
+                if let Some(override_class) = last.override_class {
+                    classes[index] = override_class;
+                }
+            }
+        }
+    }
+    levels
+}
+
+/*
+X10, BD13. 同じレベルの連続(level run)を、分離の開始と対応するPDIでつないだ列.
+列の要素はkeptの中の位置
+*/
+fn isolating_run_sequences(
+    kept: &[usize],
+    levels: &[u8],
+    classes: &[BidiClass],
+    matches: &[Option<usize>],
+) -> Vec<Vec<usize>> {
+    let mut runs: Vec<Vec<usize>> = vec![];
+    for (position, index) in kept.iter().enumerate() {
+        match runs.last_mut() {
+            Some(run) if levels[kept[*run.last().unwrap()]] == levels[*index] => run.push(position),
+            _ => runs.push(vec![position]),
+        }
+    }
+    let mut sequences: Vec<Vec<usize>> = vec![];
+    let mut continued = vec![false; runs.len()];
+    for start in 0..runs.len() {
+        if continued[start] {
+            continue;
+        }
+        let mut sequence = runs[start].clone();
+        let mut current = start;
+        // 分離の開始で終わるlevel runは、対応するPDIで始まるlevel runに続く
+        while let Some(pdi) = matches[kept[*runs[current].last().unwrap()]] {
+            let next = match runs
+                .iter()
+                .position(|run| kept[run[0]] == pdi && classes[pdi] == BidiClass::PDI)
+            {
+                Some(next) => next,
+                None => break,
+            };
+            continued[next] = true;
+            sequence.extend_from_slice(&runs[next]);
+            current = next;
+        }
+        sequences.push(sequence);
+    }
+    sequences
+}
+
+// W1-W7, N0-N2, I1-I2を1つのisolating run sequenceに適用する
+fn resolve_sequence(
+    sequence: &[usize],
+    chars: &[char],
+    classes: &mut [BidiClass],
+    levels: &mut [u8],
+    kept: &[usize],
+    base_level: u8,
+) {
+    use BidiClass::*;
+    let indices: Vec<usize> = sequence.iter().map(|position| kept[*position]).collect();
+    let level = levels[indices[0]];
+    // sos, eos. 列の前後の文字とのレベルの高い方の方向
+    let first = sequence[0];
+    let before = match first {
+        0 => base_level,
+        _ => levels[kept[first - 1]],
+    };
+    let last = *sequence.last().unwrap();
+    let last_index = kept[last];
+    let after = match (
+        kept.get(last + 1),
+        is_isolate_initiator(classes[last_index]),
+    ) {
+        (Some(next), false) => levels[*next],
+        _ => base_level,
+    };
+    let sos = direction_of(level.max(before));
+    let eos = direction_of(level.max(after));
+
+    let mut types: Vec<BidiClass> = indices.iter().map(|index| classes[*index]).collect();
+    let len = types.len();
+
+    // W1. NSMは直前の文字の種類にする
+    for i in 0..len {
+        if types[i] == NSM {
+            types[i] = match i {
+                0 => sos,
+                _ if is_isolate_initiator(types[i - 1]) || types[i - 1] == PDI => ON,
+                _ => types[i - 1],
+            };
+        }
+    }
+    // W2, W3. アラビア文字の後の数字はAN, ALはRにする
+    let mut strong = sos;
+    for class in types.iter_mut() {
+        match *class {
+            L | R | AL => strong = *class,
+            EN if strong == AL => *class = AN,
+            _ => {}
+        }
+    }
+    for class in types.iter_mut() {
+        if *class == AL {
+            *class = R;
+        }
+    }
+    // W4. 数字の間の1つの区切り文字
+    for i in 1..len.saturating_sub(1) {
+        let (previous, next) = (types[i - 1], types[i + 1]);
+        types[i] = match (types[i], previous, next) {
+            (ES, EN, EN) | (CS, EN, EN) => EN,
+            (CS, AN, AN) => AN,
+            (class, _, _) => class,
+        };
+    }
+    // W5. ENに隣接するETの連続はENにする
+    let mut i = 0;
+    while i < len {
+        if types[i] != ET {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < len && types[i] == ET {
+            i += 1;
+        }
+        let adjacent = (start > 0 && types[start - 1] == EN) || (i < len && types[i] == EN);
+        if adjacent {
+            for class in &mut types[start..i] {
+                *class = EN;
+            }
+        }
+    }
+    // W6. 残った区切り文字はONにする
+    for class in types.iter_mut() {
+        if matches!(*class, ES | ET | CS) {
+            *class = ON;
+        }
+    }
+    // W7. Lの後の数字はLにする
+    let mut strong = sos;
+    for class in types.iter_mut() {
+        match *class {
+            L | R => strong = *class,
+            EN if strong == L => *class = L,
+            _ => {}
+        }
+    }
+
+    resolve_brackets(&indices, chars, &mut types, sos, level);
+
+    // N1, N2. 中立の文字の連続は前後の強い方向が同じならその方向、違えば埋め込みの方向にする
+    let strong_of = |class: BidiClass| match class {
+        L => Some(L),
+        R | EN | AN => Some(R),
+        _ => None,
+    };
+    let mut i = 0;
+    while i < len {
+        if !is_neutral(types[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < len && is_neutral(types[i]) {
+            i += 1;
+        }
+        let previous = match start {
+            0 => sos,
+            _ => strong_of(types[start - 1]).unwrap_or(sos),
+        };
+        let next = match i {
+            _ if i == len => eos,
+            _ => strong_of(types[i]).unwrap_or(eos),
+        };
+        let resolved = match previous == next {
+            true => previous,
+            false => direction_of(level),
+        };
+        for class in &mut types[start..i] {
+            *class = resolved;
+        }
+    }
+
+    // I1, I2. 方向と数字に応じてレベルを上げる
+    for (position, index) in indices.iter().enumerate() {
+        let level = levels[*index];
+        levels[*index] = match (level % 2, types[position]) {
+            (0, R) => level + 1,
+            (0, AN) | (0, EN) => level + 2,
+            (1, L) | (1, EN) | (1, AN) => level + 1,
+            _ => level,
+        };
+        classes[*index] = types[position];
+    }
+}
+
+// N0. 対になる括弧は、内側の強い方向に合わせる
+fn resolve_brackets(
+    indices: &[usize],
+    chars: &[char],
+    types: &mut [BidiClass],
+    sos: BidiClass,
+    level: u8,
+) {
+    use BidiClass::*;
+    // BD16. 対になる括弧の位置
+    let mut pairs: Vec<(usize, usize)> = vec![];
+    let mut stack: Vec<(char, usize)> = vec![];
+    for (position, index) in indices.iter().enumerate() {
+        if types[position] != ON {
+            continue;
+        }
+        let c = chars[*index];
+        if let Some(closing) = opening_bracket(c) {
+            // 63を超える入れ子は対を探さない
+            if stack.len() == 63 {
+                break;
+            }
+            stack.push((closing, position));
+        } else if let Some(depth) = stack.iter().rposition(|(closing, _)| *closing == c) {
+            pairs.push((stack[depth].1, position));
+            stack.truncate(depth);
+        }
+    }
+    pairs.sort();
+
+    let embedding = direction_of(level);
+    let strong_of = |class: BidiClass| match class {
+        L => Some(L),
+        R | EN | AN => Some(R),
+        _ => None,
+    };
+    for (open, close) in pairs {
+        let inside: Vec<BidiClass> = types[open + 1..close]
+            .iter()
+            .filter_map(|class| strong_of(*class))
+            .collect();
+        let resolved = if inside.contains(&embedding) {
+            embedding
+        } else if let Some(opposite) = inside.first() {
+            // 括弧の前の強い方向も逆なら、逆の方向にする
+            let before = types[..open]
+                .iter()
+                .rev()
+                .find_map(|class| strong_of(*class))
+                .unwrap_or(sos);
+            match before == *opposite {
+                true => *opposite,
+                false => embedding,
+            }
+        } else {
+            continue;
+        };
+        types[open] = resolved;
+        types[close] = resolved;
+    }
+}
+
+/// L2. 最も高いレベルから奇数のレベルまで順に、そのレベル以上の連続を逆順にした表示順
+pub fn visual_order(levels: &[u8]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    let highest = match levels.iter().max() {
+        Some(highest) => *highest,
+        None => return order,
+    };
+    let lowest_odd = match levels.iter().filter(|level| *level % 2 == 1).min() {
+        Some(lowest) => *lowest,
+        None => return order,
+    };
+    for level in (lowest_odd..=highest).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level {
+                i += 1;
+            }
+            order[start..i].reverse();
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_levels() {
+        // 左から右の段落の中のヘブライ文字. 間の空白は段落の方向になる
+        assert_eq!(
+            resolve_levels("ab \u{5D0}\u{5D1} cd", 0),
+            vec![0, 0, 0, 1, 1, 0, 0, 0]
+        );
+        // 右から左の段落の中の英語と、英語に続く数字
+        assert_eq!(
+            resolve_levels("\u{5D0} ab 12", 1),
+            vec![1, 1, 2, 2, 2, 2, 2]
+        );
+        assert_eq!(resolve_levels("\u{5D0} 12", 1), vec![1, 1, 2, 2]);
+        // アラビア文字の後の数字はアラビア数字として扱い、区切り文字はその間に含める
+        assert_eq!(resolve_levels("\u{627}1,2", 0), vec![1, 2, 2, 2]);
+        // 括弧は内側の方向に合わせる
+        assert_eq!(resolve_levels("\u{5D0}(b)", 1), vec![1, 1, 2, 1]);
+        // RLOは強い文字の方向も上書きする
+        assert_eq!(
+            resolve_levels("a\u{202E}bc\u{202C}d", 0),
+            vec![0, 0, 1, 1, 1, 0]
+        );
+        // 分離の内側は外側の中立の文字の解決に影響しない
+        assert_eq!(
+            resolve_levels("\u{5D0} \u{2066}a\u{2069} \u{5D1}", 1),
+            vec![1, 1, 1, 2, 1, 1, 1]
+        );
+        // 行末の空白は段落のレベルに戻す
+        assert_eq!(resolve_levels("\u{5D0} ", 0), vec![1, 0]);
+        assert_eq!(paragraph_level("123 \u{5D0} a"), Some(1));
+        assert_eq!(paragraph_level("\u{2067}\u{5D0}\u{2069}a"), Some(0));
+        assert_eq!(paragraph_level("123"), None);
+    }
+
+    #[test]
+    fn test_visual_order() {
+        assert_eq!(visual_order(&[0, 0, 1, 1, 0]), vec![0, 1, 3, 2, 4]);
+        // 右から左の中の左から右の連続は、その中の順序を保つ
+        assert_eq!(visual_order(&[1, 2, 2, 1]), vec![3, 1, 2, 0]);
+        assert_eq!(visual_order(&[]), Vec::<usize>::new());
+        assert_eq!(mirror('('), ')');
+        assert_eq!(
+            UnicodeBidi::Isolate.controls(Direction::Rtl),
+            ("\u{2067}", "\u{2069}")
+        );
+    }
+}
@@ -1,121 +1,116 @@
 use crate::css::{Unit, Value};
-use crate::layout::entity::Dimensions;
+use crate::font::FontDatabase;
+use crate::layout::entity::{BoxType, CollapsedMargin, CollapsedMargins, Dimensions};
 use crate::layout::layout_box::LayoutBox;
 
 impl<'a> LayoutBox<'a> {
     // widthは親コンポーネントから計算可能だが、高さは子要素の合計値に左右される
-    pub fn layout_block(&mut self, containing_block: Dimensions) {
+    pub fn layout_block(&mut self, containing_block: Dimensions, fonts: &FontDatabase) {
         // widthは親のコンポーネントから計算できる
         self.set_block_width(containing_block);
         // boxがどの位置にあるのかを計算する
         self.set_block_position(containing_block);
         // boxの子要素を再起的に計算する
-        self.layout_block_children();
+        // textはinline要素としてAnonymouseBlockにまとめられ、行に並べて高さが決まる
+        self.layout_block_children(fonts);
         // heightは子要素の高さに左右されるため、子要素の描画後でないと計算できない
         self.set_block_height();
     }
 
     pub fn set_block_width(&mut self, container_block: Dimensions) {
         let style = self.get_style_node();
-
-        let auto = Value::Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or(auto.clone());
+        // 横方向の%は親要素のwidthを基準にする
+        let length = self.context.length_context(container_block.content.width);
+        // 縦書きではinline方向の始端と終端が上下になる
+        let mode = self.context.writing_mode;
+        let [left, right, _, _] = mode.sides();
+        let (width_name, _) = mode.sizes();
 
         let zero = Value::Length(0.0, Unit::Px);
-        let mut margin_left = style.lookup("margin-left", "margin", &zero);
-        let mut margin_right = style.lookup("margin-right", "margin", &zero);
-
-        let border_left = style.lookup("border-left-width", "border-width", &zero);
-        let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-        let padding_left = style.lookup("padding-left", "padding", &zero);
-        let padding_right = style.lookup("padding-right", "padding", &zero);
-
-        let total_width: f32 = [
-            &margin_left,
-            &margin_right,
-            &border_left,
-            &border_right,
-            &padding_left,
-            &padding_right,
-        ]
-        .iter()
-        .map(|v| v.to_px())
-        .sum();
-
-        // widthがcontainerの大きさを超える場合、marginがautoに指定されているなら値を0にする
-        if width != auto && total_width > container_block.content.width {
-            if margin_left == auto {
-                margin_left = Value::Length(0.0, Unit::Px);
-            }
-            if margin_right == auto {
-                margin_right = Value::Length(0.0, Unit::Px);
-            }
-        }
-
-        // containerの内容がwidthより大きくなってしまった場合の計算
-        let underflow = container_block.content.width - total_width;
+        // autoのmarginはNoneにする
+        let margin = |side: &str| match style.lookup(&format!("margin-{}", side), "margin", &zero) {
+            Value::Keyword(keyword) if keyword == "auto" => None,
+            value => Some(value.resolve(&length)),
+        };
+        let edge =
+            |name: String, fallback: &str| style.lookup(&name, fallback, &zero).resolve(&length);
 
-        match (width == auto, margin_left == auto, margin_right == auto) {
-            // overconstrainedの場合
-            (false, false, false) => {
-                margin_left = Value::Length(margin_right.to_px() + underflow, Unit::Px);
-            }
+        let border_left = style.border_width(left).resolve(&length);
+        let border_right = style.border_width(right).resolve(&length);
 
-            // margin_right, margin_leftのどちらかが指定されている場合、underflowの値を入れる。
-            (false, false, true) => margin_right = Value::Length(underflow, Unit::Px),
-            (false, true, false) => margin_left = Value::Length(underflow, Unit::Px),
+        let padding_left = edge(format!("padding-{}", left), "padding");
+        let padding_right = edge(format!("padding-{}", right), "padding");
 
-            // widthがautoの場合は、他の値は0になる
-            (true, _, _) => {
-                if margin_left == auto {
-                    margin_left = Value::Length(0.0, Unit::Px);
-                }
-                if margin_right == auto {
-                    margin_right = Value::Length(0.0, Unit::Px);
-                }
-
-                if underflow >= 0.0 {
-                    width = Value::Length(underflow, Unit::Px);
-                } else {
-                    width = Value::Length(0.0, Unit::Px);
-                    margin_right = Value::Length(margin_right.to_px() + underflow, Unit::Px);
-                }
+        // box-sizing: border-boxではwidth, min-width, max-widthがborderとpaddingを含む
+        let edges = border_left + border_right + padding_left + padding_right;
+        let border_box = self.border_box_sizing();
+        let size = |name: &str| -> Option<f32> {
+            let value = match style.value(name) {
+                Some(value @ Value::Length(..))
+                | Some(value @ Value::Percentage(_))
+                | Some(value @ Value::Math(_)) => value.resolve(&length),
+                // autoとnone
+                _ => return None,
+            };
+            match border_box {
+                true => Some((value - edges).max(0.0)),
+                false => Some(value),
             }
+        };
+        let solve = |width: Option<f32>| {
+            solve_block_width(
+                container_block.content.width,
+                width,
+                margin(left),
+                margin(right),
+                edges,
+            )
+        };
 
-            // margin-right, margin-leftの両方がautoの場合、underflowの値を半分にする
-            (false, true, true) => {
-                margin_left = Value::Length(underflow / 2.0, Unit::Px);
-                margin_right = Value::Length(underflow / 2.0, Unit::Px);
+        // max-widthを超える場合、min-widthに満たない場合はその値をwidthとして計算し直す
+        let (mut width, mut margin_left, mut margin_right) = solve(size(width_name));
+        if let Some(max) = size(&format!("max-{}", width_name)) {
+            if width > max {
+                (width, margin_left, margin_right) = solve(Some(max));
+            }
+        }
+        if let Some(min) = size(&format!("min-{}", width_name)) {
+            if width < min {
+                (width, margin_left, margin_right) = solve(Some(min));
             }
         }
 
         let d = &mut self.dimensions;
-        d.content.width = width.to_px();
-        d.padding.left = padding_left.to_px();
-        d.padding.right = padding_right.to_px();
+        d.content.width = width;
+        d.padding.left = padding_left;
+        d.padding.right = padding_right;
 
-        d.border.left = border_left.to_px();
-        d.border.right = border_right.to_px();
+        d.border.left = border_left;
+        d.border.right = border_right;
 
-        d.margin.left = margin_left.to_px();
-        d.margin.right = margin_right.to_px();
+        d.margin.left = margin_left;
+        d.margin.right = margin_right;
     }
 
     pub fn set_block_position(&mut self, containing_block: Dimensions) {
         let style = self.get_style_node();
+        // margin, paddingの縦方向の%も親要素のwidthを基準にする
+        let length = self.context.length_context(containing_block.content.width);
+        let [_, _, top, bottom] = self.context.writing_mode.sides();
         let d = &mut self.dimensions;
 
         let zero = Value::Length(0.0, Unit::Px);
+        let edge =
+            |name: String, fallback: &str| style.lookup(&name, fallback, &zero).resolve(&length);
 
-        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+        d.margin.top = edge(format!("margin-{}", top), "margin");
+        d.margin.bottom = edge(format!("margin-{}", bottom), "margin");
 
-        d.border.top = style.lookup("border-top", "border", &zero).to_px();
-        d.border.bottom = style.lookup("border-bottom", "border", &zero).to_px();
+        d.border.top = style.border_width(top).resolve(&length);
+        d.border.bottom = style.border_width(bottom).resolve(&length);
 
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+        d.padding.top = edge(format!("padding-{}", top), "padding");
+        d.padding.bottom = edge(format!("padding-{}", bottom), "padding");
 
         d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
         // boxはすべてのblockの下に配置する
@@ -126,17 +121,481 @@ impl<'a> LayoutBox<'a> {
             + d.padding.top;
     }
 
-    pub fn layout_block_children(&mut self) {
+    /*
+    子要素を縦に並べ、隣接する縦方向のmarginを相殺する. 兄弟要素の間、border, paddingのない親要素と
+    最初と最後の子要素の間、高さのない空のblockの上下のmarginは、正の値の最大と負の値の最小の和にする.
+    新しいblock formatting contextを作るblockは子要素と、writing-modeが変わるblockは他のblockと相殺しない
+    */
+    pub fn layout_block_children(&mut self, fonts: &FontDatabase) {
+        // AnonymouseBlockの行はこのblockのfontの大きさを最小の高さにする
+        let strut = fonts.measure("", &self.text_style());
+        let mode = self.context.writing_mode;
+        let root = self.context.formatting_root;
+        let top_open =
+            !root && self.dimensions.border.top == 0.0 && self.dimensions.padding.top == 0.0;
+        let bottom_open = !root
+            && self.dimensions.border.bottom == 0.0
+            && self.dimensions.padding.bottom == 0.0
+            && self.specified_height().is_none()
+            && self.min_height() == 0.0;
+        let height = self
+            .specified_height()
+            .map(|height| self.clamp_height(height));
         let d = &mut self.dimensions;
+        let mut top = CollapsedMargin::new(d.margin.top);
+        // 最後に配置した子要素の後ろで、次の子要素と相殺するmargin
+        let mut pending = CollapsedMargin::default();
+        // 先頭から高さのない子要素だけが続いている
+        let mut leading = true;
         for child in &mut self.children {
-            child.layout(*d);
-            d.content.height = d.content.height + child.dimensions.margin_box().height;
+            let mut context = child.inherit_context(self.context);
+            context.containing_block_height = height;
+            if matches!(child.box_type, BoxType::AnonymouseBlock) {
+                context.strut = Some(strut);
+            }
+            let position = d.content.y + d.content.height;
+            // writing-modeが変わるblockは、自身の論理座標で配置してから変換する.
+            // 相殺しないmarginはmargin boxの大きさに含める
+            let (y, height, collapsed) = match context.writing_mode == mode {
+                true => {
+                    child.layout(*d, context, fonts);
+                    let border_box = child.dimensions.border_box();
+                    (border_box.y, border_box.height, child.collapsed)
+                }
+                false => {
+                    context.formatting_root = true;
+                    // 直交するblockのblock方向の大きさは、このblockの幅を基準にする
+                    if mode.is_vertical() != context.writing_mode.is_vertical() {
+                        context.containing_block_height = Some(d.content.width);
+                    }
+                    child.layout_writing_mode_root(*d, context, mode, fonts);
+                    // formatting rootのmarginは相殺しないため、dimensions.marginの値をそのまま使える
+                    let margin_box = child.dimensions.margin_box();
+                    (margin_box.y, margin_box.height, CollapsedMargins::default())
+                }
+            };
+            // 親要素の上端のmarginと相殺する子要素は、親要素のcontentの上端に置く
+            let offset = match leading && top_open {
+                true => {
+                    top = top.adjoin(collapsed.top);
+                    0.0
+                }
+                false => {
+                    pending = pending.adjoin(collapsed.top);
+                    pending.resolve()
+                }
+            };
+            let dy = position + offset - y;
+            if dy != 0.0 {
+                child.map_tree(&|mut d| {
+                    d.content.y += dy;
+                    d
+                });
+            }
+            // 空のblockのmarginは次の子要素のmarginとまとめて相殺する
+            if !collapsed.through {
+                leading = false;
+                d.content.height += offset + height;
+                pending = collapsed.bottom;
+            }
+        }
+        // 最後の子要素の下端のmarginは、親要素の下端のmarginと相殺しない場合はcontentに含める
+        let mut bottom = CollapsedMargin::new(d.margin.bottom);
+        match bottom_open {
+            true => bottom = bottom.adjoin(pending),
+            false => d.content.height += pending.resolve(),
+        }
+        let through = leading && top_open && bottom_open;
+        if through {
+            top = top.adjoin(bottom);
+            bottom = top;
+        }
+        self.collapsed = CollapsedMargins {
+            top,
+            bottom,
+            through,
+        };
+    }
+
+    // box-sizing: border-box. 大きさの指定がborderとpaddingを含む
+    fn border_box_sizing(&self) -> bool {
+        matches!(
+            self.get_style_node().value("box-sizing"),
+            Some(Value::Keyword(keyword)) if keyword == "border-box"
+        )
+    }
+
+    /*
+    height, min-height, max-heightの指定値をcontentの高さ(px)にする. %は親要素のheightを基準にし、
+    親要素のheightがautoの場合はautoとして扱う. autoとnoneはNone
+    */
+    fn block_size(&self, name: &str) -> Option<f32> {
+        let basis = self.context.containing_block_height;
+        let length = self.context.length_context(basis.unwrap_or(0.0));
+        let size = match self.get_style_node().value(name) {
+            Some(value @ Value::Length(..)) => value.resolve(&length),
+            Some(value @ Value::Percentage(_)) => {
+                basis?;
+                value.resolve(&length)
+            }
+            Some(Value::Math(node)) if basis.is_some() || !node.has_percentage() => {
+                node.resolve(&length)
+            }
+            _ => return None,
+        };
+        let d = self.dimensions;
+        match self.border_box_sizing() {
+            true => {
+                let edges = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+                Some((size - edges).max(0.0))
+            }
+            false => Some(size),
         }
     }
 
+    // heightの指定値(px). autoの場合はNone
+    fn specified_height(&self) -> Option<f32> {
+        self.block_size(self.context.writing_mode.sizes().1)
+    }
+
+    // min-heightの指定値(px). 指定がない場合は0
+    fn min_height(&self) -> f32 {
+        let (_, height) = self.context.writing_mode.sizes();
+        self.block_size(&format!("min-{}", height)).unwrap_or(0.0)
+    }
+
+    // max-heightとmin-heightの範囲に収める
+    fn clamp_height(&self, height: f32) -> f32 {
+        let (_, name) = self.context.writing_mode.sizes();
+        let height = match self.block_size(&format!("max-{}", name)) {
+            Some(max) => height.min(max),
+            None => height,
+        };
+        height.max(self.min_height())
+    }
+
+    // heightを指定値か子要素の高さにする
     pub fn set_block_height(&mut self) {
-        if let Some(Value::Length(h, Unit::Px)) = self.get_style_node().value("height") {
-            self.dimensions.content.height = h;
+        let height = self
+            .specified_height()
+            .unwrap_or(self.dimensions.content.height);
+        self.dimensions.content.height = self.clamp_height(height);
+    }
+}
+
+/*
+CSS 2.1 10.3.3の式で、containerの幅に合わせてwidthとinline方向のmarginを決める.
+autoのwidthとmarginはNoneにする. edgesはcontentの外側のborderとpaddingの合計
+*/
+fn solve_block_width(
+    container: f32,
+    width: Option<f32>,
+    mut margin_left: Option<f32>,
+    mut margin_right: Option<f32>,
+    edges: f32,
+) -> (f32, f32, f32) {
+    let total =
+        width.unwrap_or(0.0) + margin_left.unwrap_or(0.0) + margin_right.unwrap_or(0.0) + edges;
+
+    // widthがcontainerの大きさを超える場合、marginがautoに指定されているなら値を0にする
+    if width.is_some() && total > container {
+        margin_left = margin_left.or(Some(0.0));
+        margin_right = margin_right.or(Some(0.0));
+    }
+
+    // containerの内容がwidthより大きくなってしまった場合の計算
+    let underflow = container - total;
+
+    match (width, margin_left, margin_right) {
+        // overconstrainedの場合は、inline方向の終端のmarginで調整する
+        (Some(width), Some(left), Some(right)) => (width, left, right + underflow),
+
+        // margin_right, margin_leftのどちらかが指定されている場合、underflowの値を入れる。
+        (Some(width), Some(left), None) => (width, left, underflow),
+        (Some(width), None, Some(right)) => (width, underflow, right),
+
+        // widthがautoの場合は、他の値は0になる
+        (None, left, right) => {
+            let (left, right) = (left.unwrap_or(0.0), right.unwrap_or(0.0));
+            match underflow >= 0.0 {
+                true => (underflow, left, right),
+                false => (0.0, left, right + underflow),
+            }
         }
+
+        // margin-right, margin-leftの両方がautoの場合、underflowの値を半分にする
+        (Some(width), None, None) => (width, underflow / 2.0, underflow / 2.0),
     }
 }
+
+#[test]
+fn test_margin_collapsing() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><div class=\"outer\"><div class=\"first\"></div><div class=\"empty\"></div><div class=\"second\"></div></div><div class=\"padded\"><div class=\"inner\"></div></div><div class=\"last\"></div></div>",
+        ".root { display: block; }
+        .outer { display: block; margin-top: 10px; }
+        .first { display: block; height: 20px; margin-top: 30px; margin-bottom: 15px; }
+        .empty { display: block; margin-top: 25px; margin-bottom: -5px; }
+        .second { display: block; height: 20px; margin-top: 5px; margin-bottom: 40px; }
+        .padded { display: block; padding: 4px; margin-top: 10px; }
+        .inner { display: block; height: 10px; margin-top: 8px; margin-bottom: 8px; }
+        .last { display: block; height: 10px; margin-top: -20px; }",
+        200.0,
+        0.0,
+    );
+    let border_box = |path: &[usize]| {
+        let mut layout_box = &layout;
+        for index in path {
+            layout_box = &layout_box.children[*index];
+        }
+        layout_box.dimensions.border_box()
+    };
+
+    // 親要素と最初の子要素のmarginは相殺し、大きい方の30pxが親要素の外側に出る
+    assert_eq!(border_box(&[0]).y, 30.0);
+    assert_eq!(border_box(&[0, 0]).y, 30.0);
+    // 兄弟要素の間の15px、空のblockの25pxと-5px、次の要素の5pxは25 - 5 = 20pxになる
+    assert_eq!(border_box(&[0, 2]).y, 30.0 + 20.0 + 20.0);
+    // 最後の子要素の下端のmarginは親要素の外側に出る
+    assert_eq!(border_box(&[0]).height, 60.0);
+    // paddingがある親要素は子要素のmarginと相殺しない. 兄弟要素の間は40pxになる
+    let padded = border_box(&[1]);
+    assert_eq!(padded.y, 90.0 + 40.0);
+    assert_eq!(border_box(&[1, 0]).y, padded.y + 4.0 + 8.0);
+    assert_eq!(padded.height, 4.0 + 8.0 + 10.0 + 8.0 + 4.0);
+    // 負のmarginは重なる
+    assert_eq!(border_box(&[2]).y, padded.y + padded.height - 20.0);
+}
+
+#[test]
+fn test_margin_collapsing_last_child() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><div class=\"parent\"><div class=\"child\"></div></div><div class=\"next\"></div></div>",
+        ".root { display: block; }
+        .parent { display: block; margin-bottom: 10px; }
+        .child { display: block; height: 20px; margin-bottom: 30px; }
+        .next { display: block; height: 10px; margin-top: 5px; }",
+        200.0,
+        0.0,
+    );
+    let parent = layout.children[0].dimensions.border_box();
+    // 最後の子要素の30px, 親要素の10px, 次の要素の5pxは相殺して30pxになる
+    assert_eq!(parent.height, 20.0);
+    assert_eq!(layout.children[1].dimensions.border_box().y, 20.0 + 30.0);
+    assert_eq!(layout.dimensions.content.height, 20.0 + 30.0 + 10.0);
+}
+
+#[test]
+fn test_margin_collapsing_border() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><div class=\"top\"><div class=\"child\"></div></div><div class=\"bottom\"><div class=\"child\"></div></div><div class=\"next\"></div></div>",
+        ".root { display: block; }
+        .top { display: block; margin-top: 5px; border-top-style: solid; border-top-width: 2px; }
+        .bottom { display: block; margin-bottom: 5px; border-bottom-style: solid; border-bottom-width: 2px; }
+        .child { display: block; height: 10px; margin-top: 8px; margin-bottom: 8px; }
+        .next { display: block; height: 10px; }",
+        200.0,
+        0.0,
+    );
+    let top = layout.children[0].dimensions.border_box();
+    let child = |index: usize| layout.children[index].children[0].dimensions.border_box();
+    // borderの上側は子要素の上端のmarginを親要素の内側に留める. 下端のmarginは相殺する
+    assert_eq!(top.y, 5.0);
+    assert_eq!(child(0).y, 5.0 + 2.0 + 8.0);
+    assert_eq!(top.height, 2.0 + 8.0 + 10.0);
+    // 兄弟要素の間では、上の要素の下端に出た8pxと子要素の8pxが相殺する
+    let bottom = layout.children[1].dimensions.border_box();
+    assert_eq!(bottom.y, top.y + top.height + 8.0);
+    assert_eq!(child(1).y, bottom.y);
+    // borderの下側は子要素の下端のmarginを親要素の内側に留める
+    assert_eq!(bottom.height, 10.0 + 8.0 + 2.0);
+    assert_eq!(
+        layout.children[2].dimensions.border_box().y,
+        bottom.y + bottom.height + 5.0
+    );
+}
+
+#[test]
+fn test_margin_collapsing_formatting_root() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><div class=\"first\"></div><div class=\"vertical\"><div class=\"child\"></div></div><div class=\"next\"></div></div>",
+        ".root { display: block; }
+        .first { display: block; height: 10px; margin-top: 20px; margin-bottom: 10px; }
+        .vertical { display: block; writing-mode: vertical-lr; width: 30px; height: 40px; margin-top: 20px; margin-bottom: 5px; }
+        .child { display: block; width: 10px; margin-left: 6px; }
+        .next { display: block; height: 10px; margin-top: 5px; }",
+        200.0,
+        0.0,
+    );
+    let d = |index: usize| layout.children[index].dimensions.border_box();
+    // root要素は最初の子要素のmarginと相殺せず、内側に含める
+    assert_eq!(layout.dimensions.border_box().y, 0.0);
+    assert_eq!(d(0).y, 20.0);
+    // writing-modeが変わるblockのmarginは兄弟要素のmarginと相殺しない
+    assert_eq!(d(1).y, 20.0 + 10.0 + 10.0 + 20.0);
+    assert_eq!(d(2).y, d(1).y + d(1).height + 5.0 + 5.0);
+    // 子要素のmarginも親要素の外側に出ない
+    let child = layout.children[1].children[0].dimensions.border_box();
+    assert_eq!(child.x, d(1).x + 6.0);
+}
+
+#[test]
+fn test_margin_collapsing_height() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><div class=\"fixed\"><div class=\"child\"></div></div><div class=\"min\"><div class=\"child\"></div></div><div class=\"next\"></div></div>",
+        ".root { display: block; }
+        .fixed { display: block; height: 50px; }
+        .min { display: block; min-height: 20px; }
+        .child { display: block; height: 10px; margin-bottom: 30px; }
+        .next { display: block; height: 10px; }",
+        200.0,
+        0.0,
+    );
+    let d = |index: usize| layout.children[index].dimensions.border_box();
+    // heightを指定した親要素では、子要素の下端のmarginは外側に出ない
+    assert_eq!(d(0).height, 50.0);
+    assert_eq!(d(1).y, 50.0);
+    // min-heightを指定した場合も親要素の内側に含める
+    assert_eq!(d(1).height, 10.0 + 30.0);
+    assert_eq!(d(2).y, 50.0 + 40.0);
+}
+
+#[test]
+fn test_block_text() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><p class=\"para\">one two three four five six</p><p class=\"para\">one</p></div>",
+        ".root { display: block; }
+        .para { display: block; margin-bottom: 4px; }",
+        60.0,
+        0.0,
+    );
+    // blockの中のtextはAnonymouseBlockの行に並べ、折り返した行の高さの合計がblockの高さになる
+    let para = |index: usize| &layout.children[index];
+    let line = para(1).dimensions.content.height;
+    assert!(matches!(
+        para(0).children[0].box_type,
+        BoxType::AnonymouseBlock
+    ));
+    assert!(line > 0.0);
+    let fragments = &para(0).children[0].children[0].fragments;
+    assert!(fragments.len() > 1);
+    assert_eq!(
+        para(0).dimensions.content.height,
+        line * fragments.len() as f32
+    );
+    assert_eq!(
+        para(1).dimensions.content.y,
+        para(0).dimensions.content.height + 4.0
+    );
+}
+
+#[test]
+fn test_block_sizes() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><div class=\"half\"></div><div class=\"column\"></div><div class=\"narrow\"></div><div class=\"wide\"></div><div class=\"fixed\"><div class=\"inner\"></div></div><div class=\"auto\"><div class=\"inner\"></div></div></div>",
+        ".root { display: block; }
+        .half { display: block; width: 50%; padding: 10px; margin-left: 10%; }
+        .column { display: block; width: 50%; padding: 10px; box-sizing: border-box; }
+        .narrow { display: block; max-width: 80px; margin-left: auto; margin-right: auto; }
+        .wide { display: block; width: 40px; min-width: 25%; min-height: 30px; }
+        .fixed { display: block; height: 100px; max-height: 60px; }
+        .auto { display: block; }
+        .inner { display: block; height: 50%; }",
+        400.0,
+        300.0,
+    );
+    let d = |index: usize| layout.children[index].dimensions;
+
+    // %のwidthとmarginは親要素のwidthを基準にする
+    assert_eq!(d(0).content.width, 200.0);
+    assert_eq!(d(0).margin.left, 40.0);
+    // border-boxではwidthがpaddingを含む
+    assert_eq!(d(1).border_box().width, 200.0);
+    assert_eq!(d(1).content.width, 180.0);
+    // max-widthに縮めてから、autoのmarginで中央に置く
+    assert_eq!(d(2).content.width, 80.0);
+    assert_eq!(d(2).margin.left, 160.0);
+    // min-widthとmin-heightまで広げる
+    assert_eq!(d(3).content.width, 100.0);
+    assert_eq!(d(3).content.height, 30.0);
+    // heightはmax-heightまで縮め、子要素の%は縮めたheightを基準にする
+    assert_eq!(d(4).content.height, 60.0);
+    assert_eq!(
+        layout.children[4].children[0].dimensions.content.height,
+        30.0
+    );
+    // heightがautoの親要素の中では%をautoとして扱う
+    assert_eq!(
+        layout.children[5].children[0].dimensions.content.height,
+        0.0
+    );
+}
+
+#[test]
+fn test_block_edges() {
+    use crate::layout::entity::EdgeSizes;
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><div class=\"sides\"></div><div class=\"keywords\"></div><div class=\"hidden\"></div><div class=\"percent\"></div></div>",
+        ".root { display: block; }
+        .sides { display: block; padding: 2px; padding-left: 10%; border-style: solid; border-width: 1px; border-top-width: 4px; border-right-style: none; }
+        .keywords { display: block; border-style: dashed; border-left-width: thick; border-bottom-width: thin; }
+        .hidden { display: block; border-width: 8px; }
+        .percent { display: block; width: 50%; padding: 10%; padding-left: 5%; }",
+        200.0,
+        0.0,
+    );
+    let d = |index: usize| layout.children[index].dimensions;
+    let edges = |edges: EdgeSizes| (edges.top, edges.right, edges.bottom, edges.left);
+
+    // 辺ごとの指定はまとめた指定より優先し、noneの辺は幅が0になる
+    assert_eq!(edges(d(0).padding), (2.0, 2.0, 2.0, 20.0));
+    assert_eq!(edges(d(0).border), (4.0, 0.0, 1.0, 1.0));
+    assert_eq!(d(0).content.width, 200.0 - 22.0 - 1.0);
+    // thin, medium, thickは1px, 3px, 5pxになる. 幅の初期値はmedium
+    assert_eq!(edges(d(1).border), (3.0, 3.0, 1.0, 5.0));
+    // border-styleの初期値はnone
+    assert_eq!(edges(d(2).border), (0.0, 0.0, 0.0, 0.0));
+    // paddingの%は上下も親要素のwidthを基準にする
+    assert_eq!(edges(d(3).padding), (20.0, 20.0, 20.0, 10.0));
+    assert_eq!(d(3).content.width, 100.0);
+}
+
+#[test]
+fn test_block_size_constraints() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><div class=\"conflict\"></div><div class=\"tall\"></div><div class=\"short\"></div></div>",
+        ".root { display: block; }
+        .conflict { display: block; width: 300px; max-width: 50px; min-width: 120px; }
+        .tall { display: block; box-sizing: border-box; height: 10px; min-height: 40px; padding: 5px; border-style: solid; border-width: 2px; }
+        .short { display: block; box-sizing: border-box; height: 100px; max-height: 30px; padding: 5px; border-style: solid; border-width: 2px; }",
+        400.0,
+        300.0,
+    );
+    let d = |index: usize| layout.children[index].dimensions;
+
+    // max-widthがmin-widthより小さい場合はmin-widthが優先される
+    assert_eq!(d(0).content.width, 120.0);
+    // border-boxではmin-height, max-heightもpaddingとborderを含む
+    assert_eq!(d(1).border_box().height, 40.0);
+    assert_eq!(d(1).content.height, 40.0 - 14.0);
+    assert_eq!(d(2).border_box().height, 30.0);
+    assert_eq!(d(2).content.height, 30.0 - 14.0);
+}
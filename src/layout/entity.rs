@@ -1,3 +1,7 @@
+use crate::css::{LengthContext, Value};
+use crate::font::{LineHeight, TextMetrics};
+use crate::layout::bidi::{Direction, UnicodeBidi};
+use crate::layout::writing_mode::WritingMode;
 use crate::style::StyledNode;
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -47,9 +51,185 @@ pub struct EdgeSizes {
     pub bottom: f32,
 }
 
+/// 相殺する縦方向のmarginの集まり. 正の値の最大と負の値の最小の和が相殺したmarginになる
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CollapsedMargin {
+    pub positive: f32,
+    pub negative: f32,
+}
+
+impl CollapsedMargin {
+    pub fn new(margin: f32) -> CollapsedMargin {
+        CollapsedMargin {
+            positive: margin.max(0.0),
+            negative: margin.min(0.0),
+        }
+    }
+
+    /// 隣接するmarginと相殺する
+    pub fn adjoin(self, other: CollapsedMargin) -> CollapsedMargin {
+        CollapsedMargin {
+            positive: self.positive.max(other.positive),
+            negative: self.negative.min(other.negative),
+        }
+    }
+
+    pub fn resolve(self) -> f32 {
+        self.positive + self.negative
+    }
+}
+
+/// blockのborderの外側で、親要素や兄弟要素のmarginと相殺するmargin.
+/// topとbottomは子要素から伝わったmarginを含む
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CollapsedMargins {
+    pub top: CollapsedMargin,
+    pub bottom: CollapsedMargin,
+    // 高さが0で、上下のmarginが隣接している(topとbottomは同じ値になる)
+    pub through: bool,
+}
+
+/// inline要素を行ごとに分けた断片
+#[derive(Debug, Default, Clone)]
+pub struct Fragment {
+    pub dimensions: Dimensions,
+    // text nodeの場合はその行に含まれる文字列
+    pub text: String,
+}
+
+/// block要素の前後で分けたinline要素の断片が、前と後ろに続きを持つかどうか
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InlineSplit {
+    pub before: bool,
+    pub after: bool,
+}
+
 #[derive(Debug)]
 pub enum BoxType<'a> {
     BlockNode(&'a StyledNode<'a>),
     InlineNode(&'a StyledNode<'a>),
     AnonymouseBlock,
 }
+
+/// 親要素から子要素へ引き継がれるlayoutの情報
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LayoutContext {
+    pub viewport: Rect,
+    pub root_font_size: f32,
+    // 要素自身のfont-size(px)
+    pub font_size: f32,
+    pub line_height: LineHeight,
+    pub text_align: TextAlign,
+    pub text_indent: TextIndent,
+    // letter-spacing, word-spacingの計算値(px)
+    pub letter_spacing: f32,
+    pub word_spacing: f32,
+    // 行の高さの最小値になる、inline formatting contextを作るblockのfontの大きさ(strut)
+    pub strut: Option<TextMetrics>,
+    pub direction: Direction,
+    // 要素自身のunicode-bidi. AnonymouseBlockは親のblockの値を使う
+    pub unicode_bidi: UnicodeBidi,
+    pub writing_mode: WritingMode,
+    // 新しいblock formatting contextを作るblock. 子要素とmarginを相殺しない
+    pub formatting_root: bool,
+    // containing blockのblock方向の大きさ(px). heightの%の基準になり、autoの場合はNone
+    pub containing_block_height: Option<f32>,
+}
+
+/// text-align. 行の中での単語の揃え方
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlign {
+    #[default]
+    Start,
+    End,
+    Left,
+    Right,
+    Center,
+    Justify,
+}
+
+impl TextAlign {
+    pub fn from_keyword(keyword: &str) -> Option<TextAlign> {
+        match keyword.to_lowercase().as_str() {
+            "start" => Some(TextAlign::Start),
+            "end" => Some(TextAlign::End),
+            "left" => Some(TextAlign::Left),
+            "right" => Some(TextAlign::Right),
+            "center" => Some(TextAlign::Center),
+            "justify" => Some(TextAlign::Justify),
+            _ => None,
+        }
+    }
+}
+
+/// text-indentの計算値. %はblockのcontentの幅を基準にする
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextIndent {
+    Length(f32),
+    Percentage(f32),
+}
+
+impl Default for TextIndent {
+    fn default() -> Self {
+        TextIndent::Length(0.0)
+    }
+}
+
+impl TextIndent {
+    pub fn resolve(&self, width: f32) -> f32 {
+        match self {
+            TextIndent::Length(length) => *length,
+            TextIndent::Percentage(percentage) => width * percentage / 100.0,
+        }
+    }
+}
+
+/// vertical-alignの計算値. Lengthは親要素のbaselineから上向きの距離(px)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum VerticalAlign {
+    #[default]
+    Baseline,
+    Sub,
+    Super,
+    TextTop,
+    TextBottom,
+    Middle,
+    // 行の上端、下端に揃える
+    Top,
+    Bottom,
+    Length(f32),
+}
+
+impl VerticalAlign {
+    /// %は要素自身のline-height(px)を基準にする
+    pub fn from_value(value: &Value, length: &LengthContext) -> VerticalAlign {
+        match value {
+            Value::Keyword(keyword) => match keyword.to_lowercase().as_str() {
+                "sub" => VerticalAlign::Sub,
+                "super" => VerticalAlign::Super,
+                "text-top" => VerticalAlign::TextTop,
+                "text-bottom" => VerticalAlign::TextBottom,
+                "middle" => VerticalAlign::Middle,
+                "top" => VerticalAlign::Top,
+                "bottom" => VerticalAlign::Bottom,
+                _ => VerticalAlign::Baseline,
+            },
+            Value::Length(..) | Value::Percentage(_) | Value::Math(_) => {
+                VerticalAlign::Length(value.resolve(length))
+            }
+            _ => VerticalAlign::Baseline,
+        }
+    }
+}
+
+impl LayoutContext {
+    pub fn length_context(&self, percentage_basis: f32) -> LengthContext {
+        LengthContext {
+            percentage_basis,
+            font_size: self.font_size,
+            root_font_size: self.root_font_size,
+            viewport_width: self.viewport.width,
+            viewport_height: self.viewport.height,
+        }
+    }
+}
@@ -0,0 +1,1626 @@
+use crate::css::{Unit, Value};
+use crate::font::{FontDatabase, TextMetrics, TextStyle};
+use crate::html::ElementType;
+use crate::layout::bidi::{
+    mirror, paragraph_level, resolve_levels, visual_order, Direction, UnicodeBidi,
+};
+use crate::layout::entity::{
+    Dimensions, EdgeSizes, Fragment, LayoutContext, TextAlign, VerticalAlign,
+};
+use crate::layout::layout_box::LayoutBox;
+use crate::layout::line_break::{
+    line_breaks_in_runs, Break, Hyphens, LineBreakOptions, OverflowWrap, SOFT_HYPHEN,
+};
+use crate::layout::ruby::RubyAnnotation;
+use crate::layout::white_space::{process_white_space, WhiteSpace};
+
+// 行に並べる要素. pathはinline formatting contextを作るboxから子要素をたどるindex
+#[derive(Debug, Clone)]
+enum InlineItem {
+    // inline要素の開始と終了. margin, border, paddingと、要素のfontの大きさを持つ.
+    // rubyの親文字は終了に注釈を持つ
+    Start(Vec<usize>, Dimensions, InlineBox),
+    End(Vec<usize>, Dimensions, Option<RubyAnnotation>),
+    /*
+    単語と、その後ろの空白. hyphenはsoft hyphenで終わる単語の行末に付けるhyphenの幅.
+    break_afterは単語の後ろで改行できるかどうか.
+    levelは単語と空白の埋め込みレベルで、奇数の場合のtextは表示順に並べ替えてある
+    */
+    Word {
+        path: Vec<usize>,
+        text: String,
+        metrics: TextMetrics,
+        space: f32,
+        trailing: String,
+        hyphen: Option<f32>,
+        break_after: bool,
+        level: u8,
+        space_level: u8,
+    },
+    // tab. intervalごとのtab stopまで進める
+    Tab {
+        interval: f32,
+        break_after: bool,
+    },
+    // 保持された改行. 空の行もこのfontの高さを持つ
+    LineBreak(TextMetrics),
+}
+
+// inline要素のfontの大きさと、親要素のbaselineに対する位置の指定
+#[derive(Debug, Clone, Copy)]
+struct InlineBox {
+    metrics: TextMetrics,
+    font_size: f32,
+    vertical_align: VerticalAlign,
+    direction: Direction,
+    unicode_bidi: UnicodeBidi,
+}
+
+// 空白を処理したtext node. inline formatting context全体で改行位置を決めてから単語に分ける
+struct TextRun {
+    path: Vec<usize>,
+    text: String,
+    style: TextStyle,
+    options: LineBreakOptions,
+    overflow_wrap: OverflowWrap,
+    white_space: WhiteSpace,
+    // tabの間隔(px)
+    tab_size: f32,
+}
+
+enum InlineContent {
+    Item(InlineItem),
+    Text(TextRun),
+}
+
+// 行に置いた要素. xは行の左端からの距離
+struct Placement {
+    item: usize,
+    x: f32,
+    // 空白の後に置いた単語. justifyではこの前の間隔を広げる
+    space_before: bool,
+    // 表示順に並べ替えた後の要素自身の幅
+    width: f32,
+    // inline要素の開始と終了が、その要素の左側にあるかどうか
+    on_left: bool,
+}
+
+impl Placement {
+    fn new(item: usize, x: f32, space_before: bool) -> Placement {
+        Placement {
+            item,
+            x,
+            space_before,
+            width: 0.0,
+            on_left: false,
+        }
+    }
+}
+
+struct Line {
+    placements: Vec<Placement>,
+    // 行末の空白を除いた幅
+    width: f32,
+    // 保持された改行で終わる行
+    forced: bool,
+    // 表示順に並べたplacementsの位置
+    visual: Vec<usize>,
+    // 単語の間隔を広げた行. 単語ごとに別の断片にする
+    justified: bool,
+    // 行の途中で改行した(最後の行ではない)かどうか
+    broken: bool,
+}
+
+impl Line {
+    fn new(placements: Vec<Placement>, width: f32, forced: bool) -> Line {
+        Line {
+            placements,
+            width,
+            forced,
+            visual: vec![],
+            justified: false,
+            broken: true,
+        }
+    }
+}
+
+// 行をまたいで続いているinline要素
+struct OpenBox {
+    path: Vec<usize>,
+    dimensions: Dimensions,
+    inline_box: InlineBox,
+    // この行でのbaselineの位置
+    frame: Frame,
+    // この行に置いた内容の左端と右端
+    extent: Option<(f32, f32)>,
+    // この行の断片が左側、右側のmargin, border, paddingを持つかどうか
+    left: bool,
+    right: bool,
+}
+
+impl OpenBox {
+    fn extend(&mut self, x: f32, width: f32) {
+        self.extent = Some(match self.extent {
+            Some((left, right)) => (left.min(x), right.max(x + width)),
+            None => (x, x + width),
+        });
+    }
+}
+
+/*
+baselineを揃える基準になるbox. shiftはgroupのbaselineから上向きの距離.
+groupはvertical-align: top, bottomの要素ごとに分け、行の高さを決めた後に位置を決める
+*/
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    metrics: TextMetrics,
+    font_size: f32,
+    group: usize,
+    shift: f32,
+}
+
+// baselineを共有する要素が、baselineの上下に出る高さ
+struct Group {
+    align: VerticalAlign,
+    ascent: f32,
+    descent: f32,
+}
+
+impl Group {
+    fn new(align: VerticalAlign) -> Group {
+        Group {
+            align,
+            ascent: f32::NEG_INFINITY,
+            descent: f32::NEG_INFINITY,
+        }
+    }
+
+    // line-heightの高さの箱を、baselineからshiftだけ上に置く
+    fn extend(&mut self, shift: f32, metrics: &TextMetrics) {
+        let baseline = metrics.baseline();
+        self.ascent = self.ascent.max(shift + baseline);
+        self.descent = self.descent.max(metrics.line_height - baseline - shift);
+    }
+}
+
+// 親要素のbaselineから、子要素のbaselineまでの上向きの距離
+fn baseline_shift(parent: &Frame, child: &TextMetrics, align: VerticalAlign) -> f32 {
+    let above = child.baseline();
+    let below = child.line_height - above;
+    match align {
+        VerticalAlign::Baseline | VerticalAlign::Top | VerticalAlign::Bottom => 0.0,
+        VerticalAlign::Sub => -parent.font_size / 5.0,
+        VerticalAlign::Super => parent.font_size / 3.0,
+        // 親要素のfontの上端、下端に揃える
+        VerticalAlign::TextTop => parent.metrics.ascent - above,
+        VerticalAlign::TextBottom => below - parent.metrics.descent,
+        // 中央を親要素のbaselineから小文字のxの高さの半分に揃える
+        VerticalAlign::Middle => (parent.metrics.x_height - (above - below)) / 2.0,
+        VerticalAlign::Length(length) => length,
+    }
+}
+
+// inline要素のbaselineの位置を決め、行の高さに含める
+fn push_frame(frames: &mut Vec<Frame>, groups: &mut Vec<Group>, inline_box: &InlineBox) -> Frame {
+    let parent = *frames.last().unwrap();
+    let (group, shift) = match inline_box.vertical_align {
+        align @ (VerticalAlign::Top | VerticalAlign::Bottom) => {
+            groups.push(Group::new(align));
+            (groups.len() - 1, 0.0)
+        }
+        align => (
+            parent.group,
+            parent.shift + baseline_shift(&parent, &inline_box.metrics, align),
+        ),
+    };
+    let frame = Frame {
+        metrics: inline_box.metrics,
+        font_size: inline_box.font_size,
+        group,
+        shift,
+    };
+    groups[group].extend(shift, &frame.metrics);
+    frames.push(frame);
+    frame
+}
+
+fn left_edge(d: &Dimensions) -> f32 {
+    d.margin.left + d.border.left + d.padding.left
+}
+
+fn right_edge(d: &Dimensions) -> f32 {
+    d.margin.right + d.border.right + d.padding.right
+}
+
+impl<'a> LayoutBox<'a> {
+    /*
+    子要素をinline formatting contextとして扱い、contentの幅に収まるように単語を行に並べる.
+    inline要素は行ごとの断片(fragment)に分け、行の高さの合計をcontentの高さにする
+    */
+    pub fn layout_inline_children(&mut self, fonts: &FontDatabase) {
+        let content = self.dimensions.content;
+        let mut contents = vec![];
+        // 行頭の空白は取り除く
+        let mut collapsible = true;
+        for (index, child) in self.children.iter_mut().enumerate() {
+            let context = child.inherit_context(self.context);
+            child.collect_inline_items(
+                vec![index],
+                context,
+                content.width,
+                fonts,
+                &mut collapsible,
+                &mut contents,
+            );
+        }
+        let (items, base_level) = inline_items(
+            contents,
+            self.context.direction,
+            self.context.unicode_bidi,
+            content.width,
+            fonts,
+        );
+
+        // 行の高さの最小値. AnonymouseBlockは親のblockのfontを使う
+        let root = Frame {
+            metrics: match self.context.strut {
+                Some(strut) => strut,
+                None => fonts.measure("", &self.text_style()),
+            },
+            font_size: self.context.font_size,
+            group: 0,
+            shift: 0.0,
+        };
+        let mut fragments = vec![];
+        let mut open = vec![];
+        let mut y = content.y;
+        let indent = self.context.text_indent.resolve(content.width);
+        let mut lines = break_lines(&items, content.width, indent);
+        for line in lines.iter_mut() {
+            // 最後の行と、保持された改行で終わる行は両端揃えにしない
+            let justify = line.broken && !line.forced;
+            reorder_line(line, &items, base_level);
+            align_line(
+                line,
+                self.context.text_align,
+                base_level,
+                content.width,
+                justify,
+            );
+            y += layout_line(&items, line, content.x, y, root, &mut open, &mut fragments);
+        }
+        self.dimensions.content.height = y - content.y;
+
+        for (path, fragment) in fragments {
+            self.descendant_mut(&path).fragments.push(fragment);
+        }
+    }
+
+    fn descendant_mut(&mut self, path: &[usize]) -> &mut LayoutBox<'a> {
+        match path.split_first() {
+            Some((index, rest)) => self.children[*index].descendant_mut(rest),
+            None => self,
+        }
+    }
+
+    fn collect_inline_items(
+        &mut self,
+        path: Vec<usize>,
+        context: LayoutContext,
+        containing_width: f32,
+        fonts: &FontDatabase,
+        collapsible: &mut bool,
+        contents: &mut Vec<InlineContent>,
+    ) {
+        self.context = context;
+        self.fragments.clear();
+
+        let style = self.text_style();
+        if !self.text().is_empty() {
+            let white_space = self.white_space();
+            let space_width = fonts.measure(" ", &style).width;
+            contents.push(InlineContent::Text(TextRun {
+                path,
+                text: process_white_space(
+                    &self.text_transform().apply(self.text()),
+                    white_space,
+                    collapsible,
+                ),
+                options: self.line_break_options(),
+                overflow_wrap: self.overflow_wrap(),
+                white_space,
+                tab_size: self.tab_size(space_width),
+                style,
+            }));
+            return;
+        }
+
+        self.set_inline_edges(containing_width);
+        let metrics = fonts.measure("", &style);
+        let vertical_align = match self.get_style_node().value("vertical-align") {
+            Some(value) => {
+                VerticalAlign::from_value(&value, &context.length_context(metrics.line_height))
+            }
+            None => VerticalAlign::Baseline,
+        };
+        contents.push(InlineContent::Item(InlineItem::Start(
+            path.clone(),
+            self.dimensions,
+            InlineBox {
+                metrics,
+                font_size: context.font_size,
+                vertical_align,
+                direction: context.direction,
+                unicode_bidi: context.unicode_bidi,
+            },
+        )));
+        if self.is_element(ElementType::Ruby) {
+            self.collect_ruby_items(&path, containing_width, fonts, collapsible, contents);
+        } else {
+            for (index, child) in self.children.iter_mut().enumerate() {
+                let mut child_path = path.clone();
+                child_path.push(index);
+                let child_context = child.inherit_context(context);
+                child.collect_inline_items(
+                    child_path,
+                    child_context,
+                    containing_width,
+                    fonts,
+                    collapsible,
+                    contents,
+                );
+            }
+        }
+        contents.push(InlineContent::Item(InlineItem::End(
+            path,
+            self.dimensions,
+            None,
+        )));
+    }
+
+    /*
+    rubyの親文字を、注釈を持つinline要素として並べる. textの親文字は断片を作らないinline要素で囲む.
+    注釈が親文字より広い場合は、ruby-alignに従って親文字の前後を空ける
+    */
+    fn collect_ruby_items(
+        &mut self,
+        path: &[usize],
+        containing_width: f32,
+        fonts: &FontDatabase,
+        collapsible: &mut bool,
+        contents: &mut Vec<InlineContent>,
+    ) {
+        let context = self.context;
+        let child_path = |index: usize| [path, &[index]].concat();
+        for (base, annotation) in self.ruby_pairs() {
+            let annotation = annotation.map(|index| {
+                let child = &mut self.children[index];
+                let child_context = child.inherit_context(context);
+                child.ruby_annotation(child_path(index), child_context, fonts)
+            });
+            let child = &mut self.children[base];
+            let child_context = child.inherit_context(context);
+            let start = contents.len();
+            child.collect_inline_items(
+                child_path(base),
+                child_context,
+                containing_width,
+                fonts,
+                collapsible,
+                contents,
+            );
+            // 親文字が行に並べる内容を持たない場合(block要素など)は注釈を付けない
+            let mut annotation = match annotation {
+                Some(annotation) if contents.len() > start => annotation,
+                _ => continue,
+            };
+            annotation.base_box = child.text().is_empty();
+            if !annotation.base_box {
+                let inline_box = InlineBox {
+                    metrics: fonts.measure("", &child.text_style()),
+                    font_size: child_context.font_size,
+                    vertical_align: VerticalAlign::Baseline,
+                    direction: child_context.direction,
+                    unicode_bidi: UnicodeBidi::Normal,
+                };
+                let d = Dimensions::default();
+                contents.insert(
+                    start,
+                    InlineContent::Item(InlineItem::Start(child_path(base), d, inline_box)),
+                );
+                contents.push(InlineContent::Item(InlineItem::End(
+                    child_path(base),
+                    d,
+                    None,
+                )));
+            }
+            let base_width: f32 = contents[start..]
+                .iter()
+                .map(|content| match content {
+                    InlineContent::Text(run) => fonts.measure(&run.text, &run.style).width,
+                    InlineContent::Item(_) => 0.0,
+                })
+                .sum();
+            let (left, _) = annotation.align.distribute(annotation.width, base_width, 1);
+            let right = (annotation.width - base_width).max(0.0) - left;
+            let end = contents.len() - 1;
+            for index in [start, end] {
+                if let InlineContent::Item(InlineItem::Start(_, d, _) | InlineItem::End(_, d, _)) =
+                    &mut contents[index]
+                {
+                    d.margin.left += left;
+                    d.margin.right += right;
+                }
+            }
+            if let InlineContent::Item(InlineItem::End(_, _, ruby)) = &mut contents[end] {
+                *ruby = Some(annotation);
+            }
+        }
+    }
+
+    // inline要素のmargin, border, padding. %は親要素のwidthを基準にする
+    fn set_inline_edges(&mut self, containing_width: f32) {
+        let style = self.get_style_node();
+        let length = self.context.length_context(containing_width);
+        let zero = Value::Length(0.0, Unit::Px);
+        // 論理方向の辺ごとに、writing-modeに応じた物理方向のpropertyを使う
+        let [left, right, top, bottom] = self.context.writing_mode.sides();
+        let edges = |edge: &dyn Fn(&str) -> Value| EdgeSizes {
+            left: edge(left).resolve(&length),
+            right: edge(right).resolve(&length),
+            top: edge(top).resolve(&length),
+            bottom: edge(bottom).resolve(&length),
+        };
+        // margin-left, padding-leftなどの指定がなければmargin, paddingを使う
+        let longhand =
+            |name: &str, side: &str| style.lookup(&format!("{}-{}", name, side), name, &zero);
+
+        let d = &mut self.dimensions;
+        d.margin = edges(&|side| longhand("margin", side));
+        d.border = edges(&|side| style.border_width(side));
+        d.padding = edges(&|side| longhand("padding", side));
+        if self.split.before {
+            (d.margin.left, d.border.left, d.padding.left) = (0.0, 0.0, 0.0);
+        }
+        if self.split.after {
+            (d.margin.right, d.border.right, d.padding.right) = (0.0, 0.0, 0.0);
+        }
+    }
+}
+
+/*
+inline formatting context全体のtextで改行できる位置(UAX #14)を求め、textを単語に分ける.
+text nodeの境目は、その前後の文字の間で改行できる場合だけ単語の区切りになる.
+埋め込みレベルが変わる位置でも単語を分け、段落の埋め込みレベルも返す
+*/
+fn inline_items(
+    contents: Vec<InlineContent>,
+    direction: Direction,
+    unicode_bidi: UnicodeBidi,
+    containing_width: f32,
+    fonts: &FontDatabase,
+) -> (Vec<InlineItem>, u8) {
+    let runs: Vec<(&str, LineBreakOptions)> = contents
+        .iter()
+        .filter_map(|content| match content {
+            InlineContent::Text(run) => Some((run.text.as_str(), run.options)),
+            InlineContent::Item(_) => None,
+        })
+        .collect();
+    let (levels, base_level) = bidi_levels(&contents, direction, unicode_bidi);
+    // 空白の前後を除き、レベルが変わる位置は改行できない単語の区切りにする
+    let mut boundaries: Vec<(usize, Option<Break>)> = levels
+        .windows(2)
+        .filter(|pair| {
+            pair[0].2 != pair[1].2 && !pair[0].1.is_whitespace() && !pair[1].1.is_whitespace()
+        })
+        .map(|pair| (pair[1].0, None))
+        .collect();
+    for (position, action) in line_breaks_in_runs(&runs) {
+        match boundaries.iter_mut().find(|(other, _)| *other == position) {
+            Some(boundary) => boundary.1 = Some(action),
+            None => boundaries.push((position, Some(action))),
+        }
+    }
+    // 縦中横のtextとrubyの親文字の中では単語を分けない
+    let mut offset = 0;
+    let mut unbreakable = vec![];
+    let mut starts = vec![];
+    for content in &contents {
+        match content {
+            InlineContent::Text(run) => {
+                if run.style.combined() {
+                    unbreakable.push(offset..offset + run.text.len());
+                }
+                offset += run.text.len();
+            }
+            InlineContent::Item(InlineItem::Start(..)) => starts.push(offset),
+            InlineContent::Item(InlineItem::End(_, _, ruby)) => {
+                if let (Some(start), Some(_)) = (starts.pop(), ruby) {
+                    unbreakable.push(start..offset);
+                }
+            }
+            InlineContent::Item(_) => {}
+        }
+    }
+    boundaries.retain(|(position, _)| {
+        !unbreakable
+            .iter()
+            .any(|range| range.start < *position && *position < range.end)
+    });
+    boundaries.sort_by_key(|(position, _)| *position);
+    let mut boundaries = boundaries.into_iter().peekable();
+    // 単語の最初の空白でない文字と、最後の文字のレベル
+    let segment_levels = |from: usize, to: usize| {
+        let chars = &levels[levels.partition_point(|(position, _, _)| *position < from)
+            ..levels.partition_point(|(position, _, _)| *position < to)];
+        let word = chars
+            .iter()
+            .find(|(_, c, _)| !c.is_whitespace())
+            .or_else(|| chars.last());
+        let level = word.map_or(base_level, |(_, _, level)| *level);
+        (level, chars.last().map_or(level, |(_, _, level)| *level))
+    };
+
+    let mut items = vec![];
+    let mut offset = 0;
+    for content in &contents {
+        let run = match content {
+            InlineContent::Text(run) => run,
+            InlineContent::Item(item) => {
+                items.push(item.clone());
+                continue;
+            }
+        };
+        let end = offset + run.text.len();
+        let mut start = 0;
+        while let Some((position, action)) = boundaries.next_if(|(position, _)| *position < end) {
+            let position = position - offset;
+            run.push_segment(
+                &run.text[start..position],
+                action,
+                segment_levels(offset + start, offset + position),
+                containing_width,
+                fonts,
+                &mut items,
+            );
+            start = position;
+        }
+        // text nodeの終わりで改行できるかは次のtext nodeの先頭の位置で決まる
+        let action = boundaries
+            .next_if(|(position, _)| *position == end)
+            .and_then(|(_, action)| action);
+        if start < run.text.len() {
+            run.push_segment(
+                &run.text[start..],
+                action,
+                segment_levels(offset + start, end),
+                containing_width,
+                fonts,
+                &mut items,
+            );
+        }
+        offset = end;
+    }
+    (items, base_level)
+}
+
+/*
+textの文字ごとの位置と埋め込みレベル(UAX #9)と、段落の埋め込みレベル.
+unicode-bidiを指定したinline要素は、前後に制御文字を置いたものとして扱う
+*/
+fn bidi_levels(
+    contents: &[InlineContent],
+    direction: Direction,
+    unicode_bidi: UnicodeBidi,
+) -> (Vec<(usize, char, u8)>, u8) {
+    let mut paragraph = String::new();
+    // 制御文字を含めた段落の中の文字の位置
+    let mut chars = vec![];
+    let mut count = 0;
+    let mut offset = 0;
+    let mut boxes = vec![];
+    for content in contents {
+        let controls = match content {
+            InlineContent::Item(InlineItem::Start(_, _, inline_box)) => {
+                boxes.push(*inline_box);
+                inline_box.unicode_bidi.controls(inline_box.direction).0
+            }
+            InlineContent::Item(InlineItem::End(..)) => match boxes.pop() {
+                Some(inline_box) => inline_box.unicode_bidi.controls(inline_box.direction).1,
+                None => "",
+            },
+            InlineContent::Item(_) => "",
+            InlineContent::Text(run) => {
+                for (position, c) in run.text.char_indices() {
+                    chars.push((offset + position, c, count));
+                    count += 1;
+                }
+                paragraph.push_str(&run.text);
+                offset += run.text.len();
+                continue;
+            }
+        };
+        paragraph.push_str(controls);
+        count += controls.chars().count();
+    }
+    let base_level = match unicode_bidi {
+        UnicodeBidi::Plaintext => paragraph_level(&paragraph).unwrap_or(direction.level()),
+        _ => direction.level(),
+    };
+    let levels = resolve_levels(&paragraph, base_level);
+    let chars = chars
+        .into_iter()
+        .map(|(position, c, index)| (position, c, levels[index]))
+        .collect();
+    (chars, base_level)
+}
+
+// 奇数の埋め込みレベルの単語は、文字を逆順にして左右を反転した形にする(L2, L4)
+fn visual_text(text: &str, level: u8) -> String {
+    match level % 2 {
+        0 => text.to_string(),
+        _ => text.chars().rev().map(mirror).collect(),
+    }
+}
+
+impl TextRun {
+    /*
+    改行できる位置で分けたtextを単語にする. 単語の後ろの空白は行末では幅を持たない.
+    tabは次のtab stopまで進め、保持された改行では必ず改行する
+    */
+    fn push_segment(
+        &self,
+        segment: &str,
+        action: Option<Break>,
+        levels: (u8, u8),
+        containing_width: f32,
+        fonts: &FontDatabase,
+        items: &mut Vec<InlineItem>,
+    ) {
+        let (segment, forced) = match action {
+            Some(Break::Mandatory) => (
+                segment
+                    .strip_suffix(|c| {
+                        matches!(
+                            c,
+                            '\n' | '\u{000B}' | '\u{000C}' | '\u{0085}' | '\u{2028}' | '\u{2029}'
+                        )
+                    })
+                    .unwrap_or(segment),
+                true,
+            ),
+            _ => (segment, false),
+        };
+        // nowrap, preでは改行しない
+        let break_after = action == Some(Break::Allowed) && self.white_space.wraps();
+        let parts: Vec<&str> = segment.split('\t').collect();
+        let last = parts.len() - 1;
+        for (index, part) in parts.iter().enumerate() {
+            if index > 0 {
+                // tabで終わる場合は、tabの後で改行できる
+                items.push(InlineItem::Tab {
+                    interval: self.tab_size,
+                    break_after: index == last && part.is_empty() && break_after,
+                });
+            }
+            self.push_word(
+                part,
+                index == last && break_after,
+                levels,
+                containing_width,
+                fonts,
+                items,
+            );
+        }
+        if forced {
+            items.push(InlineItem::LineBreak(fonts.measure("", &self.style)));
+        }
+    }
+
+    // levelsは単語と、その後ろの空白の埋め込みレベル
+    fn push_word(
+        &self,
+        part: &str,
+        break_after: bool,
+        (level, space_level): (u8, u8),
+        containing_width: f32,
+        fonts: &FontDatabase,
+        items: &mut Vec<InlineItem>,
+    ) {
+        let word = part.trim_end_matches(' ');
+        let mut trailing = &part[word.len()..];
+        if word.is_empty() && trailing.is_empty() {
+            return;
+        }
+        // break-spacesでは空白も幅を持ち、空白ごとに改行できる
+        let spaces = match self.white_space {
+            WhiteSpace::BreakSpaces => std::mem::take(&mut trailing).chars().count(),
+            _ => 0,
+        };
+        let style = &self.style;
+        // soft hyphenは描画せず、その位置で改行した場合だけhyphenを表示する
+        let hyphen = match word.ends_with(SOFT_HYPHEN) && self.options.hyphens != Hyphens::None {
+            true => Some(fonts.measure("-", style).width),
+            false => None,
+        };
+        let word: String = word.chars().filter(|c| *c != SOFT_HYPHEN).collect();
+
+        let mut pieces = vec![word];
+        if self.overflow_wrap != OverflowWrap::Normal
+            && self.white_space.wraps()
+            && fonts.measure(&pieces[0], style).width > containing_width
+        {
+            pieces = split_to_fit(&pieces[0], containing_width, |text| {
+                fonts.measure(text, style).width
+            });
+        }
+        let last = pieces.len() - 1;
+        for (index, piece) in pieces.into_iter().enumerate() {
+            let is_last = index == last;
+            if is_last && piece.is_empty() && trailing.is_empty() {
+                break;
+            }
+            // 右から左に書く文字はshapingで表示順から戻すため、表示順の文字列を測る
+            let text = visual_text(&piece, level);
+            items.push(InlineItem::Word {
+                path: self.path.clone(),
+                metrics: fonts.measure(&text, style),
+                text,
+                space: match is_last {
+                    true => fonts.measure(trailing, style).width,
+                    false => 0.0,
+                },
+                trailing: match is_last {
+                    true => trailing.to_string(),
+                    false => String::new(),
+                },
+                hyphen: hyphen.filter(|_| is_last),
+                // overflow-wrapで分けた単語の間では改行できる
+                break_after: !is_last || (spaces == 0 && break_after),
+                level,
+                space_level,
+            });
+        }
+        for index in 0..spaces {
+            items.push(InlineItem::Word {
+                path: self.path.clone(),
+                text: String::from(" "),
+                metrics: fonts.measure(" ", style),
+                space: 0.0,
+                trailing: String::new(),
+                hyphen: None,
+                break_after: index + 1 < spaces || break_after,
+                level: space_level,
+                space_level,
+            });
+        }
+    }
+}
+
+// 1文字ずつ足していき、幅を超える前で分ける. 1文字で幅を超える場合はその文字だけにする
+fn split_to_fit(text: &str, width: f32, measure: impl Fn(&str) -> f32) -> Vec<String> {
+    let mut pieces = vec![];
+    let mut piece = String::new();
+    for c in text.chars() {
+        let mut next = piece.clone();
+        next.push(c);
+        if !piece.is_empty() && measure(&next) > width {
+            pieces.push(std::mem::take(&mut piece));
+            next = c.to_string();
+        }
+        piece = next;
+    }
+    pieces.push(piece);
+    pieces
+}
+
+/*
+幅に収まるだけ単語を行に詰める. 1単語で幅を超える場合はそのまま置く.
+inline要素の開始は次の単語と同じ行に、終了は直前の単語と同じ行に置く
+*/
+fn break_lines(items: &[InlineItem], width: f32, indent: f32) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut placements = vec![];
+    // 最初の行だけtext-indentの分だけ下げる
+    let mut x = indent;
+    // 直前の単語の後ろで改行できるかどうか. 行頭では改行しない
+    let mut can_break = false;
+    // 直前の単語の後ろの空白. 次の単語が同じ行に来る場合だけ幅を取る
+    let mut space = 0.0;
+    // 直前の単語がsoft hyphenで終わる場合に、行末に付けるhyphenの幅
+    let mut hyphen = 0.0;
+    let mut starts: Vec<usize> = vec![];
+
+    for (index, item) in items.iter().enumerate() {
+        match item {
+            InlineItem::Start(..) => starts.push(index),
+            InlineItem::End(_, d, _) => {
+                for start in starts.drain(..) {
+                    x = place_start(items, start, x, &mut placements);
+                }
+                placements.push(Placement::new(index, x, false));
+                x += right_edge(d);
+            }
+            InlineItem::Word {
+                metrics,
+                space: word_space,
+                hyphen: word_hyphen,
+                break_after,
+                ..
+            } => {
+                let start_width: f32 = starts
+                    .iter()
+                    .map(|start| match &items[*start] {
+                        InlineItem::Start(_, d, _) => left_edge(d),
+                        _ => 0.0,
+                    })
+                    .sum();
+                // 単語の直後で終わるinline要素の右側も同じ行に収める
+                let end_width: f32 = items[index + 1..]
+                    .iter()
+                    .map_while(|item| match item {
+                        InlineItem::End(_, d, _) => Some(right_edge(d)),
+                        _ => None,
+                    })
+                    .sum();
+                let word_width = metrics.width + word_hyphen.unwrap_or(0.0);
+                let mut space_before = false;
+                if can_break && x + space + start_width + word_width + end_width > width {
+                    let line = std::mem::take(&mut placements);
+                    lines.push(Line::new(line, x + hyphen, false));
+                    x = 0.0;
+                } else {
+                    x += space;
+                    space_before = space > 0.0;
+                }
+                for start in starts.drain(..) {
+                    x = place_start(items, start, x, &mut placements);
+                }
+                placements.push(Placement::new(index, x, space_before));
+                x += metrics.width;
+                space = *word_space;
+                hyphen = word_hyphen.unwrap_or(0.0);
+                can_break = *break_after;
+            }
+            InlineItem::Tab {
+                interval,
+                break_after,
+            } => {
+                for start in starts.drain(..) {
+                    x = place_start(items, start, x, &mut placements);
+                }
+                x += space;
+                placements.push(Placement::new(index, x, false));
+                if *interval > 0.0 {
+                    x = ((x / interval).floor() + 1.0) * interval;
+                }
+                space = 0.0;
+                hyphen = 0.0;
+                can_break = *break_after;
+            }
+            InlineItem::LineBreak(_) => {
+                for start in starts.drain(..) {
+                    x = place_start(items, start, x, &mut placements);
+                }
+                placements.push(Placement::new(index, x, false));
+                lines.push(Line::new(std::mem::take(&mut placements), x, true));
+                x = 0.0;
+                space = 0.0;
+                hyphen = 0.0;
+                can_break = false;
+            }
+        }
+    }
+    for start in starts {
+        x = place_start(items, start, x, &mut placements);
+    }
+    let mut last = Line::new(placements, x, false);
+    last.broken = false;
+    lines.push(last);
+    lines
+}
+
+fn place_start(items: &[InlineItem], start: usize, x: f32, placements: &mut Vec<Placement>) -> f32 {
+    placements.push(Placement::new(start, x, false));
+    match &items[start] {
+        InlineItem::Start(_, d, _) => x + left_edge(d),
+        _ => x,
+    }
+}
+
+/*
+UAX #9のL2で行の要素を表示順に並べ、左端からの位置を決め直す.
+単語の後ろの空白は、空白自身のレベルで単語とは別に並べ替える.
+inline要素の開始と終了は前後の低い方のレベルにして、要素の内容と一緒に動かす
+*/
+fn reorder_line(line: &mut Line, items: &[InlineItem], base_level: u8) {
+    // 要素(Some)と空白(None)
+    let mut atoms: Vec<(Option<usize>, f32, u8)> = vec![];
+    let mut markers = vec![];
+    let mut space_level = base_level;
+    let count = line.placements.len();
+    for (index, placement) in line.placements.iter().enumerate() {
+        let next = match line.placements.get(index + 1) {
+            Some(next) => next.x,
+            None => line.width,
+        };
+        let advance = next - placement.x;
+        let (width, level) = match &items[placement.item] {
+            InlineItem::Word {
+                metrics,
+                level,
+                space_level: word_space_level,
+                ..
+            } => {
+                space_level = *word_space_level;
+                (metrics.width, *level)
+            }
+            InlineItem::Start(_, d, _) => {
+                markers.push(atoms.len());
+                (left_edge(d), base_level)
+            }
+            InlineItem::End(_, d, _) => {
+                markers.push(atoms.len());
+                (right_edge(d), base_level)
+            }
+            InlineItem::Tab { .. } => (advance, base_level),
+            InlineItem::LineBreak(_) => (0.0, base_level),
+        };
+        atoms.push((Some(index), width, level));
+        if advance - width > 0.0 && index + 1 < count {
+            atoms.push((None, advance - width, space_level));
+        }
+    }
+    let is_marker = |atom: usize| markers.contains(&atom);
+    for marker in &markers {
+        let previous = (0..*marker)
+            .rev()
+            .find(|atom| !is_marker(*atom))
+            .map_or(base_level, |atom| atoms[atom].2);
+        let next = (marker + 1..atoms.len())
+            .find(|atom| !is_marker(*atom))
+            .map_or(base_level, |atom| atoms[atom].2);
+        atoms[*marker].2 = previous.min(next);
+    }
+    let levels: Vec<u8> = atoms.iter().map(|(_, _, level)| *level).collect();
+    let order = visual_order(&levels);
+
+    // inline要素の開始と終了は、表示順で要素の内容より前にある場合に左側になる
+    let atom_items: Vec<Option<&InlineItem>> = atoms
+        .iter()
+        .map(|(index, _, _)| index.map(|index| &items[line.placements[index].item]))
+        .collect();
+    let path_of = |atom: usize| match atom_items[atom] {
+        Some(InlineItem::Start(path, ..))
+        | Some(InlineItem::End(path, ..))
+        | Some(InlineItem::Word { path, .. }) => Some(path),
+        _ => None,
+    };
+    let sides: Vec<(usize, bool)> = markers
+        .iter()
+        .map(|marker| {
+            let path = path_of(*marker).unwrap();
+            let position = order.iter().position(|atom| atom == marker).unwrap();
+            let inside = |atom: &usize| {
+                atom != marker && path_of(*atom).is_some_and(|other| other.starts_with(path))
+            };
+            let on_left = match order.iter().position(inside) {
+                Some(content) => position < content,
+                None => matches!(atom_items[*marker], Some(InlineItem::Start(..))),
+            };
+            (*marker, on_left)
+        })
+        .collect();
+    for (marker, on_left) in sides {
+        let index = atoms[marker].0.unwrap();
+        atoms[marker].1 = match (&items[line.placements[index].item], on_left) {
+            (InlineItem::Start(_, d, _), true) | (InlineItem::End(_, d, _), true) => left_edge(d),
+            (InlineItem::Start(_, d, _), false) | (InlineItem::End(_, d, _), false) => {
+                right_edge(d)
+            }
+            _ => 0.0,
+        };
+        line.placements[index].on_left = on_left;
+    }
+
+    // 左から右の段落は最初の行のtext-indentの後から、右から左の段落は左端から並べる
+    let indent = line.placements.first().map_or(0.0, |placement| placement.x);
+    let mut x = match base_level % 2 {
+        0 => indent,
+        _ => 0.0,
+    };
+    let mut after_space = false;
+    line.visual.clear();
+    for atom in order {
+        let (index, width, _) = atoms[atom];
+        match index {
+            Some(index) => {
+                let placement = &mut line.placements[index];
+                placement.x = x;
+                placement.width = width;
+                placement.space_before = after_space;
+                line.visual.push(index);
+                after_space = false;
+            }
+            None => after_space = true,
+        }
+        x += width;
+    }
+    line.width = match base_level % 2 {
+        0 => x,
+        _ => x + indent,
+    };
+}
+
+/*
+text-alignに従って行の要素を動かす. justifyでは空白の後の要素を動かして単語の間隔を広げる.
+start, endは段落の方向で左右が決まる. 幅を超える行は動かさない
+*/
+fn align_line(line: &mut Line, align: TextAlign, base_level: u8, width: f32, justify: bool) {
+    let free = width - line.width;
+    if free <= 0.0 {
+        return;
+    }
+    let rtl = base_level % 2 == 1;
+    let gaps = line
+        .placements
+        .iter()
+        .filter(|placement| placement.space_before)
+        .count();
+    let offset = match align {
+        TextAlign::Left => 0.0,
+        TextAlign::Right => free,
+        TextAlign::Start if !rtl => 0.0,
+        TextAlign::End if rtl => 0.0,
+        TextAlign::Start | TextAlign::End => free,
+        TextAlign::Center => free / 2.0,
+        TextAlign::Justify if justify && gaps > 0 => {
+            let extra = free / gaps as f32;
+            let mut shift = 0.0;
+            for index in &line.visual {
+                let placement = &mut line.placements[*index];
+                if placement.space_before {
+                    shift += extra;
+                }
+                placement.x += shift;
+            }
+            line.width = width;
+            line.justified = true;
+            return;
+        }
+        TextAlign::Justify if rtl => free,
+        TextAlign::Justify => 0.0,
+    };
+    for placement in line.placements.iter_mut() {
+        placement.x += offset;
+    }
+    line.width += offset;
+}
+
+/*
+1行分の断片を作り、行の高さを返す.
+inline要素はvertical-alignで親要素のbaselineからずらし、
+行の高さはstrutとすべての要素のline-heightの箱を含む高さになる
+*/
+fn layout_line(
+    items: &[InlineItem],
+    line: &Line,
+    origin_x: f32,
+    y: f32,
+    root: Frame,
+    open: &mut Vec<OpenBox>,
+    fragments: &mut Vec<(Vec<usize>, Fragment)>,
+) -> f32 {
+    let mut frames = vec![root];
+    let mut groups = vec![Group::new(VerticalAlign::Baseline)];
+    // 要素のない行は高さを持たない
+    if line.placements.is_empty() {
+        groups[0].ascent = 0.0;
+        groups[0].descent = 0.0;
+    } else {
+        groups[0].extend(0.0, &root.metrics);
+    }
+    // 前の行から続くinline要素は、この行に置いた内容の範囲の断片になる
+    for open_box in open.iter_mut() {
+        open_box.extent = None;
+        open_box.left = false;
+        open_box.right = false;
+        open_box.frame = push_frame(&mut frames, &mut groups, &open_box.inline_box);
+    }
+
+    // 断片と、その上端からbaselineまでの距離、baselineの位置
+    let mut line_fragments: Vec<(Vec<usize>, Fragment, f32, Frame)> = vec![];
+    let last_word = line
+        .placements
+        .iter()
+        .rposition(|placement| matches!(items[placement.item], InlineItem::Word { .. }));
+    // 直前の単語の後ろの空白とレベル. 同じ断片に続けて単語を置く場合に使う
+    let mut trailing = "";
+    let mut previous_level = None;
+    // tabの後の単語は前の単語とまとめず、別の断片にする
+    let mut after_tab = false;
+    for (index, placement) in line.placements.iter().enumerate() {
+        match &items[placement.item] {
+            InlineItem::Start(path, dimensions, inline_box) => {
+                let frame = push_frame(&mut frames, &mut groups, inline_box);
+                open.push(OpenBox {
+                    path: path.clone(),
+                    dimensions: *dimensions,
+                    inline_box: *inline_box,
+                    frame,
+                    extent: None,
+                    left: placement.on_left,
+                    right: !placement.on_left,
+                });
+                extend_boxes(open, placement.x, placement.width);
+            }
+            InlineItem::End(path, _, ruby) => {
+                extend_boxes(open, placement.x, placement.width);
+                if let Some(position) = open.iter().rposition(|open_box| &open_box.path == path) {
+                    let mut open_box = open.remove(position);
+                    frames.pop();
+                    match placement.on_left {
+                        true => open_box.left = true,
+                        false => open_box.right = true,
+                    }
+                    if let (Some(ruby), Some((left, right))) = (ruby, open_box.extent) {
+                        let frame = open_box.frame;
+                        let base = open_box.inline_box.metrics;
+                        let height = ruby.metrics.line_height;
+                        let group = &mut groups[frame.group];
+                        // 注釈は親文字のfontの上端か下端に接して置き、行の高さに含める
+                        let baseline = match ruby.over {
+                            true => {
+                                group.ascent = group.ascent.max(frame.shift + base.ascent + height);
+                                base.ascent + height
+                            }
+                            false => {
+                                group.descent =
+                                    group.descent.max(base.descent + height - frame.shift);
+                                -base.descent
+                            }
+                        };
+                        for (path, mut fragment) in ruby.fragments(left, right - left) {
+                            fragment.dimensions.content.x += origin_x;
+                            line_fragments.push((path, fragment, baseline, frame));
+                        }
+                    }
+                    if !matches!(ruby, Some(ruby) if !ruby.base_box) {
+                        line_fragments.extend(box_fragment(&open_box, origin_x));
+                    }
+                }
+            }
+            InlineItem::Word {
+                path,
+                text,
+                metrics,
+                trailing: word_trailing,
+                hyphen,
+                level,
+                ..
+            } => {
+                let frame = *frames.last().unwrap();
+                groups[frame.group].extend(frame.shift, metrics);
+                let mut text = text.clone();
+                let mut width = metrics.width;
+                // 行末のsoft hyphenで改行した場合はhyphenを表示する
+                if let (true, Some(true), Some(hyphen)) =
+                    (line.broken, last_word.map(|last| last == index), hyphen)
+                {
+                    text.push('-');
+                    width += hyphen;
+                }
+                extend_boxes(open, placement.x, width);
+                let previous_trailing = std::mem::replace(&mut trailing, word_trailing);
+                let separated = std::mem::replace(&mut after_tab, false);
+                let same_level = previous_level.replace(*level) == Some(*level);
+                // 同じtext nodeの同じレベルの単語が続く場合は1つの断片にまとめる.
+                // 奇数のレベルでは後の単語が左側に来る
+                if let Some((last_path, fragment, _, _)) = line_fragments.last_mut() {
+                    if last_path == path && same_level && !separated && !line.justified {
+                        let content = &mut fragment.dimensions.content;
+                        let x = origin_x + placement.x;
+                        if level % 2 == 0 {
+                            content.width = x + width - content.x;
+                            fragment.text.push_str(previous_trailing);
+                            fragment.text.push_str(&text);
+                        } else {
+                            content.width = content.x + content.width - x;
+                            content.x = x;
+                            fragment.text = text + previous_trailing + &fragment.text;
+                        }
+                        continue;
+                    }
+                }
+                let mut fragment = Fragment {
+                    text,
+                    ..Default::default()
+                };
+                fragment.dimensions.content.x = origin_x + placement.x;
+                fragment.dimensions.content.width = width;
+                fragment.dimensions.content.height = metrics.line_height;
+                line_fragments.push((path.clone(), fragment, metrics.baseline(), frame));
+            }
+            InlineItem::Tab { .. } => {
+                extend_boxes(open, placement.x, placement.width);
+                after_tab = true;
+            }
+            // 単語のない行も改行したtextのline-heightの高さを持つ
+            InlineItem::LineBreak(metrics) => {
+                let frame = *frames.last().unwrap();
+                groups[frame.group].extend(frame.shift, metrics);
+            }
+        }
+    }
+    // 次の行へ続くinline要素は、この行に置いた内容の範囲で分ける
+    for open_box in open.iter() {
+        line_fragments.extend(box_fragment(open_box, origin_x));
+    }
+
+    // top, bottomの要素が行に収まるように、行の高さを広げる
+    let (mut ascent, mut descent) = (groups[0].ascent, groups[0].descent);
+    for group in &groups[1..] {
+        let height = group.ascent + group.descent;
+        match group.align {
+            VerticalAlign::Top => descent = descent.max(height - ascent),
+            _ => ascent = ascent.max(height - descent),
+        }
+    }
+    // 行のbaselineからgroupのbaselineまでの上向きの距離
+    let offsets: Vec<f32> = groups
+        .iter()
+        .map(|group| match group.align {
+            VerticalAlign::Top => ascent - group.ascent,
+            VerticalAlign::Bottom => group.descent - descent,
+            _ => 0.0,
+        })
+        .collect();
+
+    let baseline = y + ascent;
+    for (path, mut fragment, fragment_baseline, frame) in line_fragments {
+        fragment.dimensions.content.y =
+            baseline - offsets[frame.group] - frame.shift - fragment_baseline;
+        fragments.push((path, fragment));
+    }
+    ascent + descent
+}
+
+// 行に置いた要素を、それを含むすべてのinline要素の断片の範囲に含める
+fn extend_boxes(open: &mut [OpenBox], x: f32, width: f32) {
+    for open_box in open.iter_mut() {
+        open_box.extend(x, width);
+    }
+}
+
+/*
+inline要素の断片. 高さはline-heightではなくfontの大きさになる.
+行で分けた側はmargin, border, paddingを持たない
+*/
+fn box_fragment(open_box: &OpenBox, origin_x: f32) -> Option<(Vec<usize>, Fragment, f32, Frame)> {
+    let (left, right) = open_box.extent?;
+    let mut d = open_box.dimensions;
+    if !open_box.left {
+        d.margin.left = 0.0;
+        d.border.left = 0.0;
+        d.padding.left = 0.0;
+    }
+    if !open_box.right {
+        d.margin.right = 0.0;
+        d.border.right = 0.0;
+        d.padding.right = 0.0;
+    }
+    let metrics = open_box.inline_box.metrics;
+    d.content.x = origin_x + left + left_edge(&d);
+    d.content.width = (right - left - left_edge(&d) - right_edge(&d)).max(0.0);
+    d.content.height = metrics.ascent + metrics.descent;
+    let fragment = Fragment {
+        dimensions: d,
+        text: String::new(),
+    };
+    Some((
+        open_box.path.clone(),
+        fragment,
+        metrics.ascent,
+        open_box.frame,
+    ))
+}
+
+#[test]
+fn test_layout_inline_children() {
+    use crate::layout::{layout_html, BoxType};
+
+    let layout = layout_html(
+        "<div class=\"a\">one two three <span class=\"b\">four five six seven</span> eight</div>",
+        ".a { display: block; }
+        .b { padding: 4px; background-color: #ff0000; }",
+        120.0,
+        0.0,
+    );
+
+    // inline要素はAnonymouseBlockにまとめられる
+    let block = &layout.children[0];
+    assert!(matches!(block.box_type, BoxType::AnonymouseBlock));
+    let words: Vec<&Fragment> = block
+        .children
+        .iter()
+        .filter(|child| !child.text().is_empty())
+        .flat_map(|child| child.fragments.iter())
+        .collect();
+    // 幅を超える前に折り返し、単語は幅に収まる
+    let line_tops: Vec<f32> = words.iter().map(|f| f.dimensions.content.y).collect();
+    assert!(line_tops.iter().any(|y| *y > line_tops[0]));
+    for fragment in &words {
+        let content = fragment.dimensions.content;
+        assert!(content.x + content.width <= 120.0 + 0.01);
+    }
+    // 行の高さの合計がblockの高さになる
+    let last = words.last().unwrap().dimensions.content;
+    assert!((block.dimensions.content.height - (last.y + last.height)).abs() < 0.01);
+    assert_eq!(
+        layout.dimensions.content.height,
+        block.dimensions.content.height
+    );
+
+    // 行をまたぐspanは複数の断片になり、paddingは最初と最後の断片だけが持つ
+    let span = &block.children[1];
+    assert!(span.fragments.len() >= 2);
+    let first = span.fragments.first().unwrap().dimensions;
+    let last = span.fragments.last().unwrap().dimensions;
+    assert_eq!((first.padding.left, first.padding.right), (4.0, 0.0));
+    assert_eq!((last.padding.left, last.padding.right), (0.0, 4.0));
+    assert_eq!(first.padding.top, 4.0);
+}
+
+#[test]
+fn test_layout_inline_line_break() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"a\"><p class=\"b\">extra\u{AD}ordinary</p><p class=\"c\">AAAAAAAAAAAAAAAA</p></div>",
+        ".a { display: block; }
+        .b { display: block; }
+        .c { display: block; overflow-wrap: break-word; }",
+        60.0,
+        0.0,
+    );
+
+    let texts = |index: usize| -> Vec<String> {
+        layout.children[index].children[0].children[0]
+            .fragments
+            .iter()
+            .map(|fragment| fragment.text.clone())
+            .collect()
+    };
+    // soft hyphenで改行した行末にはhyphenを表示する
+    assert_eq!(texts(0), vec!["extra-", "ordinary"]);
+    // 幅を超える単語は文字の間で分ける
+    let pieces = texts(1);
+    assert!(pieces.len() > 1);
+    assert_eq!(pieces.concat(), "AAAAAAAAAAAAAAAA");
+}
+
+#[test]
+fn test_layout_inline_white_space() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"a\"><pre class=\"b\">\nfn main() {\n\tok();\n\n}\n</pre><p class=\"b\">  one   two\n three  </p><p class=\"c\">one two three four</p></div>",
+        ".a { display: block; }
+        .b { display: block; }
+        .c { display: block; white-space: nowrap; }",
+        60.0,
+        0.0,
+    );
+    let fragments = |index: usize| -> Vec<Fragment> {
+        layout.children[index].children[0].children[0]
+            .fragments
+            .clone()
+    };
+
+    // preは空白と改行をそのまま表示し、幅を超えても折り返さない
+    let pre = fragments(0);
+    let texts: Vec<&str> = pre.iter().map(|f| f.text.as_str()).collect();
+    assert_eq!(texts, vec!["fn main() {", "ok();", "}"]);
+    let line_height = pre[1].dimensions.content.y - pre[0].dimensions.content.y;
+    assert!(line_height > 0.0);
+    // 空の行も高さを持つ
+    assert!(
+        (pre[2].dimensions.content.y - pre[0].dimensions.content.y - line_height * 3.0).abs()
+            < 0.01
+    );
+    // tabは空白8文字分の位置まで進める
+    let style = layout.children[0].children[0].children[0].text_style();
+    let space = FontDatabase::new().measure(" ", &style).width;
+    assert!((pre[1].dimensions.content.x - space * 8.0).abs() < 0.01);
+
+    // 連続する空白と改行は1つの空白にまとめ、行頭と行末の空白は表示しない
+    let normal = fragments(1);
+    let texts: Vec<&str> = normal.iter().map(|f| f.text.as_str()).collect();
+    assert_eq!(texts.join("|"), "one two|three");
+    assert_eq!(normal[0].dimensions.content.x, 0.0);
+
+    // nowrapは折り返さない
+    let nowrap = fragments(2);
+    assert_eq!(nowrap.len(), 1);
+    assert_eq!(nowrap[0].text, "one two three four");
+}
+
+#[test]
+fn test_layout_inline_text_align() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"a\"><p class=\"center\">one two</p><p class=\"right\">one two</p><p class=\"justify\">one two three four five six seven eight nine ten</p><p class=\"indent\">one</p></div>",
+        ".a { display: block; }
+        .center { display: block; text-align: center; }
+        .right { display: block; text-align: right; }
+        .justify { display: block; text-align: justify; }
+        .indent { display: block; text-indent: 10%; }",
+        200.0,
+        0.0,
+    );
+    let fragments = |index: usize| -> Vec<Fragment> {
+        layout.children[index].children[0].children[0]
+            .fragments
+            .clone()
+    };
+    let right_end =
+        |fragment: &Fragment| fragment.dimensions.content.x + fragment.dimensions.content.width;
+
+    // 中央揃えは左右の余白が等しい
+    let center = &fragments(0)[0];
+    assert!((center.dimensions.content.x - (200.0 - right_end(center))).abs() < 0.01);
+    // 右揃えは右端に揃える
+    assert!((right_end(&fragments(1)[0]) - 200.0).abs() < 0.01);
+    // 両端揃えは最後の行以外の右端を揃え、単語ごとの断片にする
+    let justify = fragments(2);
+    let first_line: Vec<&Fragment> = justify
+        .iter()
+        .filter(|f| f.dimensions.content.y == justify[0].dimensions.content.y)
+        .collect();
+    assert!(first_line.len() > 1);
+    assert!((right_end(first_line.last().unwrap()) - 200.0).abs() < 0.01);
+    let last = justify.last().unwrap();
+    assert!(last.dimensions.content.y > justify[0].dimensions.content.y);
+    assert!(right_end(last) < 200.0);
+    // text-indentの%はblockの幅を基準にする
+    assert_eq!(fragments(3)[0].dimensions.content.x, 20.0);
+}
+
+#[test]
+fn test_layout_inline_vertical_align() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><p class=\"line\">x<span class=\"sup\">2</span></p><p class=\"line\">x<span class=\"tall\">y</span></p><p class=\"line\">x<span class=\"top\">y</span></p><p class=\"line\"><span class=\"small\">x</span></p></div>",
+        ".root { display: block; }
+        .line { display: block; font-size: 16px; line-height: 20px; }
+        .sup { vertical-align: super; }
+        .tall { line-height: 40px; }
+        .top { vertical-align: top; line-height: 40px; }
+        .small { font-size: 8px; line-height: 1; }",
+        200.0,
+        0.0,
+    );
+    let line = |index: usize| &layout.children[index];
+    let text_y = |index: usize| {
+        line(index).children[0].children[0].fragments[0]
+            .dimensions
+            .content
+            .y
+    };
+    let span_text_y = |index: usize| {
+        line(index).children[0].children[1].children[0].fragments[0]
+            .dimensions
+            .content
+            .y
+    };
+
+    // superは親要素のfont-sizeの1/3だけ上げ、行の高さも広がる
+    assert!((text_y(0) - span_text_y(0) - 16.0 / 3.0).abs() < 0.01);
+    assert!(line(0).dimensions.content.height > 20.0);
+    // line-heightの大きい要素は上下に同じだけ行を広げる. 断片はline-heightの高さを持つ
+    assert!((line(1).dimensions.content.height - 40.0).abs() < 0.01);
+    assert!((text_y(1) - span_text_y(1) - 10.0).abs() < 0.01);
+    // topは行の上端に揃える
+    assert!((line(2).dimensions.content.height - 40.0).abs() < 0.01);
+    assert!((span_text_y(2) - line(2).dimensions.content.y).abs() < 0.01);
+    // 小さいfontだけの行もblockのfontの高さ(strut)を持つ
+    assert!((line(3).dimensions.content.height - 20.0).abs() < 0.01);
+}
+
+#[test]
+fn test_layout_inline_bidi() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><p class=\"line\">abc \u{5D0}\u{5D1}\u{5D2} def</p><p class=\"line\" dir=\"rtl\">\u{5D0}\u{5D1}\u{5D2} abc</p><p class=\"line\" dir=\"rtl\">\u{5D0} <span class=\"box\">(ab)</span></p><p class=\"line\">a<span class=\"over\">bc</span></p></div>",
+        ".root { display: block; }
+        .line { display: block; }
+        .box { padding: 5px; }
+        .over { unicode-bidi: bidi-override; direction: rtl; }",
+        200.0,
+        0.0,
+    );
+    let inline = |index: usize| &layout.children[index].children[0];
+    let right_end =
+        |fragment: &Fragment| fragment.dimensions.content.x + fragment.dimensions.content.width;
+
+    // 左から右の段落の中のヘブライ文字は、文字を逆順にした別の断片になる
+    let ltr = &inline(0).children[0].fragments;
+    let texts: Vec<&str> = ltr.iter().map(|f| f.text.as_str()).collect();
+    assert_eq!(texts, vec!["abc", "\u{5D2}\u{5D1}\u{5D0}", "def"]);
+    assert!(ltr[0].dimensions.content.x < ltr[1].dimensions.content.x);
+    assert!(ltr[1].dimensions.content.x < ltr[2].dimensions.content.x);
+
+    // dir=rtlの段落は右端から並べ、英語は段落の左側に来る
+    let rtl = &inline(1).children[0].fragments;
+    assert_eq!(rtl[0].text, "\u{5D2}\u{5D1}\u{5D0}");
+    assert_eq!(rtl[1].text, "abc");
+    assert!((right_end(&rtl[0]) - 200.0).abs() < 0.01);
+    assert!(rtl[1].dimensions.content.x < rtl[0].dimensions.content.x);
+
+    // 右から左に並べたinline要素は、内容の範囲の断片になり、左右の位置は変わらない
+    // 括弧は段落の方向になり、左右を反転して表示する
+    let span = &inline(2).children[1];
+    let mut texts = span.children[0].fragments.clone();
+    texts.sort_by(|a, b| a.dimensions.content.x.total_cmp(&b.dimensions.content.x));
+    let visual: String = texts.iter().map(|f| f.text.as_str()).collect();
+    assert_eq!(visual, "(ab)");
+    let hebrew = &inline(2).children[0].fragments[0];
+    assert!(right_end(&span.fragments[0]) <= hebrew.dimensions.content.x + 0.01);
+    assert_eq!(span.fragments[0].dimensions.padding.left, 5.0);
+    assert!((span.fragments[0].dimensions.content.x - texts[0].dimensions.content.x).abs() < 0.01);
+
+    // bidi-overrideは英語も右から左に並べる
+    let over = &inline(3).children[1].children[0].fragments[0];
+    assert_eq!(over.text, "cb");
+}
+
+#[test]
+fn test_layout_inline_block_split() {
+    use crate::layout::{layout_html, BoxType};
+
+    let layout = layout_html(
+        "<div class=\"root\"><span class=\"s\">before<div class=\"b\">BLOCK</div>after</span></div>",
+        ".root { display: block; }
+        .s { border-width: 3px; border-style: solid; padding: 4px; }
+        .b { display: block; }",
+        200.0,
+        0.0,
+    );
+    // inline要素はblock要素の前後で分け、block要素はその間に並べる
+    assert_eq!(layout.children.len(), 3);
+    let block = &layout.children[1];
+    assert!(matches!(block.box_type, BoxType::BlockNode(_)));
+    assert_eq!(block.children[0].children[0].fragments[0].text, "BLOCK");
+    let before = &layout.children[0].children[0];
+    let after = &layout.children[2].children[0];
+    assert_eq!(before.children[0].fragments[0].text, "before");
+    assert_eq!(after.children[0].fragments[0].text, "after");
+    assert!(block.dimensions.content.y > before.fragments[0].dimensions.content.y);
+    assert!(after.fragments[0].dimensions.content.y > block.dimensions.content.y);
+    // 分けた側の辺にはborderとpaddingを付けない
+    let (first, last) = (
+        &before.fragments[0].dimensions,
+        &after.fragments[0].dimensions,
+    );
+    assert_eq!((first.border.left, first.border.right), (3.0, 0.0));
+    assert_eq!((last.padding.left, last.padding.right), (0.0, 4.0));
+
+    // root要素がinline要素の場合も、断片とblock要素を縦に並べる
+    let layout = layout_html(
+        "<span>before<div class=\"b\">BLOCK</div>after</span>",
+        ".b { display: block; }",
+        200.0,
+        0.0,
+    );
+    assert_eq!(layout.children.len(), 3);
+    let y = |index: usize| layout.children[index].dimensions.content.y;
+    assert!(y(0) < y(1) && y(1) < y(2));
+    assert_eq!(
+        layout.children[2].children[0].children[0].fragments[0].text,
+        "after"
+    );
+}
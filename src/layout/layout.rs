@@ -1,14 +1,96 @@
-use crate::layout::{BoxType, Dimensions, LayoutBox};
+use crate::font::{FontDatabase, LineHeight};
+use crate::layout::writing_mode::WritingMode;
+use crate::layout::{BoxType, Dimensions, LayoutBox, LayoutContext};
+#[cfg(test)]
+use crate::style::style_html;
 use crate::style::{Display, StyledNode};
 
+// font-sizeが指定されていない場合の初期値(medium)
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
+
 // main関数から呼ばれるトップクラスの関数
-pub fn layout_tree<'a>(node: &'a StyledNode, mut containing_block: Dimensions) -> LayoutBox<'a> {
+// 文字列の大きさはfontsを使って計測する
+pub fn layout_tree<'a>(
+    node: &'a StyledNode,
+    mut containing_block: Dimensions,
+    fonts: &FontDatabase,
+) -> LayoutBox<'a> {
+    let initial_context = LayoutContext {
+        viewport: containing_block.content,
+        root_font_size: DEFAULT_FONT_SIZE,
+        font_size: DEFAULT_FONT_SIZE,
+        line_height: LineHeight::Normal,
+        ..Default::default()
+    };
     containing_block.content.height = 0.0;
     let mut root_box = build_layout_tree(node);
-    root_box.layout(containing_block);
+    // remはroot要素のfont-sizeを基準にする
+    let mut context = root_box.inherit_context(initial_context);
+    context.root_font_size = context.font_size;
+    // root要素がinlineの場合は、行に並べるためにAnonymouseBlockで包む
+    if let BoxType::InlineNode(_) = root_box.box_type {
+        context = LayoutContext {
+            root_font_size: context.root_font_size,
+            ..initial_context
+        };
+        let mut anonymous_block = LayoutBox::new(BoxType::AnonymouseBlock);
+        let pieces = split_inline(root_box);
+        // block要素で分けた場合は、inline要素の断片をそれぞれAnonymouseBlockで包んで縦に並べる
+        if pieces.len() == 1 {
+            anonymous_block.children = pieces;
+        } else {
+            for piece in pieces {
+                anonymous_block.children.push(match piece.box_type {
+                    BoxType::InlineNode(_) => {
+                        let mut wrapper = LayoutBox::new(BoxType::AnonymouseBlock);
+                        wrapper.children.push(piece);
+                        wrapper
+                    }
+                    _ => piece,
+                });
+            }
+        }
+        root_box = anonymous_block;
+    }
+    // root要素のmarginは子要素と相殺しない
+    context.formatting_root = true;
+    // root要素のheightの%はviewportの大きさを基準にする
+    context.containing_block_height = Some(match context.writing_mode.is_vertical() {
+        true => initial_context.viewport.width,
+        false => initial_context.viewport.height,
+    });
+    match context.writing_mode {
+        WritingMode::HorizontalTb => root_box.layout(containing_block, context, fonts),
+        mode => {
+            root_box.layout_writing_mode_root(
+                containing_block,
+                context,
+                WritingMode::HorizontalTb,
+                fonts,
+            );
+            // vertical-rlのroot要素は右端から並べる
+            if mode == WritingMode::VerticalRl {
+                let x = containing_block.content.width - root_box.dimensions.margin_box().width;
+                root_box.map_tree(&|mut d| {
+                    d.content.x += x;
+                    d
+                });
+            }
+        }
+    }
     root_box
 }
 
+// テスト用にHTMLとCSSから大きさwidth x heightのviewportでlayoutする
+#[cfg(test)]
+pub fn layout_html(html: &str, css: &str, width: f32, height: f32) -> LayoutBox<'static> {
+    let styled = Box::leak(Box::new(style_html(html, css)));
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = width;
+    viewport.content.height = height;
+    layout_tree(styled, viewport, &FontDatabase::new())
+}
+
 fn build_layout_tree<'a>(style_node: &'a StyledNode) -> LayoutBox<'a> {
     let mut root = LayoutBox::new(match style_node.display() {
         Display::Block => BoxType::BlockNode(style_node),
@@ -19,12 +101,54 @@ fn build_layout_tree<'a>(style_node: &'a StyledNode) -> LayoutBox<'a> {
     for child in &style_node.children {
         match child.display() {
             Display::Block => root.children.push(build_layout_tree(child)),
-            Display::Inline => root
-                .get_inline_container()
-                .children
-                .push(build_layout_tree(child)),
+            // inline要素の中のblock要素は、inline要素を含むblockまで取り出す
+            Display::Inline => match root.box_type {
+                BoxType::BlockNode(_) => {
+                    for piece in split_inline(build_layout_tree(child)) {
+                        match piece.box_type {
+                            BoxType::BlockNode(_) => root.children.push(piece),
+                            _ => root.get_inline_container().children.push(piece),
+                        }
+                    }
+                }
+                _ => root.children.push(build_layout_tree(child)),
+            },
             Display::None => {}
         }
     }
-    return root;
+    root
+}
+
+/*
+block要素を含むinline要素を、CSS 2.1 9.2.1.1のようにblock要素の前後で分ける.
+inline要素の断片とblock要素を並び順に返す. 断片は同じ要素のboxで、分けた側の辺を持たない
+*/
+fn split_inline(inline: LayoutBox) -> Vec<LayoutBox> {
+    let style = match inline.box_type {
+        BoxType::InlineNode(style) => style,
+        _ => return vec![inline],
+    };
+    let mut pieces = vec![];
+    let mut piece = LayoutBox::new(BoxType::InlineNode(style));
+    for child in inline.children {
+        for part in split_inline(child) {
+            match part.box_type {
+                BoxType::BlockNode(_) => {
+                    let next = LayoutBox::new(BoxType::InlineNode(style));
+                    pieces.push(std::mem::replace(&mut piece, next));
+                    pieces.push(part);
+                }
+                _ => piece.children.push(part),
+            }
+        }
+    }
+    pieces.push(piece);
+    let last = pieces.len() - 1;
+    for (index, piece) in pieces.iter_mut().enumerate() {
+        if let BoxType::InlineNode(_) = piece.box_type {
+            piece.split.before = index > 0;
+            piece.split.after = index < last;
+        }
+    }
+    pieces
 }
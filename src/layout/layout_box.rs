@@ -1,4 +1,11 @@
-use crate::layout::entity::{BoxType, Dimensions};
+use crate::css::Value;
+use crate::font::{FontDatabase, LineHeight};
+use crate::layout::bidi::{Direction, UnicodeBidi};
+use crate::layout::entity::{
+    BoxType, CollapsedMargins, Dimensions, Fragment, InlineSplit, LayoutContext, TextAlign,
+    TextIndent,
+};
+use crate::layout::writing_mode::WritingMode;
 use crate::style::StyledNode;
 
 #[derive(Debug)]
@@ -6,6 +13,14 @@ pub struct LayoutBox<'a> {
     pub dimensions: Dimensions,
     pub box_type: BoxType<'a>,
     pub children: Vec<LayoutBox<'a>>,
+    pub context: LayoutContext,
+    // inline要素の場合は、dimensionsの代わりに行ごとの断片を使う
+    pub fragments: Vec<Fragment>,
+    // 親要素のblockが相殺に使うmargin. dimensions.marginは相殺前の指定値のままで、
+    // 相殺したblockの位置はcollapsedから決める. margin_box()は相殺しないblockにだけ使う
+    pub collapsed: CollapsedMargins,
+    // block要素の前後で分けたinline要素では、続きの側にmargin, border, paddingを付けない
+    pub split: InlineSplit,
 }
 
 impl<'a> LayoutBox<'a> {
@@ -14,6 +29,10 @@ impl<'a> LayoutBox<'a> {
             box_type,
             dimensions: Default::default(),
             children: vec![],
+            context: Default::default(),
+            fragments: vec![],
+            collapsed: Default::default(),
+            split: Default::default(),
         }
     }
     //
@@ -39,17 +58,91 @@ impl<'a> LayoutBox<'a> {
             BoxType::AnonymouseBlock => panic!("Anonymous block box has no style node"),
         }
     }
+    // 親要素のcontextからfont-sizeを計算する. font-sizeの%やemは親要素のfont-sizeが基準になる
+    pub fn inherit_context(&self, parent: LayoutContext) -> LayoutContext {
+        let style = match self.box_type {
+            BoxType::BlockNode(style) | BoxType::InlineNode(style) => style,
+            BoxType::AnonymouseBlock => return parent,
+        };
+        let font_size = match style.value("font-size") {
+            Some(value @ Value::Length(..))
+            | Some(value @ Value::Percentage(_))
+            | Some(value @ Value::Math(_)) => {
+                value.resolve(&parent.length_context(parent.font_size))
+            }
+            _ => parent.font_size,
+        };
+        let context = LayoutContext {
+            font_size,
+            ..parent
+        };
+        // line-heightの長さは要素自身のfont-sizeを基準にpxにしてから子要素へ引き継ぐ.
+        // 数値の場合は子要素のfont-sizeに対する倍率として引き継ぐ
+        let line_height = match style.value("line-height") {
+            Some(Value::Number(number)) => LineHeight::Number(number),
+            Some(Value::Keyword(keyword)) if keyword == "normal" => LineHeight::Normal,
+            Some(value @ Value::Length(..))
+            | Some(value @ Value::Percentage(_))
+            | Some(value @ Value::Math(_)) => {
+                LineHeight::Length(value.resolve(&context.length_context(font_size)))
+            }
+            _ => parent.line_height,
+        };
+        let text_align = match style.value("text-align") {
+            Some(Value::Keyword(keyword)) => {
+                TextAlign::from_keyword(&keyword).unwrap_or(parent.text_align)
+            }
+            _ => parent.text_align,
+        };
+        let length = context.length_context(0.0);
+        let text_indent = match style.value("text-indent") {
+            Some(Value::Percentage(percentage)) => TextIndent::Percentage(percentage),
+            Some(value @ Value::Length(..)) | Some(value @ Value::Math(_)) => {
+                TextIndent::Length(value.resolve(&length))
+            }
+            _ => parent.text_indent,
+        };
+        // normalは0として扱う
+        let spacing = |name: &str, parent: f32| match style.value(name) {
+            Some(value @ Value::Length(..)) | Some(value @ Value::Math(_)) => {
+                value.resolve(&length)
+            }
+            Some(Value::Keyword(keyword)) if keyword == "normal" => 0.0,
+            _ => parent,
+        };
+        // FIXME: inline要素のwriting-modeは無視し、行を並べるblockのものを使う
+        let writing_mode = match self.box_type {
+            BoxType::InlineNode(_) => parent.writing_mode,
+            _ => {
+                WritingMode::from_value(style.value("writing-mode")).unwrap_or(parent.writing_mode)
+            }
+        };
+        LayoutContext {
+            line_height,
+            text_align,
+            text_indent,
+            letter_spacing: spacing("letter-spacing", parent.letter_spacing),
+            word_spacing: spacing("word-spacing", parent.word_spacing),
+            direction: Direction::from_value(style.value("direction")).unwrap_or(parent.direction),
+            unicode_bidi: UnicodeBidi::from_value(style.value("unicode-bidi")),
+            writing_mode,
+            formatting_root: false,
+            ..context
+        }
+    }
     // boxの種類に応じてレンダリングの処理を振り分ける
-    pub fn layout(&mut self, containing_block: Dimensions) {
+    pub fn layout(
+        &mut self,
+        containing_block: Dimensions,
+        context: LayoutContext,
+        fonts: &FontDatabase,
+    ) {
+        self.context = context;
         match self.box_type {
-            BoxType::BlockNode(_) => self.layout_block(containing_block),
-            BoxType::InlineNode(_) => self.layout_inline(containing_block),
-            BoxType::AnonymouseBlock => self.layout_anonymous_block(containing_block),
+            BoxType::BlockNode(_) => self.layout_block(containing_block, fonts),
+            // inline要素は親のAnonymouseBlockが行に並べる
+            BoxType::InlineNode(_) => panic!("Inline box must be laid out in a line box"),
+            BoxType::AnonymouseBlock => self.layout_anonymous_block(containing_block, fonts),
         }
     }
-
-    // FIXME: inline-block独自の位置調整をする
-    fn layout_inline(&mut self, containing_block: Dimensions) {
-        self.layout_block(containing_block);
-    }
 }
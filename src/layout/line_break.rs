@@ -0,0 +1,622 @@
+use crate::css::Value;
+
+/*
+UAX #14(Unicode Line Breaking Algorithm)の改行位置を求める.
+https://www.unicode.org/reports/tr14/
+classの表は主要な文字だけを持ち、表にない文字はALとして扱う
+*/
+
+/// UAX #14のline breaking class
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakClass {
+    // 必ず改行する
+    BK,
+    CR,
+    LF,
+    NL,
+    SP,
+    ZW,
+    WJ,
+    GL,
+    CM,
+    ZWJ,
+    // 開き括弧、閉じ括弧
+    OP,
+    CL,
+    CP,
+    QU,
+    EX,
+    IS,
+    // 行頭に置けない文字(々、ゝなど)
+    NS,
+    SY,
+    PR,
+    PO,
+    NU,
+    AL,
+    HL,
+    // 漢字、かななどの表意文字
+    ID,
+    IN,
+    HY,
+    BA,
+    BB,
+    B2,
+    CB,
+    // 小書きのかな、長音記号
+    CJ,
+    JL,
+    JV,
+    JT,
+    H2,
+    H3,
+    RI,
+    EB,
+    EM,
+}
+
+/// 改行の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Break {
+    // 改行してもよい
+    Allowed,
+    // 改行しなければならない
+    Mandatory,
+}
+
+/// word-break
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WordBreak {
+    #[default]
+    Normal,
+    // 単語の途中でも改行する
+    BreakAll,
+    // CJKの文字の間でも改行しない
+    KeepAll,
+}
+
+/// line-break. 禁則処理の厳しさ
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineBreak {
+    #[default]
+    Auto,
+    Loose,
+    Normal,
+    Strict,
+    Anywhere,
+}
+
+/// hyphens. autoは辞書がないためmanualとして扱う
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Hyphens {
+    None,
+    #[default]
+    Manual,
+    Auto,
+}
+
+/// overflow-wrap. 行に収まらない単語を途中で分けるかどうか
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowWrap {
+    #[default]
+    Normal,
+    BreakWord,
+    Anywhere,
+}
+
+/// 改行位置の計算に使うproperty
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LineBreakOptions {
+    pub word_break: WordBreak,
+    pub line_break: LineBreak,
+    pub hyphens: Hyphens,
+}
+
+// keywordでない値や未対応のkeywordは初期値にする
+fn keyword(value: Option<Value>) -> String {
+    match value {
+        Some(Value::Keyword(keyword)) => keyword.to_lowercase(),
+        _ => String::new(),
+    }
+}
+
+impl WordBreak {
+    pub fn from_value(value: Option<Value>) -> WordBreak {
+        match keyword(value).as_str() {
+            "break-all" => WordBreak::BreakAll,
+            "keep-all" => WordBreak::KeepAll,
+            _ => WordBreak::Normal,
+        }
+    }
+}
+
+impl LineBreak {
+    pub fn from_value(value: Option<Value>) -> LineBreak {
+        match keyword(value).as_str() {
+            "loose" => LineBreak::Loose,
+            "normal" => LineBreak::Normal,
+            "strict" => LineBreak::Strict,
+            "anywhere" => LineBreak::Anywhere,
+            _ => LineBreak::Auto,
+        }
+    }
+}
+
+impl Hyphens {
+    pub fn from_value(value: Option<Value>) -> Hyphens {
+        match keyword(value).as_str() {
+            "none" => Hyphens::None,
+            "auto" => Hyphens::Auto,
+            _ => Hyphens::Manual,
+        }
+    }
+}
+
+impl OverflowWrap {
+    pub fn from_value(value: Option<Value>) -> OverflowWrap {
+        match keyword(value).as_str() {
+            "break-word" => OverflowWrap::BreakWord,
+            "anywhere" => OverflowWrap::Anywhere,
+            _ => OverflowWrap::Normal,
+        }
+    }
+}
+
+pub const SOFT_HYPHEN: char = '\u{00AD}';
+
+pub fn break_class(c: char) -> BreakClass {
+    use BreakClass::*;
+    match c {
+        '\n' => LF,
+        '\r' => CR,
+        '\u{0B}' | '\u{0C}' | '\u{2028}' | '\u{2029}' => BK,
+        '\u{85}' => NL,
+        ' ' => SP,
+        '\t'
+        | '\u{00AD}'
+        | '|'
+        | '\u{1680}'
+        | '\u{2000}'..='\u{2006}'
+        | '\u{2008}'..='\u{200A}' => BA,
+        '\u{200B}' => ZW,
+        '\u{2060}' | '\u{FEFF}' => WJ,
+        '\u{00A0}' | '\u{034F}' | '\u{2007}' | '\u{2011}' | '\u{202F}' => GL,
+        '\u{200D}' => ZWJ,
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{3099}'..='\u{309A}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}'
+        | '\u{E0100}'..='\u{E01EF}' => CM,
+
+        // ASCII
+        '!' | '?' => EX,
+        '"' | '\'' => QU,
+        '$' | '+' | '\\' => PR,
+        '%' => PO,
+        '(' | '[' | '{' => OP,
+        ')' | ']' => CP,
+        '}' => CL,
+        ',' | '.' | ':' | ';' => IS,
+        '-' => HY,
+        '/' => SY,
+        '0'..='9' => NU,
+
+        // Latin-1, 一般句読点
+        '\u{00A1}' | '\u{00BF}' => OP,
+        '\u{00A2}' | '\u{00B0}' | '\u{2030}'..='\u{2037}' | '\u{2103}' => PO,
+        '\u{00A3}' | '\u{00A5}' | '\u{00B1}' | '\u{20A0}'..='\u{20CF}' => PR,
+        '\u{00AB}' | '\u{00BB}' | '\u{2018}'..='\u{201F}' | '\u{2039}' | '\u{203A}' => QU,
+        '\u{00B4}' => BB,
+        '\u{05D0}'..='\u{05EA}' => HL,
+        '\u{2010}' | '\u{2012}' | '\u{2013}' => BA,
+        '\u{2014}' => B2,
+        '\u{2024}'..='\u{2026}' => IN,
+        '\u{203C}' | '\u{203D}' | '\u{2047}'..='\u{2049}' => NS,
+        '\u{2044}' => IS,
+        '\u{FFFC}' => CB,
+
+        // CJKの記号と句読点
+        '\u{3000}' => BA,
+        '\u{3001}' | '\u{3002}' | '\u{FE50}' | '\u{FE52}' | '\u{FF0C}' | '\u{FF0E}'
+        | '\u{FF61}' | '\u{FF64}' => CL,
+        '\u{3008}' | '\u{300A}' | '\u{300C}' | '\u{300E}' | '\u{3010}' | '\u{3014}'
+        | '\u{3016}' | '\u{3018}' | '\u{301A}' | '\u{301D}' | '\u{FF08}' | '\u{FF3B}'
+        | '\u{FF5B}' | '\u{FF5F}' | '\u{FF62}' => OP,
+        '\u{3009}' | '\u{300B}' | '\u{300D}' | '\u{300F}' | '\u{3011}' | '\u{3015}'
+        | '\u{3017}' | '\u{3019}' | '\u{301B}' | '\u{301E}' | '\u{301F}' | '\u{FF09}'
+        | '\u{FF3D}' | '\u{FF5D}' | '\u{FF60}' | '\u{FF63}' => CL,
+        '\u{3005}'
+        | '\u{301C}'
+        | '\u{303B}'
+        | '\u{309B}'..='\u{309E}'
+        | '\u{30A0}'
+        | '\u{30FB}'
+        | '\u{30FD}'
+        | '\u{30FE}'
+        | '\u{FF1A}'
+        | '\u{FF1B}'
+        | '\u{FF65}' => NS,
+        '\u{FF01}' | '\u{FF1F}' => EX,
+        '\u{FFE0}' => PO,
+        '\u{FFE1}' | '\u{FFE5}' | '\u{FFE6}' => PR,
+        // 肌の色を変えられる絵文字
+        '\u{261D}'
+        | '\u{26F9}'
+        | '\u{270A}'..='\u{270D}'
+        | '\u{1F442}'
+        | '\u{1F443}'
+        | '\u{1F446}'..='\u{1F450}'
+        | '\u{1F466}'..='\u{1F469}' => EB,
+        '\u{1F3FB}'..='\u{1F3FF}' => EM,
+        // 小書きのかなと長音記号
+        '\u{3041}'
+        | '\u{3043}'
+        | '\u{3045}'
+        | '\u{3047}'
+        | '\u{3049}'
+        | '\u{3063}'
+        | '\u{3083}'
+        | '\u{3085}'
+        | '\u{3087}'
+        | '\u{308E}'
+        | '\u{3095}'
+        | '\u{3096}'
+        | '\u{30A1}'
+        | '\u{30A3}'
+        | '\u{30A5}'
+        | '\u{30A7}'
+        | '\u{30A9}'
+        | '\u{30C3}'
+        | '\u{30E3}'
+        | '\u{30E5}'
+        | '\u{30E7}'
+        | '\u{30EE}'
+        | '\u{30F5}'
+        | '\u{30F6}'
+        | '\u{30FC}'
+        | '\u{31F0}'..='\u{31FF}'
+        | '\u{FF67}'..='\u{FF70}' => CJ,
+        '\u{2E80}'..='\u{2FFF}'
+        | '\u{3003}'..='\u{303F}'
+        | '\u{3040}'..='\u{30FF}'
+        | '\u{3100}'..='\u{31EF}'
+        | '\u{3200}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{A000}'..='\u{A4CF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FE30}'..='\u{FE4F}'
+        | '\u{FF00}'..='\u{FF60}'
+        | '\u{1F000}'..='\u{1F1E5}'
+        | '\u{1F200}'..='\u{1F3FA}'
+        | '\u{1F400}'..='\u{1FAFF}'
+        | '\u{20000}'..='\u{3FFFD}' => ID,
+
+        // ハングル
+        '\u{1100}'..='\u{115F}' | '\u{A960}'..='\u{A97F}' => JL,
+        '\u{1160}'..='\u{11A7}' | '\u{D7B0}'..='\u{D7C6}' => JV,
+        '\u{11A8}'..='\u{11FF}' | '\u{D7CB}'..='\u{D7FB}' => JT,
+        '\u{AC00}'..='\u{D7A3}' => match (c as u32 - 0xAC00) % 28 {
+            0 => H2,
+            _ => H3,
+        },
+
+        '\u{1F1E6}'..='\u{1F1FF}' => RI,
+        _ => AL,
+    }
+}
+
+// 行頭禁則を緩めるloose用の文字(繰り返し記号など)
+fn is_loose_ns(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3005}'
+            | '\u{301C}'
+            | '\u{303B}'
+            | '\u{309D}'
+            | '\u{309E}'
+            | '\u{30A0}'
+            | '\u{30FD}'
+            | '\u{30FE}'
+    )
+}
+
+// LB1とCSSのpropertyに応じてclassを置き換える
+fn resolve_class(c: char, options: &LineBreakOptions) -> BreakClass {
+    use BreakClass::*;
+    let class = match break_class(c) {
+        CJ => match options.line_break {
+            LineBreak::Strict => NS,
+            _ => ID,
+        },
+        NS if options.line_break == LineBreak::Loose && is_loose_ns(c) => ID,
+        class => class,
+    };
+    match (options.word_break, class) {
+        (WordBreak::BreakAll, AL | HL | NU) => ID,
+        (WordBreak::KeepAll, ID | H2 | H3 | JL | JV | JT) => AL,
+        (_, class) => class,
+    }
+}
+
+/// 改行できる位置. 位置は改行後の行の先頭の文字のbyte offsetで、文字列の末尾は含まない
+pub fn line_breaks(text: &str, options: &LineBreakOptions) -> Vec<(usize, Break)> {
+    line_breaks_in_runs(&[(text, *options)])
+}
+
+/// propertyの異なる複数の文字列をつなげた文字列の改行位置. 位置はつなげた文字列のbyte offset
+pub fn line_breaks_in_runs(runs: &[(&str, LineBreakOptions)]) -> Vec<(usize, Break)> {
+    use BreakClass::*;
+    let mut chars: Vec<(usize, char, &LineBreakOptions)> = vec![];
+    let mut offset = 0;
+    for (text, options) in runs {
+        chars.extend(text.char_indices().map(|(i, c)| (offset + i, c, options)));
+        offset += text.len();
+    }
+    let classes: Vec<BreakClass> = chars
+        .iter()
+        .map(|(_, c, options)| resolve_class(*c, options))
+        .collect();
+
+    let mut breaks = vec![];
+    for i in 1..chars.len() {
+        let (position, _, options) = chars[i];
+        let action = match options.line_break {
+            // 結合文字の前以外はどこでも改行できる
+            LineBreak::Anywhere => match (classes[i - 1], classes[i]) {
+                (CR, LF) => None,
+                (BK | CR | LF | NL, _) => Some(Break::Mandatory),
+                (_, CM | ZWJ) => None,
+                _ => Some(Break::Allowed),
+            },
+            _ => pair_break(&classes, i),
+        };
+        // hyphens: noneの場合はsoft hyphenの後で改行しない
+        let (_, previous, previous_options) = chars[i - 1];
+        if previous == SOFT_HYPHEN && previous_options.hyphens == Hyphens::None {
+            continue;
+        }
+        if let Some(action) = action {
+            breaks.push((position, action));
+        }
+    }
+    // 文字列の最後の改行文字の後も改行する(LB3)
+    if let Some(BK | CR | LF | NL) = classes.last() {
+        breaks.push((offset, Break::Mandatory));
+    }
+    breaks
+}
+
+/// 改行できる位置で文字列を分ける
+pub fn split_at_breaks<'a>(text: &'a str, options: &LineBreakOptions) -> Vec<&'a str> {
+    let mut segments = vec![];
+    let mut start = 0;
+    for (position, _) in line_breaks(text, options) {
+        segments.push(&text[start..position]);
+        start = position;
+    }
+    if start < text.len() {
+        segments.push(&text[start..]);
+    }
+    segments
+}
+
+// 結合文字の前の基底文字のclass(LB9, LB10)
+fn base_class(classes: &[BreakClass], index: usize) -> BreakClass {
+    use BreakClass::*;
+    if !matches!(classes[index], CM | ZWJ) {
+        return classes[index];
+    }
+    let mut i = index;
+    while matches!(classes[i], CM | ZWJ) {
+        if i == 0 {
+            return AL;
+        }
+        i -= 1;
+    }
+    // 改行や空白に続く結合文字はALとして扱う
+    match classes[i] {
+        BK | CR | LF | NL | SP | ZW => AL,
+        class => class,
+    }
+}
+
+// 空白を飛ばして直前の文字のclassを探す(LB8, LB14~LB17)
+fn class_before_spaces(classes: &[BreakClass], index: usize) -> Option<BreakClass> {
+    let mut i = index;
+    while classes[i] == BreakClass::SP {
+        if i == 0 {
+            return None;
+        }
+        i -= 1;
+    }
+    Some(base_class(classes, i))
+}
+
+// classes[i - 1]とclasses[i]の間で改行できるかどうか(LB4~LB31)
+fn pair_break(classes: &[BreakClass], i: usize) -> Option<Break> {
+    use BreakClass::*;
+    let (raw_before, raw_after) = (classes[i - 1], classes[i]);
+
+    // 改行文字
+    match (raw_before, raw_after) {
+        (BK, _) | (LF | NL, _) => return Some(Break::Mandatory),
+        (CR, LF) => return None,
+        (CR, _) => return Some(Break::Mandatory),
+        (_, BK | CR | LF | NL | SP | ZW) => return None,
+        _ => {}
+    }
+    if class_before_spaces(classes, i - 1) == Some(ZW) || raw_before == ZW {
+        return Some(Break::Allowed);
+    }
+    if raw_before == ZWJ {
+        return None;
+    }
+    // 結合文字は直前の文字と分けない
+    if matches!(raw_after, CM | ZWJ) {
+        return None;
+    }
+
+    let before = base_class(classes, i - 1);
+    let after = raw_after;
+    let before_spaces = class_before_spaces(classes, i - 1);
+
+    // 非改行の指定
+    if before == WJ || after == WJ || before == GL {
+        return None;
+    }
+    if after == GL && !matches!(before, SP | BA | HY) {
+        return None;
+    }
+    // 行頭禁則: 閉じ括弧や句読点の前で改行しない
+    if matches!(after, CL | CP | EX | IS | SY) {
+        return None;
+    }
+    // 行末禁則: 開き括弧の後で改行しない
+    if before_spaces == Some(OP) {
+        return None;
+    }
+    if after == OP && before_spaces == Some(QU) {
+        return None;
+    }
+    if after == NS && matches!(before_spaces, Some(CL | CP)) {
+        return None;
+    }
+    if after == B2 && before_spaces == Some(B2) {
+        return None;
+    }
+    // 空白の後で改行する
+    if before == SP {
+        return Some(Break::Allowed);
+    }
+    if before == QU || after == QU {
+        return None;
+    }
+    if before == CB || after == CB {
+        return Some(Break::Allowed);
+    }
+    if matches!(after, BA | HY | NS) || before == BB {
+        return None;
+    }
+    if matches!(before, HY | BA) && i >= 2 && base_class(classes, i - 2) == HL {
+        return None;
+    }
+    if before == SY && after == HL {
+        return None;
+    }
+    if after == IN {
+        return None;
+    }
+
+    let keep = match (before, after) {
+        // 英数字
+        (AL | HL, NU) | (NU, AL | HL) => true,
+        (PR, ID | EB | EM) | (ID | EB | EM, PO) => true,
+        (PR | PO, AL | HL) | (AL | HL, PR | PO) => true,
+        // 数値
+        (CL | CP | NU, PO | PR) | (PO | PR, OP | NU) | (HY | IS | NU | SY, NU) => true,
+        // ハングル
+        (JL, JL | JV | H2 | H3) | (JV | H2, JV | JT) | (JT | H3, JT) => true,
+        (JL | JV | JT | H2 | H3, PO) | (PR, JL | JV | JT | H2 | H3) => true,
+        (AL | HL, AL | HL) | (IS, AL | HL) => true,
+        (AL | HL | NU, OP) | (CP, AL | HL | NU) => true,
+        (EB, EM) => true,
+        // 国旗は2文字ずつ組にする
+        (RI, RI) => {
+            let count = classes[..i]
+                .iter()
+                .rev()
+                .take_while(|class| **class == RI)
+                .count();
+            count % 2 == 1
+        }
+        _ => false,
+    };
+    match keep {
+        true => None,
+        false => Some(Break::Allowed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(text: &str, options: LineBreakOptions) -> Vec<&str> {
+        split_at_breaks(text, &options)
+    }
+
+    #[test]
+    fn test_line_breaks_latin() {
+        let options = LineBreakOptions::default();
+        assert_eq!(
+            split("Hello, world! (test)", options),
+            vec!["Hello, ", "world! ", "(test)"]
+        );
+        // 数値や記号の途中では改行しない
+        assert_eq!(
+            split("$12.50 well-known", options),
+            vec!["$12.50 ", "well-", "known"]
+        );
+        assert_eq!(line_breaks("a\nb", &options), vec![(2, Break::Mandatory)]);
+        assert_eq!(line_breaks("a\n", &options), vec![(2, Break::Mandatory)]);
+        // text nodeの境目は前後の文字で改行できるかが決まる
+        assert_eq!(
+            line_breaks_in_runs(&[("one ", options), ("two", options), ("three", options)]),
+            vec![(4, Break::Allowed)]
+        );
+        // soft hyphenの後で改行できる
+        assert_eq!(split("hy\u{00AD}phen", options), vec!["hy\u{00AD}", "phen"]);
+        let none = LineBreakOptions {
+            hyphens: Hyphens::None,
+            ..Default::default()
+        };
+        assert_eq!(split("hy\u{00AD}phen", none), vec!["hy\u{00AD}phen"]);
+    }
+
+    #[test]
+    fn test_line_breaks_cjk() {
+        let options = LineBreakOptions::default();
+        // 句読点や閉じ括弧は行頭に、開き括弧は行末に来ない
+        assert_eq!(
+            split("今日は「晴れ」です。", options),
+            vec!["今", "日", "は", "「晴", "れ」", "で", "す。"]
+        );
+        // 小書きのかなはstrictの場合だけ行頭禁則になる
+        assert_eq!(split("キャット", options), vec!["キ", "ャ", "ッ", "ト"]);
+        let strict = LineBreakOptions {
+            line_break: LineBreak::Strict,
+            ..Default::default()
+        };
+        assert_eq!(split("キャット", strict), vec!["キャッ", "ト"]);
+        // 繰り返し記号はlooseの場合だけ行頭に置ける
+        assert_eq!(split("人々", options), vec!["人々"]);
+        let loose = LineBreakOptions {
+            line_break: LineBreak::Loose,
+            ..Default::default()
+        };
+        assert_eq!(split("人々", loose), vec!["人", "々"]);
+    }
+
+    #[test]
+    fn test_word_break() {
+        let break_all = LineBreakOptions {
+            word_break: WordBreak::BreakAll,
+            ..Default::default()
+        };
+        assert_eq!(split("abc", break_all), vec!["a", "b", "c"]);
+        let keep_all = LineBreakOptions {
+            word_break: WordBreak::KeepAll,
+            ..Default::default()
+        };
+        assert_eq!(split("日本語 です。", keep_all), vec!["日本語 ", "です。"]);
+        let anywhere = LineBreakOptions {
+            line_break: LineBreak::Anywhere,
+            ..Default::default()
+        };
+        assert_eq!(split("a.b", anywhere), vec!["a", ".", "b"]);
+    }
+}
@@ -1,8 +1,22 @@
 mod anonymouse_block;
+pub mod bidi;
 mod block;
 mod entity;
+mod inline;
+#[allow(clippy::module_inception)]
 mod layout;
 pub mod layout_box;
-pub use crate::layout::entity::{BoxType, Dimensions, Rect};
+pub mod line_break;
+pub mod ruby;
+mod text;
+pub mod text_transform;
+pub mod white_space;
+pub mod writing_mode;
+pub use crate::layout::entity::{
+    BoxType, CollapsedMargin, CollapsedMargins, Dimensions, Fragment, LayoutContext, Rect,
+    TextAlign, TextIndent, VerticalAlign,
+};
+#[cfg(test)]
+pub use crate::layout::layout::layout_html;
 pub use crate::layout::layout::layout_tree;
 pub use crate::layout::layout_box::LayoutBox;
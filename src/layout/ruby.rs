@@ -0,0 +1,291 @@
+use crate::css::Value;
+use crate::font::{FontDatabase, TextMetrics};
+use crate::html::ElementType;
+use crate::layout::entity::{BoxType, Fragment, LayoutContext};
+use crate::layout::layout_box::LayoutBox;
+use crate::layout::white_space::process_white_space;
+use crate::layout::writing_mode::WritingMode;
+
+/// ruby-position. 注釈を親文字の上(縦書きでは右)と下のどちらに置くか
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RubyPosition {
+    #[default]
+    Over,
+    Under,
+}
+
+impl RubyPosition {
+    // alternate, inter-characterはoverとして扱う
+    pub fn from_value(value: Option<Value>) -> RubyPosition {
+        match value {
+            Some(Value::Keyword(keyword)) if keyword.to_lowercase() == "under" => {
+                RubyPosition::Under
+            }
+            _ => RubyPosition::Over,
+        }
+    }
+}
+
+/// ruby-align. 親文字と注釈のうち短い方を、長い方の幅の中に並べる方法
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RubyAlign {
+    Start,
+    Center,
+    SpaceBetween,
+    #[default]
+    SpaceAround,
+}
+
+impl RubyAlign {
+    pub fn from_value(value: Option<Value>) -> RubyAlign {
+        match value {
+            Some(Value::Keyword(keyword)) => match keyword.to_lowercase().as_str() {
+                "start" => RubyAlign::Start,
+                "center" => RubyAlign::Center,
+                "space-between" => RubyAlign::SpaceBetween,
+                _ => RubyAlign::SpaceAround,
+            },
+            _ => RubyAlign::SpaceAround,
+        }
+    }
+
+    /// 幅widthの中に、幅の合計がcontentになるcount個の文字を並べる. 最初の文字の位置と文字の間隔
+    pub fn distribute(self, width: f32, content: f32, count: usize) -> (f32, f32) {
+        let free = (width - content).max(0.0);
+        match self {
+            RubyAlign::Start => (0.0, 0.0),
+            RubyAlign::SpaceBetween if count > 1 => (0.0, free / (count - 1) as f32),
+            RubyAlign::SpaceAround if count > 0 => {
+                let gap = free / count as f32;
+                (gap / 2.0, gap)
+            }
+            _ => (free / 2.0, 0.0),
+        }
+    }
+}
+
+/// rubyの注釈(rt). 親文字をinline要素として扱い、その範囲の上か下に並べる
+#[derive(Debug, Clone)]
+pub struct RubyAnnotation {
+    // 注釈のtext nodeのpathと、文字ごとの文字列と幅
+    pub chars: Vec<(Vec<usize>, String, f32)>,
+    pub width: f32,
+    pub metrics: TextMetrics,
+    // 行の上側(block方向の始端)に置くかどうか
+    pub over: bool,
+    pub align: RubyAlign,
+    // 親文字がrbなどの要素の場合は、その要素の断片も作る
+    pub base_box: bool,
+}
+
+impl RubyAnnotation {
+    /// 親文字の範囲(left, width)に注釈の断片を並べる. 間隔を空けない文字は1つの断片にまとめる
+    pub fn fragments(&self, left: f32, width: f32) -> Vec<(Vec<usize>, Fragment)> {
+        let (offset, gap) = self.align.distribute(width, self.width, self.chars.len());
+        let mut fragments: Vec<(Vec<usize>, Fragment)> = vec![];
+        let mut x = left + offset;
+        for (path, text, char_width) in &self.chars {
+            match fragments.last_mut() {
+                Some((last_path, fragment)) if gap == 0.0 && last_path == path => {
+                    fragment.text.push_str(text);
+                    fragment.dimensions.content.width += char_width;
+                }
+                _ => {
+                    let mut fragment = Fragment {
+                        text: text.clone(),
+                        ..Default::default()
+                    };
+                    fragment.dimensions.content.x = x;
+                    fragment.dimensions.content.width = *char_width;
+                    fragment.dimensions.content.height = self.metrics.line_height;
+                    fragments.push((path.clone(), fragment));
+                }
+            }
+            x += char_width + gap;
+        }
+        fragments
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    pub fn is_element(&self, name: ElementType) -> bool {
+        match self.box_type {
+            BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+                style.node.element_data.name == name
+            }
+            BoxType::AnonymouseBlock => false,
+        }
+    }
+
+    /*
+    rubyの子要素を、親文字(rbやtext)と注釈(rt)の組に分ける. 続けて並べた親文字と注釈は順に組にする.
+    親文字の間の空白だけのtextは無視する
+    */
+    pub fn ruby_pairs(&self) -> Vec<(usize, Option<usize>)> {
+        let mut pairs = vec![];
+        let mut bases: Vec<usize> = vec![];
+        let mut annotations: Vec<usize> = vec![];
+        let mut flush = |bases: &mut Vec<usize>, annotations: &mut Vec<usize>| {
+            for (index, base) in bases.drain(..).enumerate() {
+                pairs.push((base, annotations.get(index).copied()));
+            }
+            annotations.clear();
+        };
+        for (index, child) in self.children.iter().enumerate() {
+            if child.is_element(ElementType::Rt) {
+                annotations.push(index);
+                continue;
+            }
+            if !child.text().is_empty() && child.text().trim().is_empty() {
+                continue;
+            }
+            if !annotations.is_empty() {
+                flush(&mut bases, &mut annotations);
+            }
+            bases.push(index);
+        }
+        flush(&mut bases, &mut annotations);
+        pairs
+    }
+
+    /// rtの子孫のtextを注釈にする. 注釈の文字の大きさはrtのfontで決まる
+    pub fn ruby_annotation(
+        &mut self,
+        path: Vec<usize>,
+        context: LayoutContext,
+        fonts: &FontDatabase,
+    ) -> RubyAnnotation {
+        let style = self.get_style_node();
+        // vertical-lrでは行の上側(右)がblock方向の終端になる
+        let over = (RubyPosition::from_value(style.value("ruby-position")) == RubyPosition::Over)
+            != (context.writing_mode == WritingMode::VerticalLr);
+        let mut annotation = RubyAnnotation {
+            chars: vec![],
+            width: 0.0,
+            metrics: TextMetrics::default(),
+            over,
+            align: RubyAlign::from_value(style.value("ruby-align")),
+            base_box: false,
+        };
+        self.context = context;
+        annotation.metrics = fonts.measure("", &self.text_style());
+        self.collect_annotation_chars(path, fonts, &mut annotation);
+        annotation.width = annotation.chars.iter().map(|(_, _, width)| width).sum();
+        annotation
+    }
+
+    fn collect_annotation_chars(
+        &mut self,
+        path: Vec<usize>,
+        fonts: &FontDatabase,
+        annotation: &mut RubyAnnotation,
+    ) {
+        self.fragments.clear();
+        if !self.text().is_empty() {
+            let style = self.text_style();
+            let mut collapsible = true;
+            let text = process_white_space(
+                &self.text_transform().apply(self.text()),
+                self.white_space(),
+                &mut collapsible,
+            );
+            for c in text.trim_end().chars() {
+                let text = c.to_string();
+                let width = fonts.measure(&text, &style).width;
+                annotation.chars.push((path.clone(), text, width));
+            }
+            return;
+        }
+        let context = self.context;
+        for (index, child) in self.children.iter_mut().enumerate() {
+            let mut child_path = path.clone();
+            child_path.push(index);
+            child.context = child.inherit_context(context);
+            child.collect_annotation_chars(child_path, fonts, annotation);
+        }
+    }
+}
+
+#[test]
+fn test_ruby_align_distribute() {
+    // 幅40の中に幅の合計が10の2文字を並べる
+    assert_eq!(RubyAlign::Start.distribute(40.0, 10.0, 2), (0.0, 0.0));
+    assert_eq!(RubyAlign::Center.distribute(40.0, 10.0, 2), (15.0, 0.0));
+    assert_eq!(
+        RubyAlign::SpaceBetween.distribute(40.0, 10.0, 2),
+        (0.0, 30.0)
+    );
+    assert_eq!(
+        RubyAlign::SpaceAround.distribute(40.0, 10.0, 2),
+        (7.5, 15.0)
+    );
+    // 短い方がない場合は間隔を空けない
+    assert_eq!(RubyAlign::SpaceAround.distribute(10.0, 20.0, 2), (0.0, 0.0));
+}
+
+#[test]
+fn test_layout_ruby() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><p class=\"line\">a <ruby>base<rp>(</rp><rt>xy</rt><rp>)</rp></ruby></p><p class=\"line\"><ruby>b<rt>wide note</rt></ruby>c</p><p class=\"under\"><ruby>base<rt>xy</rt></ruby></p><p class=\"line\">plain</p></div>",
+        ".root { display: block; }
+        .line { display: block; }
+        .under { display: block; ruby-position: under; ruby-align: center; }",
+        400.0,
+        0.0,
+    );
+    let inline = |index: usize| &layout.children[index].children[0];
+    let right =
+        |fragment: &Fragment| fragment.dimensions.content.x + fragment.dimensions.content.width;
+    let bottom =
+        |fragment: &Fragment| fragment.dimensions.content.y + fragment.dimensions.content.height;
+
+    // rpは表示せず、注釈は親文字の上に置く
+    let ruby = &inline(0).children[1];
+    assert_eq!(ruby.children.len(), 2);
+    let base = &ruby.children[0].fragments[0];
+    let notes = &ruby.children[1].children[0].fragments;
+    assert_eq!(base.text, "base");
+    // space-aroundでは親文字の幅に注釈の文字を均等に並べる
+    assert_eq!(notes.len(), 2);
+    assert_eq!((notes[0].text.as_str(), notes[1].text.as_str()), ("x", "y"));
+    assert!(notes[0].dimensions.content.x > base.dimensions.content.x);
+    assert!(right(&notes[1]) < right(base));
+    assert!(bottom(&notes[0]) <= base.dimensions.content.y + 0.01);
+    // 注釈の分だけ行が高くなる
+    let plain = inline(3).dimensions.content.height;
+    assert!(inline(0).dimensions.content.height > plain);
+
+    // 注釈が親文字より広い場合は、親文字の前後を空ける
+    let ruby = &inline(1).children[0];
+    let base = &ruby.children[0].fragments[0];
+    let note = &ruby.children[1].children[0].fragments;
+    let after = &inline(1).children[1].fragments[0];
+    assert!(base.dimensions.content.x > note[0].dimensions.content.x);
+    assert!(after.dimensions.content.x >= right(note.last().unwrap()) - 0.01);
+
+    // underでは親文字の下に置き、centerでは1つの断片にまとめる
+    let ruby = &inline(2).children[0];
+    let base = &ruby.children[0].fragments[0];
+    let note = &ruby.children[1].children[0].fragments[0];
+    assert_eq!(note.text, "xy");
+    assert!(note.dimensions.content.y >= bottom(base) - 0.01);
+    let center = |fragment: &Fragment| {
+        fragment.dimensions.content.x + fragment.dimensions.content.width / 2.0
+    };
+    assert!((center(note) - center(base)).abs() < 0.01);
+}
+
+#[test]
+fn test_layout_ruby_block_base() {
+    use crate::layout::layout_html;
+
+    // 行に内容を置かない親文字では注釈を付けずに配置する
+    layout_html(
+        "<div class=\"root\"><ruby><div>x</div><rt>y</rt></ruby></div>",
+        ".root { display: block; } .root div { display: block; }",
+        200.0,
+        0.0,
+    );
+}
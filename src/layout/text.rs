@@ -0,0 +1,150 @@
+use crate::css::{
+    font_families, font_feature_settings, font_stretch, font_style, font_variant_ligatures,
+    font_weight, Value, NORMAL_WEIGHT,
+};
+use crate::font::{FontQuery, TextOrientation, TextStyle};
+use crate::layout::entity::BoxType;
+use crate::layout::layout_box::LayoutBox;
+use crate::layout::line_break::{Hyphens, LineBreak, LineBreakOptions, OverflowWrap, WordBreak};
+use crate::layout::text_transform::TextTransform;
+use crate::layout::white_space::WhiteSpace;
+use crate::style::StyledNode;
+
+// tab-sizeの初期値(空白8文字分)
+const DEFAULT_TAB_SIZE: f32 = 8.0;
+
+// sideways-rl, sideways-lrではすべての文字を横に倒す
+fn text_orientation(style: &StyledNode) -> TextOrientation {
+    match style.value("writing-mode") {
+        Some(Value::Keyword(keyword)) if keyword.starts_with("sideways") => {
+            TextOrientation::Sideways
+        }
+        _ => match style.value("text-orientation") {
+            Some(Value::Keyword(keyword)) => {
+                TextOrientation::from_keyword(&keyword).unwrap_or_default()
+            }
+            _ => TextOrientation::Mixed,
+        },
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    pub fn text(&self) -> &'a str {
+        match self.box_type {
+            BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+                style.node.element_data.text.as_str()
+            }
+            BoxType::AnonymouseBlock => "",
+        }
+    }
+
+    /// 文字列の描画に使うfont. font-familyなどはstyle treeで親要素から引き継いでいる
+    pub fn text_style(&self) -> TextStyle {
+        let mut text_style = TextStyle {
+            families: vec![],
+            query: FontQuery::default(),
+            font_size: self.context.font_size,
+            line_height: self.context.line_height,
+            letter_spacing: self.context.letter_spacing,
+            word_spacing: self.context.word_spacing,
+            orientation: None,
+            combine_upright: false,
+            features: vec![],
+        };
+        let vertical = self.context.writing_mode.is_vertical();
+        if vertical {
+            text_style.orientation = Some(TextOrientation::Mixed);
+        }
+        let style = match self.box_type {
+            BoxType::BlockNode(style) | BoxType::InlineNode(style) => style,
+            BoxType::AnonymouseBlock => return text_style,
+        };
+        if vertical {
+            text_style.orientation = Some(text_orientation(style));
+            text_style.combine_upright = matches!(
+                style.value("text-combine-upright"),
+                Some(Value::Keyword(keyword)) if keyword == "all"
+            );
+        }
+        // font-familyの指定がない場合は空になり、既定のfontを使う
+        if let Some(value) = style.value("font-family") {
+            text_style.families = font_families(&value);
+        }
+        // bolder, lighterはstyle treeで数値になっている
+        if let Some(value) = style.value("font-weight") {
+            text_style.query.weight = font_weight(&value, NORMAL_WEIGHT);
+        }
+        if let Some(value) = style.value("font-style") {
+            text_style.query.style = font_style(&value);
+        }
+        if let Some(value) = style.value("font-stretch") {
+            text_style.query.stretch = font_stretch(&value);
+        }
+        // letter-spacingを指定した場合は任意の合字を使わない. font-feature-settingsの指定を優先する
+        if self.context.letter_spacing != 0.0 {
+            let tags = [b"liga", b"clig", b"dlig", b"hlig"];
+            text_style.features = tags.iter().map(|tag| (**tag, 0)).collect();
+        }
+        if let Some(value) = style.value("font-variant-ligatures") {
+            text_style.features.extend(font_variant_ligatures(&value));
+        }
+        if let Some(value) = style.value("font-feature-settings") {
+            text_style.features.extend(font_feature_settings(&value));
+        }
+        text_style
+    }
+
+    /// 改行位置の計算に使うproperty. style treeで親要素から引き継いでいる
+    pub fn line_break_options(&self) -> LineBreakOptions {
+        match self.box_type {
+            BoxType::BlockNode(style) | BoxType::InlineNode(style) => LineBreakOptions {
+                word_break: WordBreak::from_value(style.value("word-break")),
+                line_break: LineBreak::from_value(style.value("line-break")),
+                hyphens: Hyphens::from_value(style.value("hyphens")),
+            },
+            BoxType::AnonymouseBlock => LineBreakOptions::default(),
+        }
+    }
+
+    pub fn overflow_wrap(&self) -> OverflowWrap {
+        match self.box_type {
+            BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+                OverflowWrap::from_value(style.value("overflow-wrap"))
+            }
+            BoxType::AnonymouseBlock => OverflowWrap::Normal,
+        }
+    }
+
+    pub fn text_transform(&self) -> TextTransform {
+        match self.box_type {
+            BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+                TextTransform::from_value(style.value("text-transform"))
+            }
+            BoxType::AnonymouseBlock => TextTransform::None,
+        }
+    }
+
+    pub fn white_space(&self) -> WhiteSpace {
+        match self.box_type {
+            BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+                WhiteSpace::from_value(style.value("white-space"))
+            }
+            BoxType::AnonymouseBlock => WhiteSpace::Normal,
+        }
+    }
+
+    /// tabの間隔(px). 数値の場合は空白の幅に対する倍率になる
+    pub fn tab_size(&self, space_width: f32) -> f32 {
+        let value = match self.box_type {
+            BoxType::BlockNode(style) | BoxType::InlineNode(style) => style.value("tab-size"),
+            BoxType::AnonymouseBlock => None,
+        };
+        match value {
+            Some(Value::Number(number)) => number * space_width,
+            Some(value @ Value::Length(..)) | Some(value @ Value::Math(_)) => {
+                value.resolve(&self.context.length_context(0.0)).max(0.0)
+            }
+            _ => DEFAULT_TAB_SIZE * space_width,
+        }
+    }
+}
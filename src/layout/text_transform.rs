@@ -0,0 +1,77 @@
+use crate::css::Value;
+
+/// text-transform. 表示する文字の大文字、小文字、全角を変える
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TextTransform {
+    #[default]
+    None,
+    Uppercase,
+    Lowercase,
+    Capitalize,
+    FullWidth,
+}
+
+impl TextTransform {
+    pub fn from_value(value: Option<Value>) -> TextTransform {
+        let keyword = match value {
+            Some(Value::Keyword(keyword)) => keyword.to_lowercase(),
+            _ => String::new(),
+        };
+        match keyword.as_str() {
+            "uppercase" => TextTransform::Uppercase,
+            "lowercase" => TextTransform::Lowercase,
+            "capitalize" => TextTransform::Capitalize,
+            "full-width" => TextTransform::FullWidth,
+            _ => TextTransform::None,
+        }
+    }
+
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            TextTransform::None => text.to_string(),
+            TextTransform::Uppercase => text.to_uppercase(),
+            TextTransform::Lowercase => text.to_lowercase(),
+            // 単語の最初の文字だけを大文字にする
+            TextTransform::Capitalize => {
+                let mut transformed = String::new();
+                let mut word_start = true;
+                for c in text.chars() {
+                    match word_start && c.is_alphanumeric() {
+                        true => transformed.extend(c.to_uppercase()),
+                        false => transformed.push(c),
+                    }
+                    word_start = !c.is_alphanumeric() && c != '\'' && c != '\u{2019}';
+                }
+                transformed
+            }
+            // ASCIIの文字と空白を全角にする
+            TextTransform::FullWidth => text
+                .chars()
+                .map(|c| match c {
+                    ' ' => '\u{3000}',
+                    '!'..='~' => char::from_u32(c as u32 - 0x21 + 0xFF01).unwrap_or(c),
+                    _ => c,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[test]
+fn test_text_transform() {
+    let text = "hello wORLD, it's 1st";
+    assert_eq!(
+        TextTransform::Uppercase.apply(text),
+        "HELLO WORLD, IT'S 1ST"
+    );
+    assert_eq!(
+        TextTransform::Lowercase.apply(text),
+        "hello world, it's 1st"
+    );
+    assert_eq!(
+        TextTransform::Capitalize.apply(text),
+        "Hello WORLD, It's 1st"
+    );
+    assert_eq!(TextTransform::FullWidth.apply("Ab 1!"), "Ａｂ\u{3000}１！");
+    assert_eq!(TextTransform::None.apply(text), text);
+}
@@ -0,0 +1,117 @@
+use crate::css::Value;
+
+/// white-space. 空白と改行をまとめるかどうかと、行を折り返すかどうか
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WhiteSpace {
+    #[default]
+    Normal,
+    Nowrap,
+    Pre,
+    PreWrap,
+    PreLine,
+    BreakSpaces,
+}
+
+impl WhiteSpace {
+    pub fn from_value(value: Option<Value>) -> WhiteSpace {
+        let keyword = match value {
+            Some(Value::Keyword(keyword)) => keyword.to_lowercase(),
+            _ => String::new(),
+        };
+        match keyword.as_str() {
+            "nowrap" => WhiteSpace::Nowrap,
+            "pre" => WhiteSpace::Pre,
+            "pre-wrap" => WhiteSpace::PreWrap,
+            "pre-line" => WhiteSpace::PreLine,
+            "break-spaces" => WhiteSpace::BreakSpaces,
+            _ => WhiteSpace::Normal,
+        }
+    }
+
+    /// 連続する空白を1つにまとめるか
+    pub fn collapses_spaces(self) -> bool {
+        matches!(
+            self,
+            WhiteSpace::Normal | WhiteSpace::Nowrap | WhiteSpace::PreLine
+        )
+    }
+
+    /// 改行をそのまま残すか
+    pub fn preserves_newlines(self) -> bool {
+        !matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap)
+    }
+
+    /// 行の幅を超える場合に折り返すか
+    pub fn wraps(self) -> bool {
+        !matches!(self, WhiteSpace::Nowrap | WhiteSpace::Pre)
+    }
+}
+
+/*
+CSS Textの空白の処理(phase I).
+まとめる場合は改行の前後の空白を取り除き、tabと改行を空白にして、連続する空白を1つにする.
+collapsibleは直前の文字がまとめられる空白かどうかで、text nodeをまたいで引き継ぐ.
+行頭の空白を取り除くため、inline formatting contextの最初はtrueにする
+*/
+pub fn process_white_space(text: &str, white_space: WhiteSpace, collapsible: &mut bool) -> String {
+    let mut processed = String::new();
+    for c in text.chars() {
+        match c {
+            // CRLFはLFとして扱う
+            '\r' => {}
+            '\n' if white_space.preserves_newlines() => {
+                if white_space.collapses_spaces() {
+                    while processed.ends_with(' ') {
+                        processed.pop();
+                    }
+                }
+                processed.push('\n');
+                // pre-lineでは改行の後の空白も取り除く
+                *collapsible = white_space.collapses_spaces();
+            }
+            ' ' | '\t' | '\n' if white_space.collapses_spaces() => {
+                if !*collapsible {
+                    processed.push(' ');
+                    *collapsible = true;
+                }
+            }
+            _ => {
+                processed.push(c);
+                *collapsible = false;
+            }
+        }
+    }
+    processed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(text: &str, white_space: WhiteSpace) -> String {
+        process_white_space(text, white_space, &mut true)
+    }
+
+    #[test]
+    fn test_process_white_space() {
+        let text = "  a  b\t\n  c \n";
+        assert_eq!(process(text, WhiteSpace::Normal), "a b c ");
+        assert_eq!(process(text, WhiteSpace::Nowrap), "a b c ");
+        assert_eq!(process(text, WhiteSpace::PreLine), "a b\nc\n");
+        assert_eq!(process(text, WhiteSpace::Pre), text);
+        assert_eq!(process(text, WhiteSpace::PreWrap), text);
+        assert_eq!(process(text, WhiteSpace::BreakSpaces), text);
+        assert_eq!(process("a\r\nb", WhiteSpace::Pre), "a\nb");
+
+        // text nodeをまたいで空白をまとめる
+        let mut collapsible = true;
+        assert_eq!(
+            process_white_space("a ", WhiteSpace::Normal, &mut collapsible),
+            "a "
+        );
+        assert_eq!(
+            process_white_space(" b", WhiteSpace::Normal, &mut collapsible),
+            "b"
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use crate::css::{Unit, Value};
+use crate::font::FontDatabase;
+use crate::layout::entity::{Dimensions, EdgeSizes, LayoutContext, Rect};
+use crate::layout::layout_box::LayoutBox;
+
+/*
+writing-mode. layoutは論理座標(xがinline方向、yがblock方向)で計算し、
+writing-modeが変わるblockで物理座標に変換する.
+論理座標のEdgeSizesは、leftがinline方向の始端、topがblock方向の始端になる
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WritingMode {
+    #[default]
+    HorizontalTb,
+    // 行を上から下へ並べ、右から左へ重ねる
+    VerticalRl,
+    VerticalLr,
+}
+
+impl WritingMode {
+    /// sideways-rl, sideways-lrは文字をすべて横に倒した縦書き
+    pub fn from_value(value: Option<Value>) -> Option<WritingMode> {
+        match value {
+            Some(Value::Keyword(keyword)) => match keyword.to_lowercase().as_str() {
+                "horizontal-tb" => Some(WritingMode::HorizontalTb),
+                "vertical-rl" | "sideways-rl" => Some(WritingMode::VerticalRl),
+                "vertical-lr" | "sideways-lr" => Some(WritingMode::VerticalLr),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn is_vertical(self) -> bool {
+        self != WritingMode::HorizontalTb
+    }
+
+    /// 論理方向のinline始端、inline終端、block始端、block終端にあたる物理方向の名前
+    pub fn sides(self) -> [&'static str; 4] {
+        match self {
+            WritingMode::HorizontalTb => ["left", "right", "top", "bottom"],
+            WritingMode::VerticalRl => ["top", "bottom", "right", "left"],
+            WritingMode::VerticalLr => ["top", "bottom", "left", "right"],
+        }
+    }
+
+    /// inline方向とblock方向の大きさを指定するproperty
+    pub fn sizes(self) -> (&'static str, &'static str) {
+        match self.is_vertical() {
+            true => ("height", "width"),
+            false => ("width", "height"),
+        }
+    }
+
+    /// 論理座標を物理座標にする. block_sizeは変換する範囲のblock方向の大きさ
+    pub fn physical(self, d: Dimensions, block_size: f32) -> Dimensions {
+        let rect = |r: Rect| match self {
+            WritingMode::HorizontalTb => r,
+            WritingMode::VerticalRl => Rect {
+                x: block_size - r.y - r.height,
+                y: r.x,
+                width: r.height,
+                height: r.width,
+            },
+            WritingMode::VerticalLr => Rect {
+                x: r.y,
+                y: r.x,
+                width: r.height,
+                height: r.width,
+            },
+        };
+        let edges = |e: EdgeSizes| match self {
+            WritingMode::HorizontalTb => e,
+            WritingMode::VerticalRl => EdgeSizes {
+                left: e.bottom,
+                right: e.top,
+                top: e.left,
+                bottom: e.right,
+            },
+            WritingMode::VerticalLr => EdgeSizes {
+                left: e.top,
+                right: e.bottom,
+                top: e.left,
+                bottom: e.right,
+            },
+        };
+        map_dimensions(d, rect, edges)
+    }
+
+    /// 物理座標を論理座標にする. widthは変換する範囲の物理的な幅
+    pub fn logical(self, d: Dimensions, width: f32) -> Dimensions {
+        let rect = |r: Rect| match self {
+            WritingMode::HorizontalTb => r,
+            WritingMode::VerticalRl => Rect {
+                x: r.y,
+                y: width - r.x - r.width,
+                width: r.height,
+                height: r.width,
+            },
+            WritingMode::VerticalLr => Rect {
+                x: r.y,
+                y: r.x,
+                width: r.height,
+                height: r.width,
+            },
+        };
+        let edges = |e: EdgeSizes| match self {
+            WritingMode::HorizontalTb => e,
+            WritingMode::VerticalRl => EdgeSizes {
+                left: e.top,
+                right: e.bottom,
+                top: e.right,
+                bottom: e.left,
+            },
+            WritingMode::VerticalLr => EdgeSizes {
+                left: e.top,
+                right: e.bottom,
+                top: e.left,
+                bottom: e.right,
+            },
+        };
+        map_dimensions(d, rect, edges)
+    }
+}
+
+fn map_dimensions(
+    d: Dimensions,
+    rect: impl Fn(Rect) -> Rect,
+    edges: impl Fn(EdgeSizes) -> EdgeSizes,
+) -> Dimensions {
+    Dimensions {
+        content: rect(d.content),
+        padding: edges(d.padding),
+        border: edges(d.border),
+        margin: edges(d.margin),
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /*
+    親要素とwriting-modeが異なるblockは、自身の論理座標で配置してから親要素の論理座標に変換する.
+    inline方向が親要素と直交する場合、inline方向の大きさはviewportの大きさを基準にする
+    */
+    pub fn layout_writing_mode_root(
+        &mut self,
+        containing_block: Dimensions,
+        context: LayoutContext,
+        parent_mode: WritingMode,
+        fonts: &FontDatabase,
+    ) {
+        let mode = context.writing_mode;
+        let mut block = containing_block;
+        block.content = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: match (mode.is_vertical(), parent_mode.is_vertical()) {
+                (true, false) => context.viewport.height,
+                (false, true) => context.viewport.width,
+                _ => containing_block.content.width,
+            },
+            height: 0.0,
+        };
+        self.layout(block, context, fonts);
+        // 直交するblockのinline方向の終端のmarginは親要素のblock方向のmarginになるため、
+        // 残りの大きさで広げず指定した値を使う
+        if mode.is_vertical() != parent_mode.is_vertical() {
+            let [_, end, _, _] = mode.sides();
+            let length = context.length_context(block.content.width);
+            let zero = Value::Length(0.0, Unit::Px);
+            self.dimensions.margin.right = self
+                .get_style_node()
+                .lookup(&format!("margin-{}", end), "margin", &zero)
+                .resolve(&length);
+        }
+
+        let block_size = self.dimensions.margin_box().height;
+        self.map_tree(&|d| mode.physical(d, block_size));
+        let width = self.dimensions.margin_box().width;
+        self.map_tree(&|d| parent_mode.logical(d, width));
+        // 親要素の中で、先に配置した兄弟要素の後ろに置く
+        let x = containing_block.content.x;
+        let y = containing_block.content.y + containing_block.content.height;
+        self.map_tree(&|mut d| {
+            d.content.x += x;
+            d.content.y += y;
+            d
+        });
+    }
+
+    /// 子孫を含むすべてのboxと断片の位置を変換する
+    pub fn map_tree(&mut self, f: &dyn Fn(Dimensions) -> Dimensions) {
+        self.dimensions = f(self.dimensions);
+        for fragment in self.fragments.iter_mut() {
+            fragment.dimensions = f(fragment.dimensions);
+        }
+        for child in self.children.iter_mut() {
+            child.map_tree(f);
+        }
+    }
+}
+
+#[test]
+fn test_writing_mode_physical() {
+    let mut d = Dimensions {
+        content: Rect {
+            x: 10.0,
+            y: 20.0,
+            width: 100.0,
+            height: 30.0,
+        },
+        ..Default::default()
+    };
+    d.margin.left = 1.0;
+    d.margin.top = 2.0;
+    // vertical-rlはblock方向の始端が右になる
+    let physical = WritingMode::VerticalRl.physical(d, 200.0);
+    assert_eq!(
+        (
+            physical.content.x,
+            physical.content.y,
+            physical.content.width,
+            physical.content.height
+        ),
+        (150.0, 10.0, 30.0, 100.0)
+    );
+    assert_eq!((physical.margin.top, physical.margin.right), (1.0, 2.0));
+    let logical = WritingMode::VerticalRl.logical(physical, 200.0);
+    assert_eq!((logical.content.x, logical.content.y), (10.0, 20.0));
+    assert_eq!((logical.margin.left, logical.margin.top), (1.0, 2.0));
+
+    let physical = WritingMode::VerticalLr.physical(d, 200.0);
+    assert_eq!((physical.content.x, physical.content.y), (20.0, 10.0));
+    assert_eq!(physical.margin.left, 2.0);
+}
+
+#[test]
+fn test_layout_vertical() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><div class=\"rl\"><p class=\"para\">\u{3042}\u{3042}\u{3042}\u{3042}\u{3042}\u{3042}\u{3042}\u{3042}</p><p class=\"para\">a<span class=\"tcy\">12</span></p></div><div class=\"lr\"><p class=\"para\">\u{3042}\u{3042}\u{3042}\u{3042}\u{3042}\u{3042}\u{3042}\u{3042}</p></div></div>",
+        ".root { display: block; }
+        .rl { display: block; writing-mode: vertical-rl; height: 100px; }
+        .lr { display: block; writing-mode: vertical-lr; height: 100px; }
+        .para { display: block; line-height: 20px; }
+        .tcy { text-combine-upright: all; }",
+        400.0,
+        300.0,
+    );
+
+    // 縦書きのblockは、heightがinline方向の大きさになり、親要素の中では上から順に重ねる
+    let rl = &layout.children[0];
+    let lr = &layout.children[1];
+    assert_eq!(rl.dimensions.content.height, 100.0);
+    assert_eq!(rl.dimensions.content.y, 0.0);
+    assert_eq!(lr.dimensions.content.y, 100.0);
+    // 2行の段落と1行の段落の幅
+    assert_eq!(rl.dimensions.content.width, 60.0);
+
+    // 正立させた仮名は1emずつ上から並べ、収まらない分は左の行へ送る
+    let text = |block: &LayoutBox<'_>, index: usize| {
+        block.children[index].children[0].children[0]
+            .fragments
+            .clone()
+    };
+    let lines = text(rl, 0);
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].dimensions.content.y, 0.0);
+    assert_eq!(lines[0].dimensions.content.height, 96.0);
+    assert_eq!(lines[0].dimensions.content.width, 20.0);
+    assert_eq!(lines[0].dimensions.content.x, 40.0);
+    assert_eq!(lines[1].dimensions.content.x, 20.0);
+    // vertical-lrでは右の行へ送る
+    let lines = text(lr, 0);
+    assert!(lines[1].dimensions.content.x > lines[0].dimensions.content.x);
+
+    // 縦中横は1文字分の長さになる
+    let paragraph = &rl.children[1].children[0];
+    let tcy = &paragraph.children[1].children[0].fragments[0];
+    assert_eq!(tcy.text, "12");
+    assert_eq!(tcy.dimensions.content.height, 16.0);
+    let latin = &paragraph.children[0].fragments[0];
+    assert_eq!(
+        tcy.dimensions.content.y,
+        latin.dimensions.content.y + latin.dimensions.content.height
+    );
+}
@@ -0,0 +1,21 @@
+// 既存のテストの書き方(get(0), assert_eq!(x, true)など)はそのまま残す
+#![cfg_attr(
+    test,
+    allow(
+        clippy::get_first,
+        clippy::bool_assert_comparison,
+        clippy::useless_conversion
+    )
+)]
+extern crate pest;
+#[macro_use]
+extern crate pest_derive;
+
+pub mod css;
+pub mod font;
+pub mod html;
+pub mod layout;
+#[cfg(test)]
+mod mock;
+pub mod paint;
+pub mod style;
@@ -1,22 +1,10 @@
-extern crate pest;
-#[macro_use]
-extern crate pest_derive;
-
 use clap::{load_yaml, App};
 use image::{DynamicImage, ImageBuffer};
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
 
-mod css;
-mod font;
-mod html;
-mod layout;
-mod mock;
-mod paint;
-mod style;
-
-use pest::Parser;
+use parser::{css, font, html, layout, paint, style};
 
 fn main() {
     let yaml = load_yaml!("cli.yml");
@@ -24,16 +12,17 @@ fn main() {
 
     let read_source = |arg_filename: Option<&str>, default_filename: &str| {
         let path = match arg_filename {
-            Some(ref filename) => filename,
+            Some(filename) => filename,
             None => default_filename,
         };
-        let mut file = File::open(&Path::new(path)).unwrap();
+        let mut file = File::open(Path::new(path)).unwrap();
         let mut content = String::new();
         file.read_to_string(&mut content).unwrap();
         content
     };
     let html = read_source(matches.value_of("html"), "examples/test.html");
-    let css = read_source(matches.value_of("css"), "examples/test.css");
+    let css_path = matches.value_of("css").unwrap_or("examples/test.css");
+    let css = read_source(Some(css_path), css_path);
 
     let initial_containing_block = layout::Dimensions {
         content: layout::Rect {
@@ -50,11 +39,55 @@ fn main() {
     let root_node = html::parse_nodes(html.as_str());
     let stylesheet = css::parse_css(css.as_str());
     // println!("{:?}", stylesheet);
-    let style_root = style::style_tree(&root_node, &stylesheet);
+    let media = css::MediaContext {
+        width: initial_containing_block.content.width,
+        height: initial_containing_block.content.height,
+    };
+    let style_root = style::style_tree(&root_node, &stylesheet, &media);
     // println!("{:?}", style_root);
-    let layout_root = layout::layout_tree(&style_root, initial_containing_block);
+
+    // @font-faceのurlはstylesheetのあるdirectoryからの相対path
+    let mut fonts = font::FontDatabase::new();
+    match matches.values_of("font-dir") {
+        Some(dirs) => dirs.for_each(|dir| fonts.scan_dir(Path::new(dir))),
+        None => fonts.scan_system_fonts(),
+    }
+    if let Some(families) = matches.values_of("fallback-font") {
+        fonts.set_fallback_families(families.map(String::from).collect());
+    }
+    let css_dir = Path::new(css_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    fonts.add_font_faces(&stylesheet.font_faces, css_dir);
+
+    let layout_root = layout::layout_tree(&style_root, initial_containing_block, &fonts);
     // println!("{:?}", layout_root);
-    let canvas = paint::paint(&layout_root, initial_containing_block.content);
+    // 描画の間、rasterizeしたglyphを使い回す
+    let mut font_context = font::FontContext::new(&fonts);
+    if let Some(name) = matches.value_of("text-antialiasing") {
+        font_context.antialiasing = font::Antialiasing::from_name(name).unwrap();
+    }
+    let canvas = paint::paint(
+        &layout_root,
+        initial_containing_block.content,
+        &font_context,
+    );
+    if matches.is_present("glyph-stats") {
+        let stats = font_context.stats();
+        eprintln!(
+            "glyph cache: {} hits, {} misses, {} glyphs",
+            stats.hits, stats.misses, stats.glyphs
+        );
+    }
+    // どのfontでも描画できなかった文字を知らせる
+    let missing = fonts.missing_chars();
+    if !missing.is_empty() {
+        let chars: Vec<String> = missing
+            .iter()
+            .map(|c| format!("U+{:04X} {}", *c as u32, c))
+            .collect();
+        eprintln!("missing glyphs: {}", chars.join(", "));
+    }
     let filename = matches.value_of("output").unwrap_or("output.png");
     let (w, h) = (canvas.width as u32, canvas.height as u32);
     let img = ImageBuffer::from_fn(w, h, move |x, y| {
@@ -1,13 +1,20 @@
-use crate::layout::LayoutBox;
+use crate::layout::{Dimensions, LayoutBox};
+use crate::paint::border::border_radii;
 use crate::paint::entity::{DisplayCommand, DisplayList};
+use crate::paint::path::{FillRule, Path};
 use crate::paint::utils::get_color;
 
-pub fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
+pub fn render_background(list: &mut DisplayList, layout_box: &LayoutBox, d: &Dimensions) {
     // FIXME: background-colorにしか対応していないので、background両方に対応させたい
-    get_color(layout_box, "background-color").map(|color| {
-        list.push(DisplayCommand::SolidColor(
-            color,
-            layout_box.dimensions.border_box(),
-        ));
-    });
+    if let Some(color) = get_color(layout_box, "background-color") {
+        // border-radiusを指定した場合は丸めた角の外側を塗らない
+        let border_box = d.border_box();
+        let radii = border_radii(layout_box, border_box);
+        if radii.iter().all(|radius| *radius == (0.0, 0.0)) {
+            list.push(DisplayCommand::SolidColor(color, border_box));
+        } else {
+            let path = Path::rounded_rect(border_box, radii);
+            list.push(DisplayCommand::FillPath(color, path, FillRule::NonZero));
+        }
+    }
 }
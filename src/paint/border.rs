@@ -1,55 +1,467 @@
-use crate::layout::{LayoutBox, Rect};
+use std::f32::consts::FRAC_PI_2;
+
+use crate::css::{border_radius, corner_radius, BorderStyle, Color, Value};
+use crate::layout::{BoxType, Dimensions, LayoutBox, Rect};
 use crate::paint::entity::{DisplayCommand, DisplayList};
+use crate::paint::path::{
+    corner_centers, corner_start_angle, distance, FillRule, Path, Point, Radii, Subpath,
+};
+use crate::paint::stroke::{LineCap, Stroke};
 use crate::paint::utils::get_color;
 
-pub fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
-    let color = match get_color(layout_box, "border-color") {
-        Some(color) => color,
-        _ => return,
+// 辺は上、右、下、左の順、角は左上、右上、右下、左下の順に並べる.
+// i番目の辺はi番目の角と(i + 1) % 4番目の角の間にある
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+const CORNERS: [&str; 4] = ["top-left", "top-right", "bottom-right", "bottom-left"];
+// これより細い点線と破線は見えないので描かない(px)
+const MIN_PATTERN_WIDTH: f32 = 1.0 / 64.0;
+
+// 辺のborderの色. border-top-colorなどの指定がなければborder-color、その指定もなければ文字の色を使う
+fn border_color(layout_box: &LayoutBox, side: &str) -> Color {
+    let current_color = || get_color(layout_box, "color").unwrap_or(Color::from_rgba(0, 0, 0, 255));
+    let style = match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => style,
+        BoxType::AnonymouseBlock => return current_color(),
+    };
+    let currentcolor = Value::Keyword(String::from("currentcolor"));
+    match style.border_side(side, "color", &currentcolor) {
+        Value::Color(color) => color,
+        _ => current_color(),
+    }
+}
+
+/*
+border boxの角の半径. border-top-left-radiusなどの指定がなければborder-radiusを使う.
+横方向の%はborder boxの幅、縦方向の%は高さに対する割合で、隣り合う角の半径の和が辺より長い場合は
+すべての半径を同じ割合で縮める
+*/
+pub fn border_radii(layout_box: &LayoutBox, border_box: Rect) -> Radii {
+    let mut radii = [(0.0, 0.0); 4];
+    let style = match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => style,
+        BoxType::AnonymouseBlock => return radii,
+    };
+    let shorthand = style
+        .value("border-radius")
+        .and_then(|value| border_radius(&value));
+    let horizontal = layout_box.context.length_context(border_box.width);
+    let vertical = layout_box.context.length_context(border_box.height);
+    for (corner, name) in CORNERS.iter().enumerate() {
+        let radius = style
+            .value(&format!("border-{}-radius", name))
+            .and_then(|value| corner_radius(&value))
+            .or_else(|| shorthand.as_ref().map(|radii| radii[corner].clone()));
+        if let Some((rx, ry)) = radius {
+            let (rx, ry) = (rx.resolve(&horizontal), ry.resolve(&vertical));
+            // どちらかが0なら角は丸めない
+            if rx > 0.0 && ry > 0.0 {
+                radii[corner] = (rx, ry);
+            }
+        }
+    }
+    let sides = [
+        (radii[0].0 + radii[1].0, border_box.width),
+        (radii[1].1 + radii[2].1, border_box.height),
+        (radii[2].0 + radii[3].0, border_box.width),
+        (radii[3].1 + radii[0].1, border_box.height),
+    ];
+    let scale = sides
+        .iter()
+        .filter(|(sum, _)| *sum > 0.0)
+        .map(|(sum, length)| length.max(0.0) / sum)
+        .fold(1.0, f32::min);
+    for radius in radii.iter_mut() {
+        radius.0 *= scale;
+        radius.1 *= scale;
+    }
+    radii
+}
+
+// borderの幅のfractionの位置を通る輪郭. 0.0ならborder box、1.0ならpadding boxの丸めた角になる
+struct Contour {
+    centers: [Point; 4],
+    radii: Radii,
+}
+
+impl Contour {
+    fn new(border_box: Rect, radii: &Radii, widths: &[f32; 4], fraction: f32) -> Contour {
+        let [top, right, bottom, left] = widths.map(|width| width * fraction);
+        let rect = Rect {
+            x: border_box.x + left,
+            y: border_box.y + top,
+            width: (border_box.width - left - right).max(0.0),
+            height: (border_box.height - top - bottom).max(0.0),
+        };
+        // 角の楕円を辺の幅だけ内側に縮める. 幅が半径より大きければ角は尖る
+        let insets = [(left, top), (right, top), (right, bottom), (left, bottom)];
+        let mut inner = [(0.0, 0.0); 4];
+        for (corner, (x, y)) in insets.iter().enumerate() {
+            let (rx, ry) = (
+                (radii[corner].0 - x).max(0.0),
+                (radii[corner].1 - y).max(0.0),
+            );
+            if rx > 0.0 && ry > 0.0 {
+                inner[corner] = (rx, ry);
+            }
+        }
+        Contour {
+            centers: corner_centers(rect, &inner),
+            radii: inner,
+        }
+    }
+
+    fn arc(&self, path: &mut Path, corner: usize, start: f32, end: f32) {
+        path.arc_to(self.centers[corner], self.radii[corner], start, end);
+    }
+}
+
+/*
+角で隣の辺と分ける角度. 角の外側の点と内側の点を結ぶ線で分けるので、幅が広い辺ほど角の多くを占める.
+角の弧は前の辺から次の辺へ時計回りにたどる
+*/
+fn corner_split(widths: &[f32; 4], corner: usize) -> f32 {
+    let previous = widths[(corner + 3) % 4];
+    let next = widths[corner];
+    corner_start_angle(corner) + previous.atan2(next)
+}
+
+// 辺のborderのうち、幅のfromからtoまでの帯. 両端は角を分ける線で斜めに切る
+fn side_band(
+    border_box: Rect,
+    radii: &Radii,
+    widths: &[f32; 4],
+    side: usize,
+    from: f32,
+    to: f32,
+) -> Path {
+    let outer = Contour::new(border_box, radii, widths, from);
+    let inner = Contour::new(border_box, radii, widths, to);
+    let (previous, next) = (side, (side + 1) % 4);
+    let previous_split = corner_split(widths, previous);
+    let previous_end = corner_start_angle(previous) + FRAC_PI_2;
+    let next_split = corner_split(widths, next);
+    let next_start = corner_start_angle(next);
+
+    let mut path = Path::new();
+    outer.arc(&mut path, previous, previous_split, previous_end);
+    outer.arc(&mut path, next, next_start, next_split);
+    inner.arc(&mut path, next, next_split, next_start);
+    inner.arc(&mut path, previous, previous_end, previous_split);
+    path.close();
+    path
+}
+
+// groove, ridge, inset, outsetの影になる暗い色と光が当たる明るい色
+fn shades(color: Color) -> (Color, Color) {
+    let dark = |c: u8| (c as u32 * 2 / 3) as u8;
+    let light = |c: u8| c + (255 - c) / 3;
+    (
+        Color::from_rgba(dark(color.r), dark(color.g), dark(color.b), 255),
+        Color::from_rgba(light(color.r), light(color.g), light(color.b), 255),
+    )
+}
+
+// 線の種類ごとに、辺の幅の中で塗る帯の位置と色. 上と左の辺は影の側になる
+fn style_bands(
+    style: BorderStyle,
+    color: Color,
+    side: usize,
+    width: f32,
+) -> Vec<(f32, f32, Color)> {
+    let (dark, light) = shades(color);
+    let upper_left = side == 0 || side == 3;
+    let (near, far) = if upper_left {
+        (dark, light)
+    } else {
+        (light, dark)
     };
-    let d = &layout_box.dimensions;
+    match style {
+        // 3pxより細いと2本の線の間を空けられない
+        BorderStyle::Double if width >= 3.0 => {
+            vec![(0.0, 1.0 / 3.0, color), (2.0 / 3.0, 1.0, color)]
+        }
+        BorderStyle::Groove => vec![(0.0, 0.5, near), (0.5, 1.0, far)],
+        BorderStyle::Ridge => vec![(0.0, 0.5, far), (0.5, 1.0, near)],
+        BorderStyle::Inset => vec![(0.0, 1.0, near)],
+        BorderStyle::Outset => vec![(0.0, 1.0, far)],
+        _ => vec![(0.0, 1.0, color)],
+    }
+}
+
+/*
+pathのうち、辺に沿った向きでfromからtoまでの範囲を切り出す.
+上下の辺はx座標、左右の辺はy座標で切る
+*/
+fn clip_along(path: &Path, side: usize, from: f32, to: f32) -> Path {
+    let along = |point: &Point| match side % 2 {
+        0 => point.x,
+        _ => point.y,
+    };
+    let mut clipped = Path::new();
+    for subpath in &path.subpaths {
+        let points = clip_half_plane(&subpath.points, |point| along(point) - from);
+        let points = clip_half_plane(&points, |point| to - along(point));
+        if points.len() >= 3 {
+            clipped.subpaths.push(Subpath {
+                points,
+                closed: true,
+            });
+        }
+    }
+    clipped
+}
+
+// 閉じた折れ線のうちdistanceが0以上の側を残す(Sutherland-Hodgman)
+fn clip_half_plane(points: &[Point], distance: impl Fn(&Point) -> f32) -> Vec<Point> {
+    let mut clipped = vec![];
+    for (index, current) in points.iter().enumerate() {
+        let next = &points[(index + 1) % points.len()];
+        let (d0, d1) = (distance(current), distance(next));
+        if d0 >= 0.0 {
+            clipped.push(*current);
+        }
+        if (d0 >= 0.0) != (d1 >= 0.0) {
+            let t = d0 / (d0 - d1);
+            clipped.push(Point {
+                x: current.x + (next.x - current.x) * t,
+                y: current.y + (next.y - current.y) * t,
+            });
+        }
+    }
+    clipped
+}
+
+/*
+破線は幅の3倍の長さの線を幅の2倍ほどの間隔で並べる. 辺の両端が線になるように間隔を伸び縮みさせ、
+線を並べられないほど短い辺は実線にする
+*/
+fn dashes(start: f32, length: f32, width: f32) -> Vec<(f32, f32)> {
+    let dash = width * 3.0;
+    let count = ((length + width * 2.0) / (width * 5.0)).round() as usize;
+    if count < 2 {
+        return vec![(start, start + length)];
+    }
+    let gap = (length - dash * count as f32) / (count - 1) as f32;
+    if gap <= 0.0 {
+        return vec![(start, start + length)];
+    }
+    (0..count)
+        .map(|i| {
+            let from = start + (dash + gap) * i as f32;
+            (from, from + dash)
+        })
+        .collect()
+}
+
+/*
+点線は辺の幅の中央を通る線の上に、幅の2倍ほどの間隔で丸い点を並べる.
+長さ0の線を丸い端で描くと点になるので、間隔だけの破線として引く
+*/
+fn dotted_line(border_box: Rect, radii: &Radii, widths: &[f32; 4], side: usize) -> (Path, Stroke) {
+    let center = Contour::new(border_box, radii, widths, 0.5);
+    let (previous, next) = (side, (side + 1) % 4);
+    let mut line = Path::new();
+    center.arc(
+        &mut line,
+        previous,
+        corner_split(widths, previous),
+        corner_start_angle(previous) + FRAC_PI_2,
+    );
+    center.arc(
+        &mut line,
+        next,
+        corner_start_angle(next),
+        corner_split(widths, next),
+    );
+    let length: f32 = line
+        .subpaths
+        .iter()
+        .flat_map(|subpath| subpath.points.windows(2))
+        .map(|pair| distance(&pair[0], &pair[1]))
+        .sum();
+    let count = (length / (widths[side] * 2.0)).round().max(1.0);
+    let spacing = length / count;
+    let stroke = Stroke {
+        width: widths[side],
+        cap: LineCap::Round,
+        dash: vec![0.0, spacing],
+        // 両端から間隔の半分だけ離す
+        dash_offset: -spacing / 2.0,
+        ..Stroke::default()
+    };
+    (line, stroke)
+}
+
+/*
+各辺のborderを線の種類ごとに塗る. 辺の両端は角の外側と内側を結ぶ線で切り、隣の辺と色が違っても
+角で斜めに分かれる. border-radiusを指定した角は楕円の弧に沿って丸める
+*/
+pub fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox, d: &Dimensions) {
     let border_box = d.border_box();
-    // left border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: d.border.left,
-            height: border_box.height,
-        },
-    ));
-
-    // right border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x + border_box.width - d.border.right,
-            y: border_box.y,
-            width: d.border.right,
-            height: border_box.height,
-        },
-    ));
-
-    // top border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: border_box.width,
-            height: d.border.top,
-        },
-    ));
-
-    // bottom border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y + border_box.height - d.border.bottom,
-            width: border_box.width,
-            height: d.border.bottom,
-        },
-    ));
+    let widths = [d.border.top, d.border.right, d.border.bottom, d.border.left];
+    if border_box.width <= 0.0 || border_box.height <= 0.0 || widths.iter().all(|w| *w <= 0.0) {
+        return;
+    }
+    let style = match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => style,
+        BoxType::AnonymouseBlock => return,
+    };
+    let radii = border_radii(layout_box, border_box);
+    // border-styleがnoneの辺は幅が0になっている
+    for (side, name) in SIDES.iter().enumerate() {
+        let width = widths[side];
+        if width <= 0.0 {
+            continue;
+        }
+        let color = border_color(layout_box, name);
+        let border_style = style.border_style(name);
+        let patterned = matches!(border_style, BorderStyle::Dotted | BorderStyle::Dashed);
+        if patterned && width < MIN_PATTERN_WIDTH {
+            continue;
+        }
+        if border_style == BorderStyle::Dotted {
+            let (line, stroke) = dotted_line(border_box, &radii, &widths, side);
+            list.push(DisplayCommand::StrokePath(color, line, stroke));
+            continue;
+        }
+        for (from, to, color) in style_bands(border_style, color, side, width) {
+            let band = side_band(border_box, &radii, &widths, side, from, to);
+            if border_style != BorderStyle::Dashed {
+                list.push(DisplayCommand::FillPath(color, band, FillRule::NonZero));
+                continue;
+            }
+            let (start, length) = match side % 2 {
+                0 => (border_box.x, border_box.width),
+                _ => (border_box.y, border_box.height),
+            };
+            for (from, to) in dashes(start, length, width) {
+                let dash = clip_along(&band, side, from, to);
+                if !dash.is_empty() {
+                    list.push(DisplayCommand::FillPath(color, dash, FillRule::NonZero));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covers(path: &Path, x: usize, y: usize) -> f32 {
+        let mut coverage = 0.0;
+        path.rasterize(FillRule::NonZero, 40, 40, |px, py, value| {
+            if (px, py) == (x, y) {
+                coverage = value;
+            }
+        });
+        coverage
+    }
+
+    #[test]
+    fn test_side_band() {
+        let border_box = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 40.0,
+            height: 30.0,
+        };
+        let widths = [4.0, 8.0, 4.0, 8.0];
+        let radii = [(0.0, 0.0); 4];
+        let top = side_band(border_box, &radii, &widths, 0, 0.0, 1.0);
+        let left = side_band(border_box, &radii, &widths, 3, 0.0, 1.0);
+        // 左上の角は(0, 0)から(8, 4)への線で分ける
+        assert_eq!(covers(&top, 20, 1), 1.0);
+        assert_eq!(covers(&top, 6, 0), 1.0);
+        assert_eq!(covers(&top, 1, 3), 0.0);
+        assert_eq!(covers(&left, 1, 3), 1.0);
+        assert_eq!(covers(&left, 20, 1), 0.0);
+        // doubleの外側の線
+        let outer = side_band(border_box, &radii, &widths, 0, 0.0, 1.0 / 3.0);
+        assert_eq!(covers(&outer, 20, 0), 1.0);
+        assert_eq!(covers(&outer, 20, 2), 0.0);
+
+        // 丸めた角の外側は塗らない
+        let radii = [(10.0, 10.0); 4];
+        let top = side_band(border_box, &radii, &widths, 0, 0.0, 1.0);
+        assert_eq!(covers(&top, 20, 1), 1.0);
+        assert_eq!(covers(&top, 1, 1), 0.0);
+    }
+
+    #[test]
+    fn test_dashes_and_dotted_line() {
+        let dashes = dashes(0.0, 100.0, 4.0);
+        assert_eq!(dashes.first().unwrap().0, 0.0);
+        assert!((dashes.last().unwrap().1 - 100.0).abs() < 1e-3);
+        assert!(dashes
+            .iter()
+            .all(|(from, to)| (to - from - 12.0).abs() < 1e-3));
+        assert_eq!(super::dashes(0.0, 10.0, 4.0), vec![(0.0, 10.0)]);
+
+        let border_box = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 40.0,
+            height: 20.0,
+        };
+        let (line, stroke) = dotted_line(border_box, &[(0.0, 0.0); 4], &[2.0; 4], 0);
+        let points = &line.subpaths[0].points;
+        assert_eq!(points[0], Point { x: 1.0, y: 1.0 });
+        assert_eq!(*points.last().unwrap(), Point { x: 39.0, y: 1.0 });
+        // 長さ38pxの線に10個の点を並べる
+        assert!((stroke.dash[1] - 3.8).abs() < 1e-4);
+        assert_eq!(stroke.cap, LineCap::Round);
+    }
+
+    #[test]
+    fn test_shades() {
+        let (dark, light) = shades(Color::from_rgba(0, 0, 0, 255));
+        assert_eq!(dark, Color::from_rgba(0, 0, 0, 255));
+        assert_eq!(light, Color::from_rgba(85, 85, 85, 255));
+        let bands = style_bands(BorderStyle::Groove, Color::from_rgba(0, 0, 0, 255), 0, 4.0);
+        assert_eq!(bands[0].2, dark);
+        assert_eq!(bands[1].2, light);
+        assert_eq!(
+            style_bands(BorderStyle::Double, dark, 0, 2.0),
+            vec![(0.0, 1.0, dark)]
+        );
+    }
+
+    #[test]
+    fn test_render_borders_shorthand() {
+        use crate::layout::layout_html;
+
+        // shorthandの複数の値から辺ごとの幅、線の種類、色を決める
+        let layout = layout_html(
+            "<div class=\"box\"></div>",
+            ".box { display: block; width: 40px; height: 20px;
+            border-width: 6px 2px; border-style: solid dashed dotted double;
+            border-color: red blue; }",
+            100.0,
+            0.0,
+        );
+        let d = layout.dimensions;
+        assert_eq!((d.border.top, d.border.right), (6.0, 2.0));
+        assert_eq!((d.border.bottom, d.border.left), (6.0, 2.0));
+        let mut list = vec![];
+        render_borders(&mut list, &layout, &d);
+        let red = Color::new("red").unwrap();
+        let blue = Color::new("blue").unwrap();
+        let fills = |color: Color| {
+            list.iter()
+                .filter(|command| matches!(command, DisplayCommand::FillPath(c, ..) if *c == color))
+                .count()
+        };
+        // 上はsolidの1本、右はdashedの破線、左はdoubleの2本の線になる
+        assert_eq!(fills(red), 1);
+        assert!(fills(blue) > 3);
+        assert!(matches!(list[0], DisplayCommand::FillPath(color, ..) if color == red));
+        // 下はdottedの点線
+        let strokes: Vec<&DisplayCommand> = list
+            .iter()
+            .filter(|command| matches!(command, DisplayCommand::StrokePath(..)))
+            .collect();
+        assert_eq!(strokes.len(), 1);
+        assert!(matches!(strokes[0], DisplayCommand::StrokePath(color, ..) if *color == red));
+    }
 }
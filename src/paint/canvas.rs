@@ -1,7 +1,9 @@
 use crate::css::Color;
 use crate::font::FontMetrics;
+use crate::layout::Rect;
+use crate::paint::decoration::decoration_rects;
+use crate::paint::path::{FillRule, Path};
 use crate::paint::DisplayCommand;
-use std::iter::repeat;
 
 #[derive(Debug)]
 pub struct Canvas {
@@ -18,57 +20,114 @@ impl Canvas {
             b: 255,
             a: 255,
         };
-        return Canvas {
-            pixels: repeat(white).take(width * height).collect(),
+        Canvas {
+            pixels: vec![white; width * height],
             width,
             height,
-        };
+        }
     }
     pub fn paint_item(&mut self, item: &DisplayCommand) {
         match item {
-            &DisplayCommand::SolidColor(color, rect) => {
-                // clamp: 数値の最小値、最大値を指定するとその範囲内に収めてくれる
-                let x0 = rect.x.clamp(0.0, self.width as f32) as usize;
-                let y0 = rect.y.clamp(0.0, self.height as f32) as usize;
-                let x1 = (rect.x + rect.width).clamp(0.0, self.width as f32) as usize;
-                let y1 = (rect.y + rect.height).clamp(0.0, self.height as f32) as usize;
-
-                for y in y0..y1 {
-                    for x in x0..x1 {
-                        self.pixels[y * self.width + x] = color;
+            &DisplayCommand::SolidColor(color, rect) => self.fill_rect(color, rect),
+            DisplayCommand::FillPath(color, path, rule) => self.fill_path(*color, path, *rule),
+            DisplayCommand::StrokePath(color, path, stroke) => {
+                self.fill_path(*color, &stroke.outline(path), FillRule::NonZero)
+            }
+            &DisplayCommand::TextDecoration(color, style, rect, vertical) => {
+                for rect in decoration_rects(style, rect, vertical) {
+                    self.fill_rect(color, rect);
+                }
+            }
+            DisplayCommand::TextShadow(color, rect, coverage) => {
+                let width = rect.width as usize;
+                for (index, alpha) in coverage.iter().enumerate() {
+                    let x = rect.x as i32 + (index % width) as i32;
+                    let y = rect.y as i32 + (index / width) as i32;
+                    if *alpha == 0
+                        || x < 0
+                        || y < 0
+                        || x as usize >= self.width
+                        || y as usize >= self.height
+                    {
+                        continue;
                     }
+                    let pixel_index = y as usize * self.width + x as usize;
+                    self.pixels[pixel_index] = color.blend(self.pixels[pixel_index], *alpha);
                 }
             }
 
             DisplayCommand::FontSubpixel(color, metrics, bitmap) => {
-                for y in 0..metrics.height as usize {
+                for y in 0..metrics.height {
                     // x: 0, 3, 6, 9.....になる
-                    for mut x in (0..(metrics.width as usize * 3)).step_by(3) {
-                        let char_r = bitmap[x + y * metrics.width as usize * 3];
-                        let char_g = bitmap[x + 1 + y * metrics.width as usize * 3];
-                        let char_b = bitmap[x + 2 + y * metrics.width as usize * 3];
-                        let char_a = char_r.max(char_g).max(char_b);
+                    for mut x in (0..(metrics.width * 3)).step_by(3) {
+                        let char_r = bitmap[x + y * metrics.width * 3];
+                        let char_g = bitmap[x + 1 + y * metrics.width * 3];
+                        let char_b = bitmap[x + 2 + y * metrics.width * 3];
                         // fontデバッグ用
                         // print!("\x1B[48;2;{};{};{}m   ", char_r, char_g, char_b);
 
                         // subpixelしているため、実際のx座標は3倍になっている
-                        x = x / 3;
+                        x /= 3;
 
-                        let pixel_index = get_font_pixel_index(x, y, self.width, metrics);
+                        // canvasの外にはみ出した部分は描画しない
+                        let pixel_index =
+                            match get_font_pixel_index(x, y, self.width, self.height, metrics) {
+                                Some(index) => index,
+                                None => continue,
+                            };
 
-                        let font_color = Color::from_rgba(char_r, char_g, char_b, char_a);
+                        // RGBそれぞれのcoverageで文字の色と背景色を線形の値で混ぜる
                         let background_color = self.pixels[pixel_index];
-
-                        // fontの色と背景色を混ぜた値を背景色として設定する
-                        let blended_color = font_color.blend(background_color, char_a);
-                        self.pixels[pixel_index] = blended_color;
+                        self.pixels[pixel_index] =
+                            color.blend_linear(background_color, [char_r, char_g, char_b]);
                     }
                     // fontデバッグ用
                     // println!("\x1B[0m");
                 }
             }
+
+            DisplayCommand::ColorGlyph(metrics, image) => {
+                for y in 0..metrics.height {
+                    for x in 0..metrics.width {
+                        let pixel_index =
+                            match get_font_pixel_index(x, y, self.width, self.height, metrics) {
+                                Some(index) => index,
+                                None => continue,
+                            };
+                        // glyphの色をalphaで背景色と混ぜる
+                        let index = (y * metrics.width + x) * 4;
+                        let color =
+                            Color::from_rgba(image[index], image[index + 1], image[index + 2], 255);
+                        let alpha = image[index + 3];
+                        let background_color = self.pixels[pixel_index];
+                        self.pixels[pixel_index] =
+                            color.blend_linear(background_color, [alpha, alpha, alpha]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Canvas {
+    // 小数の位置にある端は覆う割合で背景色と混ぜる
+    fn fill_rect(&mut self, color: Color, rect: Rect) {
+        if rect.width > 0.0 && rect.height > 0.0 {
+            self.fill_path(color, &Path::rect(rect), FillRule::NonZero);
         }
     }
+
+    fn fill_path(&mut self, color: Color, path: &Path, rule: FillRule) {
+        let width = self.width;
+        let pixels = &mut self.pixels;
+        path.rasterize(rule, self.width, self.height, |x, y, coverage| {
+            let index = y * width + x;
+            pixels[index] = match coverage >= 1.0 {
+                true => color,
+                false => color.blend(pixels[index], (coverage * 255.0).round() as u8),
+            };
+        });
+    }
 }
 
 /*
@@ -76,9 +135,18 @@ impl Canvas {
    {y(縦) + metrics.y(縦のbounding box) * width(行数分y方向にずらす)}
    + {x(横) + metrics.x(横のbounding box)}
 */
-pub fn get_font_pixel_index(x: usize, y: usize, width: usize, metrics: &FontMetrics) -> usize {
+pub fn get_font_pixel_index(
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    metrics: &FontMetrics,
+) -> Option<usize> {
     // 参照: https://github.com/mooman219/fontdue/issues/10#issuecomment-603459057
-    let x_index: i32 = (x as f32 + metrics.x) as i32;
+    let x_index: i32 = (x as f32 + metrics.x + metrics.xmin as f32) as i32;
     let y_index: i32 = (y as f32 + metrics.y - metrics.height as f32 - metrics.ymin as f32) as i32;
-    y_index as usize * width + x_index as usize
+    if x_index < 0 || y_index < 0 || x_index as usize >= width || y_index as usize >= height {
+        return None;
+    }
+    Some(y_index as usize * width + x_index as usize)
 }
@@ -0,0 +1,322 @@
+use crate::css::{
+    text_decoration, text_underline_position, Color, DecorationStyle, TextDecoration,
+    UnderlinePosition, UnderlineSide,
+};
+use crate::font::FontDatabase;
+use crate::layout::{BoxType, LayoutBox, Rect};
+use crate::paint::entity::{DisplayCommand, DisplayList};
+use crate::paint::font::vertical_center;
+use crate::paint::utils::get_color;
+
+/*
+text-decorationは継承されず、指定した要素の子孫のtextすべてに描画する.
+線の色と太さは指定した要素で決まり、位置は描画するtextのfontで決まる
+*/
+#[derive(Debug, Clone)]
+pub struct Decoration {
+    decoration: TextDecoration,
+    color: Color,
+    // autoの場合はfontの値を使う
+    thickness: Option<f32>,
+    // 下線を引く位置. 指定した要素の値を使う
+    position: UnderlinePosition,
+}
+
+/// 下線と上線はtextの下に、取り消し線はtextの上に描画する
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecorationLayer {
+    BelowText,
+    AboveText,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DecorationLine {
+    Underline,
+    Overline,
+    LineThrough,
+}
+
+pub fn box_decoration(layout_box: &LayoutBox) -> Option<Decoration> {
+    let style = match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => style,
+        BoxType::AnonymouseBlock => return None,
+    };
+    let decoration = text_decoration(&style.value("text-decoration")?);
+    if decoration.is_none() {
+        return None;
+    }
+    let color = decoration
+        .color
+        .or_else(|| get_color(layout_box, "color"))
+        .unwrap_or_else(|| Color::new("black").unwrap());
+    let length = layout_box.context.length_context(0.0);
+    let thickness = decoration
+        .thickness
+        .as_ref()
+        .map(|thickness| thickness.resolve(&length));
+    let position = style
+        .value("text-underline-position")
+        .map(|value| text_underline_position(&value))
+        .unwrap_or_default();
+    Some(Decoration {
+        decoration,
+        color,
+        thickness,
+        position,
+    })
+}
+
+// textはlayout_boxのcontent(inline要素の場合は断片のcontent)の中に描画されている
+pub fn render_decorations(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    text: &str,
+    content: Rect,
+    fonts: &FontDatabase,
+    decorations: &[Decoration],
+    layer: DecorationLayer,
+) {
+    if text.is_empty() || decorations.is_empty() {
+        return;
+    }
+    let style = layout_box.text_style();
+    let text_metrics = fonts.measure(text, &style);
+    let lines = fonts.decoration_metrics(&style);
+    let mode = layout_box.context.writing_mode;
+    let baseline = content.y + text_metrics.baseline();
+    // 縦書きでは行の中央に置いた1emの枠の左右に線を引く
+    let center = vertical_center(mode, content, &text_metrics);
+    let half = style.font_size / 2.0;
+
+    for decoration in decorations {
+        // 引く線とfontの線の太さ
+        let mut kinds = vec![];
+        match layer {
+            DecorationLayer::BelowText => {
+                if decoration.decoration.underline {
+                    kinds.push((DecorationLine::Underline, lines.underline_thickness));
+                }
+                if decoration.decoration.overline {
+                    kinds.push((DecorationLine::Overline, lines.underline_thickness));
+                }
+            }
+            DecorationLayer::AboveText => {
+                if decoration.decoration.line_through {
+                    kinds.push((DecorationLine::LineThrough, lines.strikeout_thickness));
+                }
+            }
+        }
+        for (kind, font_thickness) in kinds {
+            // 細すぎる線は見えないため1px以上にする
+            let thickness = decoration.thickness.unwrap_or(font_thickness).max(1.0);
+            let rect = match mode.is_vertical() {
+                false => {
+                    // 線の上端のbaselineからの距離
+                    let position = match kind {
+                        DecorationLine::Underline if decoration.position.under => {
+                            -text_metrics.descent
+                        }
+                        DecorationLine::Underline => lines.underline_position,
+                        DecorationLine::Overline => text_metrics.ascent,
+                        DecorationLine::LineThrough => lines.strikeout_position,
+                    };
+                    Rect {
+                        x: content.x,
+                        y: baseline - position,
+                        width: content.width,
+                        height: thickness,
+                    }
+                }
+                true => {
+                    // 下線は指定した側、上線はその反対側、取り消し線は行の中央に引く
+                    let x = match (kind, decoration.position.side) {
+                        (DecorationLine::Underline, UnderlineSide::Right)
+                        | (DecorationLine::Overline, UnderlineSide::Left) => center + half,
+                        (DecorationLine::Underline, UnderlineSide::Left)
+                        | (DecorationLine::Overline, UnderlineSide::Right) => {
+                            center - half - thickness
+                        }
+                        (DecorationLine::LineThrough, _) => center - thickness / 2.0,
+                    };
+                    Rect {
+                        x,
+                        y: content.y,
+                        width: thickness,
+                        height: content.height,
+                    }
+                }
+            };
+            list.push(DisplayCommand::TextDecoration(
+                decoration.color,
+                decoration.decoration.style,
+                rect,
+                mode.is_vertical(),
+            ));
+        }
+    }
+}
+
+// x軸とy軸を入れ替える
+fn transpose(rect: Rect) -> Rect {
+    Rect {
+        x: rect.y,
+        y: rect.x,
+        width: rect.height,
+        height: rect.width,
+    }
+}
+
+/// 線の種類ごとに、描画する矩形に分ける. 縦書きの線は横向きの線として分けてから縦に戻す
+pub fn decoration_rects(style: DecorationStyle, rect: Rect, vertical: bool) -> Vec<Rect> {
+    if vertical {
+        return decoration_rects(style, transpose(rect), false)
+            .into_iter()
+            .map(transpose)
+            .collect();
+    }
+    let thickness = rect.height;
+    let segment = |x: f32, width: f32| Rect {
+        x,
+        width: width.min(rect.x + rect.width - x),
+        ..rect
+    };
+    let dashes = |dash: f32, gap: f32| {
+        let mut rects = vec![];
+        let mut x = rect.x;
+        while x < rect.x + rect.width {
+            rects.push(segment(x, dash));
+            x += dash + gap;
+        }
+        rects
+    };
+    match style {
+        DecorationStyle::Solid => vec![rect],
+        // 同じ太さの線を、線の太さだけ離して2本引く
+        DecorationStyle::Double => vec![
+            rect,
+            Rect {
+                y: rect.y + thickness * 2.0,
+                ..rect
+            },
+        ],
+        DecorationStyle::Dotted => dashes(thickness, thickness),
+        DecorationStyle::Dashed => dashes(thickness * 3.0, thickness * 2.0),
+        // 1pxごとに正弦波の高さの線を引く
+        DecorationStyle::Wavy => {
+            let amplitude = thickness.max(1.0);
+            let wavelength = (thickness * 6.0).max(4.0);
+            let mut rects = vec![];
+            let mut x = rect.x;
+            while x < rect.x + rect.width {
+                let phase = (x - rect.x) / wavelength * std::f32::consts::TAU;
+                rects.push(Rect {
+                    x,
+                    y: rect.y + amplitude * phase.sin(),
+                    width: 1.0_f32.min(rect.x + rect.width - x),
+                    height: thickness,
+                });
+                x += 1.0;
+            }
+            rects
+        }
+    }
+}
+
+#[test]
+fn test_decoration_rects() {
+    let rect = Rect {
+        x: 0.0,
+        y: 10.0,
+        width: 10.0,
+        height: 1.0,
+    };
+    assert_eq!(
+        decoration_rects(DecorationStyle::Solid, rect, false).len(),
+        1
+    );
+    let double = decoration_rects(DecorationStyle::Double, rect, false);
+    assert_eq!(double[1].y, 12.0);
+    // 点線は太さと同じ間隔で並べ、最後は幅に収める
+    let dotted = decoration_rects(DecorationStyle::Dotted, rect, false);
+    assert_eq!(dotted.len(), 5);
+    assert_eq!(dotted[1].x, 2.0);
+    let dashed = decoration_rects(DecorationStyle::Dashed, rect, false);
+    assert_eq!(dashed.len(), 2);
+    assert_eq!(dashed[1].width, 3.0);
+    let wavy = decoration_rects(DecorationStyle::Wavy, rect, false);
+    assert_eq!(wavy.len(), 10);
+    assert!(wavy.iter().any(|r| r.y > 10.0) && wavy.iter().any(|r| r.y < 10.0));
+}
+
+#[test]
+fn test_decoration_rects_vertical() {
+    let rect = Rect {
+        x: 10.0,
+        y: 0.0,
+        width: 1.0,
+        height: 10.0,
+    };
+    // 縦書きの線は上から下へ分け、太さはrectの幅になる
+    let dotted = decoration_rects(DecorationStyle::Dotted, rect, true);
+    assert_eq!(dotted.len(), 5);
+    assert_eq!((dotted[1].x, dotted[1].y), (10.0, 2.0));
+    assert_eq!((dotted[1].width, dotted[1].height), (1.0, 1.0));
+    let double = decoration_rects(DecorationStyle::Double, rect, true);
+    assert_eq!(double[1].x, 12.0);
+}
+
+#[test]
+fn test_render_decorations_vertical() {
+    use crate::layout::layout_html;
+
+    let layout = layout_html(
+        "<div class=\"root\"><p class=\"right\">abc</p><p class=\"left\">abc</p></div>",
+        ".root { display: block; writing-mode: vertical-rl; height: 200px; font-size: 20px; }
+        .right { display: block; text-decoration: underline overline line-through 2px; }
+        .left { display: block; text-decoration: underline 2px; text-underline-position: left; }",
+        200.0,
+        0.0,
+    );
+    let fonts = FontDatabase::new();
+    let lines = |index: usize, layer: DecorationLayer| {
+        let p = &layout.children[index];
+        let text = &p.children[0].children[0];
+        let fragment = &text.fragments[0];
+        let content = fragment.dimensions.content;
+        let mut list = vec![];
+        let decorations: Vec<Decoration> = box_decoration(p).into_iter().collect();
+        render_decorations(
+            &mut list,
+            text,
+            &fragment.text,
+            content,
+            &fonts,
+            &decorations,
+            layer,
+        );
+        let metrics = fonts.measure(&fragment.text, &text.text_style());
+        let center = vertical_center(text.context.writing_mode, content, &metrics);
+        let rects: Vec<Rect> = list
+            .into_iter()
+            .map(|command| match command {
+                DisplayCommand::TextDecoration(_, _, rect, true) => rect,
+                _ => panic!("not a vertical decoration"),
+            })
+            .collect();
+        (center, content, rects)
+    };
+
+    // 下線は1emの枠の右側、上線は左側に引き、線の長さは文字列の長さになる
+    let (center, content, below) = lines(0, DecorationLayer::BelowText);
+    assert_eq!(below.len(), 2);
+    assert_eq!(below[0].x, center + 10.0);
+    assert_eq!(below[1].x, center - 10.0 - 2.0);
+    assert_eq!((below[0].y, below[0].height), (content.y, content.height));
+    assert_eq!(below[0].width, 2.0);
+    // 取り消し線は行の中央に引く
+    let (center, _, above) = lines(0, DecorationLayer::AboveText);
+    assert_eq!(above[0].x, center - 1.0);
+    // text-underline-position: leftでは下線を左側に引く
+    let (center, _, below) = lines(1, DecorationLayer::BelowText);
+    assert_eq!(below[0].x, center - 10.0 - 2.0);
+}
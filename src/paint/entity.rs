@@ -1,11 +1,23 @@
-use crate::css::Color;
-use crate::font::{FontBitmap, FontMetrics};
+use crate::css::{Color, DecorationStyle};
+use crate::font::{ColorBitmap, FontBitmap, FontMetrics};
 use crate::layout::Rect;
+use crate::paint::path::{FillRule, Path};
+use crate::paint::stroke::Stroke;
 
 pub type DisplayList = Vec<DisplayCommand>;
 
 #[derive(Debug)]
 pub enum DisplayCommand {
     SolidColor(Color, Rect),
+    // 丸めた角や斜めの辺を持つ図形. 端のpixelは覆う割合で背景色と混ぜる
+    FillPath(Color, Path, FillRule),
+    // pathに沿って引く線. 太さや角の形、破線の模様はStrokeで指定する
+    StrokePath(Color, Path, Stroke),
     FontSubpixel(Color, FontMetrics, FontBitmap),
+    // 絵文字などの色を持つglyph. 1pxごとにalphaを乗算していないRGBAの値を持つ
+    ColorGlyph(FontMetrics, ColorBitmap),
+    // 下線などの線. rectの高さ(縦書きの線ではrectの幅)が線の太さになる
+    TextDecoration(Color, DecorationStyle, Rect, bool),
+    // ぼかした文字の影. rectの中の1pxごとのcoverageを持つ
+    TextShadow(Color, Rect, Vec<u8>),
 }
@@ -1,41 +1,330 @@
 use super::entity::DisplayList;
-use crate::css::Color;
-use crate::font::rasterize::{generate_font, init_fontdue};
-use crate::font::FontMetrics;
-use crate::layout::LayoutBox;
+use crate::css::{text_shadows, Color, Value};
+use crate::font::cache::subpixel_position;
+use crate::font::missing::rasterize_hex_box;
+use crate::font::rasterize::rotate_clockwise;
+use crate::font::{
+    Antialiasing, ColorBitmap, FontBitmap, FontContext, FontMetrics, PositionedGlyph, TextMetrics,
+};
+use crate::layout::writing_mode::WritingMode;
+use crate::layout::{BoxType, LayoutBox, Rect};
 use crate::paint::entity::DisplayCommand;
-use crate::paint::utils::{get_color, get_text};
+use crate::paint::utils::get_color;
 
 extern crate fontdue;
 
-pub fn render_font_subpixel(list: &mut DisplayList, layout_box: &LayoutBox) {
-    let text = get_text(layout_box);
-    if text == "" {
-        return;
-    }
-    let color = match get_color(layout_box, "color") {
+// colorはstyle treeで親要素から引き継いでいる
+fn text_color(layout_box: &LayoutBox) -> Color {
+    match get_color(layout_box, "color") {
         Some(color) => color,
         _ => Color::new("black").unwrap(),
-    };
+    }
+}
+
+// -webkit-font-smoothingの指定がない場合はcommand lineで指定した方法を使う
+fn antialiasing(layout_box: &LayoutBox, default: Antialiasing) -> Antialiasing {
+    match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+            match style.value("-webkit-font-smoothing") {
+                Some(Value::Keyword(keyword)) => default.with_font_smoothing(&keyword),
+                _ => default,
+            }
+        }
+        BoxType::AnonymouseBlock => default,
+    }
+}
+
+// glyphのbitmap. どのfontにもglyphがない文字は16進数の箱にする
+fn glyph_bitmap(
+    fonts: &FontContext,
+    glyph: &PositionedGlyph,
+    offset: usize,
+    antialiasing: Antialiasing,
+) -> (fontdue::Metrics, FontBitmap) {
+    match glyph.missing {
+        true => {
+            let (metrics, bitmap) = rasterize_hex_box(glyph.c, glyph.size);
+            (metrics, bitmap.into())
+        }
+        false => fonts.rasterize(&glyph.font, glyph.glyph, glyph.size, offset, antialiasing),
+    }
+}
+
+// glyphの画像. 絵文字などの色を持つglyphは文字の色のcoverageではなくRGBAの画像にする
+enum GlyphImage {
+    Coverage(FontBitmap),
+    Color(ColorBitmap),
+}
+
+fn glyph_image(
+    fonts: &FontContext,
+    glyph: &PositionedGlyph,
+    offset: usize,
+    antialiasing: Antialiasing,
+    color: Color,
+) -> (fontdue::Metrics, GlyphImage) {
+    if !glyph.missing && !glyph.font.color.is_empty() {
+        if let Some((metrics, image)) =
+            fonts.rasterize_color(&glyph.font, glyph.glyph, glyph.size, color)
+        {
+            return (metrics, GlyphImage::Color(image));
+        }
+    }
+    let (metrics, bitmap) = glyph_bitmap(fonts, glyph, offset, antialiasing);
+    (metrics, GlyphImage::Coverage(bitmap))
+}
+
+/// 縦書きの行の中央のx座標. vertical-rlでは行の上端(baselineの基準)が右になる
+pub fn vertical_center(mode: WritingMode, content: Rect, metrics: &TextMetrics) -> f32 {
+    match mode {
+        WritingMode::VerticalLr => content.x + metrics.baseline(),
+        _ => content.x + content.width - metrics.baseline(),
+    }
+}
 
-    // TODO: htmlからfont-sizeを取得する
-    let font_size: f32 = 24.0;
+// textの文字をcontentの中の位置に合わせてbitmapにする. 表示しない文字は描画しない
+fn rasterize_text(
+    layout_box: &LayoutBox,
+    text: &str,
+    content: Rect,
+    fonts: &FontContext,
+) -> Vec<(FontMetrics, GlyphImage)> {
+    // layoutと同じfontとmetricsを使う
+    let style = layout_box.text_style();
+    let text_metrics = fonts.fonts.measure(text, &style);
+    let (mut glyphs, width) = fonts.fonts.position_glyphs(text, &style);
+    glyphs.retain(|glyph| glyph.glyph != 0 || glyph.missing);
+    let antialiasing = antialiasing(layout_box, fonts.antialiasing);
+    let color = text_color(layout_box);
 
-    let d = &layout_box.dimensions;
-    let border_box = d.border_box();
+    if style.orientation.is_some() {
+        let center = vertical_center(layout_box.context.writing_mode, content, &text_metrics);
+        return glyphs
+            .into_iter()
+            .map(|glyph| {
+                let (ascent, descent) = match glyph.font.font.horizontal_line_metrics(glyph.size) {
+                    Some(line) => (line.ascent, -line.descent),
+                    None => (glyph.size * 0.8, glyph.size * 0.2),
+                };
+                // 横に倒した文字は、fontの高さの中央を行の中央に揃える. 上向きのずれは右向きになる
+                if glyph.rotated {
+                    let (mut metrics, bitmap) = glyph_bitmap(fonts, &glyph, 0, antialiasing);
+                    let bitmap = rotate_clockwise(&mut metrics, &bitmap).into();
+                    let x = center + (descent - ascent) / 2.0 + glyph.y;
+                    return (
+                        FontMetrics::from_fontdue_metrics(x, content.y + glyph.x, metrics),
+                        GlyphImage::Coverage(bitmap),
+                    );
+                }
+                let (metrics, image) = glyph_image(fonts, &glyph, 0, antialiasing, color);
+                // 正立させた文字は1emの枠の中央に置く. 縦中横は横に並べた文字列全体を中央に置く
+                let (x, y) = match style.combined() {
+                    true => (center - width / 2.0 + glyph.x, 0.0),
+                    false => (center - metrics.advance_width / 2.0, glyph.x),
+                };
+                let baseline =
+                    content.y + y + (style.font_size - (ascent + descent)) / 2.0 + ascent - glyph.y;
+                (
+                    FontMetrics::from_fontdue_metrics(x, baseline, metrics),
+                    image,
+                )
+            })
+            .collect();
+    }
 
-    let font_cli = init_fontdue();
+    // 行のすべての文字は最初に使えるfamilyのfontから求めたbaselineに揃える
+    let baseline = content.y + text_metrics.baseline();
+    glyphs
+        .into_iter()
+        .map(|glyph| {
+            // 1px未満のずれはbitmapをsubpixel単位でずらして表す
+            let (x, offset) = subpixel_position(content.x + glyph.x);
+            let (metrics, image) = glyph_image(fonts, &glyph, offset, antialiasing, color);
+            (
+                FontMetrics::from_fontdue_metrics(x, baseline - glyph.y, metrics),
+                image,
+            )
+        })
+        .collect()
+}
 
-    // 文字のX座標はfontを描画するたび、その長さずつ右にずれていく
-    let mut text_position_x = border_box.x;
-    let text_position_y = border_box.y;
-    for char in text.chars() {
-        let (metrics, bitmap) = generate_font(&font_cli, char, font_size);
-        list.push(DisplayCommand::FontSubpixel(
-            color,
-            FontMetrics::from_fontdue_metrics(text_position_x, text_position_y, metrics),
-            bitmap,
-        ));
-        text_position_x += metrics.width as f32;
+// textはlayout_boxのcontent(inline要素の場合は断片のcontent)の中に描画する
+pub fn render_font_subpixel(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    text: &str,
+    content: Rect,
+    fonts: &FontContext,
+) {
+    if text.is_empty() {
+        return;
+    }
+    let color = text_color(layout_box);
+    for (metrics, image) in rasterize_text(layout_box, text, content, fonts) {
+        list.push(match image {
+            GlyphImage::Coverage(bitmap) => DisplayCommand::FontSubpixel(color, metrics, bitmap),
+            GlyphImage::Color(image) => DisplayCommand::ColorGlyph(metrics, image),
+        });
     }
 }
+
+/*
+text-shadowの影を描画する. 文字のcoverageを1枚の画像にまとめてからぼかす.
+最初に指定した影が一番上になるため、後ろから順に描画する
+*/
+pub fn render_text_shadows(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    text: &str,
+    content: Rect,
+    fonts: &FontContext,
+) {
+    if text.is_empty() {
+        return;
+    }
+    let shadows = match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+            match style.value("text-shadow") {
+                Some(value) => text_shadows(&value),
+                None => return,
+            }
+        }
+        BoxType::AnonymouseBlock => return,
+    };
+    if shadows.is_empty() {
+        return;
+    }
+    let glyphs = rasterize_text(layout_box, text, content, fonts);
+    let length = layout_box.context.length_context(0.0);
+    for shadow in shadows.iter().rev() {
+        let offset_x = shadow.offset_x.resolve(&length);
+        let offset_y = shadow.offset_y.resolve(&length);
+        let blur = shadow.blur.resolve(&length).max(0.0);
+        let color = shadow.color.unwrap_or_else(|| text_color(layout_box));
+        if let Some((rect, coverage)) = shadow_coverage(&glyphs, offset_x, offset_y, blur) {
+            list.push(DisplayCommand::TextShadow(color, rect, coverage));
+        }
+    }
+}
+
+/*
+glyphのbitmapを影の位置に重ね、blurの半径でぼかしたcoverage.
+subpixelの3つの値の平均を1pxのcoverageにする. 色を持つglyphはalphaを使う
+*/
+fn shadow_coverage(
+    glyphs: &[(FontMetrics, GlyphImage)],
+    offset_x: f32,
+    offset_y: f32,
+    blur: f32,
+) -> Option<(Rect, Vec<u8>)> {
+    // glyphの左上の位置(canvasのget_font_pixel_indexと同じ計算)
+    let origin = |metrics: &FontMetrics| {
+        (
+            (metrics.x + metrics.xmin as f32 + offset_x) as i32,
+            (metrics.y - metrics.height as f32 - metrics.ymin as f32 + offset_y) as i32,
+        )
+    };
+    let margin = blur.ceil() as i32;
+    let mut bounds: Option<(i32, i32, i32, i32)> = None;
+    for (metrics, _) in glyphs.iter().filter(|(metrics, _)| metrics.width > 0) {
+        let (x, y) = origin(metrics);
+        let (right, bottom) = (x + metrics.width as i32, y + metrics.height as i32);
+        bounds = Some(match bounds {
+            Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(right), y1.max(bottom)),
+            None => (x, y, right, bottom),
+        });
+    }
+    let (x0, y0, x1, y1) = bounds?;
+    let (x0, y0) = (x0 - margin, y0 - margin);
+    let width = (x1 + margin - x0) as usize;
+    let height = (y1 + margin - y0) as usize;
+
+    let mut coverage = vec![0u8; width * height];
+    for (metrics, image) in glyphs {
+        let (gx, gy) = origin(metrics);
+        for y in 0..metrics.height {
+            for x in 0..metrics.width {
+                let pixel = y * metrics.width + x;
+                let value = match image {
+                    GlyphImage::Coverage(bitmap) => {
+                        let index = pixel * 3;
+                        (bitmap[index] as u16 + bitmap[index + 1] as u16 + bitmap[index + 2] as u16)
+                            / 3
+                    }
+                    GlyphImage::Color(image) => image[pixel * 4 + 3] as u16,
+                };
+                let cx = (gx - x0) as usize + x;
+                let cy = (gy - y0) as usize + y;
+                let pixel = &mut coverage[cy * width + cx];
+                *pixel = (*pixel).max(value as u8);
+            }
+        }
+    }
+    // blurの半径の半分を標準偏差とするgaussian blurを3回のbox blurで近似する
+    let sigma = blur / 2.0;
+    if sigma > 0.0 {
+        let box_width = (12.0 * sigma * sigma / 3.0 + 1.0).sqrt();
+        let radius = ((box_width - 1.0) / 2.0).round().max(1.0) as usize;
+        for _ in 0..3 {
+            coverage = box_blur(&coverage, width, height, radius);
+        }
+    }
+    let rect = Rect {
+        x: x0 as f32,
+        y: y0 as f32,
+        width: width as f32,
+        height: height as f32,
+    };
+    Some((rect, coverage))
+}
+
+// 横方向と縦方向に、前後radius pxの平均をとる
+fn box_blur(coverage: &[u8], width: usize, height: usize, radius: usize) -> Vec<u8> {
+    let average = |get: &dyn Fn(usize) -> u8, len: usize, i: usize| {
+        let from = i.saturating_sub(radius);
+        let to = (i + radius).min(len - 1);
+        let sum: u32 = (from..=to).map(|j| get(j) as u32).sum();
+        (sum / (radius * 2 + 1) as u32) as u8
+    };
+    let mut horizontal = vec![0u8; coverage.len()];
+    for y in 0..height {
+        let row = |x: usize| coverage[y * width + x];
+        for x in 0..width {
+            horizontal[y * width + x] = average(&row, width, x);
+        }
+    }
+    let mut blurred = vec![0u8; coverage.len()];
+    for x in 0..width {
+        let column = |y: usize| horizontal[y * width + x];
+        for y in 0..height {
+            blurred[y * width + x] = average(&column, height, y);
+        }
+    }
+    blurred
+}
+
+#[test]
+fn test_shadow_coverage() {
+    let metrics = FontMetrics {
+        x: 10.0,
+        y: 20.0,
+        xmin: 0,
+        ymin: 0,
+        width: 2,
+        height: 2,
+    };
+    let glyphs = vec![(metrics, GlyphImage::Coverage(vec![255u8; 12].into()))];
+    // ぼかさない場合はglyphの大きさのまま影の位置に動かす
+    let (rect, coverage) = shadow_coverage(&glyphs, 3.0, 4.0, 0.0).unwrap();
+    assert_eq!(
+        (rect.x, rect.y, rect.width, rect.height),
+        (13.0, 22.0, 2.0, 2.0)
+    );
+    assert_eq!(coverage, vec![255; 4]);
+    // ぼかすとblurの分だけ広がり、中心より外側が薄くなる
+    let (rect, coverage) = shadow_coverage(&glyphs, 0.0, 0.0, 4.0).unwrap();
+    assert_eq!((rect.width, rect.height), (10.0, 10.0));
+    let center = coverage[5 * 10 + 5];
+    assert!(center > 0 && center < 255);
+    assert!(coverage[0] < center);
+}
@@ -1,10 +1,14 @@
 mod background;
 mod border;
 mod canvas;
+mod decoration;
 mod entity;
 mod font;
+#[allow(clippy::module_inception)]
 mod paint;
+mod path;
+mod stroke;
 mod utils;
 
 pub use crate::paint::entity::DisplayCommand;
-pub use crate::paint::paint::{build_display_list, paint};
+pub use crate::paint::paint::paint;
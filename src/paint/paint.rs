@@ -1,28 +1,99 @@
-use crate::layout::{LayoutBox, Rect};
+use crate::font::FontContext;
+use crate::layout::{BoxType, LayoutBox, Rect};
 use crate::paint::background::render_background;
 use crate::paint::border::render_borders;
 use crate::paint::canvas::Canvas;
+use crate::paint::decoration::{box_decoration, render_decorations, Decoration, DecorationLayer};
 use crate::paint::entity::DisplayList;
-use crate::paint::font::render_font_subpixel;
+use crate::paint::font::{render_font_subpixel, render_text_shadows};
 
-pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
+pub fn build_display_list(layout_root: &LayoutBox, fonts: &FontContext) -> DisplayList {
     let mut list = Vec::new();
-    render_layout_box(&mut list, layout_root);
+    render_layout_box(&mut list, layout_root, fonts, &[]);
     list
 }
 
-fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
-    render_background(list, layout_box);
-    render_borders(list, layout_box);
-    render_font_subpixel(list, layout_box);
+// decorationsは祖先の要素で指定されたtext-decoration
+fn render_layout_box(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    fonts: &FontContext,
+    decorations: &[Decoration],
+) {
+    let mut decorations = decorations.to_vec();
+    decorations.extend(box_decoration(layout_box));
+    match layout_box.box_type {
+        // inline要素は行ごとの断片を描画する
+        BoxType::InlineNode(_) => {
+            for fragment in &layout_box.fragments {
+                let d = &fragment.dimensions;
+                render_background(list, layout_box, d);
+                render_borders(list, layout_box, d);
+                render_text(
+                    list,
+                    layout_box,
+                    &fragment.text,
+                    d.content,
+                    fonts,
+                    &decorations,
+                );
+            }
+        }
+        _ => {
+            let d = &layout_box.dimensions;
+            render_background(list, layout_box, d);
+            render_borders(list, layout_box, d);
+            render_text(
+                list,
+                layout_box,
+                layout_box.text(),
+                d.content,
+                fonts,
+                &decorations,
+            );
+        }
+    }
 
     for child in &layout_box.children {
-        render_layout_box(list, child);
+        render_layout_box(list, child, fonts, &decorations);
     }
 }
 
-pub fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
-    let display_list = build_display_list(layout_root);
+// 影、下線と上線、文字、取り消し線の順に重ねる
+fn render_text(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    text: &str,
+    content: Rect,
+    fonts: &FontContext,
+    decorations: &[Decoration],
+) {
+    render_text_shadows(list, layout_box, text, content, fonts);
+    let layers = [DecorationLayer::BelowText, DecorationLayer::AboveText];
+    render_decorations(
+        list,
+        layout_box,
+        text,
+        content,
+        fonts.fonts,
+        decorations,
+        layers[0],
+    );
+    render_font_subpixel(list, layout_box, text, content, fonts);
+    render_decorations(
+        list,
+        layout_box,
+        text,
+        content,
+        fonts.fonts,
+        decorations,
+        layers[1],
+    );
+}
+
+/// fontsのcacheは呼び出し側が持ち、描画の後で統計を読めるようにする
+pub fn paint(layout_root: &LayoutBox, bounds: Rect, fonts: &FontContext) -> Canvas {
+    let display_list = build_display_list(layout_root, fonts);
     // println!("{:?}", display_list);
     let mut canvas = Canvas::new(bounds.width as usize, bounds.height as usize);
     for item in display_list {
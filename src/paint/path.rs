@@ -0,0 +1,495 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use crate::layout::Rect;
+
+// 1pxの行を縦に分けて交差を求める数. 横方向は端の位置から面積を求める
+const SUBSCANLINES: usize = 16;
+// 弧を線分に分けるときの弧とのずれの許容量(px)
+const TOLERANCE: f32 = 0.02;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// 角の楕円の横方向と縦方向の半径. 左上、右上、右下、左下の順に並べる
+pub type Radii = [(f32, f32); 4];
+
+/// 重なった部分や穴を塗るかの規則. nonzeroは辺の向きを数え、evenoddは交差の数の偶奇で決める
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FillRule {
+    #[default]
+    NonZero,
+    EvenOdd,
+}
+
+impl FillRule {
+    fn is_inside(self, winding: i32) -> bool {
+        match self {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+/// 1本の折れ線. 塗るときは閉じていなくても最後の点から最初の点へ戻る
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Subpath {
+    pub points: Vec<Point>,
+    pub closed: bool,
+}
+
+/// 塗りつぶしたり線を引いたりする図形. 曲線は線分に分け、折れ線の集まりとして持つ
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Path {
+    pub subpaths: Vec<Subpath>,
+}
+
+impl Path {
+    pub fn new() -> Path {
+        Path::default()
+    }
+
+    pub fn move_to(&mut self, x: f32, y: f32) {
+        self.subpaths.push(Subpath {
+            points: vec![Point { x, y }],
+            closed: false,
+        });
+    }
+
+    pub fn line_to(&mut self, x: f32, y: f32) {
+        match self.subpaths.last_mut() {
+            Some(subpath) if !subpath.closed => subpath.points.push(Point { x, y }),
+            // 閉じた折れ線の後は、その始点から新しい折れ線を始める
+            Some(_) => {
+                let start = self.current_point();
+                self.move_to(start.x, start.y);
+                self.line_to(x, y);
+            }
+            None => self.move_to(x, y),
+        }
+    }
+
+    pub fn close(&mut self) {
+        if let Some(subpath) = self.subpaths.last_mut() {
+            subpath.closed = true;
+        }
+    }
+
+    /// 閉じた折れ線の後は、その始点が現在の点になる
+    fn current_point(&self) -> Point {
+        self.subpaths
+            .last()
+            .and_then(|subpath| match subpath.closed {
+                true => subpath.points.first().copied(),
+                false => subpath.points.last().copied(),
+            })
+            .unwrap_or_default()
+    }
+
+    /// 2次ベジェ曲線. 制御点の曲がり具合からずれがTOLERANCEに収まる数に分ける
+    pub fn quad_to(&mut self, control: Point, to: Point) {
+        let from = self.current_point();
+        let bend = distance(
+            &Point::default(),
+            &Point {
+                x: from.x - 2.0 * control.x + to.x,
+                y: from.y - 2.0 * control.y + to.y,
+            },
+        );
+        let segments = (bend / (4.0 * TOLERANCE)).sqrt().ceil().clamp(1.0, 256.0) as usize;
+        for i in 1..=segments {
+            let t = i as f32 / segments as f32;
+            let u = 1.0 - t;
+            self.line_to(
+                u * u * from.x + 2.0 * u * t * control.x + t * t * to.x,
+                u * u * from.y + 2.0 * u * t * control.y + t * t * to.y,
+            );
+        }
+    }
+
+    /// 3次ベジェ曲線
+    pub fn cubic_to(&mut self, control1: Point, control2: Point, to: Point) {
+        let from = self.current_point();
+        let bend = |a: &Point, b: &Point, c: &Point| {
+            distance(
+                &Point::default(),
+                &Point {
+                    x: a.x - 2.0 * b.x + c.x,
+                    y: a.y - 2.0 * b.y + c.y,
+                },
+            )
+        };
+        let bend = bend(&from, &control1, &control2).max(bend(&control1, &control2, &to));
+        let segments = (0.75 * bend / TOLERANCE).sqrt().ceil().clamp(1.0, 256.0) as usize;
+        for i in 1..=segments {
+            let t = i as f32 / segments as f32;
+            let u = 1.0 - t;
+            let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+            self.line_to(
+                a * from.x + b * control1.x + c * control2.x + d * to.x,
+                a * from.y + b * control1.y + c * control2.y + d * to.y,
+            );
+        }
+    }
+
+    /*
+    centerを中心とする楕円の弧をstartからendの角度(radian)まで線でつなぐ.
+    角度はx軸の正の向きから時計回り(画面のy軸は下向き)に測る. 半径が0なら中心の点になる
+    */
+    pub fn arc_to(&mut self, center: Point, radius: (f32, f32), start: f32, end: f32) {
+        let (rx, ry) = radius;
+        if rx <= 0.0 || ry <= 0.0 {
+            self.line_to(center.x, center.y);
+            return;
+        }
+        // 弦と弧のずれがTOLERANCEに収まる角度ごとに分ける
+        let r = rx.max(ry);
+        let step = 2.0 * (1.0 - TOLERANCE / r).max(-1.0).acos();
+        let segments = ((end - start).abs() / step).ceil().max(1.0) as usize;
+        for i in 0..=segments {
+            let angle = start + (end - start) * i as f32 / segments as f32;
+            self.line_to(center.x + rx * angle.cos(), center.y + ry * angle.sin());
+        }
+    }
+
+    pub fn rect(rect: Rect) -> Path {
+        Path::rounded_rect(rect, [(0.0, 0.0); 4])
+    }
+
+    /// 角を楕円の弧で丸めた長方形. 時計回りにたどる
+    pub fn rounded_rect(rect: Rect, radii: Radii) -> Path {
+        let mut path = Path::new();
+        for (corner, center) in corner_centers(rect, &radii).iter().enumerate() {
+            let start = corner_start_angle(corner);
+            path.arc_to(*center, radii[corner], start, start + FRAC_PI_2);
+        }
+        path.close();
+        path
+    }
+
+    pub fn circle(center: Point, radius: f32) -> Path {
+        let mut path = Path::new();
+        path.arc_to(center, (radius, radius), 0.0, 2.0 * PI);
+        path.close();
+        path
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subpaths.iter().all(|subpath| subpath.points.len() < 3)
+    }
+
+    /*
+    width x heightの範囲の各pixelを図形が覆う割合(0.0から1.0)を求め、0より大きいpixelごとにpaintを呼ぶ.
+    各行をSUBSCANLINES本の走査線に分け、走査線と辺の交点の間の塗る区間を1pxごとの面積として足し合わせる
+    */
+    pub fn rasterize(
+        &self,
+        rule: FillRule,
+        width: usize,
+        height: usize,
+        mut paint: impl FnMut(usize, usize, f32),
+    ) {
+        let mut edges = self.edges();
+        if edges.is_empty() || width == 0 || height == 0 {
+            return;
+        }
+        // 上端の順に並べ、走査線にかかる辺だけを調べる
+        edges.sort_by(|a, b| a.top.total_cmp(&b.top));
+        let top = edges[0].top;
+        let bottom = edges
+            .iter()
+            .map(|edge| edge.bottom)
+            .fold(f32::MIN, f32::max);
+        let first_row = top.floor().clamp(0.0, height as f32) as usize;
+        let last_row = bottom.ceil().clamp(0.0, height as f32) as usize;
+
+        let mut coverage = vec![0.0f32; width];
+        let mut active: Vec<&Edge> = vec![];
+        let mut next_edge = 0;
+        let mut crossings: Vec<(f32, i32)> = vec![];
+        for row in first_row..last_row {
+            // 塗った区間の範囲だけを読み、次の行のために0に戻す
+            let mut left = width;
+            let mut right = 0;
+            for sub in 0..SUBSCANLINES {
+                let y = row as f32 + (sub as f32 + 0.5) / SUBSCANLINES as f32;
+                while next_edge < edges.len() && edges[next_edge].top <= y {
+                    active.push(&edges[next_edge]);
+                    next_edge += 1;
+                }
+                active.retain(|edge| edge.bottom > y);
+                crossings.clear();
+                crossings.extend(
+                    active
+                        .iter()
+                        .filter(|edge| edge.top <= y)
+                        .map(|edge| (edge.x_at(y), edge.winding)),
+                );
+                crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                let mut winding = 0;
+                let mut span_start = 0.0;
+                for &(x, direction) in &crossings {
+                    let was_inside = rule.is_inside(winding);
+                    winding += direction;
+                    match (was_inside, rule.is_inside(winding)) {
+                        (false, true) => span_start = x,
+                        (true, false) => {
+                            if let Some((from, to)) = add_span(&mut coverage, span_start, x) {
+                                left = left.min(from);
+                                right = right.max(to);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            for (x, value) in coverage.iter_mut().enumerate().take(right).skip(left) {
+                if *value > 0.0 {
+                    paint(x, row, value.min(1.0));
+                }
+                *value = 0.0;
+            }
+        }
+    }
+
+    // 各折れ線を閉じた辺の一覧. 水平な辺は走査線と交わらないので除く
+    fn edges(&self) -> Vec<Edge> {
+        let mut edges = vec![];
+        for subpath in &self.subpaths {
+            let points = &subpath.points;
+            for (index, from) in points.iter().enumerate() {
+                let to = &points[(index + 1) % points.len()];
+                if from.y == to.y {
+                    continue;
+                }
+                // 下向きの辺は1、上向きの辺は-1を数える
+                let (upper, lower, winding) = match from.y < to.y {
+                    true => (from, to, 1),
+                    false => (to, from, -1),
+                };
+                edges.push(Edge {
+                    top: upper.y,
+                    bottom: lower.y,
+                    x: upper.x,
+                    slope: (lower.x - upper.x) / (lower.y - upper.y),
+                    winding,
+                });
+            }
+        }
+        edges
+    }
+}
+
+// 上端のx座標と、yが1増えるごとのxの増え方で表した辺
+#[derive(Debug)]
+struct Edge {
+    top: f32,
+    bottom: f32,
+    x: f32,
+    slope: f32,
+    winding: i32,
+}
+
+impl Edge {
+    fn x_at(&self, y: f32) -> f32 {
+        self.x + (y - self.top) * self.slope
+    }
+}
+
+pub fn distance(a: &Point, b: &Point) -> f32 {
+    ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt()
+}
+
+// 1本の走査線のx0からx1の区間を各pixelに重なる長さだけ足す. 足したpixelの範囲を返す
+fn add_span(coverage: &mut [f32], x0: f32, x1: f32) -> Option<(usize, usize)> {
+    let width = coverage.len() as f32;
+    let (x0, x1) = (x0.clamp(0.0, width), x1.clamp(0.0, width));
+    if x1 <= x0 {
+        return None;
+    }
+    let from = x0.floor() as usize;
+    let to = (x1.ceil() as usize).min(coverage.len());
+    for (x, value) in coverage.iter_mut().enumerate().take(to).skip(from) {
+        let overlap = x1.min(x as f32 + 1.0) - x0.max(x as f32);
+        *value += overlap / SUBSCANLINES as f32;
+    }
+    Some((from, to))
+}
+
+/// 角の弧が始まる角度. 左上の角は左の辺から上の辺へ、時計回りにたどる
+pub fn corner_start_angle(corner: usize) -> f32 {
+    PI + FRAC_PI_2 * corner as f32
+}
+
+/// 角の楕円の中心. 左上、右上、右下、左下の順に並べる
+pub fn corner_centers(rect: Rect, radii: &Radii) -> [Point; 4] {
+    let right = rect.x + rect.width;
+    let bottom = rect.y + rect.height;
+    [
+        Point {
+            x: rect.x + radii[0].0,
+            y: rect.y + radii[0].1,
+        },
+        Point {
+            x: right - radii[1].0,
+            y: rect.y + radii[1].1,
+        },
+        Point {
+            x: right - radii[2].0,
+            y: bottom - radii[2].1,
+        },
+        Point {
+            x: rect.x + radii[3].0,
+            y: bottom - radii[3].1,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coverage(path: &Path, rule: FillRule, width: usize, height: usize) -> Vec<f32> {
+        let mut pixels = vec![0.0; width * height];
+        path.rasterize(rule, width, height, |x, y, value| {
+            pixels[y * width + x] = value
+        });
+        pixels
+    }
+
+    #[test]
+    fn test_rasterize_rect() {
+        let rect = Rect {
+            x: 1.0,
+            y: 1.0,
+            width: 2.5,
+            height: 2.0,
+        };
+        let pixels = coverage(&Path::rect(rect), FillRule::NonZero, 5, 4);
+        assert_eq!(pixels[0], 0.0);
+        assert_eq!(pixels[5 + 1], 1.0);
+        assert_eq!(pixels[10 + 2], 1.0);
+        // 右端は半分だけ覆う
+        assert!((pixels[5 + 3] - 0.5).abs() < 1e-4);
+        assert_eq!(pixels[15 + 1], 0.0);
+    }
+
+    #[test]
+    fn test_fill_rules() {
+        // 外側を時計回り、内側を反時計回りにたどると穴になる
+        let mut path = Path::new();
+        for (x, y) in [(0.0, 0.0), (6.0, 0.0), (6.0, 6.0), (0.0, 6.0)] {
+            path.line_to(x, y);
+        }
+        path.move_to(2.0, 2.0);
+        for (x, y) in [(2.0, 4.0), (4.0, 4.0), (4.0, 2.0)] {
+            path.line_to(x, y);
+        }
+        let pixels = coverage(&path, FillRule::NonZero, 6, 6);
+        assert_eq!(pixels[6 + 1], 1.0);
+        assert_eq!(pixels[2 * 6 + 2], 0.0);
+
+        // 同じ向きにたどった内側はnonzeroでは塗り、evenoddでは穴になる
+        let mut path = Path::rect(Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 6.0,
+            height: 6.0,
+        });
+        path.subpaths.extend(
+            Path::rect(Rect {
+                x: 2.0,
+                y: 2.0,
+                width: 2.0,
+                height: 2.0,
+            })
+            .subpaths,
+        );
+        let pixels = coverage(&path, FillRule::NonZero, 6, 6);
+        assert_eq!(pixels[2 * 6 + 2], 1.0);
+        let pixels = coverage(&path, FillRule::EvenOdd, 6, 6);
+        assert_eq!(pixels[2 * 6 + 2], 0.0);
+        assert_eq!(pixels[6 + 1], 1.0);
+
+        // 対角線で半分に分けた三角形は斜めの辺のpixelを半分覆う
+        let mut triangle = Path::new();
+        for (x, y) in [(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)] {
+            triangle.line_to(x, y);
+        }
+        let pixels = coverage(&triangle, FillRule::NonZero, 4, 4);
+        assert!((pixels[3] - 0.5).abs() < 0.05);
+        assert_eq!(pixels[4 + 1], 1.0);
+    }
+
+    #[test]
+    fn test_rounded_rect() {
+        let rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 20.0,
+            height: 20.0,
+        };
+        let path = Path::rounded_rect(rect, [(10.0, 10.0); 4]);
+        let pixels = coverage(&path, FillRule::NonZero, 20, 20);
+        // 円になるので角は塗らない
+        assert_eq!(pixels[0], 0.0);
+        assert_eq!(pixels[10 * 20 + 10], 1.0);
+        assert!(pixels[10 * 20] > 0.9);
+        let area: f32 = pixels.iter().sum();
+        assert!((area - PI * 100.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_curves() {
+        let mut path = Path::new();
+        path.move_to(0.0, 10.0);
+        path.quad_to(Point { x: 10.0, y: -10.0 }, Point { x: 20.0, y: 10.0 });
+        let points = &path.subpaths[0].points;
+        assert!(points.len() > 3);
+        // 2次ベジェ曲線の頂点は制御点との中間の高さになる
+        let top = points.iter().map(|p| p.y).fold(f32::MAX, f32::min);
+        assert!((top - 0.0).abs() < 0.05);
+        assert_eq!(*points.last().unwrap(), Point { x: 20.0, y: 10.0 });
+
+        let mut path = Path::new();
+        path.move_to(0.0, 0.0);
+        path.cubic_to(
+            Point { x: 0.0, y: 10.0 },
+            Point { x: 10.0, y: 10.0 },
+            Point { x: 10.0, y: 0.0 },
+        );
+        let bottom = path.subpaths[0]
+            .points
+            .iter()
+            .map(|p| p.y)
+            .fold(0.0, f32::max);
+        assert!((bottom - 7.5).abs() < 0.05);
+        // 閉じた後のline_toは新しい折れ線になる
+        path.close();
+        path.line_to(1.0, 1.0);
+        assert_eq!(path.subpaths.len(), 2);
+    }
+
+    #[test]
+    fn test_line_to_after_close() {
+        let mut path = Path::new();
+        path.move_to(0.0, 0.0);
+        path.line_to(10.0, 0.0);
+        path.line_to(10.0, 10.0);
+        path.close();
+        path.line_to(0.0, 10.0);
+        // 閉じた折れ線の始点から新しい折れ線が始まる
+        assert_eq!(path.subpaths.len(), 2);
+        assert!(path.subpaths[0].closed);
+        assert_eq!(
+            path.subpaths[1],
+            Subpath {
+                points: vec![Point { x: 0.0, y: 0.0 }, Point { x: 0.0, y: 10.0 }],
+                closed: false,
+            }
+        );
+    }
+}
@@ -0,0 +1,487 @@
+use crate::paint::path::{distance, Path, Point, Subpath};
+
+// 同じ点とみなす距離(px)
+const EPSILON: f32 = 1e-4;
+// 1本の折れ線に繰り返す破線の模様の数の上限. 超える細かい模様は区別できないので実線にする
+const MAX_DASH_REPEATS: f32 = 10000.0;
+
+/// 線の両端の形. squareは線の太さの半分だけ伸ばす
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineCap {
+    #[default]
+    Butt,
+    Round,
+    Square,
+}
+
+/// 折れ線の角の形. miterは角が鋭くmiter_limitを超える場合にbevelになる
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineJoin {
+    #[default]
+    Miter,
+    Round,
+    Bevel,
+}
+
+/// 線の引き方. dashは線と間隔の長さを交互に並べ、dash_offsetの位置から始める
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stroke {
+    pub width: f32,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    pub miter_limit: f32,
+    pub dash: Vec<f32>,
+    pub dash_offset: f32,
+}
+
+impl Default for Stroke {
+    fn default() -> Stroke {
+        Stroke {
+            width: 1.0,
+            cap: LineCap::default(),
+            join: LineJoin::default(),
+            miter_limit: 4.0,
+            dash: vec![],
+            dash_offset: 0.0,
+        }
+    }
+}
+
+impl Stroke {
+    /*
+    線が覆う範囲を塗りつぶす図形にする. 線分ごとの長方形と角、端の図形をすべて同じ向きの多角形にするので、
+    nonzeroで塗れば重なった部分も1回だけ塗る
+    */
+    pub fn outline(&self, path: &Path) -> Path {
+        let mut outline = Path::new();
+        if self.width <= 0.0 {
+            return outline;
+        }
+        for subpath in &path.subpaths {
+            for piece in self.dash_pieces(subpath) {
+                self.outline_piece(&mut outline, &piece);
+            }
+        }
+        outline
+    }
+
+    // 奇数個の長さは2回繰り返す. 負の値や合計が0の場合は破線にしない
+    fn dash_pattern(&self) -> Option<Vec<f32>> {
+        if self.dash.iter().any(|length| *length < 0.0) || self.dash.iter().sum::<f32>() <= 0.0 {
+            return None;
+        }
+        let mut pattern = self.dash.clone();
+        if pattern.len() % 2 == 1 {
+            pattern.extend_from_slice(&self.dash);
+        }
+        Some(pattern)
+    }
+
+    // 折れ線を破線の線の部分ごとの開いた折れ線に分ける
+    fn dash_pieces(&self, subpath: &Subpath) -> Vec<Subpath> {
+        let pattern = match self.dash_pattern() {
+            Some(pattern) => pattern,
+            None => return vec![subpath.clone()],
+        };
+        let points = &subpath.points;
+        if points.is_empty() {
+            return vec![];
+        }
+        let total: f32 = pattern.iter().sum();
+        let length: f32 = points
+            .windows(2)
+            .map(|pair| distance(&pair[0], &pair[1]))
+            .sum::<f32>()
+            + match subpath.closed {
+                true => distance(&points[points.len() - 1], &points[0]),
+                false => 0.0,
+            };
+        if length / total > MAX_DASH_REPEATS {
+            return vec![subpath.clone()];
+        }
+        // dash_offsetの位置が模様のどこにあたるかを求める
+        let mut offset = self.dash_offset.rem_euclid(total);
+        let mut index = 0;
+        while offset >= pattern[index] {
+            offset -= pattern[index];
+            index = (index + 1) % pattern.len();
+        }
+        let mut remaining = pattern[index] - offset;
+
+        let mut segments: Vec<(Point, Point)> =
+            points.windows(2).map(|pair| (pair[0], pair[1])).collect();
+        if subpath.closed {
+            segments.push((points[points.len() - 1], points[0]));
+        }
+        let mut pieces = vec![];
+        let mut current = match index % 2 {
+            0 => vec![points[0]],
+            _ => vec![],
+        };
+        for (from, to) in segments {
+            let length = distance(&from, &to);
+            let mut walked = 0.0;
+            // 線か間隔がこの線分の途中で終わる
+            while length - walked > remaining {
+                // 模様の長さがwalkedの精度より小さいと進まなくなるので、実線にする
+                if remaining > 0.0 && walked + remaining <= walked {
+                    return vec![subpath.clone()];
+                }
+                walked += remaining;
+                let t = walked / length;
+                let point = Point {
+                    x: from.x + (to.x - from.x) * t,
+                    y: from.y + (to.y - from.y) * t,
+                };
+                if index % 2 == 0 {
+                    current.push(point);
+                    pieces.push(Subpath {
+                        points: std::mem::take(&mut current),
+                        closed: false,
+                    });
+                } else {
+                    current = vec![point];
+                }
+                index = (index + 1) % pattern.len();
+                remaining = pattern[index];
+            }
+            remaining -= length - walked;
+            if index % 2 == 0 {
+                current.push(to);
+            }
+        }
+        if !current.is_empty() {
+            pieces.push(Subpath {
+                points: current,
+                closed: false,
+            });
+        }
+        pieces
+    }
+
+    fn outline_piece(&self, outline: &mut Path, subpath: &Subpath) {
+        let mut points: Vec<Point> = vec![];
+        for point in &subpath.points {
+            if !matches!(points.last(), Some(last) if distance(last, point) <= EPSILON) {
+                points.push(*point);
+            }
+        }
+        if subpath.closed
+            && points.len() > 1
+            && distance(&points[0], &points[points.len() - 1]) <= EPSILON
+        {
+            points.pop();
+        }
+        let half = self.width / 2.0;
+        // 長さが0の線は端の形だけを描く
+        if points.len() == 1 {
+            let point = points[0];
+            match self.cap {
+                LineCap::Butt => {}
+                LineCap::Round => add_polygon(outline, circle(point, half)),
+                LineCap::Square => add_polygon(
+                    outline,
+                    vec![
+                        offset(point, -half, -half),
+                        offset(point, half, -half),
+                        offset(point, half, half),
+                        offset(point, -half, half),
+                    ],
+                ),
+            }
+            return;
+        }
+        if points.len() < 2 {
+            return;
+        }
+        let closed = subpath.closed && points.len() > 2;
+        let count = points.len();
+        let segments = if closed { count } else { count - 1 };
+        let direction = |i: usize| unit(points[i % count], points[(i + 1) % count]);
+        for i in 0..segments {
+            let (from, to) = (points[i], points[(i + 1) % count]);
+            let normal = normal(direction(i), half);
+            add_polygon(
+                outline,
+                vec![
+                    offset(from, normal.x, normal.y),
+                    offset(to, normal.x, normal.y),
+                    offset(to, -normal.x, -normal.y),
+                    offset(from, -normal.x, -normal.y),
+                ],
+            );
+        }
+        // 閉じた折れ線はすべての点、開いた折れ線は両端以外の点が角になる
+        let joins = if closed { 0..count } else { 1..count - 1 };
+        for i in joins {
+            let previous = direction((i + count - 1) % count);
+            self.add_join(outline, points[i], previous, direction(i));
+        }
+        if !closed {
+            let start = direction(0);
+            self.add_cap(
+                outline,
+                points[0],
+                Point {
+                    x: -start.x,
+                    y: -start.y,
+                },
+            );
+            self.add_cap(outline, points[count - 1], direction(count - 2));
+        }
+    }
+
+    // 線の端から外向きのdirectionに付ける形
+    fn add_cap(&self, outline: &mut Path, point: Point, direction: Point) {
+        let half = self.width / 2.0;
+        match self.cap {
+            LineCap::Butt => {}
+            LineCap::Round => add_polygon(outline, circle(point, half)),
+            LineCap::Square => {
+                let normal = normal(direction, half);
+                let end = offset(point, direction.x * half, direction.y * half);
+                add_polygon(
+                    outline,
+                    vec![
+                        offset(point, normal.x, normal.y),
+                        offset(end, normal.x, normal.y),
+                        offset(end, -normal.x, -normal.y),
+                        offset(point, -normal.x, -normal.y),
+                    ],
+                );
+            }
+        }
+    }
+
+    /*
+    向きがincomingからoutgoingに変わる角の外側を埋める. 内側は2本の線分の長方形が重なっている.
+    miterの先端までの長さは線の太さの1 / cos(角度の半分)倍になる
+    */
+    fn add_join(&self, outline: &mut Path, point: Point, incoming: Point, outgoing: Point) {
+        let half = self.width / 2.0;
+        let cross = incoming.x * outgoing.y - incoming.y * outgoing.x;
+        let dot = incoming.x * outgoing.x + incoming.y * outgoing.y;
+        if cross.abs() < EPSILON && dot > 0.0 {
+            return;
+        }
+        if self.join == LineJoin::Round {
+            add_polygon(outline, circle(point, half));
+            return;
+        }
+        // 時計回りに曲がる場合は法線と反対側が外側になる
+        let side = if cross > 0.0 { -half } else { half };
+        let (n0, n1) = (normal(incoming, side), normal(outgoing, side));
+        let (a, b) = (offset(point, n0.x, n0.y), offset(point, n1.x, n1.y));
+        let cos_half = ((1.0 + dot) / 2.0).max(0.0).sqrt();
+        if self.join == LineJoin::Miter && cos_half > EPSILON && 1.0 / cos_half <= self.miter_limit
+        {
+            let sum = Point {
+                x: n0.x + n1.x,
+                y: n0.y + n1.y,
+            };
+            let scale = half / cos_half / distance(&Point::default(), &sum);
+            let tip = offset(point, sum.x * scale, sum.y * scale);
+            add_polygon(outline, vec![point, a, tip, b]);
+        } else {
+            add_polygon(outline, vec![point, a, b]);
+        }
+    }
+}
+
+fn offset(point: Point, x: f32, y: f32) -> Point {
+    Point {
+        x: point.x + x,
+        y: point.y + y,
+    }
+}
+
+fn unit(from: Point, to: Point) -> Point {
+    let length = distance(&from, &to);
+    Point {
+        x: (to.x - from.x) / length,
+        y: (to.y - from.y) / length,
+    }
+}
+
+// 向きを時計回りに90度回した長さlengthの法線
+fn normal(direction: Point, length: f32) -> Point {
+    Point {
+        x: -direction.y * length,
+        y: direction.x * length,
+    }
+}
+
+fn circle(center: Point, radius: f32) -> Vec<Point> {
+    Path::circle(center, radius)
+        .subpaths
+        .pop()
+        .map(|subpath| subpath.points)
+        .unwrap_or_default()
+}
+
+// 面積が正になる向きにそろえて閉じた多角形を加える
+fn add_polygon(outline: &mut Path, mut points: Vec<Point>) {
+    let area: f32 = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum();
+    if area.abs() < EPSILON {
+        return;
+    }
+    if area < 0.0 {
+        points.reverse();
+    }
+    outline.subpaths.push(Subpath {
+        points,
+        closed: true,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paint::path::FillRule;
+
+    fn coverage(path: &Path) -> Vec<f32> {
+        let mut pixels = vec![0.0; 20 * 20];
+        path.rasterize(FillRule::NonZero, 20, 20, |x, y, value| {
+            pixels[y * 20 + x] = value
+        });
+        pixels
+    }
+
+    fn polyline(points: &[(f32, f32)]) -> Path {
+        let mut path = Path::new();
+        for (x, y) in points {
+            path.line_to(*x, *y);
+        }
+        path
+    }
+
+    #[test]
+    fn test_stroke_caps() {
+        let line = polyline(&[(5.0, 10.0), (15.0, 10.0)]);
+        let stroke = Stroke {
+            width: 4.0,
+            ..Stroke::default()
+        };
+        let pixels = coverage(&stroke.outline(&line));
+        assert_eq!(pixels[10 * 20 + 5], 1.0);
+        assert_eq!(pixels[8 * 20 + 10], 1.0);
+        assert_eq!(pixels[7 * 20 + 10], 0.0);
+        assert_eq!(pixels[10 * 20 + 4], 0.0);
+        // squareは太さの半分だけ伸びる
+        let square = Stroke {
+            cap: LineCap::Square,
+            ..stroke.clone()
+        };
+        let pixels = coverage(&square.outline(&line));
+        assert_eq!(pixels[10 * 20 + 3], 1.0);
+        assert_eq!(pixels[10 * 20 + 2], 0.0);
+        // 長さが0の線はroundなら円になる
+        let round = Stroke {
+            cap: LineCap::Round,
+            ..stroke
+        };
+        let dot = round.outline(&polyline(&[(10.0, 10.0), (10.0, 10.0)]));
+        let area: f32 = coverage(&dot).iter().sum();
+        assert!((area - std::f32::consts::PI * 4.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_stroke_joins() {
+        // 直角の角. miterは外側の角を埋め、bevelは斜めに切る
+        let corner = polyline(&[(4.0, 10.0), (10.0, 10.0), (10.0, 16.0)]);
+        let miter = Stroke {
+            width: 4.0,
+            ..Stroke::default()
+        };
+        let pixels = coverage(&miter.outline(&corner));
+        assert_eq!(pixels[8 * 20 + 11], 1.0);
+        let bevel = Stroke {
+            join: LineJoin::Bevel,
+            ..miter.clone()
+        };
+        let pixels = coverage(&bevel.outline(&corner));
+        assert_eq!(pixels[8 * 20 + 11], 0.0);
+        assert_eq!(pixels[9 * 20 + 10], 1.0);
+        // 鋭い角はmiter_limitを超えるとbevelになる
+        let sharp = polyline(&[(2.0, 8.0), (18.0, 10.0), (2.0, 12.0)]);
+        let limited = coverage(&miter.outline(&sharp));
+        let unlimited = Stroke {
+            miter_limit: 100.0,
+            ..miter
+        };
+        let unlimited = coverage(&unlimited.outline(&sharp));
+        let sum = |pixels: &[f32]| pixels.iter().sum::<f32>();
+        assert!(sum(&unlimited) > sum(&limited) + 1.0);
+        // 重なった部分も1回だけ塗る
+        assert!(unlimited.iter().all(|value| *value <= 1.0));
+    }
+
+    #[test]
+    fn test_dash_pieces() {
+        let line = polyline(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        let stroke = Stroke {
+            dash: vec![4.0, 2.0],
+            dash_offset: 1.0,
+            ..Stroke::default()
+        };
+        let pieces = stroke.dash_pieces(&line.subpaths[0]);
+        let starts: Vec<Point> = pieces.iter().map(|piece| piece.points[0]).collect();
+        assert_eq!(starts[0], Point { x: 0.0, y: 0.0 });
+        assert_eq!(starts[1], Point { x: 5.0, y: 0.0 });
+        assert_eq!(
+            pieces[1].points,
+            vec![Point { x: 5.0, y: 0.0 }, Point { x: 9.0, y: 0.0 }]
+        );
+        assert_eq!(starts[2], Point { x: 10.0, y: 1.0 });
+        // 角をまたぐ線は角の点を含む
+        let stroke = Stroke {
+            dash: vec![4.0, 3.0],
+            ..Stroke::default()
+        };
+        let pieces = stroke.dash_pieces(&line.subpaths[0]);
+        assert_eq!(
+            pieces[1].points,
+            vec![
+                Point { x: 7.0, y: 0.0 },
+                Point { x: 10.0, y: 0.0 },
+                Point { x: 10.0, y: 1.0 }
+            ]
+        );
+        // 奇数個の長さは繰り返し、負の値があれば実線になる
+        let odd = Stroke {
+            dash: vec![3.0],
+            ..Stroke::default()
+        };
+        assert_eq!(odd.dash_pattern(), Some(vec![3.0, 3.0]));
+        let invalid = Stroke {
+            dash: vec![3.0, -1.0],
+            ..Stroke::default()
+        };
+        assert_eq!(invalid.dash_pieces(&line.subpaths[0]).len(), 1);
+    }
+
+    #[test]
+    fn test_dash_pieces_tiny_pattern() {
+        // 幅0.000001pxの点線. 模様が細かすぎる場合は分けずに実線にする
+        let line = polyline(&[(0.0, 0.0), (100.0, 0.0)]);
+        let dotted = Stroke {
+            width: 0.000001,
+            cap: LineCap::Round,
+            dash: vec![0.0, 0.000002],
+            dash_offset: -0.000001,
+            ..Stroke::default()
+        };
+        assert_eq!(dotted.dash_pieces(&line.subpaths[0]), line.subpaths);
+        // 模様の合計は長くても、一部がwalkedの精度より短いと進まない
+        let line = polyline(&[(0.0, 0.0), (1000.0, 0.0)]);
+        let uneven = Stroke {
+            dash: vec![1e-9, 1.0],
+            ..Stroke::default()
+        };
+        assert_eq!(uneven.dash_pieces(&line.subpaths[0]), line.subpaths);
+    }
+}
@@ -1,5 +1,4 @@
 use crate::css::{Color, Value};
-use crate::html::ElementData;
 use crate::layout::{BoxType, LayoutBox};
 
 pub fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
@@ -11,21 +10,3 @@ pub fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
         BoxType::AnonymouseBlock => None,
     }
 }
-
-pub fn get_text<'a>(layout_box: &'a LayoutBox) -> &'a str {
-    match layout_box.box_type {
-        BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
-            style.node.element_data.text.as_str()
-        }
-        BoxType::AnonymouseBlock => "",
-    }
-}
-
-pub fn get_element_data(layout_box: &LayoutBox) -> Option<ElementData> {
-    match layout_box.box_type {
-        BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
-            Some(style.node.element_data.clone())
-        }
-        BoxType::AnonymouseBlock => None,
-    }
-}
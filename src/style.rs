@@ -1,7 +1,16 @@
-use crate::css::{Block, Selector, Specificity, StyleSheet, Unit, Value};
+use crate::css::{
+    border_side, border_style, font_weight, property_type, Block, BorderStyle, Combinator,
+    Declaration, MediaContext, PseudoClass, Selector, Specificity, StyleSheet, Unit, Value,
+    NORMAL_WEIGHT,
+};
 use crate::html::{Element, ElementData, ElementType};
-use crate::mock::element::{gen_mock_element, ElementMockOption};
-use crate::mock::stylesheet::{gen_mock_stylesheet, StyleSheetMockOption};
+#[cfg(test)]
+use crate::{
+    css::{parse_css, Color},
+    html::parse_nodes,
+    mock::element::{gen_mock_element, ElementMockOption},
+    mock::stylesheet::{gen_mock_stylesheet, StyleSheetMockOption},
+};
 use std::collections::HashMap;
 
 type PropertyMap = HashMap<String, Value>;
@@ -21,7 +30,7 @@ pub struct StyledNode<'a> {
 
 impl StyledNode<'_> {
     pub fn value(&self, name: &str) -> Option<Value> {
-        self.specified_values.get(name).map(|v| v.clone())
+        self.specified_values.get(name).cloned()
     }
 
     pub fn display(&self) -> Display {
@@ -39,6 +48,32 @@ impl StyledNode<'_> {
         self.value(name)
             .unwrap_or_else(|| self.value(fallback).unwrap_or_else(|| default.clone()))
     }
+
+    /// 辺のborderの値. border-top-styleなどの指定がなければborder-styleなどのshorthandから辺の値を取り出す
+    pub fn border_side(&self, side: &str, name: &str, default: &Value) -> Value {
+        match self.value(&format!("border-{}-{}", side, name)) {
+            Some(value) => value,
+            None => match self.value(&format!("border-{}", name)) {
+                Some(value) => border_side(&value, side),
+                None => default.clone(),
+            },
+        }
+    }
+
+    /// 辺のborder-style
+    pub fn border_style(&self, side: &str) -> BorderStyle {
+        let none = Value::Keyword(String::from("none"));
+        border_style(&self.border_side(side, "style", &none))
+    }
+
+    /// 辺のborderの幅. 初期値はmediumで、border-styleがnoneかhiddenの場合は0になる
+    pub fn border_width(&self, side: &str) -> Value {
+        if !self.border_style(side).is_visible() {
+            return Value::Length(0.0, Unit::Px);
+        }
+        let medium = Value::Length(3.0, Unit::Px);
+        self.border_side(side, "width", &medium)
+    }
 }
 
 // 要素が一致するselectorを見つけたらtrue, そうでなければfalseを返す
@@ -59,29 +94,141 @@ fn exist_match_selector(elem: &ElementData, selector: &Selector) -> bool {
     false
 }
 
+// selectorの結合子や:first-childを判定するための、木の中での要素の位置
+struct ElementPath<'a, 'p> {
+    element: &'a Element,
+    parent: Option<&'p ElementPath<'a, 'p>>,
+    // 親要素のchildrenの中でのindex
+    index: usize,
+}
+
+impl<'a, 'p> ElementPath<'a, 'p> {
+    fn root(element: &'a Element) -> Self {
+        ElementPath {
+            element,
+            parent: None,
+            index: 0,
+        }
+    }
+
+    fn ancestors(&self) -> impl Iterator<Item = &'p ElementPath<'a, 'p>> {
+        std::iter::successors(self.parent, |path| path.parent)
+    }
+
+    // 直前の兄弟要素から順に返す. textは要素ではないので含まない
+    fn previous_siblings(&self) -> Vec<ElementPath<'a, 'p>> {
+        let parent = match self.parent {
+            Some(parent) => parent,
+            None => return vec![],
+        };
+        parent.element.children[..self.index]
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, child)| child.element_data.name != ElementType::Text)
+            .map(|(index, element)| ElementPath {
+                element,
+                parent: self.parent,
+                index,
+            })
+            .collect()
+    }
+
+    fn has_next_sibling(&self) -> bool {
+        match self.parent {
+            Some(parent) => parent.element.children[self.index + 1..]
+                .iter()
+                .any(|child| child.element_data.name != ElementType::Text),
+            None => false,
+        }
+    }
+}
+
+fn match_pseudo_class(path: &ElementPath, pseudo_class: &PseudoClass) -> bool {
+    match pseudo_class {
+        PseudoClass::Is(selectors) => selectors.iter().any(|s| match_selector(path, s)),
+        PseudoClass::FirstChild => path.previous_siblings().is_empty(),
+        PseudoClass::LastChild => !path.has_next_sibling(),
+        PseudoClass::Other(_) => false,
+    }
+}
+
+// 結合子を含めてselectorが要素に一致するかどうか
+fn match_selector(path: &ElementPath, selector: &Selector) -> bool {
+    let elem = &path.element.element_data;
+    let has_simple_selector =
+        selector.element.is_some() || selector.id.is_some() || !selector.class.is_empty();
+    let matched = if has_simple_selector {
+        exist_match_selector(elem, selector)
+    } else {
+        !selector.pseudo_classes.is_empty()
+    };
+    if !matched
+        || !selector
+            .pseudo_classes
+            .iter()
+            .all(|pseudo_class| match_pseudo_class(path, pseudo_class))
+    {
+        return false;
+    }
+    match &selector.combinator {
+        None => true,
+        Some((Combinator::Descendant, left)) => path
+            .ancestors()
+            .any(|ancestor| match_selector(ancestor, left)),
+        Some((Combinator::Child, left)) => path
+            .parent
+            .is_some_and(|parent| match_selector(parent, left)),
+        Some((Combinator::NextSibling, left)) => path
+            .previous_siblings()
+            .first()
+            .is_some_and(|sibling| match_selector(sibling, left)),
+        Some((Combinator::SubsequentSibling, left)) => path
+            .previous_siblings()
+            .iter()
+            .any(|sibling| match_selector(sibling, left)),
+    }
+}
+
 type MatchedBlock<'a> = (Specificity, &'a Block);
 
-fn match_block<'a>(elem: &ElementData, block: &'a Block) -> Option<MatchedBlock<'a>> {
+fn match_block<'a>(
+    path: &ElementPath,
+    block: &'a Block,
+    media: &MediaContext,
+) -> Option<MatchedBlock<'a>> {
+    if !block.media.iter().all(|query| query.matches(media)) {
+        return None;
+    }
     block
         .selectors
         .iter()
-        .find(|selector| exist_match_selector(elem, *selector))
+        .find(|selector| match_selector(path, selector))
         .map(|selector| (selector.specificity(), block))
 }
 
-fn matching_blocks<'a>(elem: &ElementData, style_sheet: &'a StyleSheet) -> Vec<MatchedBlock<'a>> {
+fn matching_blocks<'a>(
+    path: &ElementPath,
+    style_sheet: &'a StyleSheet,
+    media: &MediaContext,
+) -> Vec<MatchedBlock<'a>> {
     style_sheet
         .blocks
         .iter()
-        .filter_map(|block| match_block(elem, block))
+        .filter_map(|block| match_block(path, block, media))
         .collect()
 }
 
-fn get_property_map(elem: &ElementData, style_sheet: &StyleSheet) -> PropertyMap {
+fn get_property_map(
+    path: &ElementPath,
+    style_sheet: &StyleSheet,
+    media: &MediaContext,
+) -> PropertyMap {
     let mut values = HashMap::new();
-    let mut blocks = matching_blocks(elem, style_sheet);
+    let mut blocks = matching_blocks(path, style_sheet, media);
 
-    blocks.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+    // 詳細度が同じ場合は後に書かれたruleを優先する(安定ソート)
+    blocks.sort_by_key(|&(a, _)| a);
     for (_, block) in blocks {
         for declaration in &block.declarations {
             values.insert(declaration.property.to_string(), declaration.value.clone());
@@ -90,23 +237,128 @@ fn get_property_map(elem: &ElementData, style_sheet: &StyleSheet) -> PropertyMap
     values
 }
 
-pub fn style_tree<'a>(root: &'a Element, style_sheet: &'a StyleSheet) -> StyledNode<'a> {
+// UA stylesheetの規則が適用される要素
+enum UaTarget {
+    Element(ElementType),
+    // dir属性の値
+    Dir(&'static str),
+}
+
+impl UaTarget {
+    fn matches(&self, element: &ElementData) -> bool {
+        match self {
+            UaTarget::Element(name) => element.name == *name,
+            UaTarget::Dir(dir) => element.dir == *dir,
+        }
+    }
+}
+
+const UA_DEFAULTS: &[(UaTarget, &str, &str)] = &[
+    // pre, textareaは空白と改行をそのまま表示する
+    (UaTarget::Element(ElementType::Pre), "white-space", "pre"),
+    (
+        UaTarget::Element(ElementType::Textarea),
+        "white-space",
+        "pre",
+    ),
+    // rpはrubyに対応した環境では表示しない. rtの注釈は小さい文字にする
+    (UaTarget::Element(ElementType::Rp), "display", "none"),
+    (UaTarget::Element(ElementType::Rt), "font-size", "50%"),
+    (UaTarget::Element(ElementType::Rt), "line-height", "normal"),
+    // 見出しは太字、emは斜体にする
+    (UaTarget::Element(ElementType::H1), "font-weight", "bold"),
+    (UaTarget::Element(ElementType::H2), "font-weight", "bold"),
+    (UaTarget::Element(ElementType::H3), "font-weight", "bold"),
+    (UaTarget::Element(ElementType::H4), "font-weight", "bold"),
+    (UaTarget::Element(ElementType::Em), "font-style", "italic"),
+    // dir属性は内容を分離し、autoは内容の最初の強い文字で方向を決める
+    (UaTarget::Dir("ltr"), "direction", "ltr"),
+    (UaTarget::Dir("ltr"), "unicode-bidi", "isolate"),
+    (UaTarget::Dir("rtl"), "direction", "rtl"),
+    (UaTarget::Dir("rtl"), "unicode-bidi", "isolate"),
+    (UaTarget::Dir("auto"), "unicode-bidi", "plaintext"),
+];
+
+pub fn style_tree<'a>(
+    root: &'a Element,
+    style_sheet: &'a StyleSheet,
+    media: &MediaContext,
+) -> StyledNode<'a> {
+    build_style_tree(&ElementPath::root(root), style_sheet, media, None)
+}
+
+fn build_style_tree<'a>(
+    path: &ElementPath<'a, '_>,
+    style_sheet: &'a StyleSheet,
+    media: &MediaContext,
+    parent: Option<&PropertyMap>,
+) -> StyledNode<'a> {
+    let root = path.element;
     // textにCSSを直接指定できない(親タグに付与する)ため、textの場合は処理をスキップ
-    let specified: PropertyMap = match root.element_data.name {
+    let mut specified: PropertyMap = match root.element_data.name {
         ElementType::Text => HashMap::new(),
-        _ => get_property_map(&root.element_data, style_sheet),
+        _ => get_property_map(path, style_sheet, media),
     };
+    // UA stylesheetの既定値は作者のstylesheetで指定されていない場合だけ使う
+    for (target, name, value) in UA_DEFAULTS {
+        if target.matches(&root.element_data) {
+            let declaration = Declaration::new(name, value).unwrap();
+            specified
+                .entry(declaration.property.to_string())
+                .or_insert(declaration.value);
+        }
+    }
+    // font-familyなど継承されるpropertyは、指定がなければ親要素の値を使う
+    if let Some(parent) = parent {
+        for (name, value) in parent {
+            if property_type(name).inherited() && !specified.contains_key(name) {
+                specified.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    // bolder, lighterは親要素のfont-weightを基準に数値にする
+    let parent_weight = parent
+        .and_then(|parent| parent.get("font-weight"))
+        .map(|weight| font_weight(weight, NORMAL_WEIGHT))
+        .unwrap_or(NORMAL_WEIGHT);
+    if let Some(weight) = specified.get_mut("font-weight") {
+        if matches!(weight, Value::Keyword(keyword) if keyword == "bolder" || keyword == "lighter")
+        {
+            *weight = Value::Number(font_weight(weight, parent_weight));
+        }
+    }
+    let children = root
+        .children
+        .iter()
+        .enumerate()
+        .map(|(index, child)| {
+            let child_path = ElementPath {
+                element: child,
+                parent: Some(path),
+                index,
+            };
+            build_style_tree(&child_path, style_sheet, media, Some(&specified))
+        })
+        .collect();
     StyledNode {
         node: root,
         specified_values: specified,
-        children: root
-            .children
-            .iter()
-            .map(|child| style_tree(child, style_sheet))
-            .collect(),
+        children,
     }
 }
 
+// テスト用にHTMLとCSSからstyle treeを作る. 木はテストの間だけ使うため解放しない
+#[cfg(test)]
+pub fn style_html(html: &str, css: &str) -> StyledNode<'static> {
+    let root = Box::leak(Box::new(parse_nodes(html)));
+    let style_sheet = Box::leak(Box::new(parse_css(css)));
+    let media = MediaContext {
+        width: 800.0,
+        height: 600.0,
+    };
+    style_tree(root, style_sheet, &media)
+}
+
 #[test]
 fn test_property_map() {
     // id: test1, width: 20pxにして、propertyMapが正常に作られているか
@@ -118,7 +370,11 @@ fn test_property_map() {
     stylesheet_option.property = String::from("width");
     stylesheet_option.value = String::from("20px");
     let style_sheet = gen_mock_stylesheet(stylesheet_option);
-    let property_map = get_property_map(&elem.element_data, &style_sheet);
+    let media = MediaContext {
+        width: 800.0,
+        height: 600.0,
+    };
+    let property_map = get_property_map(&ElementPath::root(&elem), &style_sheet, &media);
 
     let width = String::from("width");
     assert_eq!(
@@ -134,6 +390,7 @@ fn test_exist_match_selector() {
         text: String::from("hello"),
         id: String::from("test_element"),
         class: String::from("test"),
+        dir: String::new(),
     };
     let mut selector = Selector::new();
 
@@ -157,3 +414,111 @@ fn test_exist_match_selector() {
     let test3 = exist_match_selector(&test_element, &selector);
     assert_eq!(test3, true);
 }
+
+#[test]
+fn test_style_tree_nesting() {
+    let styled = style_html(
+        r#"<div class="card"><h2>title</h2><p>text</p><div><h2>deep</h2></div></div>"#,
+        "
+.card {
+    & > h2 { color: red; }
+    h2 { background-color: blue; }
+    &:hover { color: green; }
+    @media (min-width: 1000px) { display: none; }
+}
+h2 + p { margin: 2px; }
+",
+    );
+    // :hoverとmin-width: 1000pxには一致しない
+    assert_eq!(styled.value("color"), None);
+    assert_eq!(styled.value("display"), None);
+
+    let h2 = &styled.children[0];
+    assert_eq!(
+        h2.value("color"),
+        Some(Value::Color(Color::new("red").unwrap()))
+    );
+    assert_eq!(
+        h2.value("background-color"),
+        Some(Value::Color(Color::new("blue").unwrap()))
+    );
+    let p = &styled.children[1];
+    assert_eq!(p.value("margin"), Some(Value::Length(2.0, Unit::Px)));
+
+    // 子孫のh2にはbackground-colorのみ適用される
+    let deep_h2 = &styled.children[2].children[0];
+    assert_eq!(deep_h2.value("color"), None);
+    assert_eq!(
+        deep_h2.value("background-color"),
+        Some(Value::Color(Color::new("blue").unwrap()))
+    );
+}
+
+#[test]
+fn test_style_tree_inherit() {
+    let styled = style_html(
+        "<div class=\"a\"><p>text</p><p class=\"b\">text</p></div>",
+        ".a { font-family: Roboto, sans-serif; margin: 2px; font-weight: 300; }
+        .b { font-family: serif; font-weight: bolder; }",
+    );
+    let families = styled.value("font-family");
+    // font-familyはtextまで引き継がれ、marginは引き継がれない
+    let p = &styled.children[0];
+    assert_eq!(p.value("font-family"), families);
+    assert_eq!(p.children[0].value("font-family"), families);
+    assert_eq!(p.value("margin"), None);
+    // 子要素で指定した場合はその値が使われる
+    let p_b = &styled.children[1];
+    assert_eq!(
+        p_b.children[0].value("font-family"),
+        Some(Value::List(vec![Value::Keyword(String::from("serif"))]))
+    );
+    // bolderは親要素の300を基準に計算した値が引き継がれる
+    assert_eq!(p.value("font-weight"), Some(Value::Number(300.0)));
+    assert_eq!(
+        p_b.children[0].value("font-weight"),
+        Some(Value::Number(400.0))
+    );
+}
+
+#[test]
+fn test_style_tree_ua_defaults() {
+    let styled = style_html(
+        r#"<div><pre>a</pre><ruby>b<rp>(</rp><rt>c</rt></ruby><p dir="rtl">d</p><p dir="auto">e</p></div>"#,
+        "p { unicode-bidi: normal; }",
+    );
+    let keyword = |value: &str| Some(Value::Keyword(String::from(value)));
+    assert_eq!(styled.children[0].value("white-space"), keyword("pre"));
+    let ruby = &styled.children[1];
+    assert_eq!(ruby.children[1].value("display"), keyword("none"));
+    assert_eq!(
+        ruby.children[2].value("font-size"),
+        Some(Value::Percentage(50.0))
+    );
+    // 作者のstylesheetの指定はUA stylesheetより優先される
+    let rtl = &styled.children[2];
+    assert_eq!(rtl.value("direction"), keyword("rtl"));
+    assert_eq!(rtl.value("unicode-bidi"), keyword("normal"));
+    assert_eq!(styled.children[3].value("direction"), None);
+}
+
+#[test]
+fn test_style_tree_ua_font_defaults() {
+    let styled = style_html(
+        "<div><h1>a<em>b</em></h1><h4>c</h4><h2 class=\"light\">d</h2><p>e</p></div>",
+        ".light { font-weight: 300; }",
+    );
+    let keyword = |value: &str| Some(Value::Keyword(String::from(value)));
+    let h1 = &styled.children[0];
+    assert_eq!(h1.value("font-weight"), keyword("bold"));
+    // emは斜体になり、太字は見出しから引き継ぐ
+    let em = &h1.children[1];
+    assert_eq!(em.value("font-style"), keyword("italic"));
+    assert_eq!(em.value("font-weight"), keyword("bold"));
+    assert_eq!(styled.children[1].value("font-weight"), keyword("bold"));
+    assert_eq!(
+        styled.children[2].value("font-weight"),
+        Some(Value::Number(300.0))
+    );
+    assert_eq!(styled.children[3].value("font-weight"), None);
+}